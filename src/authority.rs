@@ -3,7 +3,7 @@ use std::str::FromStr;
 
 use crate::utils::ALLOWED_HOSTNAME_BYTES;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct Host {
     inner: String,
 }
@@ -59,7 +59,7 @@ impl FromStr for Host {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct Port {
     inner: u16,
 }
@@ -70,7 +70,11 @@ struct PortParseError;
 impl FromStr for Port {
     type Err = PortParseError;
 
+    // port = *DIGIT, which `u16::from_str` is more lenient about
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(PortParseError);
+        }
         match s.parse::<u16>() {
             Ok(n) => Ok(Port { inner: n }),
             Err(_) => Err(PortParseError),
@@ -78,12 +82,23 @@ impl FromStr for Port {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-struct Authority {
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Authority {
     host: Host,
     port: Option<Port>,
 }
 
+impl Authority {
+    pub fn host(&self) -> &str {
+        &self.host.inner
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.port.as_ref().map(|p| p.inner)
+    }
+}
+
+#[cfg(test)]
 impl Authority {
     fn new() -> Self {
         Self {
@@ -92,7 +107,7 @@ impl Authority {
         }
     }
 
-    fn host(self, hostname: &str) -> Self {
+    fn with_host(self, hostname: &str) -> Self {
         Self {
             host: Host {
                 inner: hostname.into(),
@@ -101,7 +116,7 @@ impl Authority {
         }
     }
 
-    fn port(self, port: u16) -> Self {
+    fn with_port(self, port: u16) -> Self {
         Self {
             host: self.host,
             port: Some(Port { inner: port }),
@@ -110,11 +125,14 @@ impl Authority {
 }
 
 #[derive(Debug, PartialEq, Eq)]
-struct AuthorityParseError;
+pub struct AuthorityParseError;
 
 impl FromStr for Authority {
     type Err = AuthorityParseError;
 
+    // User information ("user:password@") is not accepted, it has no place
+    // in "http" and "https" URIs (RFC 9110 section 4.2.4) and would
+    // otherwise be mistaken for the host.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.split(":").collect::<Vec<_>>()[..] {
             [h] => {
@@ -129,7 +147,10 @@ impl FromStr for Authority {
                     host,
                     port: Some(port),
                 }),
-                (Ok(host), Err(_)) => Ok(Authority { host, port: None }),
+                // An empty port is the same as none (RFC 3986 section 3.2.3)
+                (Ok(host), Err(_)) if p.is_empty() => {
+                    Ok(Authority { host, port: None })
+                }
                 _ => Err(AuthorityParseError),
             },
             _ => Err(AuthorityParseError),
@@ -156,20 +177,22 @@ mod tests {
     fn parsing() {
         assert_eq!(
             "www.example.com".parse(),
-            Ok(Authority::new().host("www.example.com"))
+            Ok(Authority::new().with_host("www.example.com"))
         );
         assert_eq!(
             "www.example.com:443".parse(),
-            Ok(Authority::new().host("www.example.com").port(443))
+            Ok(Authority::new().with_host("www.example.com").with_port(443))
         );
         assert_eq!(
             "www.example-2.com:80".parse(),
-            Ok(Authority::new().host("www.example-2.com").port(80))
+            Ok(Authority::new()
+                .with_host("www.example-2.com")
+                .with_port(80))
         );
         // Case insensitive
         assert_eq!(
             "WWW.EXAMPLE.COM".parse(),
-            Ok(Authority::new().host("www.example.com"))
+            Ok(Authority::new().with_host("www.example.com"))
         );
 
         // Too many ':'-separators (can only have one port number)
@@ -187,6 +210,29 @@ mod tests {
             " www.example.com".parse::<Authority>(),
             Err(AuthorityParseError)
         );
+        // Ports are all digits and fit in 16 bits
+        for invalid in [
+            "www.example.com:99999",
+            "www.example.com:http",
+            "www.example.com:+80",
+            "www.example.com:-1",
+            "www.example.com: 80",
+        ] {
+            assert_eq!(invalid.parse::<Authority>(), Err(AuthorityParseError));
+        }
+        assert_eq!(
+            "www.example.com:".parse(),
+            Ok(Authority::new().with_host("www.example.com"))
+        );
+        // No user information
+        for invalid in [
+            "user:pw@evil.com",
+            "user@evil.com",
+            "user:pw@evil.com:80",
+            "@evil.com",
+        ] {
+            assert_eq!(invalid.parse::<Authority>(), Err(AuthorityParseError));
+        }
     }
 
     #[test]
@@ -0,0 +1,619 @@
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+
+use crate::header::HeaderMap;
+use crate::{parser, response, utils};
+
+// A chunk size larger than this many hex digits cannot fit in a u64.
+const MAX_CHUNK_SIZE_DIGITS: usize = 16;
+const DEFAULT_MAX_EXTENSION_LEN: usize = 1024;
+const DEFAULT_MAX_TRAILER_SIZE: usize = 8 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChunkedError {
+    InvalidChunkSize,
+    // The chunk size does not fit in a u64
+    ChunkSizeOverflow,
+    InvalidExtension,
+    ExtensionTooLong,
+    // Lines in the chunked coding must end in CRLF, a bare LF or any other
+    // byte where a CRLF was expected is rejected.
+    MissingCrlf,
+    InvalidTrailer,
+    TrailerTooLarge,
+}
+
+impl fmt::Display for ChunkedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            Self::InvalidChunkSize => "invalid chunk size",
+            Self::ChunkSizeOverflow => "chunk size overflow",
+            Self::InvalidExtension => "invalid chunk extension",
+            Self::ExtensionTooLong => "chunk extension too long",
+            Self::MissingCrlf => "expected CRLF in chunked body",
+            Self::InvalidTrailer => "invalid trailer field",
+            Self::TrailerTooLarge => "trailer section too large",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl std::error::Error for ChunkedError {}
+
+impl From<ChunkedError> for io::Error {
+    fn from(e: ChunkedError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    // chunk-size, `digits` hex digits read so far
+    Size { size: u64, digits: usize },
+    // Everything between the chunk size and the CR ending the line
+    Extension { size: u64 },
+    SizeLf { size: u64 },
+    Data { remaining: u64 },
+    DataCr,
+    DataLf,
+    // A trailer field line or the final empty line
+    Trailer,
+    TrailerLf,
+    Done,
+}
+
+// Streaming decoder for the chunked transfer coding (RFC 9112 section 7.1).
+//
+// The decoder is push-based: hand it whatever bytes are available and it
+// writes the decoded chunk data to the output buffer, remembering where it
+// left off. Decoding stops right after the final CRLF, so anything following
+// the body (e.g. a pipelined request) is left unconsumed.
+#[derive(Debug)]
+pub struct ChunkedDecoder {
+    state: State,
+    // Chunk extension or trailer line being accumulated
+    line: Vec<u8>,
+    trailer_size: usize,
+    trailers: HeaderMap,
+    max_extension_len: usize,
+    max_trailer_size: usize,
+}
+
+impl Default for ChunkedDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChunkedDecoder {
+    pub fn new() -> Self {
+        Self {
+            state: State::Size { size: 0, digits: 0 },
+            line: vec![],
+            trailer_size: 0,
+            trailers: HeaderMap::new(),
+            max_extension_len: DEFAULT_MAX_EXTENSION_LEN,
+            max_trailer_size: DEFAULT_MAX_TRAILER_SIZE,
+        }
+    }
+
+    pub fn max_extension_len(mut self, len: usize) -> Self {
+        self.max_extension_len = len;
+        self
+    }
+
+    pub fn max_trailer_size(mut self, size: usize) -> Self {
+        self.max_trailer_size = size;
+        self
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    // Trailer fields received after the last chunk. Only complete once
+    // `is_done` returns true.
+    pub fn trailers(&self) -> &HeaderMap {
+        &self.trailers
+    }
+
+    pub fn into_trailers(self) -> HeaderMap {
+        self.trailers
+    }
+
+    // Decodes as much of `input` as fits in `out`. Returns the number of
+    // bytes consumed from `input` and the number of bytes written to `out`.
+    pub fn decode(
+        &mut self,
+        input: &[u8],
+        out: &mut [u8],
+    ) -> Result<(usize, usize), ChunkedError> {
+        let (mut consumed, mut written) = (0, 0);
+        while consumed < input.len() {
+            if let State::Data { remaining } = self.state {
+                let n = (input.len() - consumed)
+                    .min(out.len() - written)
+                    .min(remaining.try_into().unwrap_or(usize::MAX));
+                if n == 0 {
+                    // Output buffer is full
+                    break;
+                }
+                out[written..written + n]
+                    .copy_from_slice(&input[consumed..consumed + n]);
+                consumed += n;
+                written += n;
+                self.state = match remaining - n as u64 {
+                    0 => State::DataCr,
+                    remaining => State::Data { remaining },
+                };
+                continue;
+            }
+            if self.state == State::Done {
+                break;
+            }
+            self.step(input[consumed])?;
+            consumed += 1;
+        }
+        Ok((consumed, written))
+    }
+
+    // Convenience wrapper around `decode` that appends to a Vec.
+    pub fn decode_to_vec(
+        &mut self,
+        input: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<usize, ChunkedError> {
+        let mut consumed = 0;
+        loop {
+            let start = out.len();
+            out.resize(start + (input.len() - consumed).max(1), 0);
+            let (c, w) = self.decode(&input[consumed..], &mut out[start..])?;
+            out.truncate(start + w);
+            consumed += c;
+            if c == 0 || consumed == input.len() || self.is_done() {
+                return Ok(consumed);
+            }
+        }
+    }
+
+    // Advances the state machine by a single byte outside of chunk data.
+    fn step(&mut self, b: u8) -> Result<(), ChunkedError> {
+        self.state = match self.state {
+            State::Size { size, digits } => match (b as char).to_digit(16) {
+                Some(digit) => {
+                    if digits == MAX_CHUNK_SIZE_DIGITS {
+                        return Err(ChunkedError::ChunkSizeOverflow);
+                    }
+                    State::Size {
+                        size: size << 4 | digit as u64,
+                        digits: digits + 1,
+                    }
+                }
+                None if digits == 0 => {
+                    return Err(ChunkedError::InvalidChunkSize);
+                }
+                None if b == b'\r' => State::SizeLf { size },
+                None if b == b'\n' => return Err(ChunkedError::MissingCrlf),
+                None => {
+                    self.push_extension_byte(b)?;
+                    State::Extension { size }
+                }
+            },
+            State::Extension { size } => match b {
+                b'\r' => {
+                    if !is_valid_extension(&self.line) {
+                        return Err(ChunkedError::InvalidExtension);
+                    }
+                    self.line.clear();
+                    State::SizeLf { size }
+                }
+                _ => {
+                    self.push_extension_byte(b)?;
+                    State::Extension { size }
+                }
+            },
+            State::SizeLf { size } => match (b, size) {
+                (b'\n', 0) => State::Trailer,
+                (b'\n', remaining) => State::Data { remaining },
+                _ => return Err(ChunkedError::MissingCrlf),
+            },
+            State::DataCr => match b {
+                b'\r' => State::DataLf,
+                _ => return Err(ChunkedError::MissingCrlf),
+            },
+            State::DataLf => match b {
+                b'\n' => State::Size { size: 0, digits: 0 },
+                _ => return Err(ChunkedError::MissingCrlf),
+            },
+            State::Trailer => match b {
+                b'\r' => State::TrailerLf,
+                b'\n' => return Err(ChunkedError::MissingCrlf),
+                _ => {
+                    self.trailer_size += 1;
+                    if self.trailer_size > self.max_trailer_size {
+                        return Err(ChunkedError::TrailerTooLarge);
+                    }
+                    self.line.push(b);
+                    State::Trailer
+                }
+            },
+            State::TrailerLf => match b {
+                b'\n' if self.line.is_empty() => State::Done,
+                b'\n' => {
+                    self.push_trailer()?;
+                    State::Trailer
+                }
+                _ => return Err(ChunkedError::MissingCrlf),
+            },
+            State::Data { .. } | State::Done => unreachable!(),
+        };
+        Ok(())
+    }
+
+    fn push_extension_byte(&mut self, b: u8) -> Result<(), ChunkedError> {
+        if self.line.len() == self.max_extension_len {
+            return Err(ChunkedError::ExtensionTooLong);
+        }
+        self.line.push(b);
+        Ok(())
+    }
+
+    fn push_trailer(&mut self) -> Result<(), ChunkedError> {
+        let header = parser::parse_header_line(&self.line)
+            .map_err(|_| ChunkedError::InvalidTrailer)?;
+        let map = parser::headers_to_map(&[header]);
+        self.trailers.extend(map);
+        self.line.clear();
+        Ok(())
+    }
+}
+
+// chunk-ext     = *( BWS ";" BWS chunk-ext-name
+//                    [ BWS "=" BWS chunk-ext-val ] )
+// chunk-ext-val = token / quoted-string
+fn is_valid_extension(mut s: &[u8]) -> bool {
+    fn skip_whitespace(s: &[u8]) -> &[u8] {
+        let n = s.iter().take_while(|&&b| b == b' ' || b == b'\t').count();
+        &s[n..]
+    }
+    fn split_token(s: &[u8]) -> Option<(&[u8], &[u8])> {
+        let n = s
+            .iter()
+            .take_while(|&b| utils::TOKEN_BYTES.contains(b))
+            .count();
+        (n > 0).then(|| s.split_at(n))
+    }
+
+    while !s.is_empty() {
+        let Some(rest) = skip_whitespace(s).strip_prefix(b";") else {
+            return false;
+        };
+        let Some((_name, rest)) = split_token(skip_whitespace(rest)) else {
+            return false;
+        };
+        s = rest;
+        let Some(rest) = skip_whitespace(s).strip_prefix(b"=") else {
+            continue;
+        };
+        let rest = skip_whitespace(rest);
+        s = match rest.first() {
+            Some(b'"') => match parser::split_quoted_string(rest) {
+                Some((_, rest)) => rest,
+                None => return false,
+            },
+            _ => match split_token(rest) {
+                Some((_, rest)) => rest,
+                None => return false,
+            },
+        };
+    }
+    true
+}
+
+// Adapts a buffered reader producing a chunked body into a reader of the
+// decoded data. Reading returns 0 once the last chunk and the trailer section
+// have been consumed, leaving the underlying reader right after the body.
+pub struct ChunkedReader<R> {
+    inner: R,
+    decoder: ChunkedDecoder,
+}
+
+impl<R: BufRead> ChunkedReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_decoder(inner, ChunkedDecoder::new())
+    }
+
+    pub fn with_decoder(inner: R, decoder: ChunkedDecoder) -> Self {
+        Self { inner, decoder }
+    }
+
+    pub fn trailers(&self) -> &HeaderMap {
+        self.decoder.trailers()
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: BufRead> Read for ChunkedReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while !self.decoder.is_done() && !out.is_empty() {
+            let input = self.inner.fill_buf()?;
+            if input.is_empty() {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let (consumed, written) = self.decoder.decode(input, out)?;
+            self.inner.consume(consumed);
+            if written > 0 {
+                return Ok(written);
+            }
+        }
+        Ok(0)
+    }
+}
+
+// Adapts a writer so that everything written to it is sent using the chunked
+// transfer coding. Every call to `write` produces one chunk, so wrap the
+// writer in a `BufWriter` when writing many small pieces.
+//
+// `finish` must be called to write the last chunk; dropping the writer
+// without it leaves the body incomplete.
+pub struct ChunkedWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        self.finish_with_trailers(&HeaderMap::new())
+    }
+
+    pub fn finish_with_trailers(
+        mut self,
+        trailers: &HeaderMap,
+    ) -> io::Result<W> {
+        let mut last = b"0\r\n".to_vec();
+        response::write_headers(&mut last, trailers);
+        self.inner.write_all(&last)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // An empty chunk would be mistaken for the last one
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut chunk = Vec::with_capacity(buf.len() + 20);
+        write!(chunk, "{:x}\r\n", buf.len())?;
+        chunk.extend_from_slice(buf);
+        chunk.extend_from_slice(b"\r\n");
+        self.inner.write_all(&chunk)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::HeaderName;
+
+    fn decode_all(input: &[u8]) -> Result<(Vec<u8>, usize), ChunkedError> {
+        let mut decoder = ChunkedDecoder::new();
+        let mut out = vec![];
+        let consumed = decoder.decode_to_vec(input, &mut out)?;
+        assert!(decoder.is_done(), "{}", input.escape_ascii());
+        Ok((out, consumed))
+    }
+
+    #[test]
+    fn decode() {
+        let input =
+            b"4\r\nWiki\r\n5\r\npedia\r\nE\r\n in\r\n\r\nchunks.\r\n0\r\n\r\n";
+        assert_eq!(
+            decode_all(input),
+            Ok((b"Wikipedia in\r\n\r\nchunks.".to_vec(), input.len()))
+        );
+        // Data following the body is left alone
+        let input = b"3\r\nabc\r\n0\r\n\r\nGET / HTTP/1.1\r\n";
+        assert_eq!(decode_all(input), Ok((b"abc".to_vec(), 13)));
+        // Uppercase and lowercase hex, leading zeroes
+        let input = b"0000A\r\n0123456789\r\na\r\n0123456789\r\n000\r\n\r\n";
+        assert_eq!(decode_all(input).unwrap().0.len(), 20);
+    }
+
+    #[test]
+    fn decode_byte_by_byte() {
+        let input = b"5;name=value\r\nhello\r\n6\r\n world\r\n0\r\nExpires: never\r\n\r\n";
+        let mut decoder = ChunkedDecoder::new();
+        let mut out = vec![];
+        for b in input {
+            assert!(!decoder.is_done());
+            assert_eq!(decoder.decode_to_vec(&[*b], &mut out), Ok(1));
+        }
+        assert!(decoder.is_done());
+        assert_eq!(out, b"hello world");
+        assert_eq!(
+            decoder.trailers().get("expires").unwrap().as_bytes(),
+            b"never"
+        );
+    }
+
+    #[test]
+    fn decode_small_output_buffer() {
+        let input = b"a\r\n0123456789\r\n0\r\n\r\n";
+        let mut decoder = ChunkedDecoder::new();
+        let (mut consumed, mut out) = (0, vec![]);
+        let mut buf = [0; 3];
+        while !decoder.is_done() {
+            let (c, w) = decoder.decode(&input[consumed..], &mut buf).unwrap();
+            consumed += c;
+            out.extend_from_slice(&buf[..w]);
+        }
+        assert_eq!(out, b"0123456789");
+        assert_eq!(consumed, input.len());
+    }
+
+    #[test]
+    fn extensions() {
+        for input in [
+            &b"3;a\r\nabc\r\n0\r\n\r\n"[..],
+            b"3;a=b\r\nabc\r\n0\r\n\r\n",
+            b"3 ; a = b ;c\r\nabc\r\n0\r\n\r\n",
+            b"3;a=\"quoted \\\" string;\"\r\nabc\r\n0\r\n\r\n",
+            b"3\r\nabc\r\n0;last=yes\r\n\r\n",
+        ] {
+            assert_eq!(decode_all(input).unwrap().0, b"abc");
+        }
+    }
+
+    #[test]
+    fn trailers() {
+        let input = b"0\r\nDigest: sha-256=abc\r\nX-Checksum:  42 \r\n\r\n";
+        let mut decoder = ChunkedDecoder::new();
+        let mut out = vec![];
+        decoder.decode_to_vec(input, &mut out).unwrap();
+        assert!(decoder.is_done());
+        let trailers = decoder.into_trailers();
+        assert_eq!(trailers.len(), 2);
+        assert_eq!(trailers.get("digest").unwrap().as_bytes(), b"sha-256=abc");
+        assert_eq!(trailers.get("x-checksum").unwrap().as_bytes(), b"42");
+    }
+
+    #[test]
+    fn malformed() {
+        for (input, error) in [
+            // chunk sizes
+            (&b"\r\n"[..], ChunkedError::InvalidChunkSize),
+            (b"x\r\n", ChunkedError::InvalidChunkSize),
+            (b"-1\r\n", ChunkedError::InvalidChunkSize),
+            (b"+1\r\n", ChunkedError::InvalidChunkSize),
+            (b" 1\r\n", ChunkedError::InvalidChunkSize),
+            (b"0x10\r\n", ChunkedError::InvalidExtension),
+            (b"1 \r\na\r\n", ChunkedError::InvalidExtension),
+            (b"1\ta\r\n", ChunkedError::InvalidExtension),
+            (b"10000000000000000\r\n", ChunkedError::ChunkSizeOverflow),
+            (b"fffffffffffffffff\r\n", ChunkedError::ChunkSizeOverflow),
+            // extensions
+            (b"1;\r\na\r\n", ChunkedError::InvalidExtension),
+            (b"1;=b\r\na\r\n", ChunkedError::InvalidExtension),
+            (b"1;a=\r\na\r\n", ChunkedError::InvalidExtension),
+            (b"1;a=\"open\r\na\r\n", ChunkedError::InvalidExtension),
+            (b"1;a=b c\r\na\r\n", ChunkedError::InvalidExtension),
+            (b"1;a\nb\r\na\r\n", ChunkedError::InvalidExtension),
+            // line endings
+            (b"1\na\r\n", ChunkedError::MissingCrlf),
+            (b"1\r\r\na\r\n", ChunkedError::MissingCrlf),
+            (b"1\r\na\n0\r\n\r\n", ChunkedError::MissingCrlf),
+            (b"1\r\naX\r\n", ChunkedError::MissingCrlf),
+            (b"1\r\nabc\r\n", ChunkedError::MissingCrlf),
+            (b"0\r\n\n", ChunkedError::MissingCrlf),
+            (b"0\r\nA: b\n\r\n", ChunkedError::MissingCrlf),
+            // trailers
+            (b"0\r\nA b\r\n\r\n", ChunkedError::InvalidTrailer),
+            (b"0\r\n: b\r\n\r\n", ChunkedError::InvalidTrailer),
+            (b"0\r\n b\r\n\r\n", ChunkedError::InvalidTrailer),
+            (b"0\r\nA: \x00\r\n\r\n", ChunkedError::InvalidTrailer),
+        ] {
+            assert_eq!(
+                decode_all(input),
+                Err(error),
+                "{}",
+                input.escape_ascii()
+            );
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let mut input = b"1;".to_vec();
+        input.extend(vec![b'a'; 2000]);
+        let mut decoder = ChunkedDecoder::new();
+        assert_eq!(
+            decoder.decode_to_vec(&input, &mut vec![]),
+            Err(ChunkedError::ExtensionTooLong)
+        );
+
+        let mut input = b"0\r\nX: ".to_vec();
+        input.extend(vec![b'a'; 100]);
+        let mut decoder = ChunkedDecoder::new().max_trailer_size(64);
+        assert_eq!(
+            decoder.decode_to_vec(&input, &mut vec![]),
+            Err(ChunkedError::TrailerTooLarge)
+        );
+    }
+
+    #[test]
+    fn reader() {
+        let input = b"5\r\nhello\r\n1\r\n \r\n5\r\nworld\r\n0\r\n\r\nrest";
+        let mut reader =
+            ChunkedReader::new(io::BufReader::with_capacity(4, &input[..]));
+        let mut out = String::new();
+        reader.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "hello world");
+        let mut rest = String::new();
+        reader.into_inner().read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "rest");
+
+        // Truncated body
+        let mut reader = ChunkedReader::new(&b"5\r\nhel"[..]);
+        let err = reader.read_to_end(&mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut reader = ChunkedReader::new(&b"5\nhello"[..]);
+        let err = reader.read_to_end(&mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn writer() {
+        let mut writer = ChunkedWriter::new(vec![]);
+        writer.write_all(b"Wiki").unwrap();
+        writer.write_all(b"").unwrap();
+        writer.write_all(b"pedia in\r\n\r\nchunks.").unwrap();
+        let out = writer.finish().unwrap();
+        assert_eq!(
+            out,
+            b"4\r\nWiki\r\n13\r\npedia in\r\n\r\nchunks.\r\n0\r\n\r\n"
+        );
+
+        let mut trailers = HeaderMap::new();
+        trailers.append(HeaderName::EXPIRES, "0".parse().unwrap());
+        let out = ChunkedWriter::new(vec![])
+            .finish_with_trailers(&trailers)
+            .unwrap();
+        assert_eq!(out, b"0\r\nexpires: 0\r\n\r\n");
+    }
+
+    #[test]
+    fn round_trip() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let mut writer = ChunkedWriter::new(vec![]);
+        for piece in data.chunks(777) {
+            writer.write_all(piece).unwrap();
+        }
+        let mut trailers = HeaderMap::new();
+        trailers.append("x-sum".parse().unwrap(), 42u64.into());
+        let encoded = writer.finish_with_trailers(&trailers).unwrap();
+
+        let mut reader = ChunkedReader::new(&encoded[..]);
+        let mut decoded = vec![];
+        reader.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+        assert_eq!(reader.trailers(), &trailers);
+    }
+}
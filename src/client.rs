@@ -0,0 +1,1106 @@
+mod conn;
+mod pool;
+mod redirect;
+
+use std::io::{self, Read};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{error, fmt, mem};
+
+use self::conn::{BodyReader, Connection};
+use self::pool::{Pool, PoolKey};
+pub use self::redirect::{
+    Action, Attempt, Hop, Limited, RedirectError, RedirectPolicy,
+};
+use crate::coding::{self, DecodeError, DecodeLimits, DecodingReader};
+use crate::framing::{self, FramingError};
+use crate::header::{HeaderName, HeaderValue};
+use crate::method::Method;
+use crate::origin::Origin;
+use crate::parser::ParseError;
+use crate::request::Request;
+use crate::response::Response;
+use crate::scheme::Scheme;
+use crate::uri::{RequestTarget, Uri};
+use crate::version::Version;
+
+// Redirect bodies longer than this are not worth reading just to reuse
+// the connection
+const MAX_DISCARD_SIZE: u64 = 64 * 1024;
+
+const USER_AGENT: &str = concat!("websurfer/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug)]
+pub enum ClientError {
+    // The request target is not an absolute URI with a host
+    InvalidUri,
+    // Only "http" is spoken, there is no TLS
+    UnsupportedScheme,
+    // Connecting, or a read or write, took longer than configured
+    Timeout,
+    Io(io::Error),
+    InvalidResponse(ParseError),
+    InvalidFraming(FramingError),
+    // The response body is larger than `Config::max_body_size`
+    BodyTooLarge,
+    Redirect(RedirectError),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidUri => f.write_str("invalid request URI"),
+            Self::UnsupportedScheme => f.write_str("unsupported URI scheme"),
+            Self::Timeout => f.write_str("request timed out"),
+            Self::Io(e) => write!(f, "{e}"),
+            Self::InvalidResponse(e) => write!(f, "invalid response: {e:?}"),
+            Self::InvalidFraming(e) => {
+                write!(f, "invalid response framing: {e:?}")
+            }
+            Self::BodyTooLarge => f.write_str("response body too large"),
+            Self::Redirect(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Redirect(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// Socket operations running into their timeout fail with `WouldBlock` on
+// Unix and `TimedOut` on Windows.
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        if framing::is_timeout(&e) {
+            Self::Timeout
+        } else {
+            Self::Io(e)
+        }
+    }
+}
+
+impl From<ParseError> for ClientError {
+    fn from(e: ParseError) -> Self {
+        Self::InvalidResponse(e)
+    }
+}
+
+impl From<FramingError> for ClientError {
+    fn from(e: FramingError) -> Self {
+        Self::InvalidFraming(e)
+    }
+}
+
+impl From<RedirectError> for ClientError {
+    fn from(e: RedirectError) -> Self {
+        Self::Redirect(e)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    connect_timeout: Duration,
+    read_timeout: Duration,
+    write_timeout: Duration,
+    pool_idle_timeout: Duration,
+    max_idle_per_host: usize,
+    max_head_size: usize,
+    max_headers: usize,
+    max_body_size: u64,
+    decode_response_bodies: bool,
+    max_decode_ratio: u64,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(30),
+            pool_idle_timeout: Duration::from_secs(90),
+            max_idle_per_host: 8,
+            max_head_size: 64 * 1024,
+            max_headers: 100,
+            max_body_size: 16 * 1024 * 1024,
+            decode_response_bodies: true,
+            max_decode_ratio: u64::MAX,
+        }
+    }
+
+    // How long connecting to one address of a host may take.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    // How long a single read from the server may block, be it waiting for
+    // the response or in the middle of its body.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    // How long a connection is kept around for reuse after its last
+    // response.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = timeout;
+        self
+    }
+
+    // Number of idle connections kept per scheme, host and port. Zero
+    // disables connection reuse.
+    pub fn max_idle_per_host(mut self, count: usize) -> Self {
+        self.max_idle_per_host = count;
+        self
+    }
+
+    pub fn max_head_size(mut self, size: usize) -> Self {
+        self.max_head_size = size;
+        self
+    }
+
+    pub fn max_headers(mut self, count: usize) -> Self {
+        self.max_headers = count;
+        self
+    }
+
+    // The largest body `Client::request` reads into memory, and the largest
+    // a compressed body may decode to. Bodies read through `Client::send`
+    // are otherwise not limited.
+    pub fn max_body_size(mut self, size: u64) -> Self {
+        self.max_body_size = size;
+        self
+    }
+
+    // Whether to ask for compressed responses and decode them. Without it,
+    // requests ask for the body as is. Requests that come with their own
+    // `Accept-Encoding` always get the body as the server sent it.
+    pub fn decode_response_bodies(mut self, enabled: bool) -> Self {
+        self.decode_response_bodies = enabled;
+        self
+    }
+
+    // The most bytes a compressed body may decode to per byte received.
+    // There is no limit by default, `max_body_size` bounds the decoded size
+    // either way.
+    pub fn max_decode_ratio(mut self, ratio: u64) -> Self {
+        self.max_decode_ratio = ratio;
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A blocking HTTP/1.1 client. Connections are kept alive and reused for
+// later requests to the same scheme, host and port. Clones share their
+// connections, so one client can serve many threads.
+//
+// Redirects are followed as the redirect policy allows, `Limited` by
+// default.
+#[derive(Clone)]
+pub struct Client {
+    config: Arc<Config>,
+    pool: Arc<Pool>,
+    redirect: Arc<dyn RedirectPolicy>,
+}
+
+impl Client {
+    pub fn new() -> Self {
+        let config = Config::new();
+        Self {
+            pool: Arc::new(Pool::new(
+                config.max_idle_per_host,
+                config.pool_idle_timeout,
+            )),
+            config: Arc::new(config),
+            redirect: Arc::new(Limited::default()),
+        }
+    }
+
+    // Replaces the configuration, and with it the connection pool.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.pool = Arc::new(Pool::new(
+            config.max_idle_per_host,
+            config.pool_idle_timeout,
+        ));
+        self.config = Arc::new(config);
+        self
+    }
+
+    pub fn with_redirect_policy<P: RedirectPolicy>(
+        mut self,
+        policy: P,
+    ) -> Self {
+        self.redirect = Arc::new(policy);
+        self
+    }
+
+    pub fn get(&self, uri: &str) -> Result<Response, ClientError> {
+        let uri = uri.parse().map_err(|_| ClientError::InvalidUri)?;
+        self.request(Request::new(Method::Get, RequestTarget::Absolute(uri)))
+    }
+
+    pub fn post(
+        &self,
+        uri: &str,
+        body: Vec<u8>,
+    ) -> Result<Response, ClientError> {
+        let uri = uri.parse().map_err(|_| ClientError::InvalidUri)?;
+        let request = Request::new(Method::Post, RequestTarget::Absolute(uri));
+        self.request(request.with_body(body))
+    }
+
+    // Sends the request and reads the whole response into memory. The
+    // request target has to be an absolute URI.
+    pub fn request(&self, request: Request) -> Result<Response, ClientError> {
+        let limit = self.config.max_body_size;
+        self.send(request)?.into_response(limit)
+    }
+
+    // Sends the request and returns once the head of the final response
+    // has arrived, leaving the body to be read from the returned reader.
+    pub fn send(
+        &self,
+        mut request: Request,
+    ) -> Result<ResponseReader, ClientError> {
+        let decode = self.accept_encoding(&mut request);
+        let mut chain = vec![];
+        loop {
+            let uri = match request.target() {
+                RequestTarget::Absolute(uri) => uri.clone(),
+                _ => return Err(ClientError::InvalidUri),
+            };
+            let reader = self.send_once(&mut request, &uri, decode)?;
+            let response = reader.response();
+            let status = response.status();
+            let (Some(method), Some(location)) = (
+                redirect::next_method(request.method(), status),
+                response.headers().get(HeaderName::LOCATION),
+            ) else {
+                return Ok(reader);
+            };
+            let next = location
+                .to_str()
+                .and_then(|location| uri.resolve(location).ok())
+                .ok_or(RedirectError::InvalidLocation)?;
+            let cross_origin =
+                Origin::from_uri(&uri) != Origin::from_uri(&next);
+            chain.push(Hop::new(request.method(), uri, status));
+            match self.redirect.redirect(&Attempt::new(method, &next, &chain)) {
+                Action::Follow => {}
+                Action::Stop => return Ok(reader),
+                Action::Error(e) => return Err(e.into()),
+            }
+            reader.discard();
+            request = redirected(request, method, next, cross_origin);
+        }
+    }
+
+    // Makes a single request, without following redirects.
+    //
+    // Requests that do not change anything on the server are retried on a
+    // new connection if a reused one turns out to have been closed before
+    // anything came back.
+    fn send_once(
+        &self,
+        request: &mut Request,
+        uri: &Uri,
+        decode: bool,
+    ) -> Result<ResponseReader, ClientError> {
+        let key = pool_key(uri)?;
+        Self::prepare(request, uri);
+        let method = request.method();
+        loop {
+            let pooled = self.pool.take(&key);
+            let mut conn = match pooled {
+                Some(conn) => conn,
+                None => Connection::open(key.clone(), &self.config)?,
+            };
+            let result = conn
+                .send(request, uri)
+                .map_err(ClientError::from)
+                .and_then(|()| conn.read_head(method, &self.config));
+            let (response, kind) = match result {
+                Ok(head) => head,
+                Err(ClientError::Io(_))
+                    if conn.is_reused()
+                        && !conn.received()
+                        && is_idempotent(method) =>
+                {
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let persistent =
+                conn::is_persistent(request.headers(), &response, kind);
+            let pool = persistent.then(|| Arc::clone(&self.pool));
+            let body = BodyReader::new(conn, kind, pool);
+            let limits = decode.then(|| {
+                DecodeLimits::new()
+                    .max_size(self.config.max_body_size)
+                    .max_ratio(self.config.max_decode_ratio)
+            });
+            return Ok(ResponseReader::new(response, body, limits));
+        }
+    }
+
+    // Fills in the header fields the client is responsible for, again for
+    // every redirect.
+    fn prepare(request: &mut Request, uri: &Uri) {
+        request.set_version(Version::Http1_1);
+        let authority =
+            uri.authority().map(ToString::to_string).unwrap_or_default();
+        let has_body = !request.body().is_empty()
+            || matches!(
+                request.method(),
+                Method::Post | Method::Put | Method::Patch
+            );
+        let body_len = request.body().len();
+        let headers = request.headers_mut();
+        if !headers.contains_key(HeaderName::HOST) {
+            headers.insert(
+                HeaderName::HOST,
+                HeaderValue::from_bytes(authority.as_bytes())
+                    .expect("valid header value"),
+            );
+        }
+        if !headers.contains_key(HeaderName::USER_AGENT) {
+            headers.insert(
+                HeaderName::USER_AGENT,
+                HeaderValue::from_bytes(USER_AGENT.as_bytes())
+                    .expect("valid header value"),
+            );
+        }
+        // The body always goes out whole, so its length is known
+        headers.remove(HeaderName::TRANSFER_ENCODING);
+        if has_body {
+            headers.insert(HeaderName::CONTENT_LENGTH, body_len.into());
+        } else {
+            headers.remove(HeaderName::CONTENT_LENGTH);
+        }
+    }
+
+    // Asks for the content codings the client can decode. Returns whether
+    // the response body is to be decoded.
+    fn accept_encoding(&self, request: &mut Request) -> bool {
+        let headers = request.headers_mut();
+        if headers.contains_key(HeaderName::ACCEPT_ENCODING) {
+            return false;
+        }
+        // A missing field would leave any coding acceptable
+        let decode = self.config.decode_response_bodies;
+        let accept = if decode {
+            coding::accept_encoding()
+        } else {
+            HeaderValue::from_bytes(b"identity").expect("valid header value")
+        };
+        headers.insert(HeaderName::ACCEPT_ENCODING, accept);
+        decode
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+// The request that follows a redirect to `uri`. A body only goes along if
+// the method stays the same, and credentials only if the origin does.
+fn redirected(
+    mut request: Request,
+    method: Method,
+    uri: Uri,
+    cross_origin: bool,
+) -> Request {
+    let mut headers = mem::take(request.headers_mut());
+    let body = if method == request.method() {
+        request.into_body()
+    } else {
+        for name in [
+            HeaderName::CONTENT_ENCODING,
+            HeaderName::CONTENT_LANGUAGE,
+            HeaderName::CONTENT_LOCATION,
+            HeaderName::CONTENT_TYPE,
+        ] {
+            headers.remove(name);
+        }
+        vec![]
+    };
+    if cross_origin {
+        for name in [
+            HeaderName::HOST,
+            HeaderName::AUTHORIZATION,
+            HeaderName::PROXY_AUTHORIZATION,
+            HeaderName::COOKIE,
+        ] {
+            headers.remove(name);
+        }
+    }
+    let mut next = Request::new(method, RequestTarget::Absolute(uri));
+    *next.headers_mut() = headers;
+    next.with_body(body)
+}
+
+fn pool_key(uri: &Uri) -> Result<PoolKey, ClientError> {
+    let (Some(scheme), Some(authority)) = (uri.scheme(), uri.authority())
+    else {
+        return Err(ClientError::InvalidUri);
+    };
+    if *scheme != Scheme::HTTP {
+        return Err(ClientError::UnsupportedScheme);
+    }
+    Ok(PoolKey {
+        scheme: scheme.clone(),
+        host: authority.host().to_owned(),
+        port: authority.port().unwrap_or(80),
+    })
+}
+
+// Methods whose requests can be repeated without changing the outcome
+// (RFC 9110 section 9.2.2).
+fn is_idempotent(method: Method) -> bool {
+    !matches!(method, Method::Post | Method::Patch | Method::Connect)
+}
+
+// A response whose body is still to be read. Reading gives the body with
+// the transfer coding, and any content coding the client asked for,
+// undone.
+pub struct ResponseReader {
+    // Without a body
+    response: Response,
+    body: Box<dyn Read>,
+}
+
+impl ResponseReader {
+    // Decodes the body within `limits`, if given.
+    fn new(
+        mut response: Response,
+        body: BodyReader,
+        limits: Option<DecodeLimits>,
+    ) -> Self {
+        let codings = limits.and_then(|limits| {
+            coding::content_codings(response.headers())
+                .ok()
+                .filter(|codings| !codings.is_empty())
+                .map(|codings| (codings, limits))
+        });
+        let body: Box<dyn Read> = match codings {
+            Some((codings, limits)) => {
+                // The fields describe the body as sent, not as read
+                let headers = response.headers_mut();
+                headers.remove(HeaderName::CONTENT_ENCODING);
+                headers.remove(HeaderName::CONTENT_LENGTH);
+                Box::new(DecodingReader::new(body, &codings).limits(limits))
+            }
+            None => Box::new(body),
+        };
+        Self { response, body }
+    }
+
+    // The status line and header fields.
+    pub fn response(&self) -> &Response {
+        &self.response
+    }
+
+    // Reads a short body to the end so the connection can be reused.
+    fn discard(mut self) {
+        let _ = io::copy(
+            &mut (&mut self.body).take(MAX_DISCARD_SIZE),
+            &mut io::sink(),
+        );
+    }
+
+    // Reads the rest of the body, failing if there is more than `limit`
+    // bytes of it.
+    pub fn into_response(
+        mut self,
+        limit: u64,
+    ) -> Result<Response, ClientError> {
+        let mut body = vec![];
+        (&mut self.body)
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut body)
+            .map_err(|e| match DecodeError::from_io(&e) {
+                Some(DecodeError::TooLarge) => ClientError::BodyTooLarge,
+                _ => e.into(),
+            })?;
+        if body.len() as u64 > limit {
+            return Err(ClientError::BodyTooLarge);
+        }
+        Ok(self.response.with_body(body))
+    }
+}
+
+impl Read for ResponseReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.body.read(buf)
+    }
+}
+
+impl fmt::Debug for ResponseReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ResponseReader")
+            .field("response", &self.response)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+
+    use super::*;
+    use crate::response::BodyReader;
+    use crate::server::{Compress, Config as ServerConfig, Handler, Server};
+    use crate::status::StatusCode;
+
+    fn routes(request: Request) -> Response {
+        let path = request.target().path().unwrap().as_str().to_owned();
+        match path.as_str() {
+            "/echo" => {
+                let header = |name| {
+                    request.headers().get(name).map_or(String::new(), |v| {
+                        String::from_utf8_lossy(v.as_bytes()).into_owned()
+                    })
+                };
+                let body = format!(
+                    "{} {} {}",
+                    request.method(),
+                    header(HeaderName::HOST),
+                    header(HeaderName::USER_AGENT),
+                );
+                Response::new(StatusCode::OK).with_body(body.into())
+            }
+            "/port" => {
+                let port = request.peer_addr().unwrap().port().to_string();
+                Response::new(StatusCode::OK).with_body(port.into())
+            }
+            "/close" => Response::new(StatusCode::OK)
+                .with_header(HeaderName::CONNECTION, "close".parse().unwrap())
+                .with_body(b"bye".to_vec()),
+            // Of unknown length, so sent in chunks
+            "/chunked" => Response::new(StatusCode::OK)
+                .with_reader(BodyReader::new(&b"hello, world"[..], None)),
+            "/html" => Response::new(StatusCode::OK)
+                .with_header(
+                    HeaderName::CONTENT_TYPE,
+                    "text/html".parse().unwrap(),
+                )
+                .with_body(b"<p>".repeat(2000)),
+            "/spaces" => Response::new(StatusCode::OK)
+                .with_header(
+                    HeaderName::CONTENT_TYPE,
+                    "text/plain".parse().unwrap(),
+                )
+                .with_body(vec![b' '; 512 * 1024]),
+            "/slow" => {
+                thread::sleep(Duration::from_millis(500));
+                Response::new(StatusCode::OK)
+            }
+            _ => Response::new(StatusCode::OK).with_body(request.into_body()),
+        }
+    }
+
+    fn redirecting(request: Request) -> Response {
+        let path = request.target().path().unwrap().as_str().to_owned();
+        let redirect = |status, location: &str| {
+            Response::new(status).with_header(
+                HeaderName::LOCATION,
+                HeaderValue::from_bytes(location.as_bytes()).unwrap(),
+            )
+        };
+        match path.as_str() {
+            "/301" => redirect(StatusCode::MOVED_PERMANENTLY, "/echo"),
+            "/302" => redirect(StatusCode::FOUND, "echo#fragment"),
+            "/to/303" => redirect(StatusCode::SEE_OTHER, "../echo?from=303"),
+            "/307" => redirect(StatusCode::TEMPORARY_REDIRECT, "/echo"),
+            "/308" => redirect(StatusCode::PERMANENT_REDIRECT, "/echo"),
+            "/loop" => redirect(StatusCode::FOUND, "/loop"),
+            "/to/versioned" => redirect(StatusCode::FOUND, "../docs/v1..2"),
+            "/well-known" => redirect(StatusCode::FOUND, "/.well-known/x"),
+            target @ ("/docs/v1..2" | "/.well-known/x") => {
+                Response::new(StatusCode::OK).with_body(target.into())
+            }
+            "/invalid" => redirect(StatusCode::FOUND, "http://exa mple.com/"),
+            "/userinfo" => redirect(StatusCode::FOUND, "http://user:pw@evil/x"),
+            "/away" => {
+                // Same server, different origin
+                let host = request.headers().get(HeaderName::HOST).unwrap();
+                let port = host.to_str().unwrap().rsplit_once(':').unwrap().1;
+                redirect(
+                    StatusCode::FOUND,
+                    &format!("http://localhost:{port}/echo"),
+                )
+            }
+            "/echo" => {
+                let header = |name| {
+                    request.headers().get(name).map_or(String::new(), |v| {
+                        String::from_utf8_lossy(v.as_bytes()).into_owned()
+                    })
+                };
+                let body = format!(
+                    "{} {} {}|{}|{}|{}",
+                    request.method(),
+                    request.target().query().unwrap_or_default(),
+                    header(HeaderName::CONTENT_TYPE),
+                    header(HeaderName::AUTHORIZATION),
+                    header(HeaderName::COOKIE),
+                    String::from_utf8_lossy(request.body()),
+                );
+                Response::new(StatusCode::OK).with_body(body.into())
+            }
+            path => match path.strip_prefix("/chain/") {
+                Some("0") => redirect(StatusCode::FOUND, "/echo"),
+                Some(n) => {
+                    let n = n.parse::<u32>().unwrap() - 1;
+                    redirect(StatusCode::FOUND, &format!("/chain/{n}"))
+                }
+                None => Response::new(StatusCode::NOT_FOUND),
+            },
+        }
+    }
+
+    // Starts a server on an ephemeral loopback port. The server thread is
+    // left running for the rest of the test process. Each idle connection
+    // a client keeps takes up a worker, so there are a few of them.
+    fn spawn(handler: impl Handler, config: ServerConfig) -> SocketAddr {
+        let server = Server::bind("127.0.0.1:0", handler)
+            .unwrap()
+            .with_config(config.workers(4));
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+        addr
+    }
+
+    // Answers a single connection with `response`, whatever the request.
+    fn spawn_raw(response: &'static [u8]) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf);
+            stream.write_all(response).unwrap();
+        });
+        addr
+    }
+
+    fn key(addr: SocketAddr) -> PoolKey {
+        PoolKey {
+            scheme: Scheme::HTTP,
+            host: "127.0.0.1".to_owned(),
+            port: addr.port(),
+        }
+    }
+
+    #[test]
+    fn requests() {
+        let addr = spawn(routes, ServerConfig::new());
+        let client = Client::new();
+
+        let response = client.get(&format!("http://{addr}/echo")).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.body(),
+            format!("GET {addr} {USER_AGENT}").as_bytes()
+        );
+
+        let response = client
+            .post(&format!("http://{addr}/"), b"ping".to_vec())
+            .unwrap();
+        assert_eq!(response.body(), b"ping");
+
+        // Dot segments, hidden names and tildes are ordinary parts of a URI
+        for path in ["/.well-known/security.txt", "/file..txt", "/a/b~c"] {
+            let response = client
+                .post(&format!("http://{addr}{path}"), path.into())
+                .unwrap();
+            assert_eq!(response.body(), path.as_bytes());
+        }
+
+        // Same head, no body
+        let uri = format!("http://{addr}/echo").parse().unwrap();
+        let request = Request::new(Method::Head, RequestTarget::Absolute(uri));
+        let response = client.request(request).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.body().is_empty());
+        assert!(response.headers().contains_key(HeaderName::CONTENT_LENGTH));
+    }
+
+    #[test]
+    fn redirects() {
+        let addr = spawn(redirecting, ServerConfig::new());
+        let client = Client::new();
+        let request = |method, path: &str| {
+            let uri = format!("http://{addr}{path}").parse().unwrap();
+            Request::new(method, RequestTarget::Absolute(uri))
+                .with_header(
+                    HeaderName::CONTENT_TYPE,
+                    "text/plain".parse().unwrap(),
+                )
+                .with_body(b"data".to_vec())
+        };
+        let body = |method, path| {
+            let response = client.request(request(method, path)).unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            String::from_utf8(response.into_body()).unwrap()
+        };
+
+        let response = client.get(&format!("http://{addr}/301")).unwrap();
+        assert_eq!(response.body(), b"GET  |||");
+        // The redirect body was read, and the connection reused
+        assert_eq!(client.pool.idle_count(&key(addr)), 1);
+
+        // Only POST turns into GET on 301 and 302
+        assert_eq!(body(Method::Post, "/301"), "GET  |||");
+        assert_eq!(body(Method::Put, "/301"), "PUT  text/plain|||data");
+        assert_eq!(body(Method::Post, "/302"), "GET  |||");
+        assert_eq!(body(Method::Delete, "/302"), "DELETE  text/plain|||data");
+
+        // Anything but HEAD turns into GET on 303
+        assert_eq!(body(Method::Put, "/to/303"), "GET from=303 |||");
+        let response =
+            client.request(request(Method::Head, "/to/303")).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Nothing changes on 307 and 308
+        assert_eq!(body(Method::Post, "/307"), "POST  text/plain|||data");
+        assert_eq!(body(Method::Post, "/308"), "POST  text/plain|||data");
+
+        // Credentials only go to the same origin
+        let credentials = |path| {
+            request(Method::Get, path)
+                .with_header(
+                    HeaderName::AUTHORIZATION,
+                    "Bearer t".parse().unwrap(),
+                )
+                .with_header(HeaderName::COOKIE, "a=1".parse().unwrap())
+        };
+        let response = client.request(credentials("/301")).unwrap();
+        assert_eq!(response.body(), b"GET  text/plain|Bearer t|a=1|data");
+        let response = client.request(credentials("/away")).unwrap();
+        assert_eq!(response.body(), b"GET  text/plain|||data");
+
+        // Locations with dot segments and hidden names are fine
+        assert_eq!(body(Method::Get, "/to/versioned"), "/docs/v1..2");
+        assert_eq!(body(Method::Get, "/well-known"), "/.well-known/x");
+
+        assert!(matches!(
+            client.get(&format!("http://{addr}/loop")),
+            Err(ClientError::Redirect(RedirectError::Loop))
+        ));
+        for path in ["/invalid", "/userinfo"] {
+            assert!(matches!(
+                client.get(&format!("http://{addr}{path}")),
+                Err(ClientError::Redirect(RedirectError::InvalidLocation))
+            ));
+        }
+        assert_eq!(body(Method::Get, "/chain/9"), "GET  text/plain|||data");
+        assert!(matches!(
+            client.get(&format!("http://{addr}/chain/10")),
+            Err(ClientError::Redirect(RedirectError::TooManyRedirects))
+        ));
+        let client = client.clone().with_redirect_policy(Limited::new(2));
+        assert!(client.get(&format!("http://{addr}/chain/1")).is_ok());
+        assert!(matches!(
+            client.get(&format!("http://{addr}/chain/2")),
+            Err(ClientError::Redirect(RedirectError::TooManyRedirects))
+        ));
+    }
+
+    #[test]
+    fn redirect_policies() {
+        let addr = spawn(redirecting, ServerConfig::new());
+        let seen = Arc::new(std::sync::Mutex::new(vec![]));
+        let policy = {
+            let seen = Arc::clone(&seen);
+            move |attempt: &Attempt| {
+                let mut seen = seen.lock().unwrap();
+                let chain = attempt.chain();
+                let hop = &chain[chain.len() - 1];
+                seen.push(format!(
+                    "{} {} {} -> {} {}",
+                    hop.method(),
+                    hop.uri().path().as_str(),
+                    attempt.status().as_u16(),
+                    attempt.method(),
+                    attempt.uri().path().as_str(),
+                ));
+                match attempt.chain().len() {
+                    1 => Action::Follow,
+                    _ => Action::Stop,
+                }
+            }
+        };
+        let client = Client::new().with_redirect_policy(policy);
+        let response = client
+            .post(&format!("http://{addr}/chain/2"), vec![])
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            response
+                .headers()
+                .get(HeaderName::LOCATION)
+                .unwrap()
+                .as_bytes(),
+            b"/chain/0"
+        );
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                "POST /chain/2 302 -> GET /chain/1",
+                "GET /chain/1 302 -> GET /chain/0",
+            ]
+        );
+
+        // Redirects are handed over as they are
+        let client =
+            Client::new().with_redirect_policy(|_: &Attempt| Action::Stop);
+        let response = client.get(&format!("http://{addr}/loop")).unwrap();
+        assert_eq!(response.status(), StatusCode::FOUND);
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let client = Client::new();
+        for uri in [
+            "/echo",
+            "http://example.com:99999/",
+            "http://example.com:http/",
+            "http://user:pw@example.com/",
+        ] {
+            assert!(matches!(client.get(uri), Err(ClientError::InvalidUri)));
+        }
+        assert!(matches!(
+            client.get("https://example.com/"),
+            Err(ClientError::UnsupportedScheme)
+        ));
+
+        let request = Request::new(
+            Method::Get,
+            RequestTarget::Origin("/echo".parse().unwrap()),
+        );
+        assert!(matches!(
+            client.request(request),
+            Err(ClientError::InvalidUri)
+        ));
+    }
+
+    #[test]
+    fn connection_reuse() {
+        let addr = spawn(routes, ServerConfig::new());
+        let client = Client::new();
+        let url = format!("http://{addr}/port");
+        let first = client.get(&url).unwrap();
+        assert_eq!(client.pool.idle_count(&key(addr)), 1);
+        let second = client.clone().get(&url).unwrap();
+        assert_eq!(first.body(), second.body());
+        assert_eq!(client.pool.idle_count(&key(addr)), 1);
+
+        // Not returned to the pool
+        let response = client.get(&format!("http://{addr}/close")).unwrap();
+        assert_eq!(response.body(), b"bye");
+        assert_eq!(client.pool.idle_count(&key(addr)), 0);
+        assert_eq!(client.pool.host_count(), 0);
+
+        let client = client.with_config(Config::new().max_idle_per_host(0));
+        let first = client.get(&url).unwrap();
+        let second = client.get(&url).unwrap();
+        assert_ne!(first.body(), second.body());
+        assert_eq!(client.pool.idle_count(&key(addr)), 0);
+    }
+
+    #[test]
+    fn closed_connections() {
+        let config =
+            ServerConfig::new().keep_alive_timeout(Duration::from_millis(100));
+        let addr = spawn(routes, config);
+        let client = Client::new();
+        let url = format!("http://{addr}/port");
+        let first = client.get(&url).unwrap();
+        assert_eq!(client.pool.idle_count(&key(addr)), 1);
+
+        // Closed by the server in the meantime
+        thread::sleep(Duration::from_millis(300));
+        let second = client.get(&url).unwrap();
+        assert_ne!(first.body(), second.body());
+
+        // Expired on the client's side
+        let client =
+            client.with_config(Config::new().pool_idle_timeout(Duration::ZERO));
+        client.get(&url).unwrap();
+        assert_eq!(client.pool.host_count(), 1);
+        // Along with the entries of other hosts
+        let other = spawn(routes, ServerConfig::new());
+        client.get(&format!("http://{other}/port")).unwrap();
+        assert_eq!(client.pool.host_count(), 1);
+        assert_eq!(client.pool.idle_count(&key(addr)), 0);
+        assert!(client.pool.take(&key(other)).is_none());
+        assert_eq!(client.pool.host_count(), 0);
+    }
+
+    #[test]
+    fn streaming() {
+        let addr = spawn(routes, ServerConfig::new());
+        let client = Client::new();
+        let uri = format!("http://{addr}/chunked").parse().unwrap();
+        let request = Request::new(Method::Get, RequestTarget::Absolute(uri));
+        let mut reader = client.send(request).unwrap();
+        assert_eq!(reader.response().status(), StatusCode::OK);
+        let mut body = vec![];
+        let mut buf = [0; 3];
+        loop {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            body.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(body, b"hello, world");
+        assert_eq!(client.pool.idle_count(&key(addr)), 1);
+
+        // Dropped halfway, along with its connection
+        let uri = format!("http://{addr}/chunked").parse().unwrap();
+        let request = Request::new(Method::Get, RequestTarget::Absolute(uri));
+        let mut reader = client.send(request).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        drop(reader);
+        assert_eq!(client.pool.idle_count(&key(addr)), 0);
+    }
+
+    #[test]
+    fn decoding() {
+        let addr = spawn(routes.layer(Compress::new()), ServerConfig::new());
+        let url = format!("http://{addr}/html");
+        let response = Client::new().get(&url).unwrap();
+        assert_eq!(response.body(), b"<p>".repeat(2000));
+        assert!(
+            !response
+                .headers()
+                .contains_key(HeaderName::CONTENT_ENCODING)
+        );
+
+        let client = Client::new()
+            .with_config(Config::new().decode_response_bodies(false));
+        let response = client.get(&url).unwrap();
+        assert!(
+            !response
+                .headers()
+                .contains_key(HeaderName::CONTENT_ENCODING)
+        );
+        assert_eq!(response.body(), b"<p>".repeat(2000));
+
+        // Asked for by the caller, so left alone
+        let uri = url.parse().unwrap();
+        let request = Request::new(Method::Get, RequestTarget::Absolute(uri))
+            .with_header(HeaderName::ACCEPT_ENCODING, "gzip".parse().unwrap());
+        let response = Client::new().request(request).unwrap();
+        assert_eq!(
+            response
+                .headers()
+                .get(HeaderName::CONTENT_ENCODING)
+                .unwrap()
+                .as_bytes(),
+            b"gzip"
+        );
+        assert_ne!(response.body(), b"<p>".repeat(2000));
+
+        // Compresses far beyond any sensible ratio limit, and is still fine
+        let url = format!("http://{addr}/spaces");
+        let response = Client::new().get(&url).unwrap();
+        assert_eq!(response.body(), vec![b' '; 512 * 1024]);
+
+        let client =
+            Client::new().with_config(Config::new().max_body_size(256 * 1024));
+        assert!(matches!(client.get(&url), Err(ClientError::BodyTooLarge)));
+        let client =
+            Client::new().with_config(Config::new().max_decode_ratio(100));
+        assert!(matches!(
+            client.get(&url),
+            Err(ClientError::Io(e)) if matches!(
+                DecodeError::from_io(&e),
+                Some(DecodeError::RatioExceeded)
+            )
+        ));
+    }
+
+    #[test]
+    fn limits() {
+        let addr = spawn(routes, ServerConfig::new());
+        let client = Client::new().with_config(
+            Config::new()
+                .read_timeout(Duration::from_millis(100))
+                .max_body_size(3),
+        );
+        assert!(matches!(
+            client.get(&format!("http://{addr}/slow")),
+            Err(ClientError::Timeout)
+        ));
+        assert!(matches!(
+            client.post(&format!("http://{addr}/"), b"ping".to_vec()),
+            Err(ClientError::BodyTooLarge)
+        ));
+        let response = client
+            .post(&format!("http://{addr}/"), b"pin".to_vec())
+            .unwrap();
+        assert_eq!(response.body(), b"pin");
+    }
+
+    #[test]
+    fn raw_responses() {
+        let addr = spawn_raw(
+            b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 103 Early Hints\r\nLink: </a>\r\n\r\nHTTP/1.1 200 OK\r\n\r\nuntil the end",
+        );
+        let response = Client::new().get(&format!("http://{addr}/")).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(HeaderName::LINK));
+        assert_eq!(response.body(), b"until the end");
+        assert_eq!(Client::new().pool.idle_count(&key(addr)), 0);
+
+        let addr =
+            spawn_raw(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort");
+        assert!(matches!(
+            Client::new().get(&format!("http://{addr}/")),
+            Err(ClientError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+        ));
+
+        let addr =
+            spawn_raw(b"HTTP/1.1 200 OK\r\nContent-Length: 1, 2\r\n\r\n");
+        assert!(matches!(
+            Client::new().get(&format!("http://{addr}/")),
+            Err(ClientError::InvalidFraming(_))
+        ));
+
+        let addr = spawn_raw(b"HTTP/1.1 2000 OK\r\n\r\n");
+        assert!(matches!(
+            Client::new().get(&format!("http://{addr}/")),
+            Err(ClientError::InvalidResponse(_))
+        ));
+    }
+}
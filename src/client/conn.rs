@@ -0,0 +1,291 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+
+use super::pool::{Pool, PoolKey};
+use super::{ClientError, Config};
+use crate::chunked::ChunkedDecoder;
+use crate::framing::{BodyKind, has_connection_option};
+use crate::header::HeaderMap;
+use crate::method::Method;
+use crate::parser::{self, RawHeader, ResponseParser, Status};
+use crate::request::Request;
+use crate::response::{self, Response};
+use crate::status::StatusCode;
+use crate::uri::Uri;
+use crate::version::Version;
+
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+
+// A connection to an origin server, with the bytes read off it that have
+// not been consumed yet.
+pub(crate) struct Connection {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    key: PoolKey,
+    // Number of requests sent on the connection
+    requests: usize,
+    // Whether anything was received in response to the current request
+    received: bool,
+}
+
+impl Connection {
+    // Tries every address the host resolves to in turn.
+    pub(crate) fn open(
+        key: PoolKey,
+        config: &Config,
+    ) -> Result<Self, ClientError> {
+        let mut last_error = None;
+        for addr in (key.host.as_str(), key.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, config.connect_timeout) {
+                Ok(stream) => {
+                    stream.set_nodelay(true)?;
+                    stream.set_read_timeout(Some(config.read_timeout))?;
+                    stream.set_write_timeout(Some(config.write_timeout))?;
+                    return Ok(Self {
+                        stream,
+                        buf: Vec::with_capacity(READ_CHUNK_SIZE),
+                        key,
+                        requests: 0,
+                        received: false,
+                    });
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error
+            .unwrap_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "host did not resolve to any address",
+                )
+            })
+            .into())
+    }
+
+    pub(crate) fn key(&self) -> &PoolKey {
+        &self.key
+    }
+
+    pub(crate) fn stream(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    // Whether the connection served earlier requests, and so may have been
+    // closed by the server in the meantime.
+    pub(crate) fn is_reused(&self) -> bool {
+        self.requests > 1
+    }
+
+    pub(crate) fn received(&self) -> bool {
+        self.received
+    }
+
+    fn fill_buf(&mut self) -> io::Result<usize> {
+        let mut chunk = [0; READ_CHUNK_SIZE];
+        let n = self.stream.read(&mut chunk)?;
+        self.buf.extend_from_slice(&chunk[..n]);
+        self.received |= n > 0;
+        Ok(n)
+    }
+
+    // Writes the request in origin-form, the way it is sent to an origin
+    // server rather than a proxy (RFC 9112 section 3.2.1).
+    pub(crate) fn send(
+        &mut self,
+        request: &Request,
+        uri: &Uri,
+    ) -> io::Result<()> {
+        self.requests += 1;
+        self.received = false;
+        let mut head = Vec::with_capacity(256);
+        write!(head, "{} {}", request.method(), uri.path().as_str())?;
+        if let Some(query) = uri.query() {
+            write!(head, "?{query}")?;
+        }
+        write!(head, " {}\r\n", Version::Http1_1)?;
+        response::write_headers(&mut head, request.headers());
+        head.extend_from_slice(request.body());
+        self.stream.write_all(&head)?;
+        self.stream.flush()
+    }
+
+    // Reads the head of the final response, skipping interim 1xx ones.
+    pub(crate) fn read_head(
+        &mut self,
+        method: Method,
+        config: &Config,
+    ) -> Result<(Response, BodyKind), ClientError> {
+        loop {
+            let mut parser = ResponseParser::new();
+            let (response, kind) = loop {
+                let mut headers = vec![RawHeader::EMPTY; config.max_headers];
+                if let Status::Complete(raw) =
+                    parser.parse(&self.buf, &mut headers)?
+                {
+                    let kind = BodyKind::of_response(&raw, method)?;
+                    let head_len = raw.head_len;
+                    let response = Response::from_raw(raw, vec![]);
+                    self.buf.drain(..head_len);
+                    break (response, kind);
+                }
+                if self.buf.len() > config.max_head_size {
+                    return Err(ClientError::InvalidResponse(
+                        parser::ParseError::HeadTooLarge,
+                    ));
+                }
+                if self.fill_buf()? == 0 {
+                    return Err(
+                        io::Error::from(io::ErrorKind::UnexpectedEof).into()
+                    );
+                }
+            };
+            let status = response.status();
+            if status.is_informational()
+                && status != StatusCode::SWITCHING_PROTOCOLS
+            {
+                continue;
+            }
+            return Ok((response, kind));
+        }
+    }
+}
+
+// Whether the connection can carry another request after this response
+// (RFC 9112 section 9.3).
+pub(crate) fn is_persistent(
+    request_headers: &HeaderMap,
+    response: &Response,
+    kind: BodyKind,
+) -> bool {
+    let headers = response.headers();
+    let keep_alive = match response.version() {
+        Version::Http1_0 => has_connection_option(headers, "keep-alive"),
+        _ => !has_connection_option(headers, "close"),
+    };
+    keep_alive
+        && !has_connection_option(request_headers, "close")
+        && kind != BodyKind::CloseDelimited
+        && response.status() != StatusCode::SWITCHING_PROTOCOLS
+}
+
+enum BodyState {
+    Length(u64),
+    Chunked(ChunkedDecoder),
+    Close,
+}
+
+// Reads a response body off its connection as framed, handing the
+// connection back to the pool once the body has been read in full. A body
+// that is dropped before that takes its connection with it.
+pub(crate) struct BodyReader {
+    conn: Option<Connection>,
+    state: BodyState,
+    // Where the connection goes afterwards, if it can be reused
+    pool: Option<Arc<Pool>>,
+}
+
+impl BodyReader {
+    pub(crate) fn new(
+        conn: Connection,
+        kind: BodyKind,
+        pool: Option<Arc<Pool>>,
+    ) -> Self {
+        let state = match kind {
+            BodyKind::None => BodyState::Length(0),
+            BodyKind::ContentLength(n) => BodyState::Length(n),
+            BodyKind::Chunked => BodyState::Chunked(ChunkedDecoder::new()),
+            BodyKind::CloseDelimited => BodyState::Close,
+        };
+        let mut body = Self {
+            conn: Some(conn),
+            state,
+            pool,
+        };
+        if matches!(body.state, BodyState::Length(0)) {
+            body.finish();
+        }
+        body
+    }
+
+    fn finish(&mut self) {
+        let Some(conn) = self.conn.take() else {
+            return;
+        };
+        // Bytes past the end of the body mean the framing is off
+        if let Some(pool) = &self.pool
+            && conn.buf.is_empty()
+        {
+            pool.put(conn);
+        }
+    }
+
+    fn read_body(
+        conn: &mut Connection,
+        state: &mut BodyState,
+        out: &mut [u8],
+    ) -> io::Result<(usize, bool)> {
+        match state {
+            BodyState::Length(remaining) => {
+                if conn.buf.is_empty() && conn.fill_buf()? == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                let n = out.len().min(conn.buf.len()).min(*remaining as usize);
+                out[..n].copy_from_slice(&conn.buf[..n]);
+                conn.buf.drain(..n);
+                *remaining -= n as u64;
+                Ok((n, *remaining == 0))
+            }
+            BodyState::Chunked(decoder) => loop {
+                if !conn.buf.is_empty() {
+                    let (consumed, written) = decoder.decode(&conn.buf, out)?;
+                    conn.buf.drain(..consumed);
+                    if written > 0 || decoder.is_done() {
+                        return Ok((written, decoder.is_done()));
+                    }
+                    if consumed > 0 {
+                        continue;
+                    }
+                }
+                if conn.fill_buf()? == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            },
+            BodyState::Close => {
+                if conn.buf.is_empty() {
+                    let n = conn.stream.read(out)?;
+                    return Ok((n, n == 0));
+                }
+                let n = out.len().min(conn.buf.len());
+                out[..n].copy_from_slice(&conn.buf[..n]);
+                conn.buf.drain(..n);
+                Ok((n, false))
+            }
+        }
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let Some(conn) = self.conn.as_mut() else {
+            return Ok(0);
+        };
+        if out.is_empty() {
+            return Ok(0);
+        }
+        let result = Self::read_body(conn, &mut self.state, out);
+        match result {
+            Ok((n, done)) => {
+                if done {
+                    self.finish();
+                }
+                Ok(n)
+            }
+            Err(e) => {
+                // The connection is in an unknown state
+                self.conn = None;
+                Err(e)
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::conn::Connection;
+use crate::scheme::Scheme;
+
+// Connections are only reused for requests to the same origin.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct PoolKey {
+    pub(crate) scheme: Scheme,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+}
+
+struct Idle {
+    conn: Connection,
+    since: Instant,
+}
+
+// Idle keep-alive connections, most recently used last.
+pub(crate) struct Pool {
+    idle: Mutex<HashMap<PoolKey, Vec<Idle>>>,
+    max_idle_per_host: usize,
+    idle_timeout: Duration,
+}
+
+impl Pool {
+    pub(crate) fn new(
+        max_idle_per_host: usize,
+        idle_timeout: Duration,
+    ) -> Self {
+        Self {
+            idle: Mutex::new(HashMap::new()),
+            max_idle_per_host,
+            idle_timeout,
+        }
+    }
+
+    // The most recently used connection to `key` that is still open.
+    pub(crate) fn take(&self, key: &PoolKey) -> Option<Connection> {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        let conns = idle.get_mut(key)?;
+        let mut found = None;
+        while let Some(Idle { conn, since }) = conns.pop() {
+            if since.elapsed() < self.idle_timeout && is_open(&conn) {
+                found = Some(conn);
+                break;
+            }
+        }
+        if conns.is_empty() {
+            idle.remove(key);
+        }
+        found
+    }
+
+    pub(crate) fn put(&self, conn: Connection) {
+        if self.max_idle_per_host == 0 {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        // Expired connections go for every host, along with the entries of
+        // hosts left without any, so that hosts no longer talked to are not
+        // kept around
+        idle.retain(|_, conns| {
+            conns.retain(|idle| idle.since.elapsed() < self.idle_timeout);
+            !conns.is_empty()
+        });
+        let conns = idle.entry(conn.key().clone()).or_default();
+        if conns.len() >= self.max_idle_per_host {
+            conns.remove(0);
+        }
+        conns.push(Idle {
+            conn,
+            since: Instant::now(),
+        });
+    }
+
+    #[cfg(test)]
+    pub(crate) fn idle_count(&self, key: &PoolKey) -> usize {
+        let idle = self.idle.lock().unwrap();
+        idle.get(key).map_or(0, Vec::len)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn host_count(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+}
+
+// A server closing an idle connection is only noticed when reading from it,
+// which is better done before sending a request on it. Anything readable
+// at this point, the end of the stream or stray bytes, rules it out.
+fn is_open(conn: &Connection) -> bool {
+    let stream = conn.stream();
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let open = matches!(
+        stream.peek(&mut [0]),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock
+    );
+    stream.set_nonblocking(false).is_ok() && open
+}
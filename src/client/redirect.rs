@@ -0,0 +1,255 @@
+use std::{error, fmt};
+
+use crate::method::Method;
+use crate::status::StatusCode;
+use crate::uri::Uri;
+
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectError {
+    TooManyRedirects,
+    // A redirect back to a request made before
+    Loop,
+    // The `Location` is not a URI reference the client can follow
+    InvalidLocation,
+}
+
+impl fmt::Display for RedirectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooManyRedirects => f.write_str("too many redirects"),
+            Self::Loop => f.write_str("redirect loop"),
+            Self::InvalidLocation => f.write_str("invalid redirect location"),
+        }
+    }
+}
+
+impl error::Error for RedirectError {}
+
+// A request made on the way to the final response, and the status of the
+// redirect it got.
+#[derive(Debug, Clone)]
+pub struct Hop {
+    method: Method,
+    uri: Uri,
+    status: StatusCode,
+}
+
+impl Hop {
+    pub(crate) fn new(method: Method, uri: Uri, status: StatusCode) -> Self {
+        Self {
+            method,
+            uri,
+            status,
+        }
+    }
+
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+}
+
+// A redirect the client is about to follow, with the request it would
+// make next.
+#[derive(Debug)]
+pub struct Attempt<'a> {
+    method: Method,
+    uri: &'a Uri,
+    chain: &'a [Hop],
+}
+
+impl<'a> Attempt<'a> {
+    pub(crate) fn new(method: Method, uri: &'a Uri, chain: &'a [Hop]) -> Self {
+        Self { method, uri, chain }
+    }
+
+    // The method of the next request, which is GET where the redirect
+    // calls for it.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    pub fn uri(&self) -> &Uri {
+        self.uri
+    }
+
+    // The status of the redirect.
+    pub fn status(&self) -> StatusCode {
+        self.chain
+            .last()
+            .expect("redirect chain is not empty")
+            .status
+    }
+
+    // Every request made so far, the original one first.
+    pub fn chain(&self) -> &[Hop] {
+        self.chain
+    }
+
+    // Whether the next request repeats one in the chain. Only the method
+    // and URI are compared, so a form posted to a URI and redirected to
+    // that same URI with a GET is not a loop.
+    pub fn is_loop(&self) -> bool {
+        self.chain
+            .iter()
+            .any(|hop| hop.method == self.method && hop.uri == *self.uri)
+    }
+}
+
+#[derive(Debug)]
+pub enum Action {
+    Follow,
+    // Hands the redirect response itself to the caller
+    Stop,
+    Error(RedirectError),
+}
+
+// Decides which redirects a client follows. Policies are shared by all
+// clones of a client, so any state they keep needs its own
+// synchronization.
+pub trait RedirectPolicy: Send + Sync + 'static {
+    fn redirect(&self, attempt: &Attempt) -> Action;
+}
+
+impl<F> RedirectPolicy for F
+where
+    F: Fn(&Attempt) -> Action + Send + Sync + 'static,
+{
+    fn redirect(&self, attempt: &Attempt) -> Action {
+        self(attempt)
+    }
+}
+
+// Follows up to a number of redirects in a row, and fails on loops. The
+// default allows ten.
+#[derive(Debug, Clone, Copy)]
+pub struct Limited {
+    max: usize,
+}
+
+impl Limited {
+    pub fn new(max: usize) -> Self {
+        Self { max }
+    }
+}
+
+impl Default for Limited {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_REDIRECTS)
+    }
+}
+
+impl RedirectPolicy for Limited {
+    fn redirect(&self, attempt: &Attempt) -> Action {
+        if attempt.is_loop() {
+            Action::Error(RedirectError::Loop)
+        } else if attempt.chain().len() > self.max {
+            Action::Error(RedirectError::TooManyRedirects)
+        } else {
+            Action::Follow
+        }
+    }
+}
+
+// The method of the request that follows a redirect (RFC 9110 section
+// 15.4), or `None` for a status that is not followed.
+pub(crate) fn next_method(
+    method: Method,
+    status: StatusCode,
+) -> Option<Method> {
+    match status {
+        // Historically, user agents turned a POST into a GET on these
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => {
+            Some(match method {
+                Method::Post => Method::Get,
+                method => method,
+            })
+        }
+        StatusCode::SEE_OTHER => Some(match method {
+            Method::Head => Method::Head,
+            _ => Method::Get,
+        }),
+        StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => {
+            Some(method)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hop(method: Method, uri: &str) -> Hop {
+        Hop::new(method, uri.parse().unwrap(), StatusCode::FOUND)
+    }
+
+    #[test]
+    fn methods() {
+        use Method::*;
+
+        for status in [StatusCode::MOVED_PERMANENTLY, StatusCode::FOUND] {
+            assert_eq!(next_method(Post, status), Some(Get));
+            assert_eq!(next_method(Put, status), Some(Put));
+            assert_eq!(next_method(Head, status), Some(Head));
+        }
+        assert_eq!(next_method(Post, StatusCode::SEE_OTHER), Some(Get));
+        assert_eq!(next_method(Delete, StatusCode::SEE_OTHER), Some(Get));
+        assert_eq!(next_method(Head, StatusCode::SEE_OTHER), Some(Head));
+        for status in [
+            StatusCode::TEMPORARY_REDIRECT,
+            StatusCode::PERMANENT_REDIRECT,
+        ] {
+            assert_eq!(next_method(Post, status), Some(Post));
+            assert_eq!(next_method(Get, status), Some(Get));
+        }
+        assert_eq!(next_method(Get, StatusCode::MULTIPLE_CHOICES), None);
+        assert_eq!(next_method(Get, StatusCode::NOT_MODIFIED), None);
+        assert_eq!(next_method(Get, StatusCode::OK), None);
+    }
+
+    #[test]
+    fn limited() {
+        let policy = Limited::new(2);
+        let uri = "http://example.com/c".parse().unwrap();
+        let mut chain = vec![hop(Method::Get, "http://example.com/a")];
+        let attempt = Attempt::new(Method::Get, &uri, &chain);
+        assert_eq!(attempt.status(), StatusCode::FOUND);
+        assert!(matches!(policy.redirect(&attempt), Action::Follow));
+
+        chain.push(hop(Method::Get, "http://example.com/b"));
+        let attempt = Attempt::new(Method::Get, &uri, &chain);
+        assert!(matches!(policy.redirect(&attempt), Action::Follow));
+
+        chain.push(hop(Method::Get, "http://example.com/c"));
+        let next = "http://example.com/d".parse().unwrap();
+        let attempt = Attempt::new(Method::Get, &next, &chain);
+        assert!(matches!(
+            policy.redirect(&attempt),
+            Action::Error(RedirectError::TooManyRedirects)
+        ));
+
+        // Back to the start
+        let next = "http://example.com/a".parse().unwrap();
+        let attempt = Attempt::new(Method::Get, &next, &chain[..2]);
+        assert!(attempt.is_loop());
+        assert!(matches!(
+            policy.redirect(&attempt),
+            Action::Error(RedirectError::Loop)
+        ));
+
+        // Post/Redirect/Get to the same URI
+        let chain = [hop(Method::Post, "http://example.com/form")];
+        let attempt = Attempt::new(Method::Get, chain[0].uri(), &chain);
+        assert!(!attempt.is_loop());
+    }
+}
@@ -0,0 +1,1048 @@
+use std::cell::Cell;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+#[cfg(feature = "brotli")]
+use brotli::enc::StandardAlloc;
+#[cfg(feature = "brotli")]
+use brotli::{Allocator, CustomRead};
+
+use self::deflate::Deflater;
+use self::inflate::{BitReader, Inflater};
+use crate::header::{HeaderMap, HeaderName, HeaderValue};
+use crate::parser;
+
+mod deflate;
+mod inflate;
+
+// Decoded output is allowed to grow this much before the compression ratio
+// is checked, so small but very repetitive bodies do not trip it.
+const RATIO_GRACE_SIZE: u64 = 64 * 1024;
+const DEFAULT_MAX_RATIO: u64 = 200;
+
+// Brotli's quality ranges from 0 to 11. Level 5 compresses better than gzip
+// at about the same speed, which suits compressing responses as they are
+// sent. The window is 4 MiB.
+#[cfg(feature = "brotli")]
+const BROTLI_QUALITY: u32 = 5;
+#[cfg(feature = "brotli")]
+const BROTLI_WINDOW_BITS: u32 = 22;
+#[cfg(feature = "brotli")]
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    // Corrupt, truncated or failing its checksum
+    InvalidData,
+    UnsupportedCoding,
+    // The decoded data exceeds the size limit
+    TooLarge,
+    // The decoded data is suspiciously larger than the encoded data
+    RatioExceeded,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            Self::InvalidData => "invalid compressed data",
+            Self::UnsupportedCoding => "unsupported content coding",
+            Self::TooLarge => "decoded content too large",
+            Self::RatioExceeded => "compression ratio too high",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for io::Error {
+    fn from(e: DecodeError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+impl DecodeError {
+    // The decoding error behind an I/O error, if it is one.
+    pub fn from_io(e: &io::Error) -> Option<&Self> {
+        e.get_ref().and_then(|inner| inner.downcast_ref())
+    }
+}
+
+// The content codings that can be applied to a representation (RFC 9110
+// section 8.4.1). Gzip and deflate are built on DEFLATE (RFC 1951), brotli
+// comes with the "brotli" feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentCoding {
+    // The gzip file format (RFC 1952)
+    Gzip,
+    // The zlib data format (RFC 1950), which despite the name is not raw
+    // DEFLATE
+    Deflate,
+    // Brotli (RFC 7932), "br"
+    #[cfg(feature = "brotli")]
+    Brotli,
+}
+
+impl ContentCoding {
+    // Ignores case and accepts "x-gzip", which recipients should treat as
+    // "gzip".
+    pub fn from_bytes(s: &[u8]) -> Option<Self> {
+        #[cfg(feature = "brotli")]
+        if s.eq_ignore_ascii_case(b"br") {
+            return Some(Self::Brotli);
+        }
+        if s.eq_ignore_ascii_case(b"gzip") || s.eq_ignore_ascii_case(b"x-gzip")
+        {
+            Some(Self::Gzip)
+        } else if s.eq_ignore_ascii_case(b"deflate") {
+            Some(Self::Deflate)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+            #[cfg(feature = "brotli")]
+            Self::Brotli => "br",
+        }
+    }
+
+    // Encodes a complete body at once.
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let mut encoder = Encoder::new(vec![], *self);
+        encoder
+            .write_all(data)
+            .expect("writing to a Vec cannot fail");
+        encoder.finish().expect("writing to a Vec cannot fail")
+    }
+}
+
+// The codings listed in the `Content-Encoding` fields of a message, in the
+// order they were applied. "identity" is skipped, as it changes nothing.
+//
+// Only gzip, deflate and, with the "brotli" feature, br are supported. Any
+// other coding, such as "zstd" or "compress", fails the whole list, even next
+// to supported ones: a body encoded with "gzip, zstd" cannot be decoded
+// without undoing the zstd layer first.
+pub fn content_codings(
+    headers: &HeaderMap,
+) -> Result<Vec<ContentCoding>, DecodeError> {
+    headers
+        .get_all(HeaderName::CONTENT_ENCODING)
+        .flat_map(|value| parser::list_elements(value.as_bytes()))
+        .filter(|name| !name.eq_ignore_ascii_case(b"identity"))
+        .map(|name| {
+            ContentCoding::from_bytes(name)
+                .ok_or(DecodeError::UnsupportedCoding)
+        })
+        .collect()
+}
+
+// Every supported coding, as listed in an `Accept-Encoding` field.
+pub(crate) fn accept_encoding() -> HeaderValue {
+    let codings: &[u8] = if cfg!(feature = "brotli") {
+        b"gzip, deflate, br"
+    } else {
+        b"gzip, deflate"
+    };
+    HeaderValue::from_bytes(codings).expect("valid header value")
+}
+
+impl From<ContentCoding> for HeaderValue {
+    fn from(coding: ContentCoding) -> Self {
+        HeaderValue::from_bytes(coding.as_str().as_bytes())
+            .expect("valid header value")
+    }
+}
+
+// Compresses everything written to it into the underlying writer.
+//
+// `flush` pushes out everything written so far, at some cost to the
+// compression ratio. The stream is only complete once `finish` is called;
+// dropping the encoder leaves it truncated.
+pub struct Encoder<W: Write> {
+    inner: W,
+    coding: ContentCoding,
+    compressor: Compressor,
+    // Output not yet written to `inner`
+    buf: Vec<u8>,
+}
+
+enum Compressor {
+    Deflate {
+        deflater: Deflater,
+        checksum: Checksum,
+        // Modulo 2^32, as gzip records it
+        len: u32,
+    },
+    // Compresses into a buffer of its own, which is moved over to `buf`
+    #[cfg(feature = "brotli")]
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+}
+
+impl Compressor {
+    fn new(coding: ContentCoding) -> Self {
+        #[cfg(feature = "brotli")]
+        if coding == ContentCoding::Brotli {
+            return Self::Brotli(Box::new(brotli::CompressorWriter::new(
+                vec![],
+                BROTLI_BUFFER_SIZE,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW_BITS,
+            )));
+        }
+        Self::Deflate {
+            deflater: Deflater::new(),
+            checksum: Checksum::new(coding),
+            len: 0,
+        }
+    }
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(inner: W, coding: ContentCoding) -> Self {
+        let buf = match coding {
+            // No file name, modification time or other extras, and an
+            // unknown operating system
+            ContentCoding::Gzip => vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff],
+            // A 32K window and the default compression level
+            ContentCoding::Deflate => vec![0x78, 0x9c],
+            // The brotli compressor writes the whole stream itself
+            #[cfg(feature = "brotli")]
+            ContentCoding::Brotli => vec![],
+        };
+        Self {
+            inner,
+            coding,
+            compressor: Compressor::new(coding),
+            buf,
+        }
+    }
+
+    pub fn coding(&self) -> ContentCoding {
+        self.coding
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    // Writes the end of the stream and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.compressor {
+            Compressor::Deflate {
+                mut deflater,
+                checksum,
+                len,
+            } => {
+                deflater.finish(&mut self.buf);
+                match checksum {
+                    Checksum::Crc32(crc) => {
+                        self.buf.extend_from_slice(&crc.value().to_le_bytes());
+                        self.buf.extend_from_slice(&len.to_le_bytes());
+                    }
+                    Checksum::Adler32(adler) => {
+                        self.buf
+                            .extend_from_slice(&adler.value().to_be_bytes());
+                    }
+                }
+            }
+            #[cfg(feature = "brotli")]
+            Compressor::Brotli(writer) => {
+                self.buf.extend_from_slice(&writer.into_inner());
+            }
+        }
+        self.inner.write_all(&self.buf)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write_buf(&mut self) -> io::Result<()> {
+        self.inner.write_all(&self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match &mut self.compressor {
+            Compressor::Deflate {
+                deflater,
+                checksum,
+                len,
+            } => {
+                checksum.update(data);
+                *len = len.wrapping_add(data.len() as u32);
+                deflater.compress(data, &mut self.buf);
+            }
+            #[cfg(feature = "brotli")]
+            Compressor::Brotli(writer) => {
+                writer.write_all(data)?;
+                self.buf.append(writer.get_mut());
+            }
+        }
+        self.write_buf()?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.compressor {
+            Compressor::Deflate { deflater, .. } => {
+                deflater.flush(&mut self.buf)
+            }
+            #[cfg(feature = "brotli")]
+            Compressor::Brotli(writer) => {
+                writer.flush()?;
+                self.buf.append(writer.get_mut());
+            }
+        }
+        self.write_buf()?;
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecoderState {
+    Header,
+    Body,
+    Done,
+}
+
+// Decompresses a single content coding from the underlying reader.
+//
+// Gzip streams made of several members decode to their concatenation. The
+// checksum and length in the trailer are verified, so a stream that was
+// corrupted or cut short fails instead of ending early. Brotli streams fail
+// the same way, as do bytes following the end of one.
+pub struct Decoder<R: Read>(DecoderKind<R>);
+
+// Boxed, as the brotli state is large
+enum DecoderKind<R: Read> {
+    Deflate(Box<DeflateDecoder<R>>),
+    #[cfg(feature = "brotli")]
+    Brotli(Box<BrotliDecoder<R>>),
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(inner: R, coding: ContentCoding) -> Self {
+        #[cfg(feature = "brotli")]
+        if coding == ContentCoding::Brotli {
+            return Self(DecoderKind::Brotli(Box::new(brotli_decoder(inner))));
+        }
+        Self(DecoderKind::Deflate(Box::new(DeflateDecoder::new(
+            inner, coding,
+        ))))
+    }
+
+    pub fn coding(&self) -> ContentCoding {
+        match &self.0 {
+            DecoderKind::Deflate(decoder) => decoder.coding,
+            #[cfg(feature = "brotli")]
+            DecoderKind::Brotli(_) => ContentCoding::Brotli,
+        }
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match &mut self.0 {
+            DecoderKind::Deflate(decoder) => decoder.read(buf),
+            #[cfg(feature = "brotli")]
+            DecoderKind::Brotli(decoder) => {
+                let n = decoder.read(buf)?;
+                // The decoder stops at the end of the stream without looking
+                // any further, so anything following it is caught here
+                if n == 0
+                    && (decoder.read(buf)? > 0
+                        || decoder.get_mut().0.read(&mut [0])? > 0)
+                {
+                    return Err(DecodeError::InvalidData.into());
+                }
+                Ok(n)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "brotli")]
+type BrotliDecoder<R> = brotli::DecompressorCustomIo<
+    io::Error,
+    brotli::IntoIoReader<R>,
+    <StandardAlloc as Allocator<u8>>::AllocatedMemory,
+    StandardAlloc,
+    StandardAlloc,
+    StandardAlloc,
+>;
+
+// Fails with `DecodeError::InvalidData` on invalid or truncated input, and
+// passes on the errors of `inner` as they are.
+#[cfg(feature = "brotli")]
+fn brotli_decoder<R: Read>(inner: R) -> BrotliDecoder<R> {
+    let mut alloc = StandardAlloc::default();
+    let buffer = <StandardAlloc as Allocator<u8>>::alloc_cell(
+        &mut alloc,
+        BROTLI_BUFFER_SIZE,
+    );
+    brotli::DecompressorCustomIo::new(
+        brotli::IntoIoReader(inner),
+        buffer,
+        alloc,
+        StandardAlloc::default(),
+        StandardAlloc::default(),
+        DecodeError::InvalidData.into(),
+    )
+}
+
+// A gzip or zlib stream.
+struct DeflateDecoder<R: Read> {
+    bits: BitReader<R>,
+    coding: ContentCoding,
+    state: DecoderState,
+    inflater: Inflater,
+    checksum: Checksum,
+    // Modulo 2^32, as gzip records it
+    len: u32,
+}
+
+impl<R: Read> DeflateDecoder<R> {
+    fn new(inner: R, coding: ContentCoding) -> Self {
+        Self {
+            bits: BitReader::new(inner),
+            coding,
+            state: DecoderState::Header,
+            inflater: Inflater::new(),
+            checksum: Checksum::new(coding),
+            len: 0,
+        }
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        match self.coding {
+            ContentCoding::Gzip => self.read_gzip_header(),
+            ContentCoding::Deflate => {
+                let cmf = self.bits.byte()?;
+                let flg = self.bits.byte()?;
+                // DEFLATE with at most a 32K window, no preset dictionary
+                if cmf & 0x0f != 8
+                    || cmf >> 4 > 7
+                    || u16::from_be_bytes([cmf, flg]) % 31 != 0
+                    || flg & 0x20 != 0
+                {
+                    return Err(DecodeError::InvalidData.into());
+                }
+                Ok(())
+            }
+            #[cfg(feature = "brotli")]
+            ContentCoding::Brotli => unreachable!("brotli is not DEFLATE"),
+        }
+    }
+
+    // RFC 1952 section 2.3
+    fn read_gzip_header(&mut self) -> io::Result<()> {
+        const FHCRC: u8 = 0x02;
+        const FEXTRA: u8 = 0x04;
+        const FNAME: u8 = 0x08;
+        const FCOMMENT: u8 = 0x10;
+        const RESERVED: u8 = 0xe0;
+
+        let mut crc = Crc32::new();
+        let mut byte = |bits: &mut BitReader<R>| -> io::Result<u8> {
+            let b = bits.byte()?;
+            crc.update(&[b]);
+            Ok(b)
+        };
+        let mut fixed = [0; 10];
+        for b in &mut fixed {
+            *b = byte(&mut self.bits)?;
+        }
+        let flags = fixed[3];
+        if fixed[..3] != [0x1f, 0x8b, 8] || flags & RESERVED != 0 {
+            return Err(DecodeError::InvalidData.into());
+        }
+        if flags & FEXTRA != 0 {
+            let len = u16::from_le_bytes([
+                byte(&mut self.bits)?,
+                byte(&mut self.bits)?,
+            ]);
+            for _ in 0..len {
+                byte(&mut self.bits)?;
+            }
+        }
+        // Zero-terminated file name and comment
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                while byte(&mut self.bits)? != 0 {}
+            }
+        }
+        if flags & FHCRC != 0 {
+            let expected = crc.value() as u16;
+            if self.bits.u16_le()? != expected {
+                return Err(DecodeError::InvalidData.into());
+            }
+        }
+        Ok(())
+    }
+
+    // Verifies the trailer and checks for another gzip member.
+    fn read_trailer(&mut self) -> io::Result<()> {
+        match self.checksum {
+            Checksum::Crc32(crc) => {
+                if self.bits.u32_le()? != crc.value()
+                    || self.bits.u32_le()? != self.len
+                {
+                    return Err(DecodeError::InvalidData.into());
+                }
+                if self.bits.at_eof()? {
+                    self.state = DecoderState::Done;
+                } else {
+                    self.state = DecoderState::Header;
+                    self.inflater = Inflater::new();
+                    self.checksum = Checksum::new(self.coding);
+                    self.len = 0;
+                }
+            }
+            Checksum::Adler32(adler) => {
+                // Big-endian, unlike everything else in the stream
+                let trailer = self.bits.u32_le()?.swap_bytes();
+                if trailer != adler.value() {
+                    return Err(DecodeError::InvalidData.into());
+                }
+                self.state = DecoderState::Done;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DeflateDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.state {
+                DecoderState::Header => {
+                    self.read_header()?;
+                    self.state = DecoderState::Body;
+                }
+                DecoderState::Body => {
+                    let n = self.inflater.read(&mut self.bits, buf)?;
+                    if n > 0 {
+                        self.checksum.update(&buf[..n]);
+                        self.len = self.len.wrapping_add(n as u32);
+                        return Ok(n);
+                    }
+                    self.read_trailer()?;
+                }
+                DecoderState::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+// Limits on decoding, against small messages that decompress to something
+// huge ("decompression bombs").
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    max_size: u64,
+    max_ratio: u64,
+}
+
+impl DecodeLimits {
+    // No limit on the size and a ratio of at most 200 to 1.
+    pub fn new() -> Self {
+        Self {
+            max_size: u64::MAX,
+            max_ratio: DEFAULT_MAX_RATIO,
+        }
+    }
+
+    // The most bytes decoding may produce.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = size;
+        self
+    }
+
+    // The most bytes decoding may produce per byte of encoded input. Only
+    // checked once the output passes 64 KiB.
+    pub fn max_ratio(mut self, ratio: u64) -> Self {
+        self.max_ratio = ratio;
+        self
+    }
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Counts the bytes read from the encoded source.
+struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+// Undoes a stack of content codings, such as those listed by
+// `Content-Encoding: gzip, deflate`, while the data is read.
+//
+// Codings are removed in the reverse of the order they were applied. Reading
+// fails with a `DecodeError` once the output exceeds the limits.
+pub struct DecodingReader<'a> {
+    inner: Box<dyn Read + 'a>,
+    limits: DecodeLimits,
+    input: Rc<Cell<u64>>,
+    output: u64,
+}
+
+impl<'a> DecodingReader<'a> {
+    // `codings` in the order they were applied, as returned by
+    // `content_codings`.
+    pub fn new<R: Read + 'a>(source: R, codings: &[ContentCoding]) -> Self {
+        let input = Rc::new(Cell::new(0));
+        let mut inner: Box<dyn Read + 'a> = Box::new(CountingReader {
+            inner: source,
+            count: Rc::clone(&input),
+        });
+        for &coding in codings.iter().rev() {
+            inner = Box::new(Decoder::new(inner, coding));
+        }
+        Self {
+            inner,
+            limits: DecodeLimits::new(),
+            input,
+            output: 0,
+        }
+    }
+
+    pub fn limits(mut self, limits: DecodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+impl Read for DecodingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Never produce more than one byte past the limit, enough to tell
+        // that it was exceeded.
+        let allowed = self
+            .limits
+            .max_size
+            .saturating_sub(self.output)
+            .saturating_add(1);
+        let len = buf.len().min(allowed.try_into().unwrap_or(usize::MAX));
+        let n = self.inner.read(&mut buf[..len])?;
+        self.output += n as u64;
+        if self.output > self.limits.max_size {
+            return Err(DecodeError::TooLarge.into());
+        }
+        if self.output > RATIO_GRACE_SIZE
+            && self.output / self.input.get().max(1) >= self.limits.max_ratio
+        {
+            return Err(DecodeError::RatioExceeded.into());
+        }
+        Ok(n)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Checksum {
+    Crc32(Crc32),
+    Adler32(Adler32),
+}
+
+impl Checksum {
+    fn new(coding: ContentCoding) -> Self {
+        match coding {
+            ContentCoding::Gzip => Self::Crc32(Crc32::new()),
+            ContentCoding::Deflate => Self::Adler32(Adler32::new()),
+            #[cfg(feature = "brotli")]
+            ContentCoding::Brotli => unreachable!("brotli has no checksum"),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Crc32(crc) => crc.update(data),
+            Self::Adler32(adler) => adler.update(data),
+        }
+    }
+}
+
+// The CRC-32 of gzip (RFC 1952 section 8).
+#[derive(Debug, Clone, Copy)]
+struct Crc32(u32);
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+impl Crc32 {
+    fn new() -> Self {
+        Self(0)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        let mut c = !self.0;
+        for &b in data {
+            c = CRC_TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8);
+        }
+        self.0 = !c;
+    }
+
+    fn value(&self) -> u32 {
+        self.0
+    }
+}
+
+// The Adler-32 checksum of zlib (RFC 1950 section 9).
+#[derive(Debug, Clone, Copy)]
+struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+const ADLER_MOD: u32 = 65521;
+
+impl Adler32 {
+    fn new() -> Self {
+        Self { a: 1, b: 0 }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        // The sums cannot overflow within this many bytes
+        for chunk in data.chunks(5552) {
+            for &b in chunk {
+                self.a += b as u32;
+                self.b += self.a;
+            }
+            self.a %= ADLER_MOD;
+            self.b %= ADLER_MOD;
+        }
+    }
+
+    fn value(&self) -> u32 {
+        self.b << 16 | self.a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.value(), 0xcbf43926);
+        let mut adler = Adler32::new();
+        adler.update(b"Wikipedia");
+        assert_eq!(adler.value(), 0x11e60398);
+        let mut adler = Adler32::new();
+        adler.update(&[0xff; 100_000]);
+        assert_eq!(adler.value(), 0x149a302c);
+    }
+
+    #[test]
+    fn framing() {
+        let gzip = ContentCoding::Gzip.encode(b"hello");
+        assert_eq!(&gzip[..4], [0x1f, 0x8b, 8, 0]);
+        let trailer = &gzip[gzip.len() - 8..];
+        assert_eq!(trailer[..4], 0x3610a686u32.to_le_bytes());
+        assert_eq!(trailer[4..], 5u32.to_le_bytes());
+
+        let zlib = ContentCoding::Deflate.encode(b"hello");
+        assert_eq!(u16::from_be_bytes([zlib[0], zlib[1]]) % 31, 0);
+        assert_eq!(zlib[zlib.len() - 4..], 0x062c0215u32.to_be_bytes());
+    }
+
+    fn decode(
+        data: &[u8],
+        codings: &[ContentCoding],
+        limits: DecodeLimits,
+    ) -> io::Result<Vec<u8>> {
+        let mut out = vec![];
+        DecodingReader::new(data, codings)
+            .limits(limits)
+            .read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    // Every coding there is with the features enabled
+    fn all_codings() -> Vec<ContentCoding> {
+        #[allow(unused_mut)]
+        let mut codings = vec![ContentCoding::Gzip, ContentCoding::Deflate];
+        #[cfg(feature = "brotli")]
+        codings.push(ContentCoding::Brotli);
+        codings
+    }
+
+    #[test]
+    fn decoding() {
+        let text = b"Lorem ipsum dolor sit amet. ".repeat(1000);
+        for coding in all_codings() {
+            let encoded = coding.encode(&text);
+            let decoded =
+                decode(&encoded, &[coding], DecodeLimits::new()).unwrap();
+            assert_eq!(decoded, text);
+            // One byte at a time
+            let mut decoder = Decoder::new(&encoded[..], coding);
+            let mut decoded = vec![];
+            let mut byte = [0];
+            while decoder.read(&mut byte).unwrap() == 1 {
+                decoded.push(byte[0]);
+            }
+            assert_eq!(decoded, text);
+        }
+
+        // Produced by Python's gzip module, with a file name
+        let named = [
+            31, 139, 8, 8, 0, 0, 0, 0, 2, 255, 97, 46, 116, 120, 116, 0, 203,
+            72, 205, 201, 201, 7, 0, 134, 166, 16, 54, 5, 0, 0, 0,
+        ];
+        let decoded =
+            decode(&named, &[ContentCoding::Gzip], DecodeLimits::new());
+        assert_eq!(decoded.unwrap(), b"hello");
+
+        // Several gzip members
+        let mut members = ContentCoding::Gzip.encode(b"hello ");
+        members.extend(ContentCoding::Gzip.encode(b"world"));
+        let decoded =
+            decode(&members, &[ContentCoding::Gzip], DecodeLimits::new());
+        assert_eq!(decoded.unwrap(), b"hello world");
+
+        // Stacked codings are undone last to first
+        let stacked =
+            ContentCoding::Gzip.encode(&ContentCoding::Deflate.encode(&text));
+        let codings = [ContentCoding::Deflate, ContentCoding::Gzip];
+        assert_eq!(
+            decode(&stacked, &codings, DecodeLimits::new()).unwrap(),
+            text
+        );
+        assert_eq!(
+            decode(b"as is", &[], DecodeLimits::new()).unwrap(),
+            b"as is"
+        );
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn brotli() {
+        let text = b"Lorem ipsum dolor sit amet. ".repeat(1000);
+        let codings = [ContentCoding::Gzip, ContentCoding::Brotli];
+        let stacked =
+            ContentCoding::Brotli.encode(&ContentCoding::Gzip.encode(&text));
+        assert_eq!(
+            decode(&stacked, &codings, DecodeLimits::new()).unwrap(),
+            text
+        );
+
+        // Flushing makes everything written so far decodable
+        let mut encoder = Encoder::new(vec![], ContentCoding::Brotli);
+        encoder.write_all(b"hello").unwrap();
+        encoder.flush().unwrap();
+        let mut decoder =
+            Decoder::new(&encoder.get_ref()[..], ContentCoding::Brotli);
+        let mut buf = [0; 5];
+        decoder.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+
+        let brotli = ContentCoding::Brotli.encode(b"hello");
+        let mut trailing = brotli.clone();
+        trailing.push(0);
+        for invalid in [
+            &brotli[..brotli.len() - 1],
+            &trailing,
+            &ContentCoding::Gzip.encode(b"hello"),
+        ] {
+            let error =
+                decode(invalid, &[ContentCoding::Brotli], DecodeLimits::new())
+                    .unwrap_err();
+            assert_eq!(
+                DecodeError::from_io(&error).copied(),
+                Some(DecodeError::InvalidData),
+                "{invalid:?}"
+            );
+        }
+
+        // Errors of the source are passed on
+        let error =
+            decode(&stacked[..10], &codings, DecodeLimits::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn invalid_encodings() {
+        let gzip = ContentCoding::Gzip.encode(b"hello");
+        let zlib = ContentCoding::Deflate.encode(b"hello");
+        let flip = |data: &[u8], i: usize| {
+            let mut data = data.to_vec();
+            let i = if i < data.len() { i } else { data.len() - 1 };
+            data[i] ^= 0x01;
+            data
+        };
+        for (data, coding) in [
+            // Wrong magic
+            (flip(&gzip, 0), ContentCoding::Gzip),
+            // Reserved flag
+            (
+                flip(&gzip, 3).iter().map(|&b| b | 0x80).collect(),
+                ContentCoding::Gzip,
+            ),
+            // CRC and length
+            (flip(&gzip, gzip.len() - 5), ContentCoding::Gzip),
+            (flip(&gzip, gzip.len() - 1), ContentCoding::Gzip),
+            // Header check and Adler-32
+            (flip(&zlib, 1), ContentCoding::Deflate),
+            (flip(&zlib, zlib.len() - 1), ContentCoding::Deflate),
+            // Gzip is not zlib
+            (gzip.clone(), ContentCoding::Deflate),
+        ] {
+            let error = decode(&data, &[coding], DecodeLimits::new());
+            assert_eq!(
+                error.unwrap_err().kind(),
+                io::ErrorKind::InvalidData,
+                "{data:?}"
+            );
+        }
+        // Truncated
+        let error = decode(
+            &gzip[..gzip.len() - 2],
+            &[ContentCoding::Gzip],
+            DecodeLimits::new(),
+        );
+        assert_eq!(error.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn decode_limits() {
+        let data = vec![0; 1024 * 1024];
+        let gzip = ContentCoding::Gzip.encode(&data);
+        let codings = [ContentCoding::Gzip];
+
+        let limits = DecodeLimits::new().max_size(1000);
+        let error = decode(&gzip, &codings, limits).unwrap_err();
+        assert_eq!(
+            DecodeError::from_io(&error).copied(),
+            Some(DecodeError::TooLarge)
+        );
+        let limits = DecodeLimits::new()
+            .max_size(data.len() as u64)
+            .max_ratio(u64::MAX);
+        assert_eq!(decode(&gzip, &codings, limits).unwrap().len(), data.len());
+
+        // A megabyte of zeros compresses over a thousandfold
+        let error = decode(&gzip, &codings, DecodeLimits::new()).unwrap_err();
+        assert_eq!(
+            DecodeError::from_io(&error).copied(),
+            Some(DecodeError::RatioExceeded)
+        );
+        let limits = DecodeLimits::new().max_ratio(u64::MAX);
+        assert!(decode(&gzip, &codings, limits).is_ok());
+        // Small bodies are not held to the ratio
+        let gzip = ContentCoding::Gzip.encode(&data[..60_000]);
+        assert!(decode(&gzip, &codings, DecodeLimits::new()).is_ok());
+    }
+
+    #[test]
+    fn content_encoding_lists() {
+        let headers: HeaderMap = [
+            (
+                HeaderName::CONTENT_ENCODING,
+                "gzip, identity".parse().unwrap(),
+            ),
+            (HeaderName::CONTENT_ENCODING, "Deflate".parse().unwrap()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            content_codings(&headers),
+            Ok(vec![ContentCoding::Gzip, ContentCoding::Deflate])
+        );
+        let codings = |value: &str| {
+            let headers: HeaderMap =
+                [(HeaderName::CONTENT_ENCODING, value.parse().unwrap())]
+                    .into_iter()
+                    .collect();
+            content_codings(&headers)
+        };
+        for unsupported in ["gzip, zstd", "zstd", "compress, gzip"] {
+            assert_eq!(
+                codings(unsupported),
+                Err(DecodeError::UnsupportedCoding),
+                "{unsupported}"
+            );
+        }
+        #[cfg(feature = "brotli")]
+        assert_eq!(
+            codings("gzip, br"),
+            Ok(vec![ContentCoding::Gzip, ContentCoding::Brotli])
+        );
+        #[cfg(not(feature = "brotli"))]
+        assert_eq!(codings("gzip, br"), Err(DecodeError::UnsupportedCoding));
+        assert_eq!(content_codings(&HeaderMap::new()), Ok(vec![]));
+    }
+
+    #[test]
+    fn coding_names() {
+        assert_eq!(
+            ContentCoding::from_bytes(b"GZip"),
+            Some(ContentCoding::Gzip)
+        );
+        assert_eq!(
+            ContentCoding::from_bytes(b"x-gzip"),
+            Some(ContentCoding::Gzip)
+        );
+        assert_eq!(
+            ContentCoding::from_bytes(b"deflate"),
+            Some(ContentCoding::Deflate)
+        );
+        #[cfg(feature = "brotli")]
+        assert_eq!(
+            ContentCoding::from_bytes(b"BR"),
+            Some(ContentCoding::Brotli)
+        );
+        #[cfg(not(feature = "brotli"))]
+        assert_eq!(ContentCoding::from_bytes(b"br"), None);
+        assert_eq!(ContentCoding::from_bytes(b"zstd"), None);
+        for coding in all_codings() {
+            assert_eq!(
+                ContentCoding::from_bytes(coding.as_str().as_bytes()),
+                Some(coding)
+            );
+        }
+    }
+}
@@ -0,0 +1,616 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// Matches reach back at most this far (RFC 1951 section 2.5.1).
+const WINDOW_SIZE: usize = 32 * 1024;
+const WINDOW_MASK: usize = WINDOW_SIZE - 1;
+// Input is compressed in blocks of at most this many bytes, the most a
+// stored block can hold, so a block that does not compress can always be
+// sent as is.
+const BLOCK_SIZE: usize = 0xffff;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_SIZE: usize = 1 << 15;
+// How many earlier occurrences of a prefix are tried before settling for the
+// longest match found so far. Trades ratio for speed.
+const MAX_CHAIN: usize = 128;
+const NIL: usize = usize::MAX;
+
+const END_OF_BLOCK: usize = 256;
+pub(crate) const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59,
+    67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+pub(crate) const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5,
+    5, 5, 5, 0,
+];
+pub(crate) const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513,
+    769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+pub(crate) const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10,
+    11, 11, 12, 12, 13, 13,
+];
+// The order in which the lengths of the code length code are sent
+pub(crate) const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+// A streaming DEFLATE (RFC 1951) compressor.
+//
+// Input is buffered until a block is full, then turned into literals and
+// back-references by a hash chain search and sent with whichever of a
+// dynamic Huffman, fixed Huffman or stored block is the smallest.
+pub(crate) struct Deflater {
+    // The last `WINDOW_SIZE` bytes already compressed, followed by the
+    // input not compressed yet
+    window: Vec<u8>,
+    // Stream position of `window[0]`
+    base: usize,
+    // Index into `window` of the first byte not compressed yet
+    pending: usize,
+    // Stream position of the last occurrence of each 3-byte hash
+    head: Vec<usize>,
+    // Stream position of the previous occurrence of the hash at each
+    // position, indexed modulo `WINDOW_SIZE`
+    prev: Vec<usize>,
+    bits: BitWriter,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Token {
+    Literal(u8),
+    Match { len: u16, distance: u16 },
+}
+
+impl Deflater {
+    pub(crate) fn new() -> Self {
+        Self {
+            window: vec![],
+            base: 0,
+            pending: 0,
+            head: vec![NIL; HASH_SIZE],
+            prev: vec![NIL; WINDOW_SIZE],
+            bits: BitWriter::default(),
+        }
+    }
+
+    // Takes more input, appending whatever compressed output is ready to
+    // `out`.
+    pub(crate) fn compress(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        self.window.extend_from_slice(data);
+        while self.window.len() - self.pending >= BLOCK_SIZE {
+            self.compress_block(self.pending + BLOCK_SIZE, false);
+        }
+        out.append(&mut self.bits.out);
+    }
+
+    // Compresses all input so far and aligns the output to a byte boundary
+    // with an empty stored block, so a decoder can reproduce everything
+    // written so far (a "sync flush").
+    pub(crate) fn flush(&mut self, out: &mut Vec<u8>) {
+        if self.pending < self.window.len() {
+            self.compress_block(self.window.len(), false);
+        }
+        self.bits.write(0, 3);
+        self.bits.align();
+        self.bits.out.extend_from_slice(&[0x00, 0x00, 0xff, 0xff]);
+        out.append(&mut self.bits.out);
+    }
+
+    // Compresses the rest of the input as the final block.
+    pub(crate) fn finish(&mut self, out: &mut Vec<u8>) {
+        self.compress_block(self.window.len(), true);
+        self.bits.align();
+        out.append(&mut self.bits.out);
+    }
+
+    fn compress_block(&mut self, end: usize, last: bool) {
+        let start = self.pending;
+        let mut tokens = vec![];
+        let mut i = start;
+        while i < end {
+            let (len, distance) = self.find_match(i, end);
+            if len >= MIN_MATCH {
+                tokens.push(Token::Match {
+                    len: len as u16,
+                    distance: distance as u16,
+                });
+                for p in i..i + len {
+                    self.insert(p, end);
+                }
+                i += len;
+            } else {
+                tokens.push(Token::Literal(self.window[i]));
+                self.insert(i, end);
+                i += 1;
+            }
+        }
+        write_block(&mut self.bits, &tokens, &self.window[start..end], last);
+
+        // Only the last `WINDOW_SIZE` bytes can be referred to from now on
+        self.pending = end;
+        if self.pending > WINDOW_SIZE {
+            let drop = self.pending - WINDOW_SIZE;
+            self.window.drain(..drop);
+            self.base += drop;
+            self.pending -= drop;
+        }
+    }
+
+    fn hash(&self, i: usize) -> usize {
+        let w = &self.window;
+        ((w[i] as usize) << 10 ^ (w[i + 1] as usize) << 5 ^ w[i + 2] as usize)
+            & (HASH_SIZE - 1)
+    }
+
+    fn insert(&mut self, i: usize, end: usize) {
+        if i + MIN_MATCH > end {
+            return;
+        }
+        let hash = self.hash(i);
+        let pos = self.base + i;
+        self.prev[pos & WINDOW_MASK] = self.head[hash];
+        self.head[hash] = pos;
+    }
+
+    // The longest earlier occurrence of the input at `i`, as its length and
+    // distance.
+    fn find_match(&self, i: usize, end: usize) -> (usize, usize) {
+        let max_len = MAX_MATCH.min(end - i);
+        if max_len < MIN_MATCH {
+            return (0, 0);
+        }
+        let pos = self.base + i;
+        let (mut best_len, mut best_distance) = (0, 0);
+        let mut candidate = self.head[self.hash(i)];
+        for _ in 0..MAX_CHAIN {
+            if candidate == NIL
+                || candidate < self.base
+                || pos - candidate > WINDOW_SIZE
+            {
+                break;
+            }
+            let c = candidate - self.base;
+            let len = self.window[c..]
+                .iter()
+                .zip(&self.window[i..i + max_len])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len > best_len {
+                (best_len, best_distance) = (len, pos - candidate);
+                if len == max_len {
+                    break;
+                }
+            }
+            // Entries are overwritten as the window moves on, which shows
+            // as a chain that stops going backwards.
+            let next = self.prev[candidate & WINDOW_MASK];
+            if next == NIL || next >= candidate {
+                break;
+            }
+            candidate = next;
+        }
+        (best_len, best_distance)
+    }
+}
+
+fn length_code(len: u16) -> usize {
+    LENGTH_BASE.partition_point(|&base| base <= len) - 1
+}
+
+fn distance_code(distance: u16) -> usize {
+    DISTANCE_BASE.partition_point(|&base| base <= distance) - 1
+}
+
+fn write_block(bits: &mut BitWriter, tokens: &[Token], raw: &[u8], last: bool) {
+    let mut lit_freqs = [0u32; 286];
+    let mut dist_freqs = [0u32; 30];
+    lit_freqs[END_OF_BLOCK] = 1;
+    for token in tokens {
+        match *token {
+            Token::Literal(b) => lit_freqs[b as usize] += 1,
+            Token::Match { len, distance } => {
+                lit_freqs[257 + length_code(len)] += 1;
+                dist_freqs[distance_code(distance)] += 1;
+            }
+        }
+    }
+
+    let lit_lens = code_lengths(&lit_freqs, 15);
+    let dist_lens = code_lengths(&dist_freqs, 15);
+    let header = DynamicHeader::new(&lit_lens, &dist_lens);
+    let dynamic_size = 3
+        + header.size()
+        + data_size(&lit_freqs, &dist_freqs, &lit_lens, &dist_lens);
+    let (fixed_lit_lens, fixed_dist_lens) = fixed_lengths();
+    let fixed_size = 3 + data_size(
+        &lit_freqs,
+        &dist_freqs,
+        &fixed_lit_lens,
+        &fixed_dist_lens,
+    );
+    // Header, worst case padding, LEN and NLEN
+    let stored_size = 3 + 7 + 32 + 8 * raw.len();
+
+    let last = last as u32;
+    if stored_size <= dynamic_size.min(fixed_size) {
+        bits.write(last, 1);
+        bits.write(0b00, 2);
+        bits.align();
+        let len = raw.len() as u16;
+        bits.out.extend_from_slice(&len.to_le_bytes());
+        bits.out.extend_from_slice(&(!len).to_le_bytes());
+        bits.out.extend_from_slice(raw);
+    } else if fixed_size <= dynamic_size {
+        bits.write(last, 1);
+        bits.write(0b01, 2);
+        write_tokens(bits, tokens, &fixed_lit_lens, &fixed_dist_lens);
+    } else {
+        bits.write(last, 1);
+        bits.write(0b10, 2);
+        header.write(bits);
+        write_tokens(bits, tokens, &lit_lens, &dist_lens);
+    }
+}
+
+// The size in bits of the compressed data with the given code lengths.
+fn data_size(
+    lit_freqs: &[u32],
+    dist_freqs: &[u32],
+    lit_lens: &[u8],
+    dist_lens: &[u8],
+) -> usize {
+    let literals: usize = lit_freqs
+        .iter()
+        .zip(lit_lens)
+        .enumerate()
+        .map(|(symbol, (&freq, &len))| {
+            let extra = match symbol {
+                257.. => LENGTH_EXTRA[symbol - 257],
+                _ => 0,
+            };
+            freq as usize * (len + extra) as usize
+        })
+        .sum();
+    let distances: usize = dist_freqs
+        .iter()
+        .zip(dist_lens)
+        .zip(DISTANCE_EXTRA)
+        .map(|((&freq, &len), extra)| freq as usize * (len + extra) as usize)
+        .sum();
+    literals + distances
+}
+
+fn write_tokens(
+    bits: &mut BitWriter,
+    tokens: &[Token],
+    lit_lens: &[u8],
+    dist_lens: &[u8],
+) {
+    let lit_codes = canonical_codes(lit_lens);
+    let dist_codes = canonical_codes(dist_lens);
+    let write_symbol = |bits: &mut BitWriter, codes: &[u16], lens: &[u8], s| {
+        bits.write(codes[s] as u32, lens[s] as u32)
+    };
+    for token in tokens {
+        match *token {
+            Token::Literal(b) => {
+                write_symbol(bits, &lit_codes, lit_lens, b as usize)
+            }
+            Token::Match { len, distance } => {
+                let code = length_code(len);
+                write_symbol(bits, &lit_codes, lit_lens, 257 + code);
+                bits.write(
+                    (len - LENGTH_BASE[code]) as u32,
+                    LENGTH_EXTRA[code] as u32,
+                );
+                let code = distance_code(distance);
+                write_symbol(bits, &dist_codes, dist_lens, code);
+                bits.write(
+                    (distance - DISTANCE_BASE[code]) as u32,
+                    DISTANCE_EXTRA[code] as u32,
+                );
+            }
+        }
+    }
+    write_symbol(bits, &lit_codes, lit_lens, END_OF_BLOCK);
+}
+
+// The code lengths of the fixed Huffman codes (RFC 1951 section 3.2.6).
+pub(crate) fn fixed_lengths() -> ([u8; 288], [u8; 30]) {
+    let mut lit_lens = [0; 288];
+    lit_lens[..144].fill(8);
+    lit_lens[144..256].fill(9);
+    lit_lens[256..280].fill(7);
+    lit_lens[280..].fill(8);
+    (lit_lens, [5; 30])
+}
+
+// The code lengths and code length code of a dynamic Huffman block
+// (RFC 1951 section 3.2.7).
+struct DynamicHeader {
+    lengths: Vec<u8>,
+    num_lit: usize,
+    num_dist: usize,
+    // The lengths run-length encoded, as (symbol, extra bits, extra length)
+    runs: Vec<(usize, u32, u32)>,
+    cl_lens: Vec<u8>,
+    num_cl: usize,
+}
+
+impl DynamicHeader {
+    fn new(lit_lens: &[u8], dist_lens: &[u8]) -> Self {
+        let used = |lens: &[u8], min| {
+            lens.iter()
+                .rposition(|&len| len > 0)
+                .map_or(0, |i| i + 1)
+                .max(min)
+        };
+        let num_lit = used(lit_lens, 257);
+        let num_dist = used(dist_lens, 1);
+        let lengths: Vec<u8> = lit_lens[..num_lit]
+            .iter()
+            .chain(&dist_lens[..num_dist])
+            .copied()
+            .collect();
+
+        let mut runs = vec![];
+        let mut i = 0;
+        while i < lengths.len() {
+            let len = lengths[i];
+            let run = lengths[i..].iter().take_while(|&&l| l == len).count();
+            match (len, run) {
+                (0, 11..) => {
+                    let run = run.min(138);
+                    runs.push((18, (run - 11) as u32, 7));
+                    i += run;
+                }
+                (0, 3..) => {
+                    runs.push((17, (run - 3) as u32, 3));
+                    i += run;
+                }
+                (_, 4..) => {
+                    // The length itself, then repeats of it
+                    runs.push((len as usize, 0, 0));
+                    let run = (run - 1).min(6);
+                    runs.push((16, (run - 3) as u32, 2));
+                    i += 1 + run;
+                }
+                _ => {
+                    runs.push((len as usize, 0, 0));
+                    i += 1;
+                }
+            }
+        }
+
+        let mut cl_freqs = [0u32; 19];
+        for &(symbol, _, _) in &runs {
+            cl_freqs[symbol] += 1;
+        }
+        let cl_lens = code_lengths(&cl_freqs, 7);
+        let num_cl = CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&s| cl_lens[s] > 0)
+            .map_or(0, |i| i + 1)
+            .max(4);
+        Self {
+            lengths,
+            num_lit,
+            num_dist,
+            runs,
+            cl_lens,
+            num_cl,
+        }
+    }
+
+    fn size(&self) -> usize {
+        let runs: usize = self
+            .runs
+            .iter()
+            .map(|&(symbol, _, extra)| {
+                self.cl_lens[symbol] as usize + extra as usize
+            })
+            .sum();
+        5 + 5 + 4 + 3 * self.num_cl + runs
+    }
+
+    fn write(&self, bits: &mut BitWriter) {
+        debug_assert_eq!(self.lengths.len(), self.num_lit + self.num_dist);
+        bits.write((self.num_lit - 257) as u32, 5);
+        bits.write((self.num_dist - 1) as u32, 5);
+        bits.write((self.num_cl - 4) as u32, 4);
+        for &symbol in &CODE_LENGTH_ORDER[..self.num_cl] {
+            bits.write(self.cl_lens[symbol] as u32, 3);
+        }
+        let cl_codes = canonical_codes(&self.cl_lens);
+        for &(symbol, extra, extra_bits) in &self.runs {
+            bits.write(cl_codes[symbol] as u32, self.cl_lens[symbol] as u32);
+            bits.write(extra, extra_bits);
+        }
+    }
+}
+
+// Huffman code lengths for the given symbol frequencies, none longer than
+// `max_bits`. The code is always complete: if fewer than two symbols are
+// used, unused ones are given a length to make up for it.
+fn code_lengths(freqs: &[u32], max_bits: u8) -> Vec<u8> {
+    let mut lengths = vec![0; freqs.len()];
+    let used: Vec<usize> = (0..freqs.len()).filter(|&s| freqs[s] > 0).collect();
+    if used.len() < 2 {
+        let unused = (0..).filter(|s| !used.contains(s));
+        for s in used.iter().copied().chain(unused).take(2) {
+            lengths[s] = 1;
+        }
+        return lengths;
+    }
+
+    // Flattening the frequencies until the tree is shallow enough is not
+    // optimal, but it is simple and rarely needed.
+    let mut weights: Vec<u64> = used.iter().map(|&s| freqs[s] as u64).collect();
+    loop {
+        let depths = huffman_depths(&weights);
+        if depths.iter().all(|&depth| depth <= max_bits) {
+            for (&s, depth) in used.iter().zip(depths) {
+                lengths[s] = depth;
+            }
+            return lengths;
+        }
+        for weight in &mut weights {
+            *weight = weight.div_ceil(2);
+        }
+    }
+}
+
+// The depth of each leaf in a Huffman tree for the given weights.
+fn huffman_depths(weights: &[u64]) -> Vec<u8> {
+    let leaves = weights.len();
+    let mut parent = vec![0; 2 * leaves - 1];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = weights
+        .iter()
+        .enumerate()
+        .map(|(i, &w)| Reverse((w, i)))
+        .collect();
+    let mut next = leaves;
+    while let (Some(Reverse((w1, a))), Some(Reverse((w2, b)))) =
+        (heap.pop(), heap.pop())
+    {
+        parent[a] = next;
+        parent[b] = next;
+        heap.push(Reverse((w1 + w2, next)));
+        next += 1;
+    }
+    // Parents are created after their children, so walking down from the
+    // root visits every parent before its children.
+    let mut depth = vec![0u8; 2 * leaves - 1];
+    for node in (0..2 * leaves - 2).rev() {
+        depth[node] = depth[parent[node]] + 1;
+    }
+    depth.truncate(leaves);
+    depth
+}
+
+// The canonical Huffman codes for the given code lengths (RFC 1951 section
+// 3.2.2), bit-reversed since Huffman codes are packed starting with their
+// most significant bit.
+pub(crate) fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut counts = [0u16; 16];
+    for &len in lengths {
+        counts[len as usize] += 1;
+    }
+    counts[0] = 0;
+    let mut next_code = [0u16; 16];
+    let mut code = 0;
+    for bits in 1..16 {
+        code = (code + counts[bits - 1]) << 1;
+        next_code[bits] = code;
+    }
+    lengths
+        .iter()
+        .map(|&len| {
+            if len == 0 {
+                return 0;
+            }
+            let code = next_code[len as usize];
+            next_code[len as usize] += 1;
+            code.reverse_bits() >> (16 - len)
+        })
+        .collect()
+}
+
+// Packs values into bytes starting with the least significant bit.
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: u32) {
+        self.acc |= (value as u64) << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.out.push(self.acc as u8);
+            self.acc = 0;
+            self.count = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut deflater = Deflater::new();
+        let mut out = vec![];
+        deflater.compress(data, &mut out);
+        deflater.finish(&mut out);
+        out
+    }
+
+    #[test]
+    fn code_lengths_are_limited() {
+        // Fibonacci frequencies make the deepest possible tree
+        let mut freqs = vec![1u32, 1];
+        while freqs.len() < 30 {
+            freqs.push(freqs[freqs.len() - 1] + freqs[freqs.len() - 2]);
+        }
+        let lengths = code_lengths(&freqs, 15);
+        assert!(lengths.iter().all(|&len| (1..=15).contains(&len)));
+        // Complete: the Kraft sum is exactly one
+        let kraft: u64 = lengths.iter().map(|&len| 1 << (15 - len)).sum();
+        assert_eq!(kraft, 1 << 15);
+
+        assert_eq!(code_lengths(&[0, 0, 5], 15), [1, 0, 1]);
+        assert_eq!(code_lengths(&[5, 0, 0], 15), [1, 1, 0]);
+        assert_eq!(code_lengths(&[0, 0, 0], 15), [1, 1, 0]);
+    }
+
+    #[test]
+    fn canonical() {
+        // The example from RFC 1951 section 3.2.2, bit-reversed
+        let codes = canonical_codes(&[3, 3, 3, 3, 3, 2, 4, 4]);
+        let expected =
+            [0b010, 0b011, 0b100, 0b101, 0b110, 0b00, 0b1110, 0b1111];
+        for ((code, expected), len) in
+            codes.iter().zip(expected).zip([3, 3, 3, 3, 3, 2, 4, 4])
+        {
+            assert_eq!(code.reverse_bits() >> (16 - len), expected);
+        }
+    }
+
+    #[test]
+    fn block_types() {
+        // Empty: a fixed block with nothing but the end of block code
+        assert_eq!(deflate(b""), [0x03, 0x00]);
+        // Incompressible: stored
+        let mut x = 1u32;
+        let noise: Vec<u8> = (0..1000)
+            .map(|_| {
+                // xorshift
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            })
+            .collect();
+        let out = deflate(&noise);
+        assert_eq!(out[0] & 0b111, 0b001);
+        assert_eq!(&out[5..], &noise[..]);
+        // Repetitive: much smaller
+        let text = b"All work and no play makes Jack a dull boy. ".repeat(500);
+        assert!(deflate(&text).len() < text.len() / 20);
+    }
+}
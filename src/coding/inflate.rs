@@ -0,0 +1,421 @@
+use std::io::{self, Read};
+
+use super::DecodeError;
+use super::deflate::{self, fixed_lengths};
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const WINDOW_MASK: usize = WINDOW_SIZE - 1;
+const MAX_BITS: usize = 15;
+const INPUT_BUF_SIZE: usize = 8 * 1024;
+
+// Reads bits starting with the least significant bit of each byte, pulling
+// bytes from the underlying reader only as they are needed.
+pub(crate) struct BitReader<R> {
+    inner: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    end: usize,
+    acc: u32,
+    count: u32,
+}
+
+impl<R: Read> BitReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: vec![0; INPUT_BUF_SIZE].into_boxed_slice(),
+            pos: 0,
+            end: 0,
+            acc: 0,
+            count: 0,
+        }
+    }
+
+    // Whether the input is exhausted, not counting partly read bytes.
+    pub(crate) fn at_eof(&mut self) -> io::Result<bool> {
+        Ok(self.count < 8 && !self.fill()?)
+    }
+
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.pos == self.end {
+            self.end = loop {
+                match self.inner.read(&mut self.buf) {
+                    Ok(n) => break n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            };
+            self.pos = 0;
+        }
+        Ok(self.pos < self.end)
+    }
+
+    pub(crate) fn bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            if !self.fill()? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.acc |= (self.buf[self.pos] as u32) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        let value = self.acc & ((1u64 << n) - 1) as u32;
+        self.acc = self.acc.checked_shr(n).unwrap_or(0);
+        self.count -= n;
+        Ok(value)
+    }
+
+    // Skips to the next byte boundary.
+    pub(crate) fn align(&mut self) {
+        let skip = self.count % 8;
+        self.acc >>= skip;
+        self.count -= skip;
+    }
+
+    pub(crate) fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.bits(8)? as u8)
+    }
+
+    pub(crate) fn u16_le(&mut self) -> io::Result<u16> {
+        Ok(self.bits(16)? as u16)
+    }
+
+    pub(crate) fn u32_le(&mut self) -> io::Result<u32> {
+        Ok(self.bits(16)? | self.bits(16)? << 16)
+    }
+
+    // Reads byte-aligned data straight into `out`.
+    fn read_aligned(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let mut n = 0;
+        while n < out.len() && self.count >= 8 {
+            out[n] = self.byte()?;
+            n += 1;
+        }
+        if n < out.len() {
+            if !self.fill()? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let available = (self.end - self.pos).min(out.len() - n);
+            out[n..n + available]
+                .copy_from_slice(&self.buf[self.pos..self.pos + available]);
+            self.pos += available;
+            n += available;
+        }
+        Ok(n)
+    }
+}
+
+// A canonical Huffman code, decoded a bit at a time as in zlib's "puff".
+struct Huffman {
+    // Number of codes of each length
+    counts: [u16; MAX_BITS + 1],
+    // Symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, DecodeError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        // Over-subscribed codes are ambiguous. Incomplete ones are fine as
+        // long as the unused codes do not show up.
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(DecodeError::InvalidData);
+            }
+        }
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len > 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        counts[0] = 0;
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode<R: Read>(&self, bits: &mut BitReader<R>) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= bits.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(DecodeError::InvalidData.into())
+    }
+}
+
+enum State {
+    // Between blocks
+    BlockHeader,
+    Stored { remaining: usize },
+    Huffman { lit: Huffman, dist: Huffman },
+    Done,
+}
+
+// A streaming DEFLATE (RFC 1951) decompressor that pulls its input from a
+// `BitReader` as the output is read.
+pub(crate) struct Inflater {
+    state: State,
+    last_block: bool,
+    window: Box<[u8]>,
+    // Total bytes produced, whose low bits index into `window`
+    total: u64,
+    // A back-reference that did not fit into the output buffer yet
+    copy: Option<(usize, usize)>,
+}
+
+impl Inflater {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: State::BlockHeader,
+            last_block: false,
+            window: vec![0; WINDOW_SIZE].into_boxed_slice(),
+            total: 0,
+            copy: None,
+        }
+    }
+
+    // Decompresses into `out`, returning how many bytes were written. Only
+    // returns 0 for a non-empty `out` once the final block has ended, with
+    // the input positioned at the next byte boundary.
+    pub(crate) fn read<R: Read>(
+        &mut self,
+        bits: &mut BitReader<R>,
+        out: &mut [u8],
+    ) -> io::Result<usize> {
+        let mut n = 0;
+        while n < out.len() {
+            if let Some((len, distance)) = self.copy.take() {
+                let copied = len.min(out.len() - n);
+                for _ in 0..copied {
+                    let b = self.window[(self.total as usize)
+                        .wrapping_sub(distance)
+                        & WINDOW_MASK];
+                    out[n] = b;
+                    self.push(b);
+                    n += 1;
+                }
+                if copied < len {
+                    self.copy = Some((len - copied, distance));
+                }
+                continue;
+            }
+
+            match &mut self.state {
+                State::Done => break,
+                State::BlockHeader if self.last_block => {
+                    bits.align();
+                    self.state = State::Done;
+                }
+                State::BlockHeader => {
+                    self.last_block = bits.bits(1)? == 1;
+                    self.state = match bits.bits(2)? {
+                        0b00 => {
+                            bits.align();
+                            let len = bits.u16_le()?;
+                            if bits.u16_le()? != !len {
+                                return Err(DecodeError::InvalidData.into());
+                            }
+                            State::Stored {
+                                remaining: len as usize,
+                            }
+                        }
+                        0b01 => {
+                            let (lit_lens, dist_lens) = fixed_lengths();
+                            State::Huffman {
+                                lit: Huffman::new(&lit_lens)?,
+                                dist: Huffman::new(&dist_lens)?,
+                            }
+                        }
+                        0b10 => read_dynamic_header(bits)?,
+                        _ => return Err(DecodeError::InvalidData.into()),
+                    };
+                }
+                State::Stored { remaining: 0 } => {
+                    self.state = State::BlockHeader;
+                }
+                State::Stored { remaining } => {
+                    let len = (*remaining).min(out.len() - n);
+                    let read = bits.read_aligned(&mut out[n..n + len])?;
+                    *remaining -= read;
+                    for &b in &out[n..n + read] {
+                        self.push(b);
+                    }
+                    n += read;
+                }
+                State::Huffman { lit, dist } => {
+                    let symbol = lit.decode(bits)? as usize;
+                    match symbol {
+                        0..256 => {
+                            out[n] = symbol as u8;
+                            self.push(symbol as u8);
+                            n += 1;
+                        }
+                        256 => self.state = State::BlockHeader,
+                        257..286 => {
+                            let code = symbol - 257;
+                            let len = deflate::LENGTH_BASE[code] as usize
+                                + bits
+                                    .bits(deflate::LENGTH_EXTRA[code] as u32)?
+                                    as usize;
+                            let code = dist.decode(bits)? as usize;
+                            if code >= 30 {
+                                return Err(DecodeError::InvalidData.into());
+                            }
+                            let distance = deflate::DISTANCE_BASE[code]
+                                as usize
+                                + bits.bits(
+                                    deflate::DISTANCE_EXTRA[code] as u32,
+                                )? as usize;
+                            if distance as u64 > self.total {
+                                return Err(DecodeError::InvalidData.into());
+                            }
+                            self.copy = Some((len, distance));
+                        }
+                        _ => return Err(DecodeError::InvalidData.into()),
+                    }
+                }
+            }
+        }
+        Ok(n)
+    }
+
+    fn push(&mut self, b: u8) {
+        self.window[self.total as usize & WINDOW_MASK] = b;
+        self.total += 1;
+    }
+}
+
+// RFC 1951 section 3.2.7
+fn read_dynamic_header<R: Read>(bits: &mut BitReader<R>) -> io::Result<State> {
+    let num_lit = bits.bits(5)? as usize + 257;
+    let num_dist = bits.bits(5)? as usize + 1;
+    let num_cl = bits.bits(4)? as usize + 4;
+    if num_lit > 286 || num_dist > 30 {
+        return Err(DecodeError::InvalidData.into());
+    }
+    let mut cl_lens = [0u8; 19];
+    for &symbol in &deflate::CODE_LENGTH_ORDER[..num_cl] {
+        cl_lens[symbol] = bits.bits(3)? as u8;
+    }
+    let cl = Huffman::new(&cl_lens)?;
+
+    let mut lengths = vec![0u8; num_lit + num_dist];
+    let mut i = 0;
+    while i < lengths.len() {
+        let (len, repeat) = match cl.decode(bits)? {
+            len @ 0..16 => (len as u8, 1),
+            16 => match i.checked_sub(1) {
+                Some(prev) => (lengths[prev], 3 + bits.bits(2)? as usize),
+                None => return Err(DecodeError::InvalidData.into()),
+            },
+            17 => (0, 3 + bits.bits(3)? as usize),
+            _ => (0, 11 + bits.bits(7)? as usize),
+        };
+        if i + repeat > lengths.len() {
+            return Err(DecodeError::InvalidData.into());
+        }
+        lengths[i..i + repeat].fill(len);
+        i += repeat;
+    }
+    // A block without an end has no business being sent
+    if lengths[256] == 0 {
+        return Err(DecodeError::InvalidData.into());
+    }
+    Ok(State::Huffman {
+        lit: Huffman::new(&lengths[..num_lit])?,
+        dist: Huffman::new(&lengths[num_lit..])?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coding::deflate::Deflater;
+
+    fn inflate(data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut bits = BitReader::new(data);
+        let mut inflater = Inflater::new();
+        let mut out = vec![];
+        let mut buf = [0; 1000];
+        loop {
+            let n = inflater.read(&mut bits, &mut buf)?;
+            if n == 0 {
+                return Ok(out);
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut deflater = Deflater::new();
+        let mut out = vec![];
+        deflater.compress(data, &mut out);
+        deflater.finish(&mut out);
+        out
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut x = 1u32;
+        let noise: Vec<u8> = (0..100_000)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            })
+            .collect();
+        let text = b"It was the best of times, it was the worst of times. "
+            .repeat(5000);
+        for data in [&b""[..], b"a", b"aaaaaaaaaa", &noise, &text] {
+            assert_eq!(inflate(&deflate(data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn known_streams() {
+        // Produced by zlib: fixed Huffman with a back-reference, and stored
+        assert_eq!(
+            inflate(&[0x4b, 0x4c, 0x4a, 0x4e, 0x04, 0x23, 0x00]).unwrap(),
+            b"abcabcabc"
+        );
+        assert_eq!(
+            inflate(&[0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c']).unwrap(),
+            b"abc"
+        );
+    }
+
+    #[test]
+    fn invalid_streams() {
+        for invalid in [
+            // Reserved block type
+            &[0x07][..],
+            // LEN and NLEN disagree
+            &[0x01, 0x03, 0x00, 0x00, 0x00],
+            // Distance reaching before the start
+            &[0x03, 0x02, 0x00],
+        ] {
+            let error = inflate(invalid).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{invalid:?}");
+        }
+        // Truncated
+        let error = inflate(&deflate(b"hello hello")[..3]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
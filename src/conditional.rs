@@ -0,0 +1,293 @@
+use crate::date::HttpDate;
+use crate::etag::{ETag, ETagList};
+use crate::header::{HeaderMap, HeaderName};
+use crate::method::Method;
+use crate::request::Request;
+use crate::response::Response;
+use crate::status::StatusCode;
+
+// The outcome of evaluating the preconditions of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precondition {
+    // No precondition failed, the request is processed normally
+    Proceed,
+    // The client's cached copy is still current, answer with a 304
+    NotModified,
+    // A precondition failed, answer with a 412
+    Failed,
+}
+
+impl Precondition {
+    // The status to answer with instead of processing the request, if any.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Proceed => None,
+            Self::NotModified => Some(StatusCode::NOT_MODIFIED),
+            Self::Failed => Some(StatusCode::PRECONDITION_FAILED),
+        }
+    }
+}
+
+// The validators of the current representation of a resource, as sent in
+// `ETag` and `Last-Modified`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+    etag: Option<ETag>,
+    last_modified: Option<HttpDate>,
+}
+
+impl Validators {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_etag(mut self, etag: ETag) -> Self {
+        self.etag = Some(etag);
+        self
+    }
+
+    pub fn with_last_modified(mut self, date: HttpDate) -> Self {
+        self.last_modified = Some(date);
+        self
+    }
+
+    pub fn etag(&self) -> Option<&ETag> {
+        self.etag.as_ref()
+    }
+
+    pub fn last_modified(&self) -> Option<HttpDate> {
+        self.last_modified
+    }
+
+    // Evaluates the preconditions of a request on an existing resource in
+    // the order given by RFC 9110 section 13.2.2. Malformed precondition
+    // fields are ignored.
+    pub fn evaluate(&self, request: &Request) -> Precondition {
+        let headers = request.headers();
+        let is_get_or_head =
+            matches!(request.method(), Method::Get | Method::Head);
+
+        // Steps 1 and 2: the client wants to change the representation it
+        // has, so it must not have changed in the meantime.
+        if let Some(if_match) = etag_list(headers, HeaderName::IF_MATCH) {
+            if !if_match.matches(self.etag.as_ref(), true) {
+                return Precondition::Failed;
+            }
+        } else if let Some(since) =
+            date(headers, HeaderName::IF_UNMODIFIED_SINCE)
+            && let Some(last_modified) = self.last_modified
+            && last_modified > since
+        {
+            return Precondition::Failed;
+        }
+
+        // Steps 3 and 4: the client has a cached copy and only wants the
+        // representation if it changed.
+        if let Some(if_none_match) =
+            etag_list(headers, HeaderName::IF_NONE_MATCH)
+        {
+            if if_none_match.matches(self.etag.as_ref(), false) {
+                return match is_get_or_head {
+                    true => Precondition::NotModified,
+                    false => Precondition::Failed,
+                };
+            }
+        } else if is_get_or_head
+            && let Some(since) = date(headers, HeaderName::IF_MODIFIED_SINCE)
+            && let Some(last_modified) = self.last_modified
+            && last_modified <= since
+        {
+            return Precondition::NotModified;
+        }
+
+        Precondition::Proceed
+    }
+
+    // Whether the `Range` header of a request should be honoured. With
+    // `If-Range`, the client only wants the ranges if its partial copy is
+    // still current, and the full representation otherwise (RFC 9110
+    // section 13.1.5). Only strong validators match: entity tags are
+    // compared strongly and dates have to be exactly `Last-Modified`.
+    pub fn if_range(&self, request: &Request) -> bool {
+        let Some(value) = request.headers().get(HeaderName::IF_RANGE) else {
+            return true;
+        };
+        if let Ok(etag) = ETag::from_bytes(value.as_bytes()) {
+            return self.etag.as_ref().is_some_and(|tag| tag.strong_eq(&etag));
+        }
+        match HttpDate::from_bytes(value.as_bytes()) {
+            Ok(date) => self.last_modified == Some(date),
+            Err(_) => false,
+        }
+    }
+
+    // Adds `ETag` and `Last-Modified`, which have to be sent in 304
+    // responses as well as in the 200 response they stand in for.
+    pub fn apply(&self, response: &mut Response) {
+        let headers = response.headers_mut();
+        if let Some(etag) = &self.etag {
+            headers.insert(HeaderName::ETAG, etag.into());
+        }
+        if let Some(last_modified) = self.last_modified {
+            headers.insert(HeaderName::LAST_MODIFIED, last_modified.into());
+        }
+    }
+}
+
+fn etag_list(headers: &HeaderMap, name: HeaderName) -> Option<ETagList> {
+    if !headers.contains_key(&name) {
+        return None;
+    }
+    ETagList::from_values(headers.get_all(&name))
+}
+
+fn date(headers: &HeaderMap, name: HeaderName) -> Option<HttpDate> {
+    HttpDate::from_bytes(headers.get(name)?.as_bytes()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Precondition::*;
+    use super::*;
+    use crate::uri::RequestTarget;
+
+    const MODIFIED: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+    const EARLIER: &str = "Sat, 05 Nov 1994 08:49:37 GMT";
+    const LATER: &str = "Mon, 07 Nov 1994 08:49:37 GMT";
+
+    fn validators() -> Validators {
+        Validators::new()
+            .with_etag(ETag::strong("v2").unwrap())
+            .with_last_modified(MODIFIED.parse().unwrap())
+    }
+
+    fn evaluate(
+        method: Method,
+        headers: &[(HeaderName, &str)],
+    ) -> Precondition {
+        let target = RequestTarget::parse("/", &method).unwrap();
+        let request = headers.iter().fold(
+            Request::new(method, target),
+            |request, (name, value)| {
+                request.with_header(name.clone(), value.parse().unwrap())
+            },
+        );
+        validators().evaluate(&request)
+    }
+
+    #[test]
+    fn if_match() {
+        for (value, expected) in [
+            ("\"v2\"", Proceed),
+            ("\"v1\", \"v2\"", Proceed),
+            ("*", Proceed),
+            ("\"v1\"", Failed),
+            // Weak tags never match strongly
+            ("W/\"v2\"", Failed),
+            // Ignored
+            ("v2", Proceed),
+        ] {
+            let headers = [(HeaderName::IF_MATCH, value)];
+            assert_eq!(evaluate(Method::Put, &headers), expected, "{value}");
+        }
+        // Takes precedence over If-Unmodified-Since
+        let headers = [
+            (HeaderName::IF_MATCH, "\"v2\""),
+            (HeaderName::IF_UNMODIFIED_SINCE, EARLIER),
+        ];
+        assert_eq!(evaluate(Method::Put, &headers), Proceed);
+    }
+
+    #[test]
+    fn if_unmodified_since() {
+        for (value, expected) in [
+            (MODIFIED, Proceed),
+            (LATER, Proceed),
+            (EARLIER, Failed),
+            ("yesterday", Proceed),
+        ] {
+            let headers = [(HeaderName::IF_UNMODIFIED_SINCE, value)];
+            assert_eq!(evaluate(Method::Delete, &headers), expected, "{value}");
+        }
+    }
+
+    #[test]
+    fn if_none_match() {
+        for (method, value, expected) in [
+            (Method::Get, "\"v2\"", NotModified),
+            (Method::Head, "W/\"v2\"", NotModified),
+            (Method::Get, "*", NotModified),
+            (Method::Get, "\"v1\"", Proceed),
+            (Method::Post, "\"v2\"", Failed),
+            (Method::Put, "*", Failed),
+        ] {
+            let headers = [(HeaderName::IF_NONE_MATCH, value)];
+            assert_eq!(evaluate(method, &headers), expected, "{value}");
+        }
+        // Takes precedence over If-Modified-Since
+        let headers = [
+            (HeaderName::IF_NONE_MATCH, "\"v1\""),
+            (HeaderName::IF_MODIFIED_SINCE, LATER),
+        ];
+        assert_eq!(evaluate(Method::Get, &headers), Proceed);
+    }
+
+    #[test]
+    fn if_modified_since() {
+        for (method, value, expected) in [
+            (Method::Get, MODIFIED, NotModified),
+            (Method::Head, LATER, NotModified),
+            (Method::Get, EARLIER, Proceed),
+            (Method::Get, "garbage", Proceed),
+            // Only applies to GET and HEAD
+            (Method::Post, LATER, Proceed),
+        ] {
+            let headers = [(HeaderName::IF_MODIFIED_SINCE, value)];
+            assert_eq!(evaluate(method, &headers), expected, "{value}");
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        // A failed If-Match wins over a matching If-None-Match
+        let headers = [
+            (HeaderName::IF_MATCH, "\"v1\""),
+            (HeaderName::IF_NONE_MATCH, "\"v2\""),
+        ];
+        assert_eq!(evaluate(Method::Get, &headers), Failed);
+    }
+
+    #[test]
+    fn if_range() {
+        for (value, expected) in [
+            ("\"v2\"", true),
+            ("\"v1\"", false),
+            ("W/\"v2\"", false),
+            (MODIFIED, true),
+            (LATER, false),
+            ("garbage", false),
+        ] {
+            let target = RequestTarget::parse("/", &Method::Get).unwrap();
+            let request = Request::new(Method::Get, target)
+                .with_header(HeaderName::IF_RANGE, value.parse().unwrap());
+            assert_eq!(validators().if_range(&request), expected, "{value}");
+        }
+        let target = RequestTarget::parse("/", &Method::Get).unwrap();
+        assert!(Validators::new().if_range(&Request::new(Method::Get, target)));
+    }
+
+    #[test]
+    fn apply() {
+        let mut response = Response::new(StatusCode::NOT_MODIFIED);
+        validators().apply(&mut response);
+        let headers = response.headers();
+        assert_eq!(
+            headers.get(HeaderName::ETAG).unwrap().as_bytes(),
+            b"\"v2\""
+        );
+        assert_eq!(
+            headers.get(HeaderName::LAST_MODIFIED).unwrap().as_bytes(),
+            MODIFIED.as_bytes()
+        );
+    }
+}
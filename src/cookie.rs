@@ -0,0 +1,623 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::date::HttpDate;
+use crate::header::{HeaderName, HeaderValue};
+use crate::request::Request;
+use crate::utils::{self, ALLOWED_HOSTNAME_BYTES};
+
+mod jar;
+#[cfg(feature = "secure-cookies")]
+mod secure;
+
+pub use self::jar::{CookieJar, SiteContext};
+#[cfg(feature = "secure-cookies")]
+pub use self::secure::{InvalidKey, Key};
+
+// Limits of RFC 6265bis section 5.6, beyond which user agents drop cookies.
+const MAX_NAME_VALUE_LEN: usize = 4096;
+const MAX_ATTRIBUTE_VALUE_LEN: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CookieError {
+    // The name is not a token
+    InvalidName,
+    // The value has bytes outside of cookie-octet
+    InvalidValue,
+    InvalidDomain,
+    InvalidPath,
+    // Name and value, or an attribute value, are longer than user agents
+    // accept
+    TooLong,
+    // A "__Secure-" or "__Host-" name without the attributes the prefix
+    // promises
+    PrefixRequirements,
+    // SameSite=None and Partitioned are only accepted along with Secure
+    RequiresSecure,
+}
+
+impl fmt::Display for CookieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            Self::InvalidName => "invalid cookie name",
+            Self::InvalidValue => "invalid cookie value",
+            Self::InvalidDomain => "invalid cookie domain",
+            Self::InvalidPath => "invalid cookie path",
+            Self::TooLong => "cookie too long",
+            Self::PrefixRequirements => {
+                "cookie attributes do not meet the requirements of its prefix"
+            }
+            Self::RequiresSecure => "cookie attribute requires Secure",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl std::error::Error for CookieError {}
+
+// cookie-octet of RFC 6265bis section 4.1.1: printable US-ASCII except
+// whitespace, DQUOTE, comma, semicolon and backslash.
+fn is_cookie_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x2b | 0x2d..=0x3a | 0x3c..=0x5b | 0x5d..=0x7e)
+}
+
+fn is_valid_value(value: &str) -> bool {
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    value.bytes().all(is_cookie_octet)
+}
+
+// Attribute values may hold anything but controls and semicolons.
+fn is_valid_attribute_value(value: &str) -> bool {
+    value.len() <= MAX_ATTRIBUTE_VALUE_LEN
+        && value.bytes().all(|b| b != b';' && !b.is_ascii_control())
+}
+
+fn has_prefix(name: &str, prefix: &str) -> bool {
+    name.len() >= prefix.len()
+        && name.as_bytes()[..prefix.len()]
+            .eq_ignore_ascii_case(prefix.as_bytes())
+}
+
+// A name-value pair sent by a user agent in the `Cookie` header.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cookie {
+    name: String,
+    value: String,
+}
+
+impl Cookie {
+    pub fn new(name: &str, value: &str) -> Result<Self, CookieError> {
+        if !utils::is_token(name.as_bytes()) {
+            return Err(CookieError::InvalidName);
+        }
+        if !is_valid_value(value) {
+            return Err(CookieError::InvalidValue);
+        }
+        if name.len() + value.len() > MAX_NAME_VALUE_LEN {
+            return Err(CookieError::TooLong);
+        }
+        Ok(Self {
+            name: name.into(),
+            value: value.into(),
+        })
+    }
+
+    // Parses the value of a `Cookie` header, skipping pairs that are not
+    // valid rather than failing on them, as one bad cookie set by some other
+    // application on the same site should not cost the others.
+    pub fn parse_header(value: &[u8]) -> Vec<Self> {
+        value
+            .split(|&b| b == b';')
+            .filter_map(|pair| {
+                let pair = std::str::from_utf8(pair).ok()?.trim();
+                let (name, value) = pair.split_once('=')?;
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                Self::new(name.trim(), value).ok()
+            })
+            .collect()
+    }
+
+    // Every cookie the request carries, in order. HTTP/2 splits `Cookie`
+    // into several fields, so all of them are read.
+    pub fn from_request(request: &Request) -> Vec<Self> {
+        request
+            .headers()
+            .get_all(HeaderName::COOKIE)
+            .flat_map(|value| Self::parse_header(value.as_bytes()))
+            .collect()
+    }
+
+    // The first cookie of that name in the request. Names are case
+    // sensitive.
+    pub fn find(request: &Request, name: &str) -> Option<Self> {
+        Self::from_request(request)
+            .into_iter()
+            .find(|cookie| cookie.name == name)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    // Sent with cross-site requests too, which requires Secure
+    None,
+}
+
+impl SameSite {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Strict => "Strict",
+            Self::Lax => "Lax",
+            Self::None => "None",
+        }
+    }
+}
+
+// A cookie for the `Set-Cookie` response header (RFC 6265bis section 4.1).
+//
+// The builder methods never fail. Everything is checked when the cookie is
+// turned into a header value, including the rules for names starting with
+// "__Secure-" (requires Secure) and "__Host-" (requires Secure, Path=/ and
+// no Domain).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetCookie {
+    name: String,
+    value: String,
+    expires: Option<HttpDate>,
+    max_age: Option<Duration>,
+    domain: Option<String>,
+    path: Option<String>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+    partitioned: bool,
+}
+
+impl SetCookie {
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            expires: None,
+            max_age: None,
+            domain: None,
+            path: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+            partitioned: false,
+        }
+    }
+
+    // Parses a `Set-Cookie` field the way a user agent does (RFC 6265bis
+    // section 5.6): attributes that are unknown, malformed or too long are
+    // ignored, and the last of repeated attributes wins. A Domain loses its
+    // leading dot, and a Path that does not start with '/' is dropped in
+    // favour of the default path. Only a bad name or value fails.
+    pub fn from_bytes(s: &[u8]) -> Result<Self, CookieError> {
+        let s =
+            std::str::from_utf8(s).map_err(|_| CookieError::InvalidValue)?;
+        if s.bytes().any(|b| b != b'\t' && b.is_ascii_control()) {
+            return Err(CookieError::InvalidValue);
+        }
+        let mut parts = s.split(';');
+        let pair = parts.next().unwrap_or_default();
+        let (name, value) =
+            pair.split_once('=').ok_or(CookieError::InvalidName)?;
+        let trim = |s: &'_ str| s.trim_matches([' ', '\t']).to_owned();
+        let Cookie { name, value } = Cookie::new(&trim(name), &trim(value))?;
+        let mut cookie = Self::new(&name, &value);
+        for attribute in parts {
+            let (key, value) =
+                attribute.split_once('=').unwrap_or((attribute, ""));
+            let (key, value) = (trim(key), trim(value));
+            if value.len() > MAX_ATTRIBUTE_VALUE_LEN {
+                continue;
+            }
+            match key.to_ascii_lowercase().as_str() {
+                "expires" => {
+                    if let Ok(date) =
+                        HttpDate::from_cookie_date(value.as_bytes())
+                    {
+                        cookie.expires = Some(date);
+                    }
+                }
+                "max-age" => {
+                    let digits = value.strip_prefix('-').unwrap_or(&value);
+                    if digits.is_empty()
+                        || !digits.bytes().all(|b| b.is_ascii_digit())
+                    {
+                        continue;
+                    }
+                    // Zero or less expires the cookie right away
+                    let secs = if value.starts_with('-') {
+                        0
+                    } else {
+                        value.parse().unwrap_or(u64::MAX)
+                    };
+                    cookie.max_age = Some(Duration::from_secs(secs));
+                }
+                "domain" if !value.is_empty() => {
+                    let domain = value.strip_prefix('.').unwrap_or(&value);
+                    cookie.domain = Some(domain.to_ascii_lowercase());
+                }
+                "path" => {
+                    cookie.path = value.starts_with('/').then_some(value);
+                }
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => {
+                    cookie.same_site = match value.to_ascii_lowercase().as_str()
+                    {
+                        "strict" => Some(SameSite::Strict),
+                        "lax" => Some(SameSite::Lax),
+                        "none" => Some(SameSite::None),
+                        _ => None,
+                    };
+                }
+                "partitioned" => cookie.partitioned = true,
+                _ => {}
+            }
+        }
+        Ok(cookie)
+    }
+
+    // A cookie telling the user agent to delete the cookie of that name. It
+    // has to carry the same Domain and Path as the cookie it removes.
+    pub fn removal(name: &str) -> Self {
+        Self::new(name, "")
+            .expires(HttpDate::UNIX_EPOCH)
+            .max_age(Duration::ZERO)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    // When the cookie expires. Max-Age takes precedence where both are set.
+    pub fn expires(mut self, date: HttpDate) -> Self {
+        self.expires = Some(date);
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    // Shares the cookie with subdomains of the domain. Without it, the
+    // cookie is only sent back to the host that set it.
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    // Only send the cookie over secure channels.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    // Hide the cookie from scripts.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    // Keep the cookie in storage partitioned by the top-level site (CHIPS).
+    pub fn partitioned(mut self, partitioned: bool) -> Self {
+        self.partitioned = partitioned;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), CookieError> {
+        Cookie::new(&self.name, &self.value)?;
+        if let Some(domain) = &self.domain {
+            let host = domain.strip_prefix('.').unwrap_or(domain);
+            if host.is_empty()
+                || !host.bytes().all(|b| ALLOWED_HOSTNAME_BYTES.contains(&b))
+            {
+                return Err(CookieError::InvalidDomain);
+            }
+            if !is_valid_attribute_value(domain) {
+                return Err(CookieError::TooLong);
+            }
+        }
+        if let Some(path) = &self.path
+            && (!path.starts_with('/') || !is_valid_attribute_value(path))
+        {
+            return Err(CookieError::InvalidPath);
+        }
+        if has_prefix(&self.name, "__Secure-") && !self.secure {
+            return Err(CookieError::PrefixRequirements);
+        }
+        if has_prefix(&self.name, "__Host-")
+            && (!self.secure
+                || self.domain.is_some()
+                || self.path.as_deref() != Some("/"))
+        {
+            return Err(CookieError::PrefixRequirements);
+        }
+        if (self.same_site == Some(SameSite::None) || self.partitioned)
+            && !self.secure
+        {
+            return Err(CookieError::RequiresSecure);
+        }
+        Ok(())
+    }
+
+    // The value of a `Set-Cookie` field, after checking the cookie.
+    pub fn to_header_value(&self) -> Result<HeaderValue, CookieError> {
+        self.validate()?;
+        Ok(HeaderValue::from_bytes(self.to_string().as_bytes())
+            .expect("valid cookies are valid header values"))
+    }
+}
+
+// Formats the cookie whether it is valid or not, see `to_header_value`.
+impl fmt::Display for SetCookie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={expires}")?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={domain}")?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, "; Path={path}")?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site.as_str())?;
+        }
+        if self.partitioned {
+            f.write_str("; Partitioned")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::method::Method;
+    use crate::uri::RequestTarget;
+
+    fn pairs(cookies: &[Cookie]) -> Vec<(&str, &str)> {
+        cookies.iter().map(|c| (c.name(), c.value())).collect()
+    }
+
+    #[test]
+    fn parsing() {
+        let cookies = Cookie::parse_header(b"SID=31d4d96e407aad42; lang=en-US");
+        assert_eq!(
+            pairs(&cookies),
+            [("SID", "31d4d96e407aad42"), ("lang", "en-US")]
+        );
+        let cookies =
+            Cookie::parse_header(b" a = 1 ;b=\"2\";;c;d=x y;e=;f=\xff;g=3");
+        assert_eq!(
+            pairs(&cookies),
+            [("a", "1"), ("b", "2"), ("e", ""), ("g", "3")]
+        );
+
+        let target = RequestTarget::parse("/", &Method::Get).unwrap();
+        let request = Request::new(Method::Get, target)
+            .with_header(HeaderName::COOKIE, "a=1; b=2".parse().unwrap())
+            .with_header(HeaderName::COOKIE, "b=3".parse().unwrap());
+        assert_eq!(
+            pairs(&Cookie::from_request(&request)),
+            [("a", "1"), ("b", "2"), ("b", "3")]
+        );
+        assert_eq!(Cookie::find(&request, "b").unwrap().value(), "2");
+        assert_eq!(Cookie::find(&request, "B"), None);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(Cookie::new("session_id", "abc-123%2F").is_ok());
+        assert!(Cookie::new("quoted", "\"abc\"").is_ok());
+        for name in ["", "a b", "a=b", "a;b", "\u{e9}"] {
+            assert_eq!(Cookie::new(name, "v"), Err(CookieError::InvalidName));
+        }
+        for value in ["a b", "a;b", "a,b", "a\\b", "\"a", "\u{e9}"] {
+            assert_eq!(Cookie::new("n", value), Err(CookieError::InvalidValue));
+        }
+        let long = "v".repeat(4096);
+        assert_eq!(Cookie::new("n", &long), Err(CookieError::TooLong));
+    }
+
+    #[test]
+    fn set_cookie() {
+        let cookie = SetCookie::new("SID", "31d4d96e407aad42")
+            .path("/")
+            .domain("example.com")
+            .expires(HttpDate::from_unix_secs(1445412480))
+            .max_age(Duration::from_secs(3600))
+            .secure(true)
+            .http_only(true)
+            .same_site(SameSite::Lax);
+        assert_eq!(
+            cookie.to_header_value().unwrap().as_bytes(),
+            b"SID=31d4d96e407aad42; Expires=Wed, 21 Oct 2015 07:28:00 GMT; \
+              Max-Age=3600; Domain=example.com; Path=/; Secure; HttpOnly; \
+              SameSite=Lax"
+        );
+        assert_eq!(
+            SetCookie::removal("SID").path("/").to_string(),
+            "SID=; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Max-Age=0; Path=/"
+        );
+        let cookie = SetCookie::new("__Host-id", "1")
+            .secure(true)
+            .path("/")
+            .same_site(SameSite::None)
+            .partitioned(true);
+        assert_eq!(
+            cookie.to_header_value().unwrap().as_bytes(),
+            b"__Host-id=1; Path=/; Secure; SameSite=None; Partitioned"
+        );
+    }
+
+    #[test]
+    fn set_cookie_parsing() {
+        let parse = |s: &str| SetCookie::from_bytes(s.as_bytes());
+        assert_eq!(
+            parse(
+                " SID = 31d4 ; path=/docs;DOMAIN=.Example.com; secure; \
+                 HttpOnly;Expires=Wed, 21 Oct 2015 07:28:00 GMT;Max-Age=60; \
+                 SameSite=strict; Partitioned"
+            ),
+            Ok(SetCookie::new("SID", "31d4")
+                .path("/docs")
+                .domain("example.com")
+                .secure(true)
+                .http_only(true)
+                .expires(HttpDate::from_unix_secs(1445412480))
+                .max_age(Duration::from_secs(60))
+                .same_site(SameSite::Strict)
+                .partitioned(true))
+        );
+        // Malformed attributes are ignored and later ones win
+        assert_eq!(
+            parse(
+                "a=\"1\"; Max-Age=1x; Max-Age=-5; Expires=never; Path=docs; \
+                 Domain=; SameSite=sometimes; Version=1; Path=/a; Path=/b"
+            ),
+            Ok(SetCookie::new("a", "\"1\"")
+                .max_age(Duration::ZERO)
+                .path("/b"))
+        );
+        assert_eq!(parse("a=1; Path=docs").unwrap().path, None);
+        let long = format!("a=1; Path=/{}", "x".repeat(1024));
+        assert_eq!(parse(&long).unwrap().path, None);
+        assert_eq!(parse("a=").unwrap(), SetCookie::new("a", ""));
+
+        assert_eq!(parse("a"), Err(CookieError::InvalidName));
+        assert_eq!(parse("=1"), Err(CookieError::InvalidName));
+        assert_eq!(parse("a=1 2"), Err(CookieError::InvalidValue));
+        assert_eq!(parse("a=1\n; Secure"), Err(CookieError::InvalidValue));
+        let parsed = parse(
+            &SetCookie::new("__Host-id", "1")
+                .secure(true)
+                .path("/")
+                .same_site(SameSite::Lax)
+                .to_string(),
+        );
+        assert_eq!(
+            parsed.unwrap().to_string(),
+            "__Host-id=1; Path=/; Secure; SameSite=Lax"
+        );
+    }
+
+    #[test]
+    fn set_cookie_rules() {
+        let secure = |cookie: SetCookie| cookie.secure(true);
+        for (cookie, error) in [
+            (SetCookie::new("a b", "1"), CookieError::InvalidName),
+            (SetCookie::new("a", "1 2"), CookieError::InvalidValue),
+            (
+                SetCookie::new("a", "1").domain(""),
+                CookieError::InvalidDomain,
+            ),
+            (
+                SetCookie::new("a", "1").domain("exa;mple.com"),
+                CookieError::InvalidDomain,
+            ),
+            (SetCookie::new("a", "1").path("x"), CookieError::InvalidPath),
+            (
+                SetCookie::new("a", "1").path("/a;b"),
+                CookieError::InvalidPath,
+            ),
+            (
+                SetCookie::new("a", "1")
+                    .path(&format!("/{}", "a".repeat(1024))),
+                CookieError::InvalidPath,
+            ),
+            (
+                SetCookie::new("__Secure-a", "1"),
+                CookieError::PrefixRequirements,
+            ),
+            // Prefixes are matched without regard to case
+            (
+                SetCookie::new("__SECURE-a", "1"),
+                CookieError::PrefixRequirements,
+            ),
+            (
+                secure(SetCookie::new("__Host-a", "1")),
+                CookieError::PrefixRequirements,
+            ),
+            (
+                secure(SetCookie::new("__Host-a", "1").path("/x")),
+                CookieError::PrefixRequirements,
+            ),
+            (
+                secure(
+                    SetCookie::new("__host-a", "1").path("/").domain("a.com"),
+                ),
+                CookieError::PrefixRequirements,
+            ),
+            (
+                SetCookie::new("a", "1").same_site(SameSite::None),
+                CookieError::RequiresSecure,
+            ),
+            (
+                SetCookie::new("a", "1").partitioned(true),
+                CookieError::RequiresSecure,
+            ),
+        ] {
+            assert_eq!(cookie.to_header_value(), Err(error), "{cookie}");
+        }
+        assert!(secure(SetCookie::new("__Secure-a", "1")).validate().is_ok());
+        assert!(
+            SetCookie::new("a", "1")
+                .domain(".example.com")
+                .validate()
+                .is_ok()
+        );
+    }
+}
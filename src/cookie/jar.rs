@@ -0,0 +1,1110 @@
+use std::io::{self, BufRead, Read, Write};
+use std::net::Ipv4Addr;
+
+use super::{Cookie, SameSite, SetCookie, has_prefix};
+use crate::date::HttpDate;
+use crate::header::{HeaderName, HeaderValue};
+use crate::json::{self, Value};
+use crate::response::Response;
+use crate::uri::Uri;
+use crate::utils::ALLOWED_HOSTNAME_BYTES;
+
+// The minimums RFC 6265bis section 6.1 asks user agents to support.
+const DEFAULT_MAX_PER_DOMAIN: usize = 50;
+const DEFAULT_MAX_COOKIES: usize = 3000;
+// No cookie outlives this, however far in the future it claims to expire
+// (RFC 6265bis section 5.6.1).
+const MAX_LIFETIME_SECS: u64 = 400 * 24 * 60 * 60;
+
+const NETSCAPE_HEADER: &str = "# Netscape HTTP Cookie File";
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+// How a request relates to the site of whatever made it, which decides what
+// SameSite cookies it may send and set (RFC 6265bis section 5.2). A client
+// acting on its own behalf, not for a page, makes same-site requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SiteContext {
+    SameSite,
+    // A cross-site top-level navigation with a safe method, such as following
+    // a link, which gets Lax cookies along
+    CrossSiteNavigation,
+    CrossSite,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StoredCookie {
+    name: String,
+    value: String,
+    // Lowercase, without a leading dot
+    domain: String,
+    // Only sent to `domain` itself, not to its subdomains
+    host_only: bool,
+    path: String,
+    // `None` for session cookies
+    expires: Option<HttpDate>,
+    secure_only: bool,
+    http_only: bool,
+    // `None` is the "Default" enforcement, which is treated as Lax
+    same_site: Option<SameSite>,
+    // When the cookie was last sent, on the jar's own clock
+    last_access: u64,
+}
+
+impl StoredCookie {
+    fn is_expired(&self, now: HttpDate) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    // Cookies with the same name, domain and path replace each other.
+    fn same_key(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.domain == other.domain
+            && self.host_only == other.host_only
+            && self.path == other.path
+    }
+}
+
+// Client-side cookie storage following RFC 6265bis section 5.7, with
+// retrieval as in section 5.8.
+//
+// Cookies are kept in creation order, and a cookie that replaces another
+// takes over its place. Past the per-domain or total limit, the cookies that
+// have gone unused the longest are evicted first. There is no public suffix
+// list: a Domain attribute has to name the request's host or a parent
+// domain of at least two labels, which keeps out "com" but not "co.uk".
+//
+// Jars can be saved to and loaded from a Netscape `cookies.txt` file, as
+// read and written by curl and wget, or a JSON file, which also keeps the
+// SameSite attribute. Both include session cookies; call
+// `clear_session_cookies` after loading to start a new session.
+#[derive(Debug, Clone)]
+pub struct CookieJar {
+    cookies: Vec<StoredCookie>,
+    max_per_domain: usize,
+    max_cookies: usize,
+    clock: u64,
+}
+
+fn is_ip_address(host: &str) -> bool {
+    host.parse::<Ipv4Addr>().is_ok()
+}
+
+// domain-match of RFC 6265bis section 5.1.3
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.len() > domain.len()
+            && host.ends_with(domain)
+            && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+            && !is_ip_address(host))
+}
+
+// path-match of RFC 6265bis section 5.1.4
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/')
+                || request_path.as_bytes()[cookie_path.len()] == b'/'))
+}
+
+// default-path of RFC 6265bis section 5.1.4: the directory of the request
+// path.
+fn default_path(request_path: &str) -> &str {
+    match request_path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &request_path[..i],
+    }
+}
+
+fn is_secure(uri: &Uri) -> bool {
+    uri.scheme()
+        .is_some_and(|scheme| matches!(scheme.as_str(), "https" | "wss"))
+}
+
+// Limits an expiry to the longest lifetime a cookie may have from `now`.
+fn clamp_expiry(expires: Option<HttpDate>, now: HttpDate) -> Option<HttpDate> {
+    let latest = HttpDate::from_unix_secs(
+        now.unix_secs().saturating_add(MAX_LIFETIME_SECS),
+    );
+    expires.map(|expires| expires.min(latest))
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self {
+            cookies: Vec::new(),
+            max_per_domain: DEFAULT_MAX_PER_DOMAIN,
+            max_cookies: DEFAULT_MAX_COOKIES,
+            clock: 0,
+        }
+    }
+
+    pub fn max_per_domain(mut self, max: usize) -> Self {
+        self.max_per_domain = max;
+        self
+    }
+
+    pub fn max_cookies(mut self, max: usize) -> Self {
+        self.max_cookies = max;
+        self
+    }
+
+    // The number of cookies stored, including any that have expired since.
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.cookies.clear();
+    }
+
+    // Forgets the cookies without an expiry, as a user agent does when the
+    // session ends.
+    pub fn clear_session_cookies(&mut self) {
+        self.cookies.retain(|cookie| cookie.expires.is_some());
+    }
+
+    // Stores a cookie received in a response to a request for `uri`, or
+    // removes the stored one if the cookie has already expired. Returns
+    // false if the cookie was rejected.
+    pub fn store(
+        &mut self,
+        cookie: &SetCookie,
+        uri: &Uri,
+        context: SiteContext,
+    ) -> bool {
+        self.store_at(cookie, uri, context, HttpDate::now())
+    }
+
+    // Stores every valid `Set-Cookie` of the response. Returns how many were
+    // accepted.
+    pub fn store_response(
+        &mut self,
+        response: &Response,
+        uri: &Uri,
+        context: SiteContext,
+    ) -> usize {
+        let now = HttpDate::now();
+        response
+            .headers()
+            .get_all(HeaderName::SET_COOKIE)
+            .filter_map(|value| SetCookie::from_bytes(value.as_bytes()).ok())
+            .filter(|cookie| self.store_at(cookie, uri, context, now))
+            .count()
+    }
+
+    fn store_at(
+        &mut self,
+        cookie: &SetCookie,
+        uri: &Uri,
+        context: SiteContext,
+        now: HttpDate,
+    ) -> bool {
+        let Some(host) = uri.authority().map(|a| a.host()) else {
+            return false;
+        };
+        let secure_uri = is_secure(uri);
+
+        let (domain, host_only) = match &cookie.domain {
+            Some(domain) => {
+                let domain = domain.to_ascii_lowercase();
+                let domain =
+                    domain.strip_prefix('.').unwrap_or(&domain).to_owned();
+                // Without a public suffix list, at least keep cookies off
+                // top-level domains
+                if !domain_matches(host, &domain)
+                    || (domain != host && !domain.contains('.'))
+                {
+                    return false;
+                }
+                (domain, false)
+            }
+            None => (host.to_owned(), true),
+        };
+        let path = match &cookie.path {
+            Some(path) if path.starts_with('/') => path.clone(),
+            _ => default_path(uri.path().as_str()).to_owned(),
+        };
+        if cookie.secure && !secure_uri {
+            return false;
+        }
+        // As `SetCookie::validate` insists on
+        if cookie.same_site == Some(SameSite::None) && !cookie.secure {
+            return false;
+        }
+        // Responses to cross-site requests only get to set cookies meant for
+        // cross-site use, except for top-level navigations
+        if context == SiteContext::CrossSite
+            && cookie.same_site != Some(SameSite::None)
+        {
+            return false;
+        }
+        if has_prefix(&cookie.name, "__Secure-") && !cookie.secure {
+            return false;
+        }
+        if has_prefix(&cookie.name, "__Host-")
+            && (!cookie.secure
+                || !host_only
+                || cookie.path.as_deref() != Some("/"))
+        {
+            return false;
+        }
+        // An insecure origin may not overwrite, or shadow, a secure cookie
+        if !secure_uri
+            && self.cookies.iter().any(|c| {
+                c.secure_only
+                    && c.name == cookie.name
+                    && (domain_matches(&c.domain, &domain)
+                        || domain_matches(&domain, &c.domain))
+                    && path_matches(&path, &c.path)
+            })
+        {
+            return false;
+        }
+
+        // Max-Age takes precedence over Expires
+        let expires = match (cookie.max_age, cookie.expires) {
+            (Some(max_age), _) => Some(HttpDate::from_unix_secs(
+                now.unix_secs().saturating_add(max_age.as_secs()),
+            )),
+            (None, expires) => expires,
+        };
+        let expires = clamp_expiry(expires, now);
+        let stored = StoredCookie {
+            name: cookie.name.clone(),
+            value: cookie.value.clone(),
+            domain,
+            host_only,
+            path,
+            expires,
+            secure_only: cookie.secure,
+            http_only: cookie.http_only,
+            same_site: cookie.same_site,
+            last_access: self.tick(),
+        };
+        self.insert(stored, now);
+        true
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    // Replaces a cookie with the same key, keeping its place, or adds the
+    // cookie at the end. Expired cookies only remove.
+    fn insert(&mut self, cookie: StoredCookie, now: HttpDate) {
+        let existing = self.cookies.iter().position(|c| c.same_key(&cookie));
+        match existing {
+            Some(i) if cookie.is_expired(now) => {
+                self.cookies.remove(i);
+            }
+            Some(i) => self.cookies[i] = cookie,
+            None if cookie.is_expired(now) => {}
+            None => {
+                let domain = cookie.domain.clone();
+                self.cookies.push(cookie);
+                self.evict(&domain, now);
+            }
+        }
+    }
+
+    fn evict(&mut self, domain: &str, now: HttpDate) {
+        let in_domain = |c: &StoredCookie| c.domain == domain;
+        if self.cookies.iter().filter(|c| in_domain(c)).count()
+            > self.max_per_domain
+            || self.cookies.len() > self.max_cookies
+        {
+            self.cookies.retain(|c| !c.is_expired(now));
+        }
+        while self.cookies.iter().filter(|c| in_domain(c)).count()
+            > self.max_per_domain
+        {
+            self.evict_least_recently_used(in_domain);
+        }
+        while self.cookies.len() > self.max_cookies {
+            self.evict_least_recently_used(|_| true);
+        }
+    }
+
+    fn evict_least_recently_used(
+        &mut self,
+        filter: impl Fn(&StoredCookie) -> bool,
+    ) {
+        let oldest = self
+            .cookies
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| filter(c))
+            .min_by_key(|(_, c)| c.last_access)
+            .map(|(i, _)| i);
+        if let Some(i) = oldest {
+            self.cookies.remove(i);
+        }
+    }
+
+    // The cookies to send with a request for `uri`, those with longer paths
+    // first and older ones before newer ones otherwise.
+    pub fn cookies(&mut self, uri: &Uri, context: SiteContext) -> Vec<Cookie> {
+        self.cookies_at(uri, context, HttpDate::now())
+    }
+
+    // The `Cookie` header for a request for `uri`, if there are any cookies
+    // to send.
+    pub fn header_value(
+        &mut self,
+        uri: &Uri,
+        context: SiteContext,
+    ) -> Option<HeaderValue> {
+        let cookies = self.cookies(uri, context);
+        if cookies.is_empty() {
+            return None;
+        }
+        let header = cookies
+            .iter()
+            .map(Cookie::to_string)
+            .collect::<Vec<_>>()
+            .join("; ");
+        Some(
+            HeaderValue::from_bytes(header.as_bytes())
+                .expect("stored cookies are valid header values"),
+        )
+    }
+
+    fn cookies_at(
+        &mut self,
+        uri: &Uri,
+        context: SiteContext,
+        now: HttpDate,
+    ) -> Vec<Cookie> {
+        self.cookies.retain(|c| !c.is_expired(now));
+        let Some(host) = uri.authority().map(|a| a.host()) else {
+            return Vec::new();
+        };
+        let secure_uri = is_secure(uri);
+        let path = uri.path().as_str();
+        let clock = self.tick();
+        let mut matching: Vec<&mut StoredCookie> = self
+            .cookies
+            .iter_mut()
+            .filter(|c| {
+                let domain_ok = if c.host_only {
+                    host == c.domain
+                } else {
+                    domain_matches(host, &c.domain)
+                };
+                let same_site_ok = match context {
+                    SiteContext::SameSite => true,
+                    SiteContext::CrossSiteNavigation => {
+                        c.same_site != Some(SameSite::Strict)
+                    }
+                    SiteContext::CrossSite => {
+                        c.same_site == Some(SameSite::None)
+                    }
+                };
+                domain_ok
+                    && path_matches(path, &c.path)
+                    && (secure_uri || !c.secure_only)
+                    && same_site_ok
+            })
+            .collect();
+        // Stable, so creation order breaks ties
+        matching.sort_by_key(|c| std::cmp::Reverse(c.path.len()));
+        matching
+            .into_iter()
+            .map(|c| {
+                c.last_access = clock;
+                Cookie {
+                    name: c.name.clone(),
+                    value: c.value.clone(),
+                }
+            })
+            .collect()
+    }
+
+    // One cookie per line: domain, whether subdomains match, path, whether
+    // the cookie is secure, expiry in Unix time (0 for session cookies), name
+    // and value, separated by tabs. HttpOnly cookies have their line
+    // prefixed with "#HttpOnly_", as curl does.
+    pub fn write_netscape<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{NETSCAPE_HEADER}")?;
+        let flag = |b: bool| if b { "TRUE" } else { "FALSE" };
+        for c in &self.cookies {
+            writeln!(
+                writer,
+                "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                if c.http_only { HTTP_ONLY_PREFIX } else { "" },
+                if c.host_only { "" } else { "." },
+                c.domain,
+                flag(!c.host_only),
+                c.path,
+                flag(c.secure_only),
+                c.expires.map_or(0, |date| date.unix_secs()),
+                c.name,
+                c.value
+            )?;
+        }
+        writer.flush()
+    }
+
+    // Adds the cookies of a Netscape `cookies.txt` file, skipping expired
+    // ones. Lines that cannot be read fail the whole file.
+    pub fn read_netscape<R: BufRead>(&mut self, reader: R) -> io::Result<()> {
+        let now = HttpDate::now();
+        let mut cookies = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let (line, http_only) = match line.strip_prefix(HTTP_ONLY_PREFIX) {
+                Some(line) => (line, true),
+                None => (line.as_str(), false),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let cookie =
+                parse_netscape_line(line, http_only).ok_or_else(|| {
+                    invalid_data(format!("invalid cookie on line {}", i + 1))
+                })?;
+            cookies.push(cookie);
+        }
+        for cookie in cookies {
+            self.load(cookie, now);
+        }
+        Ok(())
+    }
+
+    // A JSON array with an object per cookie, in creation order.
+    pub fn write_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(b"[")?;
+        for (i, c) in self.cookies.iter().enumerate() {
+            let expires = c.expires.map_or_else(
+                || "null".into(),
+                |date| date.unix_secs().to_string(),
+            );
+            let same_site = c.same_site.map_or_else(
+                || "null".into(),
+                |same_site| json::string(same_site.as_str()),
+            );
+            write!(
+                writer,
+                "{}\n  {{\"name\":{},\"value\":{},\"domain\":{},\
+                 \"host_only\":{},\"path\":{},\"expires\":{},\"secure\":{},\
+                 \"http_only\":{},\"same_site\":{}}}",
+                if i > 0 { "," } else { "" },
+                json::string(&c.name),
+                json::string(&c.value),
+                json::string(&c.domain),
+                c.host_only,
+                json::string(&c.path),
+                expires,
+                c.secure_only,
+                c.http_only,
+                same_site
+            )?;
+        }
+        writer.write_all(b"\n]\n")?;
+        writer.flush()
+    }
+
+    // Adds the cookies of a file written by `write_json`, skipping expired
+    // ones.
+    pub fn read_json<R: Read>(&mut self, mut reader: R) -> io::Result<()> {
+        let mut s = String::new();
+        reader.read_to_string(&mut s)?;
+        let value = Value::parse(&s)
+            .ok_or_else(|| invalid_data("invalid JSON".into()))?;
+        let entries = value.as_array().ok_or_else(|| {
+            invalid_data("expected an array of cookies".into())
+        })?;
+        let cookies = entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                parse_json_cookie(entry)
+                    .ok_or_else(|| invalid_data(format!("invalid cookie #{i}")))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let now = HttpDate::now();
+        for cookie in cookies {
+            self.load(cookie, now);
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, mut cookie: StoredCookie, now: HttpDate) {
+        // Left out rather than inserted, which would delete a live cookie
+        // of the same name that the jar already holds
+        if cookie.is_expired(now) {
+            return;
+        }
+        cookie.expires = clamp_expiry(cookie.expires, now);
+        cookie.last_access = self.tick();
+        self.insert(cookie, now);
+    }
+}
+
+impl Default for CookieJar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Checks what a file claims about a cookie, so that a loaded jar holds
+// nothing a response could not have put there.
+fn checked_cookie(cookie: StoredCookie) -> Option<StoredCookie> {
+    Cookie::new(&cookie.name, &cookie.value).ok()?;
+    let valid_domain = !cookie.domain.is_empty()
+        && cookie
+            .domain
+            .bytes()
+            .all(|b| ALLOWED_HOSTNAME_BYTES.contains(&b));
+    let valid_path = cookie.path.starts_with('/')
+        && cookie
+            .path
+            .bytes()
+            .all(|b| b != b';' && !b.is_ascii_control());
+    (valid_domain && valid_path).then_some(cookie)
+}
+
+fn parse_netscape_line(line: &str, http_only: bool) -> Option<StoredCookie> {
+    let mut fields = line.split('\t');
+    let mut next = || fields.next();
+    let domain = next()?;
+    let include_subdomains = next()?;
+    let path = next()?;
+    let secure = next()?;
+    let expires = next()?;
+    let name = next()?;
+    // curl leaves out the tab before an empty value
+    let value = next().unwrap_or_default();
+    if next().is_some() {
+        return None;
+    }
+    let flag = |s: &str| match s {
+        "TRUE" => Some(true),
+        "FALSE" => Some(false),
+        _ => None,
+    };
+    let expires: u64 = expires.parse().ok()?;
+    checked_cookie(StoredCookie {
+        name: name.into(),
+        value: value.into(),
+        domain: domain
+            .strip_prefix('.')
+            .unwrap_or(domain)
+            .to_ascii_lowercase(),
+        host_only: !flag(include_subdomains)?,
+        path: path.into(),
+        expires: (expires != 0).then(|| HttpDate::from_unix_secs(expires)),
+        secure_only: flag(secure)?,
+        http_only,
+        same_site: None,
+        last_access: 0,
+    })
+}
+
+fn parse_json_cookie(entry: &Value) -> Option<StoredCookie> {
+    let string = |key| entry.get(key)?.as_str().map(String::from);
+    let flag = |key| entry.get(key)?.as_bool();
+    let expires = match entry.get("expires")? {
+        Value::Null => None,
+        value => Some(HttpDate::from_unix_secs(value.as_u64()?)),
+    };
+    let same_site = match entry.get("same_site")? {
+        Value::Null => None,
+        value => Some(match value.as_str()? {
+            "Strict" => SameSite::Strict,
+            "Lax" => SameSite::Lax,
+            "None" => SameSite::None,
+            _ => return None,
+        }),
+    };
+    checked_cookie(StoredCookie {
+        name: string("name")?,
+        value: string("value")?,
+        domain: string("domain")?.to_ascii_lowercase(),
+        host_only: flag("host_only")?,
+        path: string("path")?,
+        expires,
+        secure_only: flag("secure")?,
+        http_only: flag("http_only")?,
+        same_site,
+        last_access: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::status::StatusCode;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn uri(s: &str) -> Uri {
+        s.parse().unwrap()
+    }
+
+    fn set(s: &str) -> SetCookie {
+        SetCookie::from_bytes(s.as_bytes()).unwrap()
+    }
+
+    fn store(jar: &mut CookieJar, cookie: &str, url: &str) -> bool {
+        jar.store_at(
+            &set(cookie),
+            &uri(url),
+            SiteContext::SameSite,
+            HttpDate::from_unix_secs(NOW),
+        )
+    }
+
+    fn header(jar: &mut CookieJar, url: &str, context: SiteContext) -> String {
+        jar.cookies_at(&uri(url), context, HttpDate::from_unix_secs(NOW))
+            .iter()
+            .map(Cookie::to_string)
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    fn get(jar: &mut CookieJar, url: &str) -> String {
+        header(jar, url, SiteContext::SameSite)
+    }
+
+    #[test]
+    fn domains() {
+        let mut jar = CookieJar::new();
+        assert!(store(&mut jar, "host=1", "http://www.example.com/"));
+        assert!(store(
+            &mut jar,
+            "sub=2; Domain=.Example.COM",
+            "http://www.example.com/"
+        ));
+        assert_eq!(get(&mut jar, "http://www.example.com/"), "host=1; sub=2");
+        assert_eq!(get(&mut jar, "http://example.com/"), "sub=2");
+        assert_eq!(get(&mut jar, "http://a.b.example.com/"), "sub=2");
+        assert_eq!(get(&mut jar, "http://a.www.example.com/"), "sub=2");
+        assert_eq!(get(&mut jar, "http://notexample.com/"), "");
+        assert_eq!(get(&mut jar, "http://example.org/"), "");
+
+        // Only the host itself or a parent domain, and not a top-level one
+        for cookie in [
+            "a=1; Domain=other.com",
+            "a=1; Domain=a.www.example.com",
+            "a=1; Domain=ample.com",
+            "a=1; Domain=com",
+        ] {
+            assert!(
+                !store(&mut jar, cookie, "http://www.example.com/"),
+                "{cookie}"
+            );
+        }
+        // IP addresses only match exactly
+        assert!(store(
+            &mut jar,
+            "ip=1; Domain=127.0.0.1",
+            "http://127.0.0.1/"
+        ));
+        assert!(!store(&mut jar, "ip=2; Domain=0.0.1", "http://127.0.0.1/"));
+        assert_eq!(get(&mut jar, "http://127.0.0.1:8080/"), "ip=1");
+        assert!(!store(&mut jar, "a=1", "/relative"));
+    }
+
+    #[test]
+    fn paths() {
+        let mut jar = CookieJar::new();
+        store(&mut jar, "root=1; Path=/", "http://a.com/");
+        store(&mut jar, "docs=2; Path=/docs", "http://a.com/");
+        store(&mut jar, "slash=3; Path=/docs/", "http://a.com/");
+        // The default path is the directory of the request
+        store(&mut jar, "default=4", "http://a.com/docs/web/page.html?x=1");
+        store(&mut jar, "top=5", "http://a.com/page");
+        store(&mut jar, "bad=6; Path=docs", "http://a.com/x/y");
+
+        assert_eq!(get(&mut jar, "http://a.com/"), "root=1; top=5");
+        assert_eq!(get(&mut jar, "http://a.com/docs"), "docs=2; root=1; top=5");
+        assert_eq!(
+            get(&mut jar, "http://a.com/docs/web/x"),
+            "default=4; slash=3; docs=2; root=1; top=5"
+        );
+        assert_eq!(get(&mut jar, "http://a.com/docsx"), "root=1; top=5");
+        assert_eq!(get(&mut jar, "http://a.com/x/y"), "bad=6; root=1; top=5");
+    }
+
+    #[test]
+    fn replacing_and_expiry() {
+        let mut jar = CookieJar::new();
+        store(&mut jar, "a=1", "http://a.com/");
+        store(&mut jar, "b=2; Max-Age=60", "http://a.com/");
+        store(&mut jar, "a=3", "http://a.com/");
+        // Same name, different path: a separate cookie
+        store(&mut jar, "a=4; Path=/x", "http://a.com/");
+        assert_eq!(get(&mut jar, "http://a.com/x"), "a=4; a=3; b=2");
+        assert_eq!(jar.len(), 3);
+
+        let later = HttpDate::from_unix_secs(NOW + 60);
+        assert_eq!(
+            jar.cookies_at(&uri("http://a.com/"), SiteContext::SameSite, later),
+            [Cookie::new("a", "3").unwrap()]
+        );
+        assert_eq!(jar.len(), 2);
+
+        // Removal with an expired cookie, by Max-Age or Expires
+        store(&mut jar, "b=2; Max-Age=60", "http://a.com/");
+        assert!(store(&mut jar, "a=; Max-Age=0", "http://a.com/"));
+        assert!(store(
+            &mut jar,
+            "b=; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+            "http://a.com/"
+        ));
+        assert_eq!(get(&mut jar, "http://a.com/x"), "a=4");
+        // Max-Age wins over Expires
+        store(
+            &mut jar,
+            "c=1; Max-Age=10; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+            "http://a.com/",
+        );
+        assert_eq!(get(&mut jar, "http://a.com/"), "c=1");
+
+        store(&mut jar, "d=1; Max-Age=10", "http://a.com/");
+        jar.clear_session_cookies();
+        assert_eq!(get(&mut jar, "http://a.com/x"), "c=1; d=1");
+        jar.clear();
+        assert!(jar.is_empty());
+    }
+
+    #[test]
+    fn lifetime_limit() {
+        let latest = HttpDate::from_unix_secs(NOW + 400 * 24 * 60 * 60);
+        let mut jar = CookieJar::new();
+        store(
+            &mut jar,
+            "a=1; Max-Age=99999999999999999999",
+            "http://a.com/",
+        );
+        store(
+            &mut jar,
+            "b=1; Expires=Wed, 01 Jan 2200 00:00:00 GMT",
+            "http://a.com/",
+        );
+        store(&mut jar, "c=1; Max-Age=60", "http://a.com/");
+        let expires: Vec<_> = jar.cookies.iter().map(|c| c.expires).collect();
+        assert_eq!(
+            expires,
+            [
+                Some(latest),
+                Some(latest),
+                Some(HttpDate::from_unix_secs(NOW + 60))
+            ]
+        );
+        let later = HttpDate::from_unix_secs(latest.unix_secs() - 1);
+        assert_eq!(
+            jar.cookies_at(&uri("http://a.com/"), SiteContext::SameSite, later)
+                .len(),
+            2
+        );
+
+        // Files are no way around it
+        let now = HttpDate::now().unix_secs();
+        for file in [
+            "a.com\tFALSE\t/\tFALSE\t18446744073709551615\ta\t1\n",
+            "[{\"name\":\"a\",\"value\":\"1\",\"domain\":\"a.com\",\
+             \"host_only\":true,\"path\":\"/\",\
+             \"expires\":18446744073709551615,\"secure\":false,\
+             \"http_only\":false,\"same_site\":null}]",
+        ] {
+            let mut jar = CookieJar::new();
+            if file.starts_with('[') {
+                jar.read_json(file.as_bytes()).unwrap();
+            } else {
+                jar.read_netscape(file.as_bytes()).unwrap();
+            }
+            let expires = jar.cookies[0].expires.unwrap().unix_secs();
+            assert!(expires <= now + 401 * 24 * 60 * 60, "{expires}");
+            assert!(expires >= now + 400 * 24 * 60 * 60, "{expires}");
+        }
+    }
+
+    #[test]
+    fn security() {
+        let mut jar = CookieJar::new();
+        assert!(!store(&mut jar, "s=1; Secure", "http://a.com/"));
+        assert!(store(&mut jar, "s=1; Secure", "https://a.com/"));
+        assert_eq!(get(&mut jar, "http://a.com/"), "");
+        assert_eq!(get(&mut jar, "https://a.com/"), "s=1");
+        // Insecure origins can neither overwrite nor shadow secure cookies
+        assert!(!store(&mut jar, "s=2", "http://a.com/"));
+        assert!(!store(&mut jar, "s=2; Path=/x", "http://a.com/"));
+        assert!(!store(&mut jar, "s=2; Domain=a.com", "http://www.a.com/"));
+        assert!(store(&mut jar, "s=2; Path=/x", "https://a.com/"));
+
+        let https = "https://a.com/x/y";
+        assert!(!store(&mut jar, "__Secure-a=1", https));
+        assert!(store(&mut jar, "__Secure-a=1; Secure", https));
+        assert!(!store(&mut jar, "__Host-a=1; Secure", https));
+        assert!(!store(
+            &mut jar,
+            "__Host-a=1; Secure; Path=/; Domain=a.com",
+            https
+        ));
+        assert!(!store(&mut jar, "__Host-a=1; Path=/", https));
+        assert!(store(&mut jar, "__Host-a=1; Secure; Path=/", https));
+        assert!(!store(&mut jar, "n=1; SameSite=None", https));
+        assert!(store(&mut jar, "n=1; SameSite=None; Secure", https));
+
+        store(&mut jar, "h=1; HttpOnly", "http://a.com/");
+        assert_eq!(get(&mut jar, "http://a.com/"), "h=1");
+    }
+
+    #[test]
+    fn same_site() {
+        let mut jar = CookieJar::new();
+        let https = "https://a.com/";
+        store(&mut jar, "strict=1; SameSite=Strict", https);
+        store(&mut jar, "lax=2; SameSite=Lax", https);
+        store(&mut jar, "default=3", https);
+        store(&mut jar, "none=4; SameSite=None; Secure", https);
+        assert_eq!(
+            header(&mut jar, https, SiteContext::SameSite),
+            "strict=1; lax=2; default=3; none=4"
+        );
+        assert_eq!(
+            header(&mut jar, https, SiteContext::CrossSiteNavigation),
+            "lax=2; default=3; none=4"
+        );
+        assert_eq!(header(&mut jar, https, SiteContext::CrossSite), "none=4");
+
+        // Cross-site responses only set cookies meant for cross-site use
+        let now = HttpDate::from_unix_secs(NOW);
+        let mut store_in = |cookie, context| {
+            jar.store_at(&set(cookie), &uri(https), context, now)
+        };
+        assert!(!store_in("a=1", SiteContext::CrossSite));
+        assert!(!store_in("a=1; SameSite=Lax", SiteContext::CrossSite));
+        assert!(store_in(
+            "a=1; SameSite=None; Secure",
+            SiteContext::CrossSite
+        ));
+        assert!(store_in(
+            "b=1; SameSite=Strict",
+            SiteContext::CrossSiteNavigation
+        ));
+    }
+
+    #[test]
+    fn eviction() {
+        let mut jar = CookieJar::new().max_per_domain(3).max_cookies(5);
+        for i in 0..3 {
+            store(&mut jar, &format!("a{i}=1; Path=/{i}"), "http://a.com/");
+        }
+        // Using a0 makes a1 the least recently used
+        assert_eq!(get(&mut jar, "http://a.com/0"), "a0=1");
+        store(&mut jar, "a3=1; Path=/3", "http://a.com/");
+        assert_eq!(jar.len(), 3);
+        assert_eq!(get(&mut jar, "http://a.com/1"), "");
+        assert_eq!(get(&mut jar, "http://a.com/0"), "a0=1");
+
+        // Expired cookies go first, then the least recently used overall
+        store(&mut jar, "b0=1; Max-Age=1", "http://b.com/");
+        store(&mut jar, "b1=1", "http://b.com/");
+        let later = HttpDate::from_unix_secs(NOW + 5);
+        let b2 = set("b2=1");
+        jar.store_at(&b2, &uri("http://b.com/"), SiteContext::SameSite, later);
+        assert_eq!(jar.len(), 5);
+        assert_eq!(get(&mut jar, "http://b.com/"), "b1=1; b2=1");
+        store(&mut jar, "c0=1", "http://c.com/");
+        assert_eq!(jar.len(), 5);
+        assert_eq!(get(&mut jar, "http://a.com/2"), "");
+        assert_eq!(get(&mut jar, "http://a.com/3"), "a3=1");
+        assert_eq!(get(&mut jar, "http://c.com/"), "c0=1");
+    }
+
+    #[test]
+    fn headers() {
+        let mut jar = CookieJar::new();
+        let url = uri("http://a.com/");
+        let response = Response::new(StatusCode::OK)
+            .with_header(HeaderName::SET_COOKIE, "a=1".parse().unwrap())
+            .with_header(HeaderName::SET_COOKIE, "b=2; Path=/".parse().unwrap())
+            .with_header(HeaderName::SET_COOKIE, "bad".parse().unwrap())
+            .with_header(
+                HeaderName::SET_COOKIE,
+                "s=1; Secure".parse().unwrap(),
+            );
+        assert_eq!(
+            jar.store_response(&response, &url, SiteContext::SameSite),
+            2
+        );
+        assert_eq!(
+            jar.header_value(&url, SiteContext::SameSite)
+                .unwrap()
+                .as_bytes(),
+            b"a=1; b=2"
+        );
+        assert_eq!(
+            jar.header_value(&uri("http://b.com/"), SiteContext::SameSite),
+            None
+        );
+        assert!(jar.store(
+            &SetCookie::new("c", "3").max_age(Duration::from_secs(60)),
+            &url,
+            SiteContext::SameSite
+        ));
+        assert_eq!(jar.cookies(&url, SiteContext::SameSite).len(), 3);
+    }
+
+    // Stored at the current time, so the cookies have not expired by the
+    // time they are loaded again.
+    fn sample_jar() -> CookieJar {
+        let mut jar = CookieJar::new();
+        let mut store = |cookie: &str, url: &str| {
+            jar.store(&set(cookie), &uri(url), SiteContext::SameSite);
+        };
+        let expires = "Expires=Wed, 01 Jan 2200 00:00:00 GMT";
+        store("session=abc; HttpOnly", "http://www.example.com/app/x");
+        store(
+            &format!("pref=\"dark\"; Domain=example.com; {expires}"),
+            "http://www.example.com/",
+        );
+        store(
+            &format!("s=1; Secure; SameSite=Strict; {expires}"),
+            "https://example.com/",
+        );
+        store("empty=", "http://example.com/");
+        jar
+    }
+
+    #[test]
+    fn netscape_files() {
+        let jar = sample_jar();
+        let expires = jar.cookies[1].expires.unwrap().unix_secs();
+        let mut file = Vec::new();
+        jar.write_netscape(&mut file).unwrap();
+        assert_eq!(
+            String::from_utf8(file.clone()).unwrap(),
+            format!(
+                "# Netscape HTTP Cookie File\n\
+                 #HttpOnly_www.example.com\tFALSE\t/app\tFALSE\t0\tsession\tabc\n\
+                 .example.com\tTRUE\t/\tFALSE\t{expires}\tpref\t\"dark\"\n\
+                 example.com\tFALSE\t/\tTRUE\t{expires}\ts\t1\n\
+                 example.com\tFALSE\t/\tFALSE\t0\tempty\t\n"
+            )
+        );
+        let mut loaded = CookieJar::new();
+        loaded.read_netscape(&file[..]).unwrap();
+        // SameSite does not survive the format
+        let mut expected = jar.cookies.clone();
+        expected[2].same_site = None;
+        let strip = |cookies: &[StoredCookie]| {
+            cookies
+                .iter()
+                .map(|c| StoredCookie {
+                    last_access: 0,
+                    ..c.clone()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(strip(&loaded.cookies), strip(&expected));
+
+        // As written by curl, without the tab before an empty value
+        let mut loaded = CookieJar::new();
+        loaded
+            .read_netscape(
+                &b"# comment\n\n.A.com\tTRUE\t/\tFALSE\t0\ta\n\
+                   b.com\tFALSE\t/\tFALSE\t1\told\t1\n"[..],
+            )
+            .unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(get(&mut loaded, "http://www.a.com/"), "a=");
+
+        // An expired entry leaves a live cookie of the same name alone
+        assert!(store(&mut loaded, "old=2", "http://b.com/"));
+        loaded
+            .read_netscape(&b"b.com\tFALSE\t/\tFALSE\t1\told\t1\n"[..])
+            .unwrap();
+        assert_eq!(get(&mut loaded, "http://b.com/"), "old=2");
+
+        for invalid in [
+            "a.com\tTRUE\t/\tFALSE\t0",
+            "a.com\tYES\t/\tFALSE\t0\ta\t1",
+            "a.com\tTRUE\t/\tFALSE\tsoon\ta\t1",
+            "a.com\tTRUE\tx\tFALSE\t0\ta\t1",
+            "a.com\tTRUE\t/\tFALSE\t0\ta b\t1",
+            "a.com\tTRUE\t/\tFALSE\t0\ta\t1 2",
+            "a.com\tTRUE\t/\tFALSE\t0\ta\t1\textra",
+            "\tTRUE\t/\tFALSE\t0\ta\t1",
+        ] {
+            let error = CookieJar::new()
+                .read_netscape(invalid.as_bytes())
+                .unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn json_files() {
+        let jar = sample_jar();
+        let mut file = Vec::new();
+        jar.write_json(&mut file).unwrap();
+        let file = String::from_utf8(file).unwrap();
+        assert!(file.starts_with(
+            "[\n  {\"name\":\"session\",\"value\":\"abc\",\
+             \"domain\":\"www.example.com\",\"host_only\":true,\
+             \"path\":\"/app\",\"expires\":null,\"secure\":false,\
+             \"http_only\":true,\"same_site\":null},\n"
+        ));
+        let mut loaded = CookieJar::new();
+        loaded.read_json(file.as_bytes()).unwrap();
+        let strip = |cookies: &[StoredCookie]| {
+            cookies
+                .iter()
+                .map(|c| StoredCookie {
+                    last_access: 0,
+                    ..c.clone()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(strip(&loaded.cookies), strip(&jar.cookies));
+
+        let mut empty = Vec::new();
+        CookieJar::new().write_json(&mut empty).unwrap();
+        assert_eq!(empty, b"[\n]\n");
+        loaded.read_json(&empty[..]).unwrap();
+
+        // An expired entry leaves a live cookie of the same name alone
+        let mut loaded = CookieJar::new();
+        assert!(store(&mut loaded, "old=2", "http://b.com/"));
+        loaded
+            .read_json(
+                &b"[{\"name\":\"old\",\"value\":\"1\",\"domain\":\"b.com\",\
+                   \"host_only\":true,\"path\":\"/\",\"expires\":1,\
+                   \"secure\":false,\"http_only\":false,\"same_site\":null}]"[..],
+            )
+            .unwrap();
+        assert_eq!(get(&mut loaded, "http://b.com/"), "old=2");
+
+        for invalid in [
+            "",
+            "{}",
+            "[1]",
+            "[{\"name\":\"a\"}]",
+            "[{\"name\":\"a b\",\"value\":\"1\",\"domain\":\"a.com\",\
+             \"host_only\":true,\"path\":\"/\",\"expires\":null,\
+             \"secure\":false,\"http_only\":false,\"same_site\":null}]",
+            "[{\"name\":\"a\",\"value\":\"1\",\"domain\":\"a.com\",\
+             \"host_only\":true,\"path\":\"/\",\"expires\":null,\
+             \"secure\":false,\"http_only\":false,\"same_site\":\"Loose\"}]",
+        ] {
+            let error =
+                CookieJar::new().read_json(invalid.as_bytes()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::fmt;
+
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce, Tag};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::{Cookie, SetCookie};
+use crate::utils::{base64_decode, base64_encode};
+
+const TAG_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+// Base64 of a 32-byte HMAC
+const SIGNATURE_LEN: usize = 44;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidKey;
+
+impl fmt::Display for InvalidKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("cookie keys need at least 32 bytes")
+    }
+}
+
+impl std::error::Error for InvalidKey {}
+
+// A secret for signing and encrypting cookies. Separate keys for each use
+// are derived from the master key with HMAC-SHA256, so one master key can
+// do both.
+//
+// Signed cookies carry their value in the clear, prefixed with a base64
+// HMAC-SHA256 of the name and value. Encrypted cookies are sealed with
+// ChaCha20-Poly1305, with the name as associated data so that a value cannot
+// be moved to another cookie. The nonce is derived from the name and value
+// (like in SIV modes) rather than drawn at random, which means encrypting
+// the same cookie twice gives the same value: an observer learns when a
+// cookie has not changed, but nothing else.
+#[derive(Clone)]
+pub struct Key {
+    signing: [u8; 32],
+    encryption: [u8; 32],
+    nonce: [u8; 32],
+}
+
+impl Key {
+    // The master key should come from a secure random source and hold at
+    // least 32 bytes.
+    pub fn from_bytes(master: &[u8]) -> Result<Self, InvalidKey> {
+        if master.len() < 32 {
+            return Err(InvalidKey);
+        }
+        let derive = |purpose: &[u8]| {
+            hmac(master, &[b"websurfer cookie ", purpose])
+                .finalize()
+                .into_bytes()
+                .into()
+        };
+        Ok(Self {
+            signing: derive(b"signing"),
+            encryption: derive(b"encryption"),
+            nonce: derive(b"nonce"),
+        })
+    }
+
+    fn signature(&self, name: &str, value: &str) -> HmacSha256 {
+        hmac(&self.signing, &[name.as_bytes(), b"=", value.as_bytes()])
+    }
+
+    // Prefixes the value of the cookie with its signature.
+    pub fn sign(&self, cookie: SetCookie) -> SetCookie {
+        let mac = self.signature(&cookie.name, &cookie.value).finalize();
+        let value = base64_encode(&mac.into_bytes()) + &cookie.value;
+        SetCookie { value, ..cookie }
+    }
+
+    // The cookie with its original value if the signature is good.
+    pub fn verify(&self, cookie: &Cookie) -> Option<Cookie> {
+        let value = &cookie.value;
+        let (signature, value) = value.split_at_checked(SIGNATURE_LEN)?;
+        let signature = base64_decode(signature.as_bytes())?;
+        // In constant time
+        self.signature(&cookie.name, value)
+            .verify_slice(&signature)
+            .ok()?;
+        Some(Cookie {
+            name: cookie.name.clone(),
+            value: value.into(),
+        })
+    }
+
+    // Replaces the value of the cookie with its base64 encrypted form.
+    pub fn encrypt(&self, cookie: SetCookie) -> SetCookie {
+        let mac = hmac(
+            &self.nonce,
+            &[cookie.name.as_bytes(), b"=", cookie.value.as_bytes()],
+        )
+        .finalize()
+        .into_bytes();
+        let nonce = Nonce::from_slice(&mac[..NONCE_LEN]);
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(cookie.value.as_bytes());
+        let tag = self
+            .cipher()
+            .encrypt_in_place_detached(
+                nonce,
+                cookie.name.as_bytes(),
+                &mut sealed[NONCE_LEN..],
+            )
+            .expect("cookie values are far below the ChaCha20 size limit");
+        sealed.extend_from_slice(&tag);
+        let value = base64_encode(&sealed);
+        SetCookie { value, ..cookie }
+    }
+
+    // The cookie with its original value if it decrypts.
+    pub fn decrypt(&self, cookie: &Cookie) -> Option<Cookie> {
+        let mut sealed = base64_decode(cookie.value.as_bytes())?;
+        if sealed.len() < NONCE_LEN + TAG_LEN {
+            return None;
+        }
+        let tag = sealed.split_off(sealed.len() - TAG_LEN);
+        let mut data = sealed.split_off(NONCE_LEN);
+        self.cipher()
+            .decrypt_in_place_detached(
+                Nonce::from_slice(&sealed),
+                cookie.name.as_bytes(),
+                &mut data,
+                Tag::from_slice(&tag),
+            )
+            .ok()?;
+        Some(Cookie {
+            name: cookie.name.clone(),
+            value: String::from_utf8(data).ok()?,
+        })
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(&self.encryption.into())
+    }
+}
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> HmacSha256 {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key)
+        .expect("HMAC takes keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac
+}
+
+// Keeps the key material out of logs.
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Key").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+    // What the user agent sends back for a cookie the server set.
+    fn returned(cookie: &SetCookie) -> Cookie {
+        let header = cookie.to_header_value().unwrap();
+        let pair = header.as_bytes().split(|&b| b == b';').next().unwrap();
+        Cookie::parse_header(pair).pop().unwrap()
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(Key::from_bytes(&MASTER[1..]).unwrap_err(), InvalidKey);
+        assert_eq!(
+            format!("{:?}", Key::from_bytes(MASTER).unwrap()),
+            "Key { .. }"
+        );
+    }
+
+    #[test]
+    fn signed_cookies() {
+        let key = Key::from_bytes(MASTER).unwrap();
+        let cookie = key.sign(SetCookie::new("user", "42").path("/"));
+        assert_eq!(cookie.value().len(), SIGNATURE_LEN + 2);
+        assert!(cookie.value().ends_with("42"));
+        let verified = key.verify(&returned(&cookie)).unwrap();
+        assert_eq!((verified.name(), verified.value()), ("user", "42"));
+
+        let tampered = Cookie::new("user", &cookie.value().replace("42", "43"));
+        assert_eq!(key.verify(&tampered.unwrap()), None);
+        let renamed = Cookie::new("admin", cookie.value()).unwrap();
+        assert_eq!(key.verify(&renamed), None);
+        let other = Key::from_bytes(&[7; 32]).unwrap();
+        assert_eq!(other.verify(&returned(&cookie)), None);
+        assert_eq!(key.verify(&Cookie::new("user", "42").unwrap()), None);
+    }
+
+    #[test]
+    fn encrypted_cookies() {
+        let key = Key::from_bytes(MASTER).unwrap();
+        // Encryption lifts the restrictions on the value
+        let cookie = key.encrypt(SetCookie::new("session", "id=7; role=\"x\""));
+        assert!(!cookie.value().contains("role"));
+        let decrypted = key.decrypt(&returned(&cookie)).unwrap();
+        assert_eq!(decrypted.value(), "id=7; role=\"x\"");
+        // Deterministic for a given name and value
+        assert_eq!(
+            key.encrypt(SetCookie::new("session", "id=7; role=\"x\"")),
+            cookie
+        );
+        assert_ne!(key.encrypt(SetCookie::new("session", "id=8")), cookie);
+
+        let moved = Cookie::new("other", cookie.value()).unwrap();
+        assert_eq!(key.decrypt(&moved), None);
+        let other = Key::from_bytes(&[7; 32]).unwrap();
+        assert_eq!(other.decrypt(&returned(&cookie)), None);
+        let mut sealed = base64_decode(cookie.value().as_bytes()).unwrap();
+        sealed[NONCE_LEN] ^= 1;
+        let tampered = Cookie::new("session", &base64_encode(&sealed));
+        assert_eq!(key.decrypt(&tampered.unwrap()), None);
+        for value in ["", "not-base64", "AAAA"] {
+            let cookie = Cookie::new("session", value).unwrap();
+            assert_eq!(key.decrypt(&cookie), None);
+        }
+    }
+}
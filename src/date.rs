@@ -0,0 +1,665 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::header::HeaderValue;
+
+const DAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const LONG_DAY_NAMES: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct",
+    "Nov", "Dec",
+];
+
+// A point in time with a resolution of one second, as carried in HTTP date
+// fields such as `Date` and `Last-Modified`. Dates before the Unix epoch are
+// not representable.
+//
+// All three formats of RFC 9110 section 5.6.7 are parsed, but dates are
+// always formatted as IMF-fixdate, the only one senders may generate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HttpDate {
+    // Seconds since the Unix epoch
+    secs: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidHttpDate;
+
+// A calendar date and time of day in UTC.
+#[derive(Debug, PartialEq, Eq)]
+struct DateTime {
+    year: u64,
+    // 1-12
+    month: u8,
+    // 1-31
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+impl HttpDate {
+    pub const UNIX_EPOCH: Self = Self { secs: 0 };
+
+    pub fn now() -> Self {
+        SystemTime::now().into()
+    }
+
+    pub fn from_unix_secs(secs: u64) -> Self {
+        Self { secs }
+    }
+
+    pub fn unix_secs(&self) -> u64 {
+        self.secs
+    }
+
+    pub fn from_bytes(s: &[u8]) -> Result<Self, InvalidHttpDate> {
+        parse_imf_fixdate(s)
+            .or_else(|| parse_rfc850_date(s, Self::now().to_date_time().year))
+            .or_else(|| parse_asctime_date(s))
+            .and_then(|dt| Self::from_date_time(&dt))
+            .ok_or(InvalidHttpDate)
+    }
+
+    // Parses the `Expires` attribute of a cookie with the lenient algorithm
+    // of RFC 6265bis section 5.1.1, which picks the time, day, month and
+    // year out of whatever tokens are there. Dates before the epoch become
+    // the epoch.
+    pub fn from_cookie_date(s: &[u8]) -> Result<Self, InvalidHttpDate> {
+        let mut time = None;
+        let mut day = None;
+        let mut month = None;
+        let mut year = None;
+        for token in s.split(|&b| is_cookie_date_delimiter(b)) {
+            if token.is_empty() {
+                continue;
+            }
+            if time.is_none()
+                && let Some(t) = parse_cookie_time(token)
+            {
+                time = Some(t);
+            } else if day.is_none()
+                && let Some(d) = parse_cookie_digits(token, 1, 2)
+            {
+                day = Some(d as u8);
+            } else if month.is_none()
+                && let Some(m) = token.get(..3).and_then(|m| {
+                    MONTH_NAMES.iter().position(|name| {
+                        name.as_bytes().eq_ignore_ascii_case(m)
+                    })
+                })
+            {
+                month = Some(m as u8 + 1);
+            } else if year.is_none()
+                && let Some(y) = parse_cookie_digits(token, 2, 4)
+            {
+                year = Some(match y {
+                    70..=99 => y + 1900,
+                    0..=69 => y + 2000,
+                    y => y,
+                });
+            }
+        }
+        let (Some((hour, minute, second)), Some(day), Some(month), Some(year)) =
+            (time, day, month, year)
+        else {
+            return Err(InvalidHttpDate);
+        };
+        if year < 1601
+            || day == 0
+            || day > days_in_month(year, month)
+            || hour > 23
+            || minute > 59
+            || second > 59
+        {
+            return Err(InvalidHttpDate);
+        }
+        if year < 1970 {
+            return Ok(Self::UNIX_EPOCH);
+        }
+        let dt = DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        };
+        Self::from_date_time(&dt).ok_or(InvalidHttpDate)
+    }
+
+    // The timestamp format of the Common Log Format, always in UTC, e.g.
+    // "06/Nov/1994:08:49:37 +0000".
+    pub fn to_common_log(&self) -> String {
+        let dt = self.to_date_time();
+        format!(
+            "{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000",
+            dt.day,
+            MONTH_NAMES[dt.month as usize - 1],
+            dt.year,
+            dt.hour,
+            dt.minute,
+            dt.second
+        )
+    }
+
+    // RFC 3339 in UTC, e.g. "1994-11-06T08:49:37Z".
+    pub fn to_rfc3339(&self) -> String {
+        let dt = self.to_date_time();
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second
+        )
+    }
+
+    fn from_date_time(dt: &DateTime) -> Option<Self> {
+        if dt.year < 1970
+            || !(1..=12).contains(&dt.month)
+            || dt.day == 0
+            || dt.day > days_in_month(dt.year, dt.month)
+            || dt.hour > 23
+            || dt.minute > 59
+            || dt.second > 60
+        {
+            return None;
+        }
+        let days = days_from_civil(dt.year, dt.month, dt.day);
+        // Leap seconds are not representable in Unix time
+        let secs = days * 86400
+            + dt.hour as u64 * 3600
+            + dt.minute as u64 * 60
+            + dt.second.min(59) as u64;
+        Some(Self { secs })
+    }
+
+    fn to_date_time(self) -> DateTime {
+        let (year, month, day) = civil_from_days(self.secs / 86400);
+        let secs_of_day = self.secs % 86400;
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (secs_of_day / 3600) as u8,
+            minute: (secs_of_day / 60 % 60) as u8,
+            second: (secs_of_day % 60) as u8,
+        }
+    }
+
+    // Index into `DAY_NAMES`. 1970-01-01 was a Thursday.
+    fn weekday(&self) -> usize {
+        ((self.secs / 86400 + 3) % 7) as usize
+    }
+}
+
+// IMF-fixdate  = day-name "," SP date1 SP time-of-day SP GMT
+// e.g. "Sun, 06 Nov 1994 08:49:37 GMT"
+//
+// The day name is redundant and only checked for being one.
+fn parse_imf_fixdate(s: &[u8]) -> Option<DateTime> {
+    if s.len() != 29 {
+        return None;
+    }
+    let (day_name, rest) = s.split_at(3);
+    DAY_NAMES.iter().find(|name| name.as_bytes() == day_name)?;
+    let rest = rest.strip_prefix(b", ")?;
+    let (date, rest) = rest.split_at(11);
+    let (day, month, year) = parse_date1(date)?;
+    let rest = rest.strip_prefix(b" ")?;
+    let (time, zone) = rest.split_at(8);
+    let (hour, minute, second) = parse_time_of_day(time)?;
+    if zone != b" GMT" {
+        return None;
+    }
+    Some(DateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+    })
+}
+
+// rfc850-date = day-name-l "," SP date2 SP time-of-day SP GMT
+// date2       = day "-" month "-" 2DIGIT
+// e.g. "Sunday, 06-Nov-94 08:49:37 GMT"
+//
+// Two-digit years are placed in the century that puts them at most 50 years
+// after `current_year`, as required by RFC 9110 section 5.6.7.
+fn parse_rfc850_date(s: &[u8], current_year: u64) -> Option<DateTime> {
+    let (day_name, rest) = s.split_at(s.iter().position(|&b| b == b',')?);
+    LONG_DAY_NAMES
+        .iter()
+        .find(|name| name.as_bytes() == day_name)?;
+    let rest = rest.strip_prefix(b", ")?;
+    if rest.len() != 22 {
+        return None;
+    }
+    let (date, rest) = rest.split_at(9);
+    let (day, month, year) = match *date {
+        [d1, d2, b'-', m1, m2, m3, b'-', y1, y2] => (
+            parse_digits(&[d1, d2])? as u8,
+            parse_month(&[m1, m2, m3])?,
+            parse_digits(&[y1, y2])?,
+        ),
+        _ => return None,
+    };
+    let mut year = current_year - current_year % 100 + year;
+    if year > current_year + 50 {
+        year -= 100;
+    } else if year + 50 <= current_year {
+        year += 100;
+    }
+    let rest = rest.strip_prefix(b" ")?;
+    let (time, zone) = rest.split_at(8);
+    let (hour, minute, second) = parse_time_of_day(time)?;
+    if zone != b" GMT" {
+        return None;
+    }
+    Some(DateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+    })
+}
+
+// asctime-date = day-name SP date3 SP time-of-day SP year
+// date3        = month SP ( 2DIGIT / ( SP DIGIT ) )
+// e.g. "Sun Nov  6 08:49:37 1994"
+fn parse_asctime_date(s: &[u8]) -> Option<DateTime> {
+    if s.len() != 24 {
+        return None;
+    }
+    let (day_name, rest) = s.split_at(3);
+    DAY_NAMES.iter().find(|name| name.as_bytes() == day_name)?;
+    let (date, rest) = rest.split_at(8);
+    let (month, day) = match *date {
+        [b' ', m1, m2, m3, b' ', b' ', d, b' '] => {
+            (parse_month(&[m1, m2, m3])?, parse_digits(&[d])?)
+        }
+        [b' ', m1, m2, m3, b' ', d1, d2, b' '] => {
+            (parse_month(&[m1, m2, m3])?, parse_digits(&[d1, d2])?)
+        }
+        _ => return None,
+    };
+    let (time, year) = rest.split_at(8);
+    let (hour, minute, second) = parse_time_of_day(time)?;
+    let year = parse_digits(year.strip_prefix(b" ")?)?;
+    Some(DateTime {
+        year,
+        month,
+        day: day as u8,
+        hour,
+        minute,
+        second,
+    })
+}
+
+// date1 = day SP month SP year, e.g. "06 Nov 1994"
+fn parse_date1(s: &[u8]) -> Option<(u8, u8, u64)> {
+    match *s {
+        [d1, d2, b' ', m1, m2, m3, b' ', y1, y2, y3, y4] => Some((
+            parse_digits(&[d1, d2])? as u8,
+            parse_month(&[m1, m2, m3])?,
+            parse_digits(&[y1, y2, y3, y4])?,
+        )),
+        _ => None,
+    }
+}
+
+// time-of-day = hour ":" minute ":" second, e.g. "08:49:37"
+fn parse_time_of_day(s: &[u8]) -> Option<(u8, u8, u8)> {
+    match *s {
+        [h1, h2, b':', m1, m2, b':', s1, s2] => Some((
+            parse_digits(&[h1, h2])? as u8,
+            parse_digits(&[m1, m2])? as u8,
+            parse_digits(&[s1, s2])? as u8,
+        )),
+        _ => None,
+    }
+}
+
+fn parse_month(s: &[u8]) -> Option<u8> {
+    MONTH_NAMES
+        .iter()
+        .position(|name| name.as_bytes() == s)
+        .map(|i| i as u8 + 1)
+}
+
+// delimiter = %x09 / %x20-2F / %x3B-40 / %x5B-60 / %x7B-7E
+fn is_cookie_date_delimiter(b: u8) -> bool {
+    matches!(b, 0x09 | 0x20..=0x2f | 0x3b..=0x40 | 0x5b..=0x60 | 0x7b..=0x7e)
+}
+
+// Between `min` and `max` digits, optionally followed by anything that does
+// not start with a digit.
+fn parse_cookie_digits(s: &[u8], min: usize, max: usize) -> Option<u64> {
+    let len = s.iter().take_while(|b| b.is_ascii_digit()).count();
+    if !(min..=max).contains(&len) {
+        return None;
+    }
+    parse_digits(&s[..len])
+}
+
+// hms-time = time-field ":" time-field ":" time-field, with fields of one or
+// two digits, optionally followed by anything that does not start with a
+// digit.
+fn parse_cookie_time(s: &[u8]) -> Option<(u8, u8, u8)> {
+    let mut fields = s.splitn(3, |&b| b == b':');
+    let hour =
+        parse_digits(fields.next().filter(|f| (1..=2).contains(&f.len()))?)?;
+    let minute =
+        parse_digits(fields.next().filter(|f| (1..=2).contains(&f.len()))?)?;
+    let second = parse_cookie_digits(fields.next()?, 1, 2)?;
+    Some((hour as u8, minute as u8, second as u8))
+}
+
+fn parse_digits(s: &[u8]) -> Option<u64> {
+    if !s.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(s.iter().fold(0, |n, &d| n * 10 + (d - b'0') as u64))
+}
+
+fn is_leap_year(year: u64) -> bool {
+    year.is_multiple_of(4)
+        && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u64, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 of a date in the proleptic Gregorian calendar, after
+// Howard Hinnant's `days_from_civil`. Years are shifted to start in March so
+// that the leap day comes last.
+fn days_from_civil(year: u64, month: u8, day: u8) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let month = month as u64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5
+            + day as u64
+            - 1;
+    let day_of_era =
+        year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    // 719468 days separate 0000-03-01 from 1970-01-01
+    era * 146097 + day_of_era - 719468
+}
+
+// The inverse of `days_from_civil`.
+fn civil_from_days(days: u64) -> (u64, u8, u8) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524
+        - day_of_era / 146096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    (year, month, day)
+}
+
+// Truncates to whole seconds. Times before the epoch become the epoch.
+impl From<SystemTime> for HttpDate {
+    fn from(time: SystemTime) -> Self {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self { secs }
+    }
+}
+
+// Dates beyond what the platform can represent become the latest time it
+// can, to whole seconds.
+impl From<HttpDate> for SystemTime {
+    fn from(date: HttpDate) -> Self {
+        let after_epoch =
+            |secs| UNIX_EPOCH.checked_add(Duration::from_secs(secs));
+        if let Some(time) = after_epoch(date.secs) {
+            return time;
+        }
+        // The range differs between platforms, so search for its end
+        let (mut low, mut high) = (0, date.secs);
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            match after_epoch(mid) {
+                Some(_) => low = mid,
+                None => high = mid,
+            }
+        }
+        UNIX_EPOCH + Duration::from_secs(low)
+    }
+}
+
+impl FromStr for HttpDate {
+    type Err = InvalidHttpDate;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_bytes(s.as_bytes())
+    }
+}
+
+// Always IMF-fixdate.
+impl fmt::Display for HttpDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dt = self.to_date_time();
+        write!(
+            f,
+            "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            DAY_NAMES[self.weekday()],
+            dt.day,
+            MONTH_NAMES[dt.month as usize - 1],
+            dt.year,
+            dt.hour,
+            dt.minute,
+            dt.second
+        )
+    }
+}
+
+impl From<HttpDate> for HeaderValue {
+    fn from(date: HttpDate) -> Self {
+        HeaderValue::from_bytes(date.to_string().as_bytes())
+            .expect("dates are valid header values")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 9110 section 5.6.7
+    const EXAMPLE: u64 = 784111777;
+
+    #[test]
+    fn parsing() {
+        assert_eq!(
+            "Sun, 06 Nov 1994 08:49:37 GMT".parse(),
+            Ok(HttpDate::from_unix_secs(EXAMPLE))
+        );
+        assert_eq!(
+            "Thu, 01 Jan 1970 00:00:00 GMT".parse(),
+            Ok(HttpDate::UNIX_EPOCH)
+        );
+        assert_eq!(
+            "Tue, 29 Feb 2000 23:59:59 GMT".parse(),
+            Ok(HttpDate::from_unix_secs(951868799))
+        );
+        // Leap seconds are folded into the second before
+        assert_eq!(
+            "Sat, 31 Dec 2016 23:59:60 GMT".parse(),
+            Ok(HttpDate::from_unix_secs(1483228799))
+        );
+        for invalid in [
+            "",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 6 Nov 1994 08:49:37 GMT",
+            "Sun,  06 Nov 1994 08:49:37 GMT",
+            "Sun, 06 nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:60:00 GMT",
+            "Sun, 31 Nov 1994 08:49:37 GMT",
+            "Fri, 29 Feb 2019 00:00:00 GMT",
+            "Wed, 31 Dec 1969 23:59:59 GMT",
+            "Xyz, 06 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 08:49:37 GMT ",
+        ] {
+            assert_eq!(
+                invalid.parse::<HttpDate>(),
+                Err(InvalidHttpDate),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn obsolete_formats() {
+        let example = Ok(HttpDate::from_unix_secs(EXAMPLE));
+        assert_eq!("Sunday, 06-Nov-94 08:49:37 GMT".parse(), example);
+        assert_eq!("Sun Nov  6 08:49:37 1994".parse(), example);
+        assert_eq!(
+            "Thu Jan 01 00:00:00 1970".parse(),
+            Ok(HttpDate::UNIX_EPOCH)
+        );
+        for invalid in [
+            "Sun, 06-Nov-94 08:49:37 GMT",
+            "Sunday, 06-Nov-1994 08:49:37 GMT",
+            "Sunday, 6-Nov-94 08:49:37 GMT",
+            "Sunday, 06 Nov 94 08:49:37 GMT",
+            "Sunday 06-Nov-94 08:49:37 GMT",
+            "Sun Nov 6 08:49:37 1994",
+            "Sun Nov  6 08:49:37 1994 GMT",
+            "Sun Nov 6  08:49:37 1994",
+            "Sunday Nov  6 08:49:37 1994",
+        ] {
+            assert_eq!(
+                invalid.parse::<HttpDate>(),
+                Err(InvalidHttpDate),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn cookie_dates() {
+        let parse = |s: &str| HttpDate::from_cookie_date(s.as_bytes());
+        let example = Ok(HttpDate::from_unix_secs(EXAMPLE));
+        for date in [
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+            "06 november 1994 8:49:37",
+            "1994-NOV-6 08:49:37am",
+            "6 Nov 94, 08:49:37.000 +0100",
+        ] {
+            assert_eq!(parse(date), example, "{date}");
+        }
+        assert_eq!(
+            parse("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Ok(HttpDate::from_unix_secs(1445412480))
+        );
+        assert_eq!(
+            parse("Thu, 01 Jan 2069 00:00:00 GMT"),
+            parse("Thu, 01 Jan 69 00:00:00 GMT")
+        );
+        assert_eq!(parse("01 Jan 1900 00:00:00"), Ok(HttpDate::UNIX_EPOCH));
+        for invalid in [
+            "",
+            "Sun, 06 Nov 1994",
+            "Sun, 06 Nov 08:49:37 GMT",
+            "Sun, Nov 1994 08:49:37 GMT",
+            "Sun, 06 1994 08:49:37 GMT",
+            "Sun, 32 Nov 1994 08:49:37 GMT",
+            "Sun, 31 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:49:37 GMT",
+            "Sun, 06 Nov 1994 08:60:37 GMT",
+            "Sun, 06 Nov 1994 08:49:60 GMT",
+            "Sun, 06 Nov 1600 08:49:37 GMT",
+            "Sun, 06 Nov 19945 08:49:37 GMT",
+        ] {
+            assert_eq!(parse(invalid), Err(InvalidHttpDate), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn two_digit_years() {
+        let year = |s: &[u8], current_year| {
+            parse_rfc850_date(s, current_year).unwrap().year
+        };
+        assert_eq!(year(b"Sunday, 06-Nov-94 08:49:37 GMT", 2026), 1994);
+        assert_eq!(year(b"Sunday, 06-Nov-76 08:49:37 GMT", 2026), 2076);
+        assert_eq!(year(b"Sunday, 06-Nov-77 08:49:37 GMT", 2026), 1977);
+        assert_eq!(year(b"Sunday, 06-Nov-26 08:49:37 GMT", 2026), 2026);
+        assert_eq!(year(b"Sunday, 06-Nov-49 08:49:37 GMT", 1999), 2049);
+        assert_eq!(year(b"Sunday, 06-Nov-50 08:49:37 GMT", 1999), 1950);
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(
+            HttpDate::from_unix_secs(EXAMPLE).to_string(),
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+        assert_eq!(
+            HttpDate::UNIX_EPOCH.to_string(),
+            "Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+        assert_eq!(
+            HttpDate::from_unix_secs(253402300799).to_string(),
+            "Fri, 31 Dec 9999 23:59:59 GMT"
+        );
+        let date = HttpDate::from_unix_secs(EXAMPLE);
+        assert_eq!(date.to_common_log(), "06/Nov/1994:08:49:37 +0000");
+        assert_eq!(date.to_rfc3339(), "1994-11-06T08:49:37Z");
+    }
+
+    #[test]
+    fn round_trip() {
+        // Every day over a few centuries, at varying times of day
+        for day in (0..150_000).step_by(7) {
+            let date = HttpDate::from_unix_secs(day * 86400 + day % 86400);
+            assert_eq!(date.to_string().parse(), Ok(date));
+        }
+    }
+
+    #[test]
+    fn system_time() {
+        let time = UNIX_EPOCH + Duration::new(EXAMPLE, 999_999_999);
+        let date = HttpDate::from(time);
+        assert_eq!(date.unix_secs(), EXAMPLE);
+        assert_eq!(
+            SystemTime::from(date),
+            UNIX_EPOCH + Duration::from_secs(EXAMPLE)
+        );
+        let before_epoch = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(HttpDate::from(before_epoch), HttpDate::UNIX_EPOCH);
+
+        let latest = SystemTime::from(HttpDate::from_unix_secs(u64::MAX));
+        assert!(latest > SystemTime::from(HttpDate::from(time)));
+        assert!(latest.checked_add(Duration::from_secs(1)).is_none());
+    }
+}
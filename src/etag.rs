@@ -0,0 +1,237 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::header::HeaderValue;
+use crate::parser;
+
+// An entity tag as defined in RFC 9110 section 8.8.3.
+//
+// entity-tag = [ weak ] opaque-tag
+// weak       = %s"W/"
+// opaque-tag = DQUOTE *etagc DQUOTE
+// etagc      = %x21 / %x23-7E / obs-text
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ETag {
+    weak: bool,
+    // Without the surrounding quotes
+    tag: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidETag;
+
+impl ETag {
+    pub fn strong(tag: &str) -> Result<Self, InvalidETag> {
+        Self::new(false, tag)
+    }
+
+    pub fn weak(tag: &str) -> Result<Self, InvalidETag> {
+        Self::new(true, tag)
+    }
+
+    fn new(weak: bool, tag: &str) -> Result<Self, InvalidETag> {
+        if !tag.bytes().all(is_etagc) {
+            return Err(InvalidETag);
+        }
+        Ok(Self {
+            weak,
+            tag: tag.into(),
+        })
+    }
+
+    pub fn from_bytes(s: &[u8]) -> Result<Self, InvalidETag> {
+        let (weak, opaque) = match s.strip_prefix(b"W/") {
+            Some(opaque) => (true, opaque),
+            None => (false, s),
+        };
+        let tag = opaque
+            .strip_prefix(b"\"")
+            .and_then(|t| t.strip_suffix(b"\""))
+            .ok_or(InvalidETag)?;
+        // obs-text is allowed by the grammar but has no place in a `String`
+        let tag = std::str::from_utf8(tag).map_err(|_| InvalidETag)?;
+        Self::new(weak, tag)
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    // Strong comparison: both tags are strong and identical. Used where the
+    // representations have to be byte-for-byte identical, e.g. `If-Match`.
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    // Weak comparison: the tags are identical, weak or not. Used for cache
+    // validation, e.g. `If-None-Match`.
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+}
+
+fn is_etagc(b: u8) -> bool {
+    b == 0x21 || (0x23..=0x7e).contains(&b) || b >= 0x80
+}
+
+// The value of `If-Match` and `If-None-Match`.
+//
+// If-Match = "*" / #entity-tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ETagList {
+    // Matches any current representation
+    Any,
+    Tags(Vec<ETag>),
+}
+
+impl ETagList {
+    // Parses every field line of the header as one combined list. Returns
+    // `None` if any element is invalid, in which case the header should be
+    // ignored.
+    pub fn from_values<'a, I>(values: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = &'a HeaderValue>,
+    {
+        let mut tags = vec![];
+        let mut any = false;
+        for value in values {
+            for element in parser::list_elements(value.as_bytes()) {
+                match element {
+                    b"*" => any = true,
+                    _ => tags.push(ETag::from_bytes(element).ok()?),
+                }
+            }
+        }
+        match (any, tags.is_empty()) {
+            (true, true) => Some(Self::Any),
+            (false, false) => Some(Self::Tags(tags)),
+            // "*" mixed with tags, or nothing at all
+            _ => None,
+        }
+    }
+
+    // Whether the list matches the current entity tag of a representation,
+    // using strong comparison if `strong` is set and weak comparison
+    // otherwise.
+    pub fn matches(&self, current: Option<&ETag>, strong: bool) -> bool {
+        match (self, current) {
+            (Self::Any, _) => true,
+            (Self::Tags(_), None) => false,
+            (Self::Tags(tags), Some(current)) => tags.iter().any(|tag| {
+                if strong {
+                    tag.strong_eq(current)
+                } else {
+                    tag.weak_eq(current)
+                }
+            }),
+        }
+    }
+}
+
+impl FromStr for ETag {
+    type Err = InvalidETag;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_bytes(s.as_bytes())
+    }
+}
+
+impl fmt::Display for ETag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.weak {
+            f.write_str("W/")?;
+        }
+        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+impl From<&ETag> for HeaderValue {
+    fn from(etag: &ETag) -> Self {
+        HeaderValue::from_bytes(etag.to_string().as_bytes())
+            .expect("entity tags are valid header values")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        let etag: ETag = "\"xyzzy\"".parse().unwrap();
+        assert!(!etag.is_weak());
+        assert_eq!(etag.tag(), "xyzzy");
+        let etag: ETag = "W/\"xyzzy\"".parse().unwrap();
+        assert!(etag.is_weak());
+        assert_eq!(etag.tag(), "xyzzy");
+        assert_eq!("\"\"".parse::<ETag>().unwrap().tag(), "");
+
+        for invalid in
+            ["xyzzy", "\"xyzzy", "w/\"xyzzy\"", "\"xy\"zy\"", "\"a b\""]
+        {
+            assert_eq!(invalid.parse::<ETag>(), Err(InvalidETag), "{invalid}");
+        }
+        assert_eq!(ETag::strong("a\"b"), Err(InvalidETag));
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(ETag::strong("abc").unwrap().to_string(), "\"abc\"");
+        assert_eq!(ETag::weak("abc").unwrap().to_string(), "W/\"abc\"");
+    }
+
+    // RFC 9110 section 8.8.3.2
+    #[test]
+    fn comparison() {
+        let w1 = ETag::weak("1").unwrap();
+        let w2 = ETag::weak("2").unwrap();
+        let s1 = ETag::strong("1").unwrap();
+        for (a, b, strong, weak) in [
+            (&w1, &w1, false, true),
+            (&w1, &w2, false, false),
+            (&w1, &s1, false, true),
+            (&s1, &s1, true, true),
+        ] {
+            assert_eq!(a.strong_eq(b), strong);
+            assert_eq!(a.weak_eq(b), weak);
+        }
+    }
+
+    #[test]
+    fn lists() {
+        let parse = |values: &[&str]| {
+            let values: Vec<HeaderValue> =
+                values.iter().map(|v| v.parse().unwrap()).collect();
+            ETagList::from_values(&values)
+        };
+        assert_eq!(parse(&["*"]), Some(ETagList::Any));
+        assert_eq!(
+            parse(&["\"a\", W/\"b\"", "\"c\""]),
+            Some(ETagList::Tags(vec![
+                ETag::strong("a").unwrap(),
+                ETag::weak("b").unwrap(),
+                ETag::strong("c").unwrap(),
+            ]))
+        );
+        // Commas inside tags do not split them
+        assert_eq!(
+            parse(&["\"a,b\""]),
+            Some(ETagList::Tags(vec![ETag::strong("a,b").unwrap()]))
+        );
+        assert_eq!(parse(&["*, \"a\""]), None);
+        assert_eq!(parse(&["a"]), None);
+        assert_eq!(parse(&[""]), None);
+
+        let list = parse(&["W/\"a\", \"b\""]).unwrap();
+        let a = ETag::strong("a").unwrap();
+        assert!(list.matches(Some(&a), false));
+        assert!(!list.matches(Some(&a), true));
+        assert!(list.matches(Some(&ETag::strong("b").unwrap()), true));
+        assert!(!list.matches(None, false));
+        assert!(ETagList::Any.matches(None, true));
+    }
+}
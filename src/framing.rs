@@ -0,0 +1,485 @@
+use std::io;
+
+use crate::header::{HeaderMap, HeaderName};
+use crate::method::Method;
+use crate::parser::{self, RawHeader, RawRequest, RawResponse};
+use crate::status::StatusCode;
+use crate::utils;
+use crate::version::Version;
+
+// How the length of a message body is determined (RFC 9112 section 6.3).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyKind {
+    // The message has no body at all
+    None,
+    ContentLength(u64),
+    Chunked,
+    // The body ends when the server closes the connection. Only possible for
+    // responses.
+    CloseDelimited,
+}
+
+// Ambiguous or invalid framing. Any of these means the message boundaries
+// cannot be trusted, so the connection must be closed after responding.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FramingError {
+    // Both Content-Length and Transfer-Encoding are present in a request
+    ConflictingFraming,
+    // Content-Length is not a single valid decimal number
+    InvalidContentLength,
+    // Content-Length appears more than once, even with identical values
+    DuplicateContentLength,
+    // Transfer-Encoding in a HTTP/1.0 message
+    TransferEncodingOnHttp10,
+    InvalidTransferEncoding,
+    // The final transfer coding of a request is not chunked
+    ChunkedNotFinal,
+    // A transfer coding other than chunked
+    UnsupportedTransferCoding,
+}
+
+impl FramingError {
+    // The status a server should answer a request with this error with.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::UnsupportedTransferCoding => StatusCode::NOT_IMPLEMENTED,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+fn values<'a>(
+    headers: &'a [RawHeader],
+    name: &'a str,
+) -> impl Iterator<Item = &'a [u8]> {
+    headers
+        .iter()
+        .filter(move |h| h.name.eq_ignore_ascii_case(name))
+        .map(|h| h.value)
+}
+
+// Content-Length = 1*DIGIT
+//
+// RFC 9110 section 8.6 allows accepting repeated identical values, but any
+// repetition at all is a strong indicator of an attempt at smuggling so it is
+// rejected outright, as is a list of values within a single field line.
+fn content_length(headers: &[RawHeader]) -> Result<Option<u64>, FramingError> {
+    let mut values = values(headers, "content-length");
+    let Some(value) = values.next() else {
+        return Ok(None);
+    };
+    if values.next().is_some() || value.contains(&b',') {
+        return Err(FramingError::DuplicateContentLength);
+    }
+    if value.is_empty() || !value.iter().all(u8::is_ascii_digit) {
+        return Err(FramingError::InvalidContentLength);
+    }
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(Some)
+        .ok_or(FramingError::InvalidContentLength)
+}
+
+// Returns whether the final transfer coding is chunked, or None if the
+// message has no Transfer-Encoding. Every coding, across all field lines,
+// must be a valid token, and chunked must not be applied more than once.
+fn transfer_encoding(
+    headers: &[RawHeader],
+) -> Result<Option<bool>, FramingError> {
+    let mut present = false;
+    let mut chunked_count = 0;
+    let mut last_is_chunked = false;
+    for value in values(headers, "transfer-encoding") {
+        present = true;
+        for coding in parser::list_elements(value) {
+            // Transfer codings may carry parameters, chunked never does.
+            let name = coding
+                .split(|&b| b == b';')
+                .next()
+                .map(parser::trim_whitespace)
+                .unwrap_or_default();
+            if !utils::is_token(name) {
+                return Err(FramingError::InvalidTransferEncoding);
+            }
+            last_is_chunked = coding.eq_ignore_ascii_case(b"chunked");
+            if last_is_chunked {
+                chunked_count += 1;
+            } else if name.eq_ignore_ascii_case(b"chunked") {
+                return Err(FramingError::InvalidTransferEncoding);
+            }
+        }
+    }
+    if !present {
+        return Ok(None);
+    }
+    if chunked_count > 1 {
+        return Err(FramingError::InvalidTransferEncoding);
+    }
+    Ok(Some(last_is_chunked))
+}
+
+impl BodyKind {
+    // Determines the body length of a request as received by a server.
+    pub fn of_request(request: &RawRequest) -> Result<Self, FramingError> {
+        let te = transfer_encoding(request.headers)?;
+        let cl = content_length(request.headers)?;
+        match (te, cl) {
+            (Some(_), _) if request.version < Version::Http1_1 => {
+                Err(FramingError::TransferEncodingOnHttp10)
+            }
+            // RFC 9112 section 6.3 lets a server process the request using
+            // Transfer-Encoding, but a message containing both is almost
+            // always an attempt at request smuggling (CL.TE / TE.CL).
+            (Some(_), Some(_)) => Err(FramingError::ConflictingFraming),
+            (Some(true), None) => Ok(Self::Chunked),
+            // If chunked is not the final coding of a request, the length
+            // cannot be determined reliably.
+            (Some(false), None) => Err(if contains_chunked(request.headers) {
+                FramingError::ChunkedNotFinal
+            } else {
+                FramingError::UnsupportedTransferCoding
+            }),
+            (None, Some(0)) | (None, None) => Ok(Self::None),
+            (None, Some(n)) => Ok(Self::ContentLength(n)),
+        }
+    }
+
+    // Determines the body length of a response as received by a client that
+    // sent a request with `request_method`.
+    pub fn of_response(
+        response: &RawResponse,
+        request_method: Method,
+    ) -> Result<Self, FramingError> {
+        if !response_has_body(request_method, response.status) {
+            return Ok(Self::None);
+        }
+        let te = transfer_encoding(response.headers)?;
+        if te.is_some() && response.version < Version::Http1_1 {
+            return Err(FramingError::TransferEncodingOnHttp10);
+        }
+        match te {
+            // Transfer-Encoding overrides Content-Length in responses
+            Some(true) => Ok(Self::Chunked),
+            Some(false) => Ok(Self::CloseDelimited),
+            None => match content_length(response.headers)? {
+                Some(0) => Ok(Self::None),
+                Some(n) => Ok(Self::ContentLength(n)),
+                None => Ok(Self::CloseDelimited),
+            },
+        }
+    }
+}
+
+// Distinguishes a chunked coding that is not the last one from codings we do
+// not implement at all.
+fn contains_chunked(headers: &[RawHeader]) -> bool {
+    values(headers, "transfer-encoding")
+        .flat_map(parser::list_elements)
+        .any(|c| c.eq_ignore_ascii_case(b"chunked"))
+}
+
+// Responses to HEAD requests, successful responses to CONNECT (which turn
+// the connection into a tunnel), and 1xx, 204 and 304 responses never have a
+// body, whatever their header fields say.
+pub fn response_has_body(request_method: Method, status: StatusCode) -> bool {
+    !(request_method == Method::Head
+        || (request_method == Method::Connect && status.is_success())
+        || status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED)
+}
+
+// Whether a `Connection` field lists `option`, such as "close" or
+// "keep-alive".
+pub(crate) fn has_connection_option(headers: &HeaderMap, option: &str) -> bool {
+    headers.get_all(HeaderName::CONNECTION).any(|value| {
+        parser::list_elements(value.as_bytes())
+            .any(|element| element.eq_ignore_ascii_case(option.as_bytes()))
+    })
+}
+
+// Socket reads running into their timeout fail with `WouldBlock` on Unix and
+// `TimedOut` on Windows.
+pub(crate) fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{RequestParser, ResponseParser, Status};
+
+    fn request_kind(buf: &[u8]) -> Result<BodyKind, FramingError> {
+        let mut headers = [RawHeader::EMPTY; 16];
+        let Ok(Status::Complete(raw)) =
+            RequestParser::new().parse(buf, &mut headers)
+        else {
+            panic!("expected a complete request: {}", buf.escape_ascii());
+        };
+        BodyKind::of_request(&raw)
+    }
+
+    fn response_kind(
+        buf: &[u8],
+        method: Method,
+    ) -> Result<BodyKind, FramingError> {
+        let mut headers = [RawHeader::EMPTY; 16];
+        let Ok(Status::Complete(raw)) =
+            ResponseParser::new().parse(buf, &mut headers)
+        else {
+            panic!("expected a complete response: {}", buf.escape_ascii());
+        };
+        BodyKind::of_response(&raw, method)
+    }
+
+    #[test]
+    fn requests() {
+        for (input, kind) in [
+            (&b"GET / HTTP/1.1\r\nHost: a\r\n\r\n"[..], BodyKind::None),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 0\r\n\r\n",
+                BodyKind::None,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 42\r\n\r\n",
+                BodyKind::ContentLength(42),
+            ),
+            (
+                b"POST / HTTP/1.0\r\nContent-Length: 007\r\n\r\n",
+                BodyKind::ContentLength(7),
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n",
+                BodyKind::Chunked,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: Chunked \r\n\r\n",
+                BodyKind::Chunked,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: , chunked\r\n\r\n",
+                BodyKind::Chunked,
+            ),
+        ] {
+            assert_eq!(request_kind(input), Ok(kind), "{}", input.escape_ascii());
+        }
+    }
+
+    #[test]
+    fn responses() {
+        let chunked: &[u8] =
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Length: 5\r\n\r\n";
+        assert_eq!(response_kind(chunked, Method::Get), Ok(BodyKind::Chunked));
+        assert_eq!(response_kind(chunked, Method::Head), Ok(BodyKind::None));
+
+        let length: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n";
+        assert_eq!(
+            response_kind(length, Method::Get),
+            Ok(BodyKind::ContentLength(5))
+        );
+        assert_eq!(response_kind(length, Method::Head), Ok(BodyKind::None));
+        assert_eq!(response_kind(length, Method::Connect), Ok(BodyKind::None));
+
+        let close: &[u8] = b"HTTP/1.0 200 OK\r\n\r\n";
+        assert_eq!(
+            response_kind(close, Method::Get),
+            Ok(BodyKind::CloseDelimited)
+        );
+        let gzip: &[u8] = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip\r\n\r\n";
+        assert_eq!(
+            response_kind(gzip, Method::Get),
+            Ok(BodyKind::CloseDelimited)
+        );
+
+        for status in ["100 Continue", "204 No Content", "304 Not Modified"] {
+            let buf =
+                format!("HTTP/1.1 {status}\r\nContent-Length: 10\r\n\r\n");
+            assert_eq!(
+                response_kind(buf.as_bytes(), Method::Get),
+                Ok(BodyKind::None)
+            );
+        }
+
+        assert_eq!(
+            response_kind(
+                b"HTTP/1.0 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+                Method::Get
+            ),
+            Err(FramingError::TransferEncodingOnHttp10)
+        );
+        assert_eq!(
+            response_kind(
+                b"HTTP/1.1 200 OK\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+                Method::Get
+            ),
+            Err(FramingError::DuplicateContentLength)
+        );
+    }
+
+    #[test]
+    fn has_body() {
+        assert!(response_has_body(Method::Get, StatusCode::OK));
+        assert!(response_has_body(Method::Post, StatusCode::NOT_FOUND));
+        assert!(response_has_body(Method::Connect, StatusCode::BAD_GATEWAY));
+        assert!(!response_has_body(Method::Head, StatusCode::OK));
+        assert!(!response_has_body(Method::Connect, StatusCode::OK));
+        assert!(!response_has_body(Method::Get, StatusCode::CONTINUE));
+        assert!(!response_has_body(Method::Get, StatusCode::NO_CONTENT));
+        assert!(!response_has_body(Method::Get, StatusCode::NOT_MODIFIED));
+    }
+
+    // Known request smuggling payloads. Each of these must be rejected, since
+    // a front-end and back-end disagreeing on where the body ends lets an
+    // attacker prepend data to the next request on the connection.
+    #[test]
+    fn smuggling() {
+        for (input, error) in [
+            // CL.TE and TE.CL
+            (
+                &b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 13\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nSMUGGLED"[..],
+                FramingError::ConflictingFraming,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n8\r\nSMUGGLED\r\n0\r\n\r\n",
+                FramingError::ConflictingFraming,
+            ),
+            // TE.TE: obfuscated or duplicated Transfer-Encoding
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: x\r\n\r\n",
+                FramingError::ChunkedNotFinal,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\nTransfer-encoding: cow\r\n\r\n",
+                FramingError::ChunkedNotFinal,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked, identity\r\n\r\n",
+                FramingError::ChunkedNotFinal,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: xchunked\r\n\r\n",
+                FramingError::UnsupportedTransferCoding,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunkedx\r\n\r\n",
+                FramingError::UnsupportedTransferCoding,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: identity\r\n\r\n",
+                FramingError::UnsupportedTransferCoding,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: \x0bchunked\r\n\r\n",
+                FramingError::InvalidTransferEncoding,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: \"chunked\"\r\n\r\n",
+                FramingError::InvalidTransferEncoding,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked;x=y\r\n\r\n",
+                FramingError::InvalidTransferEncoding,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: chunked\r\n\r\n",
+                FramingError::InvalidTransferEncoding,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked, chunked\r\n\r\n",
+                FramingError::InvalidTransferEncoding,
+            ),
+            // Transfer-Encoding is not defined for HTTP/1.0
+            (
+                b"POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\n",
+                FramingError::TransferEncodingOnHttp10,
+            ),
+            (
+                b"POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\nContent-Length: 5\r\n\r\n",
+                FramingError::TransferEncodingOnHttp10,
+            ),
+            // CL.CL: duplicate or conflicting Content-Length
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\n",
+                FramingError::DuplicateContentLength,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\n",
+                FramingError::DuplicateContentLength,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5, 5\r\n\r\n",
+                FramingError::DuplicateContentLength,
+            ),
+            // Malformed Content-Length
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: +5\r\n\r\n",
+                FramingError::InvalidContentLength,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: -1\r\n\r\n",
+                FramingError::InvalidContentLength,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 0x5\r\n\r\n",
+                FramingError::InvalidContentLength,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5 6\r\n\r\n",
+                FramingError::InvalidContentLength,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length:\r\n\r\n",
+                FramingError::InvalidContentLength,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 99999999999999999999\r\n\r\n",
+                FramingError::InvalidContentLength,
+            ),
+        ] {
+            assert_eq!(
+                request_kind(input),
+                Err(error),
+                "{}",
+                input.escape_ascii()
+            );
+        }
+    }
+
+    // Variants the head parser already refuses before framing is considered.
+    #[test]
+    fn smuggling_rejected_by_parser() {
+        for input in [
+            &b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding : chunked\r\n\r\n"[..],
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding:\r\n chunked\r\n\r\n",
+            b"POST / HTTP/1.1\r\nHost: a\r\n Transfer-Encoding: chunked\r\n\r\n",
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\rX: y\r\n\r\n",
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length\x00: 5\r\n\r\n",
+        ] {
+            let mut headers = [RawHeader::EMPTY; 16];
+            assert!(
+                RequestParser::new().parse(input, &mut headers).is_err(),
+                "{}",
+                input.escape_ascii()
+            );
+        }
+    }
+
+    #[test]
+    fn connection_options() {
+        let mut headers = HeaderMap::new();
+        assert!(!has_connection_option(&headers, "close"));
+        headers.append(HeaderName::CONNECTION, "Upgrade".parse().unwrap());
+        headers.append(
+            HeaderName::CONNECTION,
+            "foo, Keep-Alive ,close".parse().unwrap(),
+        );
+        assert!(has_connection_option(&headers, "close"));
+        assert!(has_connection_option(&headers, "keep-alive"));
+        assert!(has_connection_option(&headers, "upgrade"));
+        assert!(!has_connection_option(&headers, "clos"));
+    }
+}
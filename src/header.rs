@@ -0,0 +1,643 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::utils;
+
+// Generates the `StandardHeader` enum together with the `HeaderName`
+// constants and the lookup table used when parsing. Keeping everything in one
+// list makes it impossible for the three to drift apart.
+macro_rules! standard_headers {
+    ($(($variant:ident, $konst:ident, $name:literal);)+) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        enum StandardHeader {
+            $($variant,)+
+        }
+
+        impl StandardHeader {
+            fn as_str(&self) -> &'static str {
+                match self {
+                    $(Self::$variant => $name,)+
+                }
+            }
+
+            // `lowercase` must already be lowercased.
+            fn from_lowercase(lowercase: &[u8]) -> Option<Self> {
+                match lowercase {
+                    $(n if n == $name.as_bytes() => Some(Self::$variant),)+
+                    _ => None,
+                }
+            }
+        }
+
+        impl HeaderName {
+            $(
+                pub const $konst: HeaderName = HeaderName {
+                    inner: Repr::Standard(StandardHeader::$variant),
+                };
+            )+
+        }
+    };
+}
+
+standard_headers! {
+    (Accept, ACCEPT, "accept");
+    (AcceptCharset, ACCEPT_CHARSET, "accept-charset");
+    (AcceptEncoding, ACCEPT_ENCODING, "accept-encoding");
+    (AcceptLanguage, ACCEPT_LANGUAGE, "accept-language");
+    (AcceptRanges, ACCEPT_RANGES, "accept-ranges");
+    (AccessControlAllowCredentials, ACCESS_CONTROL_ALLOW_CREDENTIALS, "access-control-allow-credentials");
+    (AccessControlAllowHeaders, ACCESS_CONTROL_ALLOW_HEADERS, "access-control-allow-headers");
+    (AccessControlAllowMethods, ACCESS_CONTROL_ALLOW_METHODS, "access-control-allow-methods");
+    (AccessControlAllowOrigin, ACCESS_CONTROL_ALLOW_ORIGIN, "access-control-allow-origin");
+    (AccessControlExposeHeaders, ACCESS_CONTROL_EXPOSE_HEADERS, "access-control-expose-headers");
+    (AccessControlMaxAge, ACCESS_CONTROL_MAX_AGE, "access-control-max-age");
+    (AccessControlRequestHeaders, ACCESS_CONTROL_REQUEST_HEADERS, "access-control-request-headers");
+    (AccessControlRequestMethod, ACCESS_CONTROL_REQUEST_METHOD, "access-control-request-method");
+    (Age, AGE, "age");
+    (Allow, ALLOW, "allow");
+    (Authorization, AUTHORIZATION, "authorization");
+    (CacheControl, CACHE_CONTROL, "cache-control");
+    (Connection, CONNECTION, "connection");
+    (ContentDisposition, CONTENT_DISPOSITION, "content-disposition");
+    (ContentEncoding, CONTENT_ENCODING, "content-encoding");
+    (ContentLanguage, CONTENT_LANGUAGE, "content-language");
+    (ContentLength, CONTENT_LENGTH, "content-length");
+    (ContentLocation, CONTENT_LOCATION, "content-location");
+    (ContentRange, CONTENT_RANGE, "content-range");
+    (ContentType, CONTENT_TYPE, "content-type");
+    (Cookie, COOKIE, "cookie");
+    (Date, DATE, "date");
+    (ETag, ETAG, "etag");
+    (Expect, EXPECT, "expect");
+    (Expires, EXPIRES, "expires");
+    (Forwarded, FORWARDED, "forwarded");
+    (From, FROM, "from");
+    (Host, HOST, "host");
+    (IfMatch, IF_MATCH, "if-match");
+    (IfModifiedSince, IF_MODIFIED_SINCE, "if-modified-since");
+    (IfNoneMatch, IF_NONE_MATCH, "if-none-match");
+    (IfRange, IF_RANGE, "if-range");
+    (IfUnmodifiedSince, IF_UNMODIFIED_SINCE, "if-unmodified-since");
+    (KeepAlive, KEEP_ALIVE, "keep-alive");
+    (LastModified, LAST_MODIFIED, "last-modified");
+    (Link, LINK, "link");
+    (Location, LOCATION, "location");
+    (MaxForwards, MAX_FORWARDS, "max-forwards");
+    (Origin, ORIGIN, "origin");
+    (Pragma, PRAGMA, "pragma");
+    (ProxyAuthenticate, PROXY_AUTHENTICATE, "proxy-authenticate");
+    (ProxyAuthorization, PROXY_AUTHORIZATION, "proxy-authorization");
+    (Range, RANGE, "range");
+    (Referer, REFERER, "referer");
+    (RetryAfter, RETRY_AFTER, "retry-after");
+    (Server, SERVER, "server");
+    (SetCookie, SET_COOKIE, "set-cookie");
+    (Te, TE, "te");
+    (Trailer, TRAILER, "trailer");
+    (TransferEncoding, TRANSFER_ENCODING, "transfer-encoding");
+    (Upgrade, UPGRADE, "upgrade");
+    (UserAgent, USER_AGENT, "user-agent");
+    (Vary, VARY, "vary");
+    (Via, VIA, "via");
+    (WwwAuthenticate, WWW_AUTHENTICATE, "www-authenticate");
+}
+
+// Longest standard header name, used to lowercase into a stack buffer before
+// looking names up in the table above.
+const MAX_STANDARD_HEADER_LEN: usize = 32;
+
+// Header field names are case-insensitive, so they are always stored in
+// lowercase. Well-known names are interned and never allocate.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct HeaderName {
+    inner: Repr,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Repr {
+    Standard(StandardHeader),
+    Custom(Box<str>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidHeaderName;
+
+impl HeaderName {
+    // field-name = token
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, InvalidHeaderName> {
+        if !utils::is_token(bytes) {
+            return Err(InvalidHeaderName);
+        }
+        if bytes.len() <= MAX_STANDARD_HEADER_LEN {
+            let mut buf = [0; MAX_STANDARD_HEADER_LEN];
+            let buf = &mut buf[..bytes.len()];
+            buf.copy_from_slice(bytes);
+            buf.make_ascii_lowercase();
+            if let Some(standard) = StandardHeader::from_lowercase(buf) {
+                return Ok(Self {
+                    inner: Repr::Standard(standard),
+                });
+            }
+        }
+        // Tokens are pure ASCII so this can never fail.
+        let name = String::from_utf8_lossy(bytes).to_ascii_lowercase();
+        Ok(Self {
+            inner: Repr::Custom(name.into_boxed_str()),
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        match &self.inner {
+            Repr::Standard(standard) => standard.as_str(),
+            Repr::Custom(name) => name,
+        }
+    }
+}
+
+impl FromStr for HeaderName {
+    type Err = InvalidHeaderName;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_bytes(s.as_bytes())
+    }
+}
+
+impl fmt::Display for HeaderName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for HeaderName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl AsRef<str> for HeaderName {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+// A field value is an arbitrary sequence of octets, except that CR, LF and
+// NUL are never allowed (RFC 9110 section 5.5). Leading and trailing
+// whitespace is not part of the value and is stripped by the parser.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct HeaderValue {
+    inner: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidHeaderValue;
+
+impl HeaderValue {
+    fn is_valid_value_byte(b: &u8) -> bool {
+        !matches!(b, b'\r' | b'\n' | b'\0')
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, InvalidHeaderValue> {
+        if !bytes.iter().all(Self::is_valid_value_byte) {
+            return Err(InvalidHeaderValue);
+        }
+        Ok(Self {
+            inner: bytes.to_vec(),
+        })
+    }
+
+    fn empty() -> Self {
+        Self { inner: vec![] }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.inner
+    }
+
+    // Values are allowed to contain obs-text, in which case they are not
+    // valid UTF-8 (and should be treated as opaque data anyway).
+    pub fn to_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.inner).ok()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl FromStr for HeaderValue {
+    type Err = InvalidHeaderValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_bytes(s.as_bytes())
+    }
+}
+
+impl From<u64> for HeaderValue {
+    fn from(n: u64) -> Self {
+        Self {
+            inner: n.to_string().into_bytes(),
+        }
+    }
+}
+
+impl From<usize> for HeaderValue {
+    fn from(n: usize) -> Self {
+        Self {
+            inner: n.to_string().into_bytes(),
+        }
+    }
+}
+
+impl From<HeaderName> for HeaderValue {
+    fn from(name: HeaderName) -> Self {
+        Self {
+            inner: name.as_str().as_bytes().to_vec(),
+        }
+    }
+}
+
+impl fmt::Debug for HeaderValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\"", self.inner.escape_ascii())
+    }
+}
+
+// Anything that can be used to look up a field in a `HeaderMap`. Lookups with
+// a `&str` compare case-insensitively without allocating.
+pub trait AsHeaderName {
+    fn matches(&self, name: &HeaderName) -> bool;
+}
+
+impl AsHeaderName for HeaderName {
+    fn matches(&self, name: &HeaderName) -> bool {
+        self == name
+    }
+}
+
+impl AsHeaderName for &HeaderName {
+    fn matches(&self, name: &HeaderName) -> bool {
+        *self == name
+    }
+}
+
+impl AsHeaderName for &str {
+    fn matches(&self, name: &HeaderName) -> bool {
+        self.eq_ignore_ascii_case(name.as_str())
+    }
+}
+
+impl AsHeaderName for String {
+    fn matches(&self, name: &HeaderName) -> bool {
+        self.eq_ignore_ascii_case(name.as_str())
+    }
+}
+
+// Header fields in the order they were added.
+//
+// Like `Query`, fields are kept in a contiguous vector rather than a HashMap.
+// Messages rarely carry more than a few dozen fields, for which a linear scan
+// over interned names beats hashing, and a vector preserves insertion order
+// and repeated names for free.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    entries: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self { entries: vec![] }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+
+    // Number of field lines, counting every value of a repeated name.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn contains_key<K: AsHeaderName>(&self, name: K) -> bool {
+        self.entries.iter().any(|(n, _)| name.matches(n))
+    }
+
+    // Returns the first value of `name`.
+    pub fn get<K: AsHeaderName>(&self, name: K) -> Option<&HeaderValue> {
+        self.entries
+            .iter()
+            .find(|(n, _)| name.matches(n))
+            .map(|(_, v)| v)
+    }
+
+    pub fn get_mut<K: AsHeaderName>(
+        &mut self,
+        name: K,
+    ) -> Option<&mut HeaderValue> {
+        self.entries
+            .iter_mut()
+            .find(|(n, _)| name.matches(n))
+            .map(|(_, v)| v)
+    }
+
+    // Returns every value of `name` in insertion order.
+    pub fn get_all<K: AsHeaderName>(
+        &self,
+        name: K,
+    ) -> impl Iterator<Item = &HeaderValue> {
+        self.entries
+            .iter()
+            .filter(move |(n, _)| name.matches(n))
+            .map(|(_, v)| v)
+    }
+
+    // Sets `name` to a single value, replacing any existing values. The field
+    // keeps the position of its first occurrence. Returns the first of the
+    // replaced values.
+    pub fn insert(
+        &mut self,
+        name: HeaderName,
+        value: HeaderValue,
+    ) -> Option<HeaderValue> {
+        let Some(index) = self.entries.iter().position(|(n, _)| *n == name)
+        else {
+            self.entries.push((name, value));
+            return None;
+        };
+        let previous = std::mem::replace(&mut self.entries[index].1, value);
+        let mut i = index + 1;
+        while i < self.entries.len() {
+            if self.entries[i].0 == name {
+                self.entries.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        Some(previous)
+    }
+
+    // Adds another value for `name`, keeping the existing ones.
+    pub fn append(&mut self, name: HeaderName, value: HeaderValue) {
+        self.entries.push((name, value));
+    }
+
+    // Removes every value of `name`, returning the first one.
+    pub fn remove<K: AsHeaderName>(&mut self, name: K) -> Option<HeaderValue> {
+        let mut removed = None;
+        self.entries.retain_mut(|(n, v)| {
+            if !name.matches(n) {
+                return true;
+            }
+            if removed.is_none() {
+                removed = Some(std::mem::replace(v, HeaderValue::empty()));
+            }
+            false
+        });
+        removed
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            inner: self.entries.iter(),
+        }
+    }
+
+    // Distinct field names in order of first occurrence.
+    pub fn keys(&self) -> impl Iterator<Item = &HeaderName> {
+        let mut seen = HashSet::new();
+        self.entries
+            .iter()
+            .map(|(name, _)| name)
+            .filter(move |name| seen.insert(*name))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &HeaderValue> {
+        self.entries.iter().map(|(_, v)| v)
+    }
+}
+
+pub struct Iter<'a> {
+    inner: std::slice::Iter<'a, (HeaderName, HeaderValue)>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a HeaderName, &'a HeaderValue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(n, v)| (n, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+impl<'a> IntoIterator for &'a HeaderMap {
+    type Item = (&'a HeaderName, &'a HeaderValue);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for HeaderMap {
+    type Item = (HeaderName, HeaderValue);
+    type IntoIter = std::vec::IntoIter<(HeaderName, HeaderValue)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl FromIterator<(HeaderName, HeaderValue)> for HeaderMap {
+    fn from_iter<T: IntoIterator<Item = (HeaderName, HeaderValue)>>(
+        iter: T,
+    ) -> Self {
+        Self {
+            entries: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Extend<(HeaderName, HeaderValue)> for HeaderMap {
+    fn extend<T: IntoIterator<Item = (HeaderName, HeaderValue)>>(
+        &mut self,
+        iter: T,
+    ) {
+        self.entries.extend(iter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(s: &str) -> HeaderName {
+        s.parse().unwrap()
+    }
+
+    fn value(s: &str) -> HeaderValue {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_names() {
+        assert_eq!("Content-Length".parse(), Ok(HeaderName::CONTENT_LENGTH));
+        assert_eq!("content-length".parse(), Ok(HeaderName::CONTENT_LENGTH));
+        assert_eq!("HOST".parse(), Ok(HeaderName::HOST));
+        assert_eq!("ETag".parse(), Ok(HeaderName::ETAG));
+        assert_eq!(
+            "Access-Control-Allow-Credentials".parse(),
+            Ok(HeaderName::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+        );
+        // Custom names are lowercased
+        assert_eq!(name("X-Request-Id").as_str(), "x-request-id");
+        assert_eq!(name("X-Request-Id"), name("x-request-id"));
+        // Every tchar is allowed
+        assert_eq!(
+            name("!#$%&'*+-.^_`|~09azAZ").as_str(),
+            "!#$%&'*+-.^_`|~09azaz"
+        );
+
+        assert_eq!("".parse::<HeaderName>(), Err(InvalidHeaderName));
+        assert_eq!("Host ".parse::<HeaderName>(), Err(InvalidHeaderName));
+        assert_eq!("Host:".parse::<HeaderName>(), Err(InvalidHeaderName));
+        assert_eq!("X Y".parse::<HeaderName>(), Err(InvalidHeaderName));
+        assert_eq!("(comment)".parse::<HeaderName>(), Err(InvalidHeaderName));
+        assert_eq!("na\"me".parse::<HeaderName>(), Err(InvalidHeaderName));
+        assert_eq!("héader".parse::<HeaderName>(), Err(InvalidHeaderName));
+        assert_eq!(HeaderName::from_bytes(b"a\r\nb"), Err(InvalidHeaderName));
+    }
+
+    #[test]
+    fn standard_names_are_interned() {
+        assert!(matches!(name("Set-Cookie").inner, Repr::Standard(_)));
+        assert!(matches!(name("X-Powered-By").inner, Repr::Custom(_)));
+        // Longer than any standard name
+        assert!(matches!(
+            name("X-A-Very-Long-Header-Name-That-Is-Not-Standard").inner,
+            Repr::Custom(_)
+        ));
+    }
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(value("text/html").as_bytes(), b"text/html");
+        assert_eq!(value("").as_bytes(), b"");
+        assert_eq!(value("a\tb").as_bytes(), b"a\tb");
+        // obs-text
+        assert_eq!(
+            HeaderValue::from_bytes(b"caf\xe9").unwrap().as_bytes(),
+            b"caf\xe9"
+        );
+        assert_eq!(HeaderValue::from_bytes(b"caf\xe9").unwrap().to_str(), None);
+
+        assert_eq!("a\rb".parse::<HeaderValue>(), Err(InvalidHeaderValue));
+        assert_eq!("a\nb".parse::<HeaderValue>(), Err(InvalidHeaderValue));
+        assert_eq!("a\0b".parse::<HeaderValue>(), Err(InvalidHeaderValue));
+        assert_eq!(
+            "evil\r\nSet-Cookie: a=b".parse::<HeaderValue>(),
+            Err(InvalidHeaderValue)
+        );
+
+        assert_eq!(HeaderValue::from(42u64).as_bytes(), b"42");
+    }
+
+    #[test]
+    fn case_insensitive_lookup() {
+        let mut map = HeaderMap::new();
+        map.insert(HeaderName::CONTENT_TYPE, value("text/plain"));
+        map.insert(name("X-Custom"), value("1"));
+
+        assert_eq!(map.get("content-type"), Some(&value("text/plain")));
+        assert_eq!(map.get("Content-Type"), Some(&value("text/plain")));
+        assert_eq!(
+            map.get(HeaderName::CONTENT_TYPE),
+            Some(&value("text/plain"))
+        );
+        assert_eq!(map.get("x-custom"), Some(&value("1")));
+        assert_eq!(map.get("X-CUSTOM"), Some(&value("1")));
+        let custom = name("x-CUSTOM");
+        assert_eq!(map.get(&custom), Some(&value("1")));
+        assert!(map.contains_key(custom));
+        assert_eq!(map.get("x-other"), None);
+        assert!(map.contains_key("X-Custom"));
+        assert!(!map.contains_key("Content"));
+    }
+
+    #[test]
+    fn multiple_values() {
+        let mut map = HeaderMap::new();
+        map.append(HeaderName::SET_COOKIE, value("a=1"));
+        map.append(HeaderName::VARY, value("Accept"));
+        map.append(name("Set-Cookie"), value("b=2"));
+
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get("set-cookie"), Some(&value("a=1")));
+        assert_eq!(
+            map.get_all("Set-Cookie").collect::<Vec<_>>(),
+            [&value("a=1"), &value("b=2")]
+        );
+        assert_eq!(
+            map.keys().collect::<Vec<_>>(),
+            [&HeaderName::SET_COOKIE, &HeaderName::VARY]
+        );
+
+        // Insert replaces every value and keeps the first position
+        assert_eq!(
+            map.insert(HeaderName::SET_COOKIE, value("c=3")),
+            Some(value("a=1"))
+        );
+        assert_eq!(map.len(), 2);
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            [
+                (&HeaderName::SET_COOKIE, &value("c=3")),
+                (&HeaderName::VARY, &value("Accept"))
+            ]
+        );
+
+        map.append(HeaderName::VARY, value("Origin"));
+        assert_eq!(map.remove("vary"), Some(value("Accept")));
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.remove("vary"), None);
+    }
+
+    #[test]
+    fn insertion_order() {
+        let map: HeaderMap = [
+            (HeaderName::HOST, value("example.com")),
+            (name("X-B"), value("b")),
+            (HeaderName::ACCEPT, value("*/*")),
+            (name("X-A"), value("a")),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            map.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>(),
+            ["host", "x-b", "accept", "x-a"]
+        );
+        assert_eq!(
+            map.values().map(HeaderValue::as_bytes).collect::<Vec<_>>(),
+            [&b"example.com"[..], b"b", b"*/*", b"a"]
+        );
+    }
+}
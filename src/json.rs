@@ -0,0 +1,315 @@
+// Just enough JSON (RFC 8259) for the files and logs the crate writes
+// itself.
+
+use std::fmt::Write as _;
+
+// Objects keep their members in order. Numbers are kept as written, so that
+// integers do not lose precision by going through a float.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+// Deep enough for any document we read, shallow enough not to overflow the
+// stack on hostile input.
+const MAX_DEPTH: usize = 64;
+
+impl Value {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parser = Parser {
+            s: s.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value(0)?;
+        parser.whitespace();
+        (parser.pos == parser.s.len()).then_some(value)
+    }
+
+    // The member of an object, the first if there are several.
+    pub fn get(&self, key: &str) -> Option<&Self> {
+        match self {
+            Self::Object(members) => {
+                members.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Self]> {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+// A string literal, escaping quotes, backslashes and control characters.
+pub fn string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' || c == '\u{7f}' => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.s.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        self.whitespace();
+        let found = self.s.get(self.pos) == Some(&b);
+        self.pos += found as usize;
+        found
+    }
+
+    fn literal(&mut self, literal: &str, value: Value) -> Option<Value> {
+        let found = self.s[self.pos..].starts_with(literal.as_bytes());
+        self.pos += literal.len() * found as usize;
+        found.then_some(value)
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        self.whitespace();
+        match self.s.get(self.pos)? {
+            b'n' => self.literal("null", Value::Null),
+            b't' => self.literal("true", Value::Bool(true)),
+            b'f' => self.literal("false", Value::Bool(false)),
+            b'"' => self.string().map(Value::String),
+            b'[' => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        values.push(self.value(depth + 1)?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return None;
+                        }
+                    }
+                }
+                Some(Value::Array(values))
+            }
+            b'{' => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.whitespace();
+                        let key = self.string()?;
+                        if !self.eat(b':') {
+                            return None;
+                        }
+                        members.push((key, self.value(depth + 1)?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return None;
+                        }
+                    }
+                }
+                Some(Value::Object(members))
+            }
+            _ => self.number(),
+        }
+    }
+
+    // number = [ "-" ] int [ frac ] [ exp ]
+    fn number(&mut self) -> Option<Value> {
+        let start = self.pos;
+        let digits = |p: &mut Self| {
+            let n = p.s[p.pos..]
+                .iter()
+                .take_while(|b| b.is_ascii_digit())
+                .count();
+            p.pos += n;
+            n
+        };
+        self.pos += (self.s.get(self.pos) == Some(&b'-')) as usize;
+        let int_start = self.pos;
+        let int_len = digits(self);
+        if int_len == 0 || (int_len > 1 && self.s[int_start] == b'0') {
+            return None;
+        }
+        if self.s.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            if digits(self) == 0 {
+                return None;
+            }
+        }
+        if let Some(b'e' | b'E') = self.s.get(self.pos) {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.s.get(self.pos) {
+                self.pos += 1;
+            }
+            if digits(self) == 0 {
+                return None;
+            }
+        }
+        let number = std::str::from_utf8(&self.s[start..self.pos]).ok()?;
+        Some(Value::Number(number.into()))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.s.get(self.pos) != Some(&b'"') {
+            return None;
+        }
+        self.pos += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let b = *self.s.get(self.pos)?;
+            self.pos += 1;
+            match b {
+                b'"' => return String::from_utf8(bytes).ok(),
+                b'\\' => {
+                    let escape = *self.s.get(self.pos)?;
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return None,
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                b if b < 0x20 => return None,
+                b => bytes.push(b),
+            }
+        }
+    }
+
+    // The four hex digits after "\u", and a low surrogate after a high one.
+    fn unicode_escape(&mut self) -> Option<char> {
+        let hex = |p: &mut Self| {
+            let digits =
+                std::str::from_utf8(p.s.get(p.pos..p.pos + 4)?).ok()?;
+            p.pos += 4;
+            u32::from_str_radix(digits, 16).ok()
+        };
+        let high = hex(self)?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high);
+        }
+        if !self.s[self.pos..].starts_with(b"\\u") {
+            return None;
+        }
+        self.pos += 2;
+        let low = hex(self)?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return None;
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        let value = Value::parse(
+            r#" {"a": [1, -2.5e3, true, false, null], "b": {"c": "d\"\u00e9\ud83d\ude00\n"}, "a": 0} "#,
+        )
+        .unwrap();
+        let a = value.get("a").unwrap().as_array().unwrap();
+        assert_eq!(a[0].as_u64(), Some(1));
+        assert_eq!(a[1], Value::Number("-2.5e3".into()));
+        assert_eq!(a[1].as_u64(), None);
+        assert_eq!(a[2].as_bool(), Some(true));
+        assert_eq!(a[4], Value::Null);
+        let c = value.get("b").and_then(|b| b.get("c"));
+        assert_eq!(c.and_then(Value::as_str), Some("d\"\u{e9}\u{1f600}\n"));
+        assert_eq!(Value::parse("[]"), Some(Value::Array(Vec::new())));
+        assert_eq!(Value::parse("{}"), Some(Value::Object(Vec::new())));
+
+        for invalid in [
+            "",
+            "[",
+            "[1,]",
+            "{\"a\"}",
+            "{\"a\":1,}",
+            "01",
+            "1.",
+            "-",
+            "1e",
+            "tru",
+            "\"a",
+            "\"\\x\"",
+            "\"\\ud83d\"",
+            "\"\u{1}\"",
+            "[1] 2",
+            "{a: 1}",
+        ] {
+            assert_eq!(Value::parse(invalid), None, "{invalid}");
+        }
+        let deep = "[".repeat(1000) + &"]".repeat(1000);
+        assert_eq!(Value::parse(&deep), None);
+    }
+
+    #[test]
+    fn strings() {
+        let s = "a\"b\\c\nd\u{1}\u{e9}";
+        assert_eq!(string(s), r#""a\"b\\c\nd\u0001é""#);
+        assert_eq!(Value::parse(&string(s)), Some(Value::String(s.into())));
+    }
+}
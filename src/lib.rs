@@ -1,9 +1,28 @@
+pub(crate) mod json;
 pub(crate) mod utils;
 
-pub(crate) mod authority;
-pub(crate) mod method;
-pub(crate) mod path;
-pub(crate) mod query;
-pub(crate) mod scheme;
-pub(crate) mod uri;
-pub(crate) mod version;
+pub mod authority;
+pub mod chunked;
+pub mod client;
+pub mod coding;
+pub mod conditional;
+pub mod cookie;
+pub mod date;
+pub mod etag;
+pub mod framing;
+pub mod header;
+pub mod method;
+pub mod mime;
+pub mod negotiation;
+pub mod origin;
+pub mod parser;
+pub mod path;
+pub mod query;
+pub mod range;
+pub mod request;
+pub mod response;
+pub mod scheme;
+pub mod server;
+pub mod status;
+pub mod uri;
+pub mod version;
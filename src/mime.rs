@@ -0,0 +1,153 @@
+use std::path::Path;
+
+// Used for files whose type cannot be told from their extension, as
+// recommended by RFC 9110 section 8.3.
+pub const DEFAULT: &str = "application/octet-stream";
+
+// Extension to media type, sorted by extension for binary search. Textual
+// types carry a charset since files are expected to be UTF-8.
+const TYPES: &[(&str, &str)] = &[
+    ("7z", "application/x-7z-compressed"),
+    ("aac", "audio/aac"),
+    ("apng", "image/apng"),
+    ("avif", "image/avif"),
+    ("bin", "application/octet-stream"),
+    ("bmp", "image/bmp"),
+    ("css", "text/css; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+    ("epub", "application/epub+zip"),
+    ("flac", "audio/flac"),
+    ("gif", "image/gif"),
+    ("gz", "application/gzip"),
+    ("htm", "text/html; charset=utf-8"),
+    ("html", "text/html; charset=utf-8"),
+    ("ico", "image/x-icon"),
+    ("ics", "text/calendar; charset=utf-8"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("jsonld", "application/ld+json"),
+    ("m4a", "audio/mp4"),
+    ("map", "application/json"),
+    ("md", "text/markdown; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("mpeg", "video/mpeg"),
+    ("oga", "audio/ogg"),
+    ("ogg", "audio/ogg"),
+    ("ogv", "video/ogg"),
+    ("otf", "font/otf"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("rss", "application/rss+xml"),
+    ("rtf", "application/rtf"),
+    ("svg", "image/svg+xml"),
+    ("tar", "application/x-tar"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("ttf", "font/ttf"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("wasm", "application/wasm"),
+    ("wav", "audio/wav"),
+    ("weba", "audio/webm"),
+    ("webm", "video/webm"),
+    ("webmanifest", "application/manifest+json"),
+    ("webp", "image/webp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("xhtml", "application/xhtml+xml"),
+    ("xml", "application/xml"),
+    ("zip", "application/zip"),
+];
+
+// Looks up the media type for a file extension, ignoring case.
+pub fn from_extension(extension: &str) -> Option<&'static str> {
+    let extension = extension.to_ascii_lowercase();
+    TYPES
+        .binary_search_by(|&(ext, _)| ext.cmp(&extension))
+        .ok()
+        .map(|i| TYPES[i].1)
+}
+
+// Guesses the media type of a file from its name, falling back to `DEFAULT`.
+pub fn from_path(path: &Path) -> &'static str {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(from_extension)
+        .unwrap_or(DEFAULT)
+}
+
+// Whether a media type is worth compressing. Text and structured text are,
+// while most image, audio, video, font and archive formats are compressed
+// already.
+pub fn is_compressible(media_type: &str) -> bool {
+    let essence = media_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let Some((type_name, subtype)) = essence.split_once('/') else {
+        return false;
+    };
+    type_name == "text"
+        || subtype.ends_with("+json")
+        || subtype.ends_with("+xml")
+        || matches!(
+            essence.as_str(),
+            "application/javascript"
+                | "application/json"
+                | "application/rtf"
+                | "application/wasm"
+                | "application/x-tar"
+                | "application/xml"
+                | "font/otf"
+                | "font/ttf"
+                | "image/bmp"
+                | "image/x-icon"
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_is_sorted() {
+        assert!(TYPES.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn lookup() {
+        assert_eq!(from_extension("html"), Some("text/html; charset=utf-8"));
+        assert_eq!(from_extension("PNG"), Some("image/png"));
+        assert_eq!(from_extension("unknown"), None);
+        assert_eq!(from_path(Path::new("a/b/style.css")), TYPES[6].1);
+        assert_eq!(from_path(Path::new("archive.tar.gz")), "application/gzip");
+        assert_eq!(from_path(Path::new("Makefile")), DEFAULT);
+        assert_eq!(from_path(Path::new(".png")), DEFAULT);
+    }
+
+    #[test]
+    fn compressible() {
+        for media_type in [
+            "text/html; charset=utf-8",
+            "Application/JSON",
+            "image/svg+xml",
+            "application/ld+json",
+        ] {
+            assert!(is_compressible(media_type), "{media_type}");
+        }
+        for media_type in [
+            "image/png",
+            "application/gzip",
+            "font/woff2",
+            "video/mp4",
+            "text",
+        ] {
+            assert!(!is_compressible(media_type), "{media_type}");
+        }
+    }
+}
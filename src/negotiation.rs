@@ -0,0 +1,739 @@
+use crate::header::{HeaderName, HeaderValue};
+use crate::request::Request;
+use crate::response::Response;
+use crate::{parser, utils};
+
+// A weight between 0 and 1 with at most three decimals, kept in thousandths
+// (RFC 9110 section 12.4.2). A weight of zero means "not acceptable".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Quality(u16);
+
+impl Quality {
+    pub const ZERO: Self = Self(0);
+    pub const ONE: Self = Self(1000);
+
+    pub fn from_thousandths(n: u16) -> Option<Self> {
+        (n <= 1000).then_some(Self(n))
+    }
+
+    pub fn thousandths(&self) -> u16 {
+        self.0
+    }
+
+    // qvalue = ( "0" [ "." 0*3DIGIT ] ) / ( "1" [ "." 0*3("0") ] )
+    fn from_bytes(s: &[u8]) -> Option<Self> {
+        let (int, frac) = match s.iter().position(|&b| b == b'.') {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => (s, &b""[..]),
+        };
+        if frac.len() > 3 || !frac.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        let frac = frac
+            .iter()
+            .chain(b"000")
+            .take(3)
+            .fold(0, |n, &d| n * 10 + (d - b'0') as u16);
+        match int {
+            b"0" => Some(Self(frac)),
+            b"1" if frac == 0 => Some(Self::ONE),
+            _ => None,
+        }
+    }
+}
+
+// One element of an `Accept*` header, split into the range it names, its
+// parameters and its weight.
+struct Element<'a> {
+    range: &'a [u8],
+    // With lowercase names and unquoted values
+    params: Vec<(String, String)>,
+    quality: Quality,
+}
+
+// element    = range parameters
+// parameters = *( OWS ";" OWS [ parameter ] )
+// parameter  = token "=" ( token / quoted-string )
+//
+// The "q" parameter is the weight, and everything after it an extension
+// that is ignored.
+fn parse_element(element: &[u8]) -> Option<Element<'_>> {
+    let end = element.iter().position(|&b| b == b';');
+    let (range, mut rest) = element.split_at(end.unwrap_or(element.len()));
+    let mut parsed = Element {
+        range: parser::trim_whitespace(range),
+        params: vec![],
+        quality: Quality::ONE,
+    };
+    while let Some(after) = rest.strip_prefix(b";") {
+        let param = parser::trim_whitespace(after);
+        if param.is_empty() || param[0] == b';' {
+            rest = param;
+            continue;
+        }
+        let eq = param.iter().position(|&b| b == b'=')?;
+        let (name, value) = (&param[..eq], &param[eq + 1..]);
+        if !utils::is_token(name) {
+            return None;
+        }
+        let (value, after) = match parser::split_quoted_string(value) {
+            Some((quoted, after)) => (unescape(quoted), after),
+            None => {
+                let end = value
+                    .iter()
+                    .position(|&b| b == b';' || b == b' ' || b == b'\t')
+                    .unwrap_or(value.len());
+                if !utils::is_token(&value[..end]) {
+                    return None;
+                }
+                (value[..end].to_vec(), &value[end..])
+            }
+        };
+        if name.eq_ignore_ascii_case(b"q") {
+            parsed.quality = Quality::from_bytes(&value)?;
+            return Some(parsed);
+        }
+        parsed.params.push((
+            String::from_utf8(name.to_ascii_lowercase()).ok()?,
+            String::from_utf8(value).ok()?,
+        ));
+        rest = parser::trim_whitespace(after);
+    }
+    rest.is_empty().then_some(parsed)
+}
+
+// Removes the backslashes of quoted-pairs.
+fn unescape(quoted: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(quoted.len());
+    let mut bytes = quoted.iter();
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'\\' => unescaped.extend(bytes.next()),
+            b => unescaped.push(b),
+        }
+    }
+    unescaped
+}
+
+// Parses every field line of a header as one combined list, skipping
+// elements that are invalid.
+fn elements<'a, T>(
+    request: &'a Request,
+    name: HeaderName,
+    parse: impl Fn(Element<'a>) -> Option<T>,
+) -> Vec<T> {
+    request
+        .headers()
+        .get_all(name)
+        .flat_map(|value| parser::list_elements(value.as_bytes()))
+        .filter_map(parse_element)
+        .filter_map(parse)
+        .collect()
+}
+
+// The first of the offers with the highest weight, provided that weight is
+// not zero. The order of the offers thus breaks ties in the server's favour.
+fn best<O>(offers: &[O], quality: impl Fn(&O) -> Quality) -> Option<&O> {
+    let mut best: Option<(&O, Quality)> = None;
+    for offer in offers {
+        let q = quality(offer);
+        if q > best.map_or(Quality::ZERO, |(_, q)| q) {
+            best = Some((offer, q));
+        }
+    }
+    best.map(|(offer, _)| offer)
+}
+
+// A media range of an `Accept` header, such as "text/*" or
+// "text/html;level=1".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaRange {
+    // Lowercase, "*" for any
+    type_name: String,
+    // Lowercase, "*" for any
+    subtype: String,
+    params: Vec<(String, String)>,
+    quality: Quality,
+}
+
+impl MediaRange {
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    pub fn subtype(&self) -> &str {
+        &self.subtype
+    }
+
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    pub fn quality(&self) -> Quality {
+        self.quality
+    }
+
+    fn any() -> Self {
+        Self {
+            type_name: "*".into(),
+            subtype: "*".into(),
+            params: vec![],
+            quality: Quality::ONE,
+        }
+    }
+
+    // media-range = ( "*/*" / ( type "/" "*" ) / ( type "/" subtype ) )
+    //               parameters
+    fn from_element(element: Element<'_>) -> Option<Self> {
+        let slash = element.range.iter().position(|&b| b == b'/')?;
+        let (type_name, subtype) =
+            (&element.range[..slash], &element.range[slash + 1..]);
+        if !utils::is_token(type_name)
+            || !utils::is_token(subtype)
+            || (type_name == b"*" && subtype != b"*")
+        {
+            return None;
+        }
+        Some(Self {
+            type_name: String::from_utf8(type_name.to_ascii_lowercase())
+                .ok()?,
+            subtype: String::from_utf8(subtype.to_ascii_lowercase()).ok()?,
+            params: element.params,
+            quality: element.quality,
+        })
+    }
+
+    // How specific the range is, to pick the one that applies when several
+    // match (RFC 9110 section 12.5.1): "*/*" < "type/*" < "type/subtype" <
+    // "type/subtype" with parameters.
+    fn specificity(&self) -> (bool, bool, usize) {
+        (
+            self.type_name != "*",
+            self.subtype != "*",
+            self.params.len(),
+        )
+    }
+
+    fn matches(&self, offer: &MediaRange) -> bool {
+        (self.type_name == "*" || self.type_name == offer.type_name)
+            && (self.subtype == "*" || self.subtype == offer.subtype)
+            && self.params.iter().all(|param| offer.params.contains(param))
+    }
+}
+
+// The media types a client accepts, RFC 9110 section 12.5.1.
+//
+// Accept = #( media-range [ weight ] )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Accept {
+    ranges: Vec<MediaRange>,
+}
+
+impl Accept {
+    // A client that sends no valid `Accept` accepts any media type.
+    pub fn from_request(request: &Request) -> Self {
+        let ranges = elements(request, HeaderName::ACCEPT, |element| {
+            MediaRange::from_element(element)
+        });
+        match ranges.is_empty() {
+            true => Self {
+                ranges: vec![MediaRange::any()],
+            },
+            false => Self { ranges },
+        }
+    }
+
+    pub fn ranges(&self) -> &[MediaRange] {
+        &self.ranges
+    }
+
+    // The weight the client gives to a media type such as "application/json"
+    // or "text/html; charset=utf-8", taken from the most specific range that
+    // matches it. Unparseable media types are not acceptable.
+    pub fn quality(&self, media_type: &str) -> Quality {
+        let Some(offer) = parse_element(media_type.as_bytes())
+            .and_then(MediaRange::from_element)
+        else {
+            return Quality::ZERO;
+        };
+        self.ranges
+            .iter()
+            .filter(|range| range.matches(&offer))
+            .max_by_key(|range| range.specificity())
+            .map_or(Quality::ZERO, |range| range.quality)
+    }
+
+    // The media type the client prefers among those the server offers.
+    pub fn negotiate<'o, O: AsRef<str>>(
+        &self,
+        offers: &'o [O],
+    ) -> Option<&'o O> {
+        best(offers, |offer| self.quality(offer.as_ref()))
+    }
+}
+
+// An element of `Accept-Language`, `Accept-Encoding` or `Accept-Charset`:
+// a language range, content coding or charset, or "*" for any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preference {
+    // Lowercase
+    value: String,
+    quality: Quality,
+}
+
+impl Preference {
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn quality(&self) -> Quality {
+        self.quality
+    }
+
+    // The elements of these headers take no parameters besides the weight.
+    fn from_element(
+        element: Element<'_>,
+        is_valid: fn(&[u8]) -> bool,
+    ) -> Option<Self> {
+        if !element.params.is_empty() || !is_valid(element.range) {
+            return None;
+        }
+        Some(Self {
+            value: String::from_utf8(element.range.to_ascii_lowercase())
+                .ok()?,
+            quality: element.quality,
+        })
+    }
+
+    fn any() -> Self {
+        Self {
+            value: "*".into(),
+            quality: Quality::ONE,
+        }
+    }
+}
+
+// The natural languages a client prefers, RFC 9110 section 12.5.4.
+//
+// Accept-Language = #( language-range [ weight ] )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AcceptLanguage {
+    ranges: Vec<Preference>,
+}
+
+impl AcceptLanguage {
+    // A client that sends no valid `Accept-Language` accepts any language.
+    pub fn from_request(request: &Request) -> Self {
+        let ranges =
+            elements(request, HeaderName::ACCEPT_LANGUAGE, |element| {
+                Preference::from_element(element, is_language_range)
+            });
+        match ranges.is_empty() {
+            true => Self {
+                ranges: vec![Preference::any()],
+            },
+            false => Self { ranges },
+        }
+    }
+
+    pub fn ranges(&self) -> &[Preference] {
+        &self.ranges
+    }
+
+    // The weight the client gives to a language tag such as "en-US", taken
+    // from the longest range that matches it. Ranges match as in the basic
+    // filtering of RFC 4647 section 3.3.1: "en" matches "en" and "en-US",
+    // but not "eng".
+    pub fn quality(&self, tag: &str) -> Quality {
+        self.ranges
+            .iter()
+            .filter(|range| {
+                let range = range.value.as_bytes();
+                let tag = tag.as_bytes();
+                range == b"*"
+                    || tag.eq_ignore_ascii_case(range)
+                    || (tag.len() > range.len()
+                        && tag[..range.len()].eq_ignore_ascii_case(range)
+                        && tag[range.len()] == b'-')
+            })
+            .max_by_key(|range| match range.value.as_str() {
+                "*" => 0,
+                value => value.len(),
+            })
+            .map_or(Quality::ZERO, |range| range.quality)
+    }
+
+    // The language the client prefers among those the server offers.
+    pub fn negotiate<'o, O: AsRef<str>>(
+        &self,
+        offers: &'o [O],
+    ) -> Option<&'o O> {
+        best(offers, |offer| self.quality(offer.as_ref()))
+    }
+}
+
+// language-range = ( 1*8ALPHA *( "-" 1*8alphanum ) ) / "*"
+fn is_language_range(s: &[u8]) -> bool {
+    if s == b"*" {
+        return true;
+    }
+    s.split(|&b| b == b'-').enumerate().all(|(i, subtag)| {
+        (1..=8).contains(&subtag.len())
+            && subtag.iter().all(|b| match i {
+                0 => b.is_ascii_alphabetic(),
+                _ => b.is_ascii_alphanumeric(),
+            })
+    })
+}
+
+// The content codings a client accepts, RFC 9110 section 12.5.3.
+//
+// Accept-Encoding = #( codings [ weight ] )
+// codings         = content-coding / "identity" / "*"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AcceptEncoding {
+    codings: Vec<Preference>,
+}
+
+impl AcceptEncoding {
+    // A client that sends no `Accept-Encoding` accepts any coding, while an
+    // empty one means that only "identity", no coding at all, is accepted.
+    pub fn from_request(request: &Request) -> Self {
+        if !request.headers().contains_key(HeaderName::ACCEPT_ENCODING) {
+            return Self {
+                codings: vec![Preference::any()],
+            };
+        }
+        Self {
+            codings: elements(
+                request,
+                HeaderName::ACCEPT_ENCODING,
+                |element| Preference::from_element(element, utils::is_token),
+            ),
+        }
+    }
+
+    pub fn codings(&self) -> &[Preference] {
+        &self.codings
+    }
+
+    // The weight the client gives to a content coding such as "gzip".
+    // "identity" is acceptable unless it is excluded explicitly, by name or
+    // through "*".
+    pub fn quality(&self, coding: &str) -> Quality {
+        let exact = self
+            .codings
+            .iter()
+            .find(|pref| pref.value.eq_ignore_ascii_case(coding));
+        let any = self.codings.iter().find(|pref| pref.value == "*");
+        match (exact.or(any), coding.eq_ignore_ascii_case("identity")) {
+            (Some(pref), _) => pref.quality,
+            (None, true) => Quality::ONE,
+            (None, false) => Quality::ZERO,
+        }
+    }
+
+    // The coding the client prefers among those the server offers, which
+    // should include "identity" unless an unencoded response is not an
+    // option.
+    pub fn negotiate<'o, O: AsRef<str>>(
+        &self,
+        offers: &'o [O],
+    ) -> Option<&'o O> {
+        best(offers, |offer| self.quality(offer.as_ref()))
+    }
+}
+
+// The charsets a client accepts, RFC 9110 section 12.5.2.
+//
+// Accept-Charset = #( ( token / "*" ) [ weight ] )
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AcceptCharset {
+    charsets: Vec<Preference>,
+}
+
+impl AcceptCharset {
+    // A client that sends no valid `Accept-Charset` accepts any charset.
+    pub fn from_request(request: &Request) -> Self {
+        let charsets =
+            elements(request, HeaderName::ACCEPT_CHARSET, |element| {
+                Preference::from_element(element, utils::is_token)
+            });
+        match charsets.is_empty() {
+            true => Self {
+                charsets: vec![Preference::any()],
+            },
+            false => Self { charsets },
+        }
+    }
+
+    pub fn charsets(&self) -> &[Preference] {
+        &self.charsets
+    }
+
+    // The weight the client gives to a charset such as "utf-8".
+    pub fn quality(&self, charset: &str) -> Quality {
+        let exact = self
+            .charsets
+            .iter()
+            .find(|pref| pref.value.eq_ignore_ascii_case(charset));
+        let any = self.charsets.iter().find(|pref| pref.value == "*");
+        exact.or(any).map_or(Quality::ZERO, |pref| pref.quality)
+    }
+
+    // The charset the client prefers among those the server offers.
+    pub fn negotiate<'o, O: AsRef<str>>(
+        &self,
+        offers: &'o [O],
+    ) -> Option<&'o O> {
+        best(offers, |offer| self.quality(offer.as_ref()))
+    }
+}
+
+// Adds a request header to the `Vary` header of a response whose content
+// was negotiated on it, so caches do not hand it to clients that would
+// have gotten a different one. Does nothing if the header is listed already
+// or the response varies on everything ("*").
+pub fn vary(response: &mut Response, name: HeaderName) {
+    let headers = response.headers_mut();
+    let mut listed: Vec<&[u8]> = headers
+        .get_all(HeaderName::VARY)
+        .flat_map(|value| parser::list_elements(value.as_bytes()))
+        .collect();
+    if listed.iter().any(|element| {
+        *element == b"*"
+            || element.eq_ignore_ascii_case(name.as_str().as_bytes())
+    }) {
+        return;
+    }
+    listed.push(name.as_str().as_bytes());
+    let value = HeaderValue::from_bytes(&listed.join(&b", "[..]))
+        .expect("list of valid header values");
+    headers.insert(HeaderName::VARY, value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::method::Method;
+    use crate::status::StatusCode;
+    use crate::uri::RequestTarget;
+
+    fn request(name: HeaderName, values: &[&str]) -> Request {
+        let target = RequestTarget::parse("/", &Method::Get).unwrap();
+        let mut request = Request::new(Method::Get, target);
+        for value in values {
+            request
+                .headers_mut()
+                .append(name.clone(), value.parse().unwrap());
+        }
+        request
+    }
+
+    fn q(n: u16) -> Quality {
+        Quality::from_thousandths(n).unwrap()
+    }
+
+    #[test]
+    fn qvalues() {
+        for (s, expected) in [
+            ("1", Some(1000)),
+            ("1.000", Some(1000)),
+            ("0", Some(0)),
+            ("0.5", Some(500)),
+            ("0.05", Some(50)),
+            ("0.123", Some(123)),
+            ("0.", Some(0)),
+            ("1.001", None),
+            ("0.1234", None),
+            ("2", None),
+            (".5", None),
+            ("", None),
+        ] {
+            assert_eq!(
+                Quality::from_bytes(s.as_bytes()),
+                expected.map(Quality),
+                "{s}"
+            );
+        }
+    }
+
+    #[test]
+    fn parameters() {
+        let element =
+            parse_element(b"text/html ; level=1;Q=0.5; ext=\"a;b\"").unwrap();
+        assert_eq!(element.range, b"text/html");
+        assert_eq!(element.params, [("level".into(), "1".into())]);
+        assert_eq!(element.quality, q(500));
+
+        let element = parse_element(b"a/b;x=\"q\\\"uo;ted\";;y=z").unwrap();
+        assert_eq!(
+            element.params,
+            [("x".into(), "q\"uo;ted".into()), ("y".into(), "z".into()),]
+        );
+
+        for invalid in [
+            &b"a/b;q=2"[..],
+            b"a/b;x",
+            b"a/b;x=\"open",
+            b"a/b;=1",
+            b"a/b;x=y z",
+        ] {
+            assert!(parse_element(invalid).is_none());
+        }
+    }
+
+    // The example from RFC 9110 section 12.5.1
+    #[test]
+    fn accept() {
+        let accept = Accept::from_request(&request(
+            HeaderName::ACCEPT,
+            &[
+                "text/*;q=0.3, text/plain;q=0.7, text/plain;format=flowed",
+                "text/plain;format=fixed;q=0.4, */*;q=0.5",
+            ],
+        ));
+        for (media_type, expected) in [
+            ("text/plain;format=flowed", 1000),
+            ("text/plain", 700),
+            ("text/html", 300),
+            ("image/jpeg", 500),
+            ("text/plain;format=fixed", 400),
+            ("text/html;level=3", 300),
+            ("Text/Plain", 700),
+            ("garbage", 0),
+        ] {
+            assert_eq!(accept.quality(media_type), q(expected), "{media_type}");
+        }
+
+        let accept = Accept::from_request(&request(
+            HeaderName::ACCEPT,
+            &["text/html, application/json;q=0.9, */*;q=0.1"],
+        ));
+        let offers = ["application/json", "text/html"];
+        assert_eq!(accept.negotiate(&offers), Some(&"text/html"));
+        assert_eq!(
+            accept.negotiate(&["image/png", "text/plain"]),
+            Some(&"image/png")
+        );
+
+        let accept =
+            Accept::from_request(&request(HeaderName::ACCEPT, &["image/*"]));
+        assert_eq!(accept.negotiate(&offers), None);
+
+        // Missing or invalid: anything goes, so the server's first choice
+        for values in [&[][..], &["*/html, nonsense"]] {
+            let accept =
+                Accept::from_request(&request(HeaderName::ACCEPT, values));
+            assert_eq!(accept.negotiate(&offers), Some(&"application/json"));
+        }
+    }
+
+    #[test]
+    fn accept_language() {
+        let accept = AcceptLanguage::from_request(&request(
+            HeaderName::ACCEPT_LANGUAGE,
+            &["da, en-gb;q=0.8, en;q=0.7, *;q=0.1"],
+        ));
+        for (tag, expected) in [
+            ("da", 1000),
+            ("en-GB", 800),
+            ("en-gb-oed", 800),
+            ("en", 700),
+            ("en-US", 700),
+            ("eng", 100),
+            ("de", 100),
+        ] {
+            assert_eq!(accept.quality(tag), q(expected), "{tag}");
+        }
+        assert_eq!(accept.negotiate(&["en-US", "en-GB"]), Some(&"en-GB"));
+
+        let accept = AcceptLanguage::from_request(&request(
+            HeaderName::ACCEPT_LANGUAGE,
+            &["fr-CH, fr;q=0.9"],
+        ));
+        assert_eq!(accept.negotiate(&["de", "en"]), None);
+        assert!(!is_language_range(b"toolongtag"));
+        assert!(!is_language_range(b"en-"));
+        assert!(is_language_range(b"zh-Hant-TW"));
+    }
+
+    #[test]
+    fn accept_encoding() {
+        let accept = AcceptEncoding::from_request(&request(
+            HeaderName::ACCEPT_ENCODING,
+            &["gzip;q=0.8, br"],
+        ));
+        assert_eq!(accept.quality("GZIP"), q(800));
+        assert_eq!(accept.quality("deflate"), Quality::ZERO);
+        assert_eq!(accept.quality("identity"), Quality::ONE);
+        assert_eq!(accept.negotiate(&["gzip", "identity"]), Some(&"identity"));
+        assert_eq!(accept.negotiate(&["gzip", "br"]), Some(&"br"));
+
+        for (values, identity) in [
+            (&["gzip, identity;q=0"][..], false),
+            (&["*;q=0"], false),
+            (&["*;q=0, identity;q=0.5"], true),
+            // Only identity
+            (&[""], true),
+            // Anything
+            (&[], true),
+        ] {
+            let accept = AcceptEncoding::from_request(&request(
+                HeaderName::ACCEPT_ENCODING,
+                values,
+            ));
+            let negotiated = accept.negotiate(&["identity"]).is_some();
+            assert_eq!(negotiated, identity, "{values:?}");
+        }
+        let accept = AcceptEncoding::from_request(&request(
+            HeaderName::ACCEPT_ENCODING,
+            &[""],
+        ));
+        assert_eq!(accept.quality("gzip"), Quality::ZERO);
+        let accept = AcceptEncoding::from_request(&request(
+            HeaderName::ACCEPT_ENCODING,
+            &[],
+        ));
+        assert_eq!(accept.quality("gzip"), Quality::ONE);
+    }
+
+    #[test]
+    fn accept_charset() {
+        let accept = AcceptCharset::from_request(&request(
+            HeaderName::ACCEPT_CHARSET,
+            &["iso-8859-5, unicode-1-1;q=0.8"],
+        ));
+        assert_eq!(accept.quality("ISO-8859-5"), Quality::ONE);
+        assert_eq!(accept.quality("utf-8"), Quality::ZERO);
+        assert_eq!(
+            accept.negotiate(&["utf-8", "unicode-1-1"]),
+            Some(&"unicode-1-1")
+        );
+        let accept = AcceptCharset::from_request(&request(
+            HeaderName::ACCEPT_CHARSET,
+            &[],
+        ));
+        assert_eq!(accept.negotiate(&["utf-8"]), Some(&"utf-8"));
+    }
+
+    #[test]
+    fn vary_header() {
+        let mut response = Response::new(StatusCode::OK);
+        vary(&mut response, HeaderName::ACCEPT);
+        vary(&mut response, HeaderName::ACCEPT_ENCODING);
+        vary(&mut response, HeaderName::ACCEPT);
+        assert_eq!(
+            response.headers().get(HeaderName::VARY).unwrap().as_bytes(),
+            b"accept, accept-encoding"
+        );
+
+        let mut response = Response::new(StatusCode::OK)
+            .with_header(HeaderName::VARY, "*".parse().unwrap());
+        vary(&mut response, HeaderName::ACCEPT);
+        assert_eq!(
+            response.headers().get(HeaderName::VARY).unwrap().as_bytes(),
+            b"*"
+        );
+    }
+}
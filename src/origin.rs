@@ -0,0 +1,148 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::authority::Authority;
+use crate::header::{HeaderName, HeaderValue};
+use crate::request::Request;
+use crate::scheme::Scheme;
+use crate::uri::Uri;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidOrigin;
+
+// A serialized origin, the scheme, host and port triple of the `Origin`
+// header (RFC 6454 section 7), such as "https://example.com:8443".
+//
+// A port that is the default for the scheme is dropped, so origins that
+// only differ in spelling it out compare equal. Opaque origins, serialized
+// as "null", are not represented.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Origin {
+    scheme: Scheme,
+    authority: Authority,
+}
+
+impl Origin {
+    pub fn from_bytes(s: &[u8]) -> Result<Self, InvalidOrigin> {
+        std::str::from_utf8(s).map_err(|_| InvalidOrigin)?.parse()
+    }
+
+    // The origin in the request's `Origin` header, `None` if it is missing,
+    // invalid or "null".
+    pub fn from_request(request: &Request) -> Option<Self> {
+        let value = request.headers().get(HeaderName::ORIGIN)?;
+        Self::from_bytes(value.as_bytes()).ok()
+    }
+
+    // The origin of an absolute URI, `None` for one in origin-form.
+    pub fn from_uri(uri: &Uri) -> Option<Self> {
+        let (scheme, authority) = (uri.scheme()?, uri.authority()?);
+        format!("{scheme}://{authority}").parse().ok()
+    }
+
+    pub fn scheme(&self) -> &Scheme {
+        &self.scheme
+    }
+
+    pub fn host(&self) -> &str {
+        self.authority.host()
+    }
+
+    // `None` for the default port of the scheme.
+    pub fn port(&self) -> Option<u16> {
+        self.authority.port()
+    }
+}
+
+fn default_port(scheme: &Scheme) -> Option<u16> {
+    match scheme.as_str() {
+        "http" | "ws" => Some(80),
+        "https" | "wss" => Some(443),
+        _ => None,
+    }
+}
+
+impl FromStr for Origin {
+    type Err = InvalidOrigin;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s.split_once("://").ok_or(InvalidOrigin)?;
+        let scheme: Scheme = scheme.parse().map_err(|_| InvalidOrigin)?;
+        if scheme == Scheme::EMPTY {
+            return Err(InvalidOrigin);
+        }
+        // Only the authority, without a path or a trailing slash. The empty
+        // port a URI may have is not allowed either.
+        if rest.ends_with(':') {
+            return Err(InvalidOrigin);
+        }
+        let authority: Authority = rest.parse().map_err(|_| InvalidOrigin)?;
+        let authority = match authority.port() {
+            Some(port) if Some(port) == default_port(&scheme) => {
+                authority.host().parse().map_err(|_| InvalidOrigin)?
+            }
+            _ => authority,
+        };
+        Ok(Self { scheme, authority })
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}://{}", self.scheme, self.authority)
+    }
+}
+
+impl From<&Origin> for HeaderValue {
+    fn from(origin: &Origin) -> Self {
+        HeaderValue::from_bytes(origin.to_string().as_bytes())
+            .expect("valid header value")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        let origin: Origin = "https://Example.com:8443".parse().unwrap();
+        assert_eq!(origin.scheme(), &Scheme::HTTPS);
+        assert_eq!(origin.host(), "example.com");
+        assert_eq!(origin.port(), Some(8443));
+        assert_eq!(origin.to_string(), "https://example.com:8443");
+
+        // Default ports are dropped
+        let origin: Origin = "HTTP://example.com:80".parse().unwrap();
+        assert_eq!(origin.port(), None);
+        assert_eq!(origin, "http://example.com".parse().unwrap());
+        assert_eq!(origin.to_string(), "http://example.com");
+        assert_ne!(origin, "https://example.com".parse().unwrap());
+        assert_ne!(origin, "http://example.com:8080".parse().unwrap());
+
+        assert!(Origin::from_bytes(b"http://127.0.0.1:3000").is_ok());
+        let uri = "http://example.com:80/path?q".parse().unwrap();
+        assert_eq!(Origin::from_uri(&uri), Some(origin));
+        assert_eq!(Origin::from_uri(&"/path".parse().unwrap()), None);
+        for invalid in [
+            "null",
+            "example.com",
+            "://example.com",
+            "http://",
+            "http://example.com/",
+            "http://example.com/path",
+            "http://user@example.com",
+            "http://example.com:",
+            "http://example.com:http",
+            "http://example.com:99999",
+            "http://example.com:80:80",
+            "http://exa mple.com",
+        ] {
+            assert_eq!(
+                invalid.parse::<Origin>(),
+                Err(InvalidOrigin),
+                "{invalid}"
+            );
+        }
+    }
+}
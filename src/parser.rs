@@ -0,0 +1,827 @@
+use crate::header::{HeaderMap, HeaderName, HeaderValue};
+use crate::method::Method;
+use crate::status::StatusCode;
+use crate::uri::RequestTarget;
+use crate::utils;
+use crate::version::Version;
+
+// Result of feeding a (possibly incomplete) buffer to one of the parsers.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Status<T> {
+    Complete(T),
+    // More bytes are needed before anything can be returned.
+    Partial,
+}
+
+impl<T> Status<T> {
+    pub fn is_complete(&self) -> bool {
+        matches!(self, Self::Complete(_))
+    }
+}
+
+// A header field line borrowed straight from the input buffer. The name is
+// guaranteed to be a valid token and the value has been stripped of
+// surrounding whitespace and never contains CR, LF or NUL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawHeader<'b> {
+    pub name: &'b str,
+    pub value: &'b [u8],
+}
+
+impl RawHeader<'_> {
+    pub const EMPTY: RawHeader<'static> = RawHeader {
+        name: "",
+        value: b"",
+    };
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidRequestLine,
+    InvalidStatusLine,
+    InvalidStatusCode,
+    // The method is a valid token but not one we implement
+    UnknownMethod,
+    InvalidTarget,
+    InvalidVersion,
+    // A well-formed HTTP-version of a major version we do not support
+    UnsupportedVersion,
+    InvalidHeaderName,
+    InvalidHeaderValue,
+    // Line folding (a field line starting with whitespace) is obsolete and
+    // rejected outright, see RFC 9112 section 5.2.
+    ObsoleteLineFolding,
+    // Missing in a HTTP/1.1 request, or sent more than once
+    InvalidHost,
+    // More field lines than the caller provided room for
+    TooManyHeaders,
+    // The request line is longer than `RequestParser::max_request_line_len`
+    RequestLineTooLong,
+    // The head is larger than `RequestParser::max_head_size`
+    HeadTooLarge,
+}
+
+// Scans for the empty line terminating a message head. The scan picks up
+// where the previous call left off, so feeding a growing buffer is linear in
+// its length rather than quadratic.
+#[derive(Debug, Default)]
+struct HeadScanner {
+    // Where the line currently being scanned starts
+    line_start: usize,
+    // Where the first non-empty line starts
+    head_start: Option<usize>,
+}
+
+impl HeadScanner {
+    // Returns the range of the head, excluding the terminating empty line,
+    // and the total number of bytes consumed including it.
+    fn scan(&mut self, buf: &[u8]) -> Option<(usize, usize, usize)> {
+        while let Some(lf) =
+            buf[self.line_start..].iter().position(|&b| b == b'\n')
+        {
+            let lf = self.line_start + lf;
+            let line = strip_cr(&buf[self.line_start..lf]);
+            match (line.is_empty(), self.head_start) {
+                (true, Some(head_start)) => {
+                    // Excludes the LF ending the last non-empty line
+                    let head_end = self.line_start - 1;
+                    *self = Self::default();
+                    return Some((head_start, head_end, lf + 1));
+                }
+                // Empty lines preceding the start line are ignored, see
+                // RFC 9112 section 2.2.
+                (true, None) => {}
+                (false, None) => self.head_start = Some(self.line_start),
+                (false, Some(_)) => {}
+            }
+            self.line_start = lf + 1;
+        }
+        None
+    }
+}
+
+// HTTP/1.1 lines end in CRLF, but a lone LF is accepted as well (RFC 9112
+// section 2.2).
+fn strip_cr(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn lines(head: &[u8]) -> impl Iterator<Item = &[u8]> {
+    head.split(|&b| b == b'\n').map(strip_cr)
+}
+
+fn is_whitespace(b: &u8) -> bool {
+    matches!(b, b' ' | b'\t')
+}
+
+pub(crate) fn trim_whitespace(mut bytes: &[u8]) -> &[u8] {
+    while let [first, rest @ ..] = bytes
+        && is_whitespace(first)
+    {
+        bytes = rest;
+    }
+    while let [rest @ .., last] = bytes
+        && is_whitespace(last)
+    {
+        bytes = rest;
+    }
+    bytes
+}
+
+// quoted-string = DQUOTE *( qdtext / quoted-pair ) DQUOTE
+// qdtext        = HTAB / SP / %x21 / %x23-5B / %x5D-7E / obs-text
+// quoted-pair   = "\" ( HTAB / SP / VCHAR / obs-text )
+pub(crate) fn split_quoted_string(s: &[u8]) -> Option<(&[u8], &[u8])> {
+    let s = s.strip_prefix(b"\"")?;
+    let mut i = 0;
+    while i < s.len() {
+        match s[i] {
+            b'"' => return Some((&s[..i], &s[i + 1..])),
+            b'\\' if i + 1 < s.len() && is_qd_byte(s[i + 1]) => i += 2,
+            b if b != b'\\' && is_qd_byte(b) => i += 1,
+            _ => return None,
+        }
+    }
+    None
+}
+
+fn is_qd_byte(b: u8) -> bool {
+    b == b'\t' || b == b' ' || (b'!'..=b'~').contains(&b) || b >= 0x80
+}
+
+// Splits a comma-separated list field value (the "#rule" from RFC 9110
+// section 5.6.1) into its trimmed, non-empty elements. Commas inside
+// quoted-strings do not split.
+pub(crate) fn list_elements(value: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = value;
+    std::iter::from_fn(move || {
+        loop {
+            if rest.is_empty() {
+                return None;
+            }
+            let (mut i, mut quoted) = (0, false);
+            while i < rest.len() && (quoted || rest[i] != b',') {
+                match rest[i] {
+                    b'"' => quoted = !quoted,
+                    b'\\' if quoted => i += 1,
+                    _ => {}
+                }
+                i += 1;
+            }
+            let element = trim_whitespace(&rest[..i.min(rest.len())]);
+            rest = rest.get(i + 1..).unwrap_or_default();
+            if !element.is_empty() {
+                return Some(element);
+            }
+        }
+    })
+}
+
+// field-line = field-name ":" OWS field-value OWS
+pub(crate) fn parse_header_line(
+    line: &[u8],
+) -> Result<RawHeader<'_>, ParseError> {
+    if line.first().is_some_and(is_whitespace) {
+        return Err(ParseError::ObsoleteLineFolding);
+    }
+    let Some(colon) = line.iter().position(|&b| b == b':') else {
+        return Err(ParseError::InvalidHeaderName);
+    };
+    // No whitespace is allowed between the name and the colon. Being lenient
+    // here is a well-known source of request smuggling.
+    let (name, value) = (&line[..colon], trim_whitespace(&line[colon + 1..]));
+    if !utils::is_token(name) {
+        return Err(ParseError::InvalidHeaderName);
+    }
+    if value.iter().any(|b| matches!(b, b'\r' | b'\0')) {
+        return Err(ParseError::InvalidHeaderValue);
+    }
+    Ok(RawHeader {
+        // Tokens are ASCII
+        name: std::str::from_utf8(name)
+            .map_err(|_| ParseError::InvalidHeaderName)?,
+        value,
+    })
+}
+
+fn parse_header_lines<'h, 'b>(
+    lines: impl Iterator<Item = &'b [u8]>,
+    headers: &'h mut [RawHeader<'b>],
+) -> Result<&'h [RawHeader<'b>], ParseError> {
+    let mut count = 0;
+    for line in lines {
+        let header = parse_header_line(line)?;
+        let Some(slot) = headers.get_mut(count) else {
+            return Err(ParseError::TooManyHeaders);
+        };
+        *slot = header;
+        count += 1;
+    }
+    Ok(&headers[..count])
+}
+
+pub fn headers_to_map(raw: &[RawHeader]) -> HeaderMap {
+    raw.iter()
+        .filter_map(|h| {
+            Some((
+                HeaderName::from_bytes(h.name.as_bytes()).ok()?,
+                HeaderValue::from_bytes(h.value).ok()?,
+            ))
+        })
+        .collect()
+}
+
+// A parsed request head. Header fields borrow from the input buffer and the
+// caller provided header slice; nothing but the request target is allocated.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RawRequest<'h, 'b> {
+    pub method: Method,
+    pub target: RequestTarget,
+    pub version: Version,
+    pub headers: &'h [RawHeader<'b>],
+    // Length of the head including the terminating empty line. The body (or
+    // the next pipelined request) starts at this offset.
+    pub head_len: usize,
+}
+
+// Push-based HTTP/1.1 request head parser.
+//
+// Call `parse` with everything received so far every time more bytes arrive.
+// The buffer must keep its previous contents as a prefix between calls until
+// `Status::Complete` is returned, after which the parser is ready for the
+// next request.
+//
+// Both the request line and the head as a whole are limited in size. The
+// limits are checked on partial input too, so a client trickling in an
+// endless head is rejected as soon as it crosses them rather than once the
+// head is complete.
+#[derive(Debug)]
+pub struct RequestParser {
+    scanner: HeadScanner,
+    max_request_line_len: usize,
+    max_head_size: usize,
+}
+
+impl Default for RequestParser {
+    fn default() -> Self {
+        Self {
+            scanner: HeadScanner::default(),
+            max_request_line_len: 8 * 1024,
+            max_head_size: 64 * 1024,
+        }
+    }
+}
+
+impl RequestParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Longest request line accepted, excluding the line terminator.
+    pub fn max_request_line_len(mut self, len: usize) -> Self {
+        self.max_request_line_len = len;
+        self
+    }
+
+    // Largest head accepted, including leading empty lines and the empty
+    // line terminating it.
+    pub fn max_head_size(mut self, size: usize) -> Self {
+        self.max_head_size = size;
+        self
+    }
+
+    pub fn parse<'h, 'b>(
+        &mut self,
+        buf: &'b [u8],
+        headers: &'h mut [RawHeader<'b>],
+    ) -> Result<Status<RawRequest<'h, 'b>>, ParseError> {
+        let Some((head_start, head_end, head_len)) = self.scanner.scan(buf)
+        else {
+            // Until the request line is complete, the line being scanned is
+            // the request line.
+            if self.scanner.head_start.is_none()
+                && buf.len() - self.scanner.line_start
+                    > self.max_request_line_len
+            {
+                return Err(ParseError::RequestLineTooLong);
+            }
+            if buf.len() > self.max_head_size {
+                return Err(ParseError::HeadTooLarge);
+            }
+            return Ok(Status::Partial);
+        };
+        let mut lines = lines(&buf[head_start..head_end]);
+        let request_line = lines.next().unwrap_or_default();
+        if request_line.len() > self.max_request_line_len {
+            return Err(ParseError::RequestLineTooLong);
+        }
+        if head_len > self.max_head_size {
+            return Err(ParseError::HeadTooLarge);
+        }
+        let (method, target, version) = parse_request_line(request_line)?;
+        let headers = parse_header_lines(lines, headers)?;
+
+        // RFC 9112 section 3.2: A server MUST respond with a 400 to any
+        // HTTP/1.1 request message that lacks a Host header field and to any
+        // request message that contains more than one Host header field.
+        let hosts = headers
+            .iter()
+            .filter(|h| h.name.eq_ignore_ascii_case("host"))
+            .count();
+        if hosts > 1 || (hosts == 0 && version >= Version::Http1_1) {
+            return Err(ParseError::InvalidHost);
+        }
+
+        Ok(Status::Complete(RawRequest {
+            method,
+            target,
+            version,
+            headers,
+            head_len,
+        }))
+    }
+}
+
+// A parsed response head, see `RawRequest`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RawResponse<'h, 'b> {
+    pub version: Version,
+    pub status: StatusCode,
+    pub reason: &'b [u8],
+    pub headers: &'h [RawHeader<'b>],
+    pub head_len: usize,
+}
+
+// Push-based HTTP/1.1 response head parser, see `RequestParser`.
+#[derive(Debug, Default)]
+pub struct ResponseParser {
+    scanner: HeadScanner,
+}
+
+impl ResponseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse<'h, 'b>(
+        &mut self,
+        buf: &'b [u8],
+        headers: &'h mut [RawHeader<'b>],
+    ) -> Result<Status<RawResponse<'h, 'b>>, ParseError> {
+        let Some((head_start, head_end, head_len)) = self.scanner.scan(buf)
+        else {
+            return Ok(Status::Partial);
+        };
+        let mut lines = lines(&buf[head_start..head_end]);
+        let status_line = lines.next().unwrap_or_default();
+        let (version, status, reason) = parse_status_line(status_line)?;
+        let headers = parse_header_lines(lines, headers)?;
+        Ok(Status::Complete(RawResponse {
+            version,
+            status,
+            reason,
+            headers,
+            head_len,
+        }))
+    }
+}
+
+// status-line = HTTP-version SP status-code SP [ reason-phrase ]
+//
+// Some servers leave out the second SP when the reason phrase is empty, which
+// is accepted as well.
+fn parse_status_line(
+    line: &[u8],
+) -> Result<(Version, StatusCode, &[u8]), ParseError> {
+    let mut parts = line.splitn(3, |&b| b == b' ');
+    let (Some(version), Some(status)) = (parts.next(), parts.next()) else {
+        return Err(ParseError::InvalidStatusLine);
+    };
+    let reason = parts.next().unwrap_or_default();
+    // reason-phrase = 1*( HTAB / SP / VCHAR / obs-text )
+    if reason.iter().any(|&b| b != b'\t' && b.is_ascii_control()) {
+        return Err(ParseError::InvalidStatusLine);
+    }
+    let version =
+        Version::from_bytes(version).map_err(|_| ParseError::InvalidVersion)?;
+    let status = StatusCode::from_bytes(status)
+        .map_err(|_| ParseError::InvalidStatusCode)?;
+    Ok((version, status, reason))
+}
+
+// request-line = method SP request-target SP HTTP-version
+fn parse_request_line(
+    line: &[u8],
+) -> Result<(Method, RequestTarget, Version), ParseError> {
+    let mut parts = line.split(|&b| b == b' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::InvalidRequestLine);
+    };
+    if !utils::is_token(method) {
+        return Err(ParseError::InvalidRequestLine);
+    }
+    let method = std::str::from_utf8(method)
+        .ok()
+        .and_then(|m| m.parse::<Method>().ok())
+        .ok_or(ParseError::UnknownMethod)?;
+    let target = std::str::from_utf8(target)
+        .ok()
+        .and_then(|t| RequestTarget::parse(t, &method).ok())
+        .ok_or(ParseError::InvalidTarget)?;
+    let version = match Version::from_bytes(version) {
+        Ok(version @ (Version::Http1_0 | Version::Http1_1)) => version,
+        Ok(_) => return Err(ParseError::UnsupportedVersion),
+        Err(_) => match *version {
+            [b'H', b'T', b'T', b'P', b'/', major, b'.', minor]
+                if major.is_ascii_digit() && minor.is_ascii_digit() =>
+            {
+                return Err(ParseError::UnsupportedVersion);
+            }
+            _ => return Err(ParseError::InvalidVersion),
+        },
+    };
+    Ok((method, target, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Parsed = (Method, String, Version, Vec<(String, Vec<u8>)>, usize);
+
+    fn parse_all(buf: &[u8]) -> Result<Status<Parsed>, ParseError> {
+        let mut headers = [RawHeader::EMPTY; 16];
+        Ok(match RequestParser::new().parse(buf, &mut headers)? {
+            Status::Complete(req) => Status::Complete((
+                req.method,
+                req.target.to_string(),
+                req.version,
+                req.headers
+                    .iter()
+                    .map(|h| (h.name.to_string(), h.value.to_vec()))
+                    .collect(),
+                req.head_len,
+            )),
+            Status::Partial => Status::Partial,
+        })
+    }
+
+    #[test]
+    fn simple_request() {
+        let buf = b"GET /index.html?lang=en HTTP/1.1\r\nHost: example.com\r\nAccept: */*\r\n\r\n";
+        let mut headers = [RawHeader::EMPTY; 16];
+        let Ok(Status::Complete(req)) =
+            RequestParser::new().parse(buf, &mut headers)
+        else {
+            panic!("expected a complete request");
+        };
+        assert_eq!(req.method, Method::Get);
+        assert_eq!(req.target.path().unwrap().as_str(), "/index.html");
+        assert_eq!(req.target.query(), Some("lang=en"));
+        assert_eq!(req.version, Version::Http1_1);
+        assert_eq!(
+            req.headers,
+            [
+                RawHeader {
+                    name: "Host",
+                    value: b"example.com"
+                },
+                RawHeader {
+                    name: "Accept",
+                    value: b"*/*"
+                },
+            ]
+        );
+        assert_eq!(req.head_len, buf.len());
+
+        let map = headers_to_map(req.headers);
+        assert_eq!(map.get("host").unwrap().as_bytes(), b"example.com");
+    }
+
+    #[test]
+    fn headers_borrow_from_input() {
+        let buf = b"GET / HTTP/1.1\r\nHost: a\r\n\r\n".to_vec();
+        let mut headers = [RawHeader::EMPTY; 4];
+        let Ok(Status::Complete(req)) =
+            RequestParser::new().parse(&buf, &mut headers)
+        else {
+            panic!("expected a complete request");
+        };
+        let range = buf.as_ptr_range();
+        assert!(range.contains(&req.headers[0].name.as_ptr()));
+        assert!(range.contains(&req.headers[0].value.as_ptr()));
+    }
+
+    #[test]
+    fn incremental() {
+        let buf = b"POST /submit HTTP/1.1\r\nHost: example.com\r\nContent-Length: 5\r\n\r\nhello";
+        let mut parser = RequestParser::new();
+        // Feed the request one byte at a time
+        for end in 0..buf.len() {
+            let mut headers = [RawHeader::EMPTY; 16];
+            match parser.parse(&buf[..end], &mut headers) {
+                Ok(Status::Partial) => assert!(end < buf.len() - 5),
+                Ok(Status::Complete(req)) => {
+                    assert_eq!(end, buf.len() - 5);
+                    assert_eq!(req.head_len, buf.len() - 5);
+                    assert_eq!(req.method, Method::Post);
+                    assert_eq!(req.headers.len(), 2);
+                    break;
+                }
+                Err(e) => panic!("unexpected error {e:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn pipelined() {
+        let buf = b"GET /a HTTP/1.1\r\nHost: x\r\n\r\nGET /b HTTP/1.1\r\nHost: x\r\n\r\n";
+        let mut parser = RequestParser::new();
+        let mut headers = [RawHeader::EMPTY; 4];
+        let Ok(Status::Complete(first)) = parser.parse(buf, &mut headers)
+        else {
+            panic!("expected a complete request");
+        };
+        assert_eq!(first.target.to_string(), "/a");
+        let rest = &buf[first.head_len..];
+        let mut headers = [RawHeader::EMPTY; 4];
+        let Ok(Status::Complete(second)) = parser.parse(rest, &mut headers)
+        else {
+            panic!("expected a complete request");
+        };
+        assert_eq!(second.target.to_string(), "/b");
+        assert_eq!(second.head_len, rest.len());
+    }
+
+    #[test]
+    fn lenient_line_endings() {
+        // Bare LF line endings and leading empty lines are tolerated
+        let Ok(Status::Complete((method, target, _, headers, len))) =
+            parse_all(b"\r\n\nGET / HTTP/1.0\nAccept:text/html \t\n\n")
+        else {
+            panic!("expected a complete request");
+        };
+        assert_eq!(method, Method::Get);
+        assert_eq!(target, "/");
+        assert_eq!(headers, [("Accept".into(), b"text/html".to_vec())]);
+        assert_eq!(len, 38);
+
+        assert_eq!(parse_all(b"\r\n\r\n\r\n"), Ok(Status::Partial));
+        assert_eq!(parse_all(b""), Ok(Status::Partial));
+    }
+
+    #[test]
+    fn versions() {
+        let Ok(Status::Complete((_, _, version, _, _))) =
+            parse_all(b"GET / HTTP/1.0\r\n\r\n")
+        else {
+            panic!("expected a complete request");
+        };
+        assert_eq!(version, Version::Http1_0);
+        assert_eq!(
+            parse_all(b"GET / HTTP/1.1\r\n\r\n"),
+            Err(ParseError::InvalidHost)
+        );
+        assert_eq!(
+            parse_all(b"GET / HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n"),
+            Err(ParseError::InvalidHost)
+        );
+        assert_eq!(
+            parse_all(b"GET / HTTP/x.y\r\n\r\n"),
+            Err(ParseError::InvalidVersion)
+        );
+        assert_eq!(
+            parse_all(b"GET / http/1.1\r\n\r\n"),
+            Err(ParseError::InvalidVersion)
+        );
+        // Only HTTP/1.x can be sent in a request line
+        for version in
+            ["HTTP/0.9", "HTTP/2", "HTTP/2.0", "HTTP/3.0", "HTTP/4.2"]
+        {
+            let buf = format!("GET / {version}\r\nHost: a\r\n\r\n");
+            assert_eq!(
+                parse_all(buf.as_bytes()),
+                Err(ParseError::UnsupportedVersion)
+            );
+        }
+    }
+
+    #[test]
+    fn targets() {
+        // Any pchar, empty segments and dot segments
+        for target in [
+            "/wiki/Special:Search",
+            "/a;jsessionid=1",
+            "/search,all",
+            "/@user",
+            "/a//b",
+            "/!$&'()*+=",
+            "/a/../b",
+            "/.well-known/x",
+        ] {
+            let buf = format!("GET {target} HTTP/1.1\r\nHost: a\r\n\r\n");
+            let Ok(Status::Complete((_, parsed, _, _, _))) =
+                parse_all(buf.as_bytes())
+            else {
+                panic!("expected a complete request for {target}");
+            };
+            assert_eq!(parsed, target);
+        }
+    }
+
+    #[test]
+    fn malformed_request_lines() {
+        for (input, error) in [
+            (
+                &b"GET  / HTTP/1.1\r\n\r\n"[..],
+                ParseError::InvalidRequestLine,
+            ),
+            (b"GET / HTTP/1.1 \r\n\r\n", ParseError::InvalidRequestLine),
+            (b"GET /\r\n\r\n", ParseError::InvalidRequestLine),
+            (b"GET\t/ HTTP/1.1\r\n\r\n", ParseError::InvalidRequestLine),
+            (b"G(T / HTTP/1.1\r\n\r\n", ParseError::InvalidRequestLine),
+            (b"BREW / HTTP/1.1\r\n\r\n", ParseError::UnknownMethod),
+            (b"get / HTTP/1.1\r\n\r\n", ParseError::UnknownMethod),
+            (b"GET /a[0] HTTP/1.1\r\n\r\n", ParseError::InvalidTarget),
+            (b"GET * HTTP/1.1\r\n\r\n", ParseError::InvalidTarget),
+            (b"GET /\xff HTTP/1.1\r\n\r\n", ParseError::InvalidTarget),
+        ] {
+            assert_eq!(
+                parse_all(input),
+                Err(error),
+                "{}",
+                input.escape_ascii()
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_headers() {
+        for (input, error) in [
+            (
+                &b"GET / HTTP/1.1\r\nHost : a\r\n\r\n"[..],
+                ParseError::InvalidHeaderName,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nHost: a\r\nNo colon\r\n\r\n",
+                ParseError::InvalidHeaderName,
+            ),
+            (
+                b"GET / HTTP/1.1\r\n: empty\r\n\r\n",
+                ParseError::InvalidHeaderName,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nHost: a\r\nX: a\rb\r\n\r\n",
+                ParseError::InvalidHeaderValue,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nHost: a\r\nX: a\0b\r\n\r\n",
+                ParseError::InvalidHeaderValue,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nHost: a\r\nX: a\r\n b\r\n\r\n",
+                ParseError::ObsoleteLineFolding,
+            ),
+            (
+                b"GET / HTTP/1.1\r\n Host: a\r\n\r\n",
+                ParseError::ObsoleteLineFolding,
+            ),
+        ] {
+            assert_eq!(
+                parse_all(input),
+                Err(error),
+                "{}",
+                input.escape_ascii()
+            );
+        }
+    }
+
+    #[test]
+    fn responses() {
+        let buf = b"HTTP/1.1 404 Not Found\r\nContent-Length: 3\r\n\r\nabc";
+        let mut headers = [RawHeader::EMPTY; 4];
+        let Ok(Status::Complete(res)) =
+            ResponseParser::new().parse(buf, &mut headers)
+        else {
+            panic!("expected a complete response");
+        };
+        assert_eq!(res.version, Version::Http1_1);
+        assert_eq!(res.status, StatusCode::NOT_FOUND);
+        assert_eq!(res.reason, b"Not Found");
+        assert_eq!(
+            res.headers,
+            [RawHeader {
+                name: "Content-Length",
+                value: b"3"
+            }]
+        );
+        assert_eq!(&buf[res.head_len..], b"abc");
+
+        // Empty reason phrase, with and without the trailing SP
+        for buf in [&b"HTTP/1.0 200 \r\n\r\n"[..], b"HTTP/1.0 200\r\n\r\n"] {
+            let mut headers = [RawHeader::EMPTY; 4];
+            let Ok(Status::Complete(res)) =
+                ResponseParser::new().parse(buf, &mut headers)
+            else {
+                panic!("expected a complete response");
+            };
+            assert_eq!(res.status, StatusCode::OK);
+            assert_eq!(res.reason, b"");
+        }
+
+        let mut parser = ResponseParser::new();
+        let mut headers = [RawHeader::EMPTY; 4];
+        assert_eq!(
+            parser.parse(b"HTTP/1.1 200 OK\r\n", &mut headers),
+            Ok(Status::Partial)
+        );
+    }
+
+    #[test]
+    fn malformed_responses() {
+        for (input, error) in [
+            (&b"HTTP/1.1\r\n\r\n"[..], ParseError::InvalidStatusLine),
+            (b"HTTP/1.1 20 OK\r\n\r\n", ParseError::InvalidStatusCode),
+            (b"HTTP/1.1 2000 OK\r\n\r\n", ParseError::InvalidStatusCode),
+            (b"HTTP/1.1  200 OK\r\n\r\n", ParseError::InvalidStatusCode),
+            (b"HTTX/1.1 200 OK\r\n\r\n", ParseError::InvalidVersion),
+            (
+                b"HTTP/1.1 200 O\x00K\r\n\r\n",
+                ParseError::InvalidStatusLine,
+            ),
+            (
+                b"HTTP/1.1 200 OK\r\nBad Name: x\r\n\r\n",
+                ParseError::InvalidHeaderName,
+            ),
+        ] {
+            let mut headers = [RawHeader::EMPTY; 4];
+            assert_eq!(
+                ResponseParser::new().parse(input, &mut headers),
+                Err(error),
+                "{}",
+                input.escape_ascii()
+            );
+        }
+    }
+
+    #[test]
+    fn list_values() {
+        let split = |v: &'static [u8]| list_elements(v).collect::<Vec<_>>();
+        assert_eq!(split(b"a, b,c"), [&b"a"[..], b"b", b"c"]);
+        assert_eq!(split(b" , a ,, b , "), [&b"a"[..], b"b"]);
+        assert_eq!(split(b"a;q=\"x, y\", b"), [&b"a;q=\"x, y\""[..], b"b"]);
+        assert_eq!(split(b"\"a\\\", b\", c"), [&b"\"a\\\", b\""[..], b"c"]);
+        assert!(split(b"").is_empty());
+        assert!(split(b" ,, ").is_empty());
+    }
+
+    #[test]
+    fn too_many_headers() {
+        let buf = b"GET / HTTP/1.1\r\nHost: a\r\nA: 1\r\nB: 2\r\n\r\n";
+        let mut headers = [RawHeader::EMPTY; 2];
+        assert_eq!(
+            RequestParser::new().parse(buf, &mut headers),
+            Err(ParseError::TooManyHeaders)
+        );
+    }
+
+    #[test]
+    fn size_limits() {
+        let parse = |buf: &[u8]| {
+            let mut headers = [RawHeader::EMPTY; 16];
+            RequestParser::new()
+                .max_request_line_len(20)
+                .max_head_size(40)
+                .parse(buf, &mut headers)
+                .map(|status| status.is_complete())
+        };
+        assert_eq!(parse(b"GET /012345 HTTP/1.1\r\nHost: a\r\n\r\n"), Ok(true));
+        assert_eq!(
+            parse(b"GET /0123456 HTTP/1.1\r\nHost: a\r\n\r\n"),
+            Err(ParseError::RequestLineTooLong)
+        );
+        // Rejected before the line is complete
+        assert_eq!(parse(b"GET /012345678901234"), Ok(false));
+        assert_eq!(
+            parse(b"GET /0123456789012345"),
+            Err(ParseError::RequestLineTooLong)
+        );
+        // Leading empty lines do not count against the request line
+        assert_eq!(parse(b"\r\n\r\nGET /012345 HTTP/1.1\r\n"), Ok(false));
+
+        assert_eq!(
+            parse(b"GET / HTTP/1.1\r\nHost: a\r\nX: 0123456\r\n\r\n"),
+            Ok(true)
+        );
+        assert_eq!(
+            parse(b"GET / HTTP/1.1\r\nHost: a\r\nX: 012345678\r\n\r\n"),
+            Err(ParseError::HeadTooLarge)
+        );
+        assert_eq!(
+            parse(b"GET / HTTP/1.1\r\nHost: a\r\nX: 0123456789abcdef"),
+            Err(ParseError::HeadTooLarge)
+        );
+    }
+}
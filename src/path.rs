@@ -2,8 +2,8 @@ use std::str::FromStr;
 
 use crate::utils::{self, ALLOWED_PATH_BYTES};
 
-#[derive(Debug, PartialEq, Eq)]
-struct Path {
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Path {
     pathstr: String,
 }
 
@@ -15,10 +15,14 @@ impl Path {
     fn is_valid_path_byte(b: &u8) -> bool {
         ALLOWED_PATH_BYTES.contains(b)
     }
+
+    pub fn as_str(&self) -> &str {
+        &self.pathstr
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
-struct PathParseError;
+pub struct PathParseError;
 
 impl FromStr for Path {
     type Err = PathParseError;
@@ -31,64 +35,21 @@ impl FromStr for Path {
             return Err(PathParseError);
         }
 
-        // TODO: Special case. Technically only valid if method is OPTIONS.
-        if s.contains(&b'*') {
-            if s.len() == 1 {
-                unimplemented!("star path not implemented")
-            } else {
-                return Err(PathParseError);
-            }
-        }
-
-        // First character MUST be '/' in a path
+        // First character MUST be '/' in a path. This also rules out the
+        // asterisk-form ("*") of a request target, which is not a path and is
+        // handled by `RequestTarget`.
         if s[0] != b'/' {
             return Err(PathParseError);
         }
 
-        // No double slashes
-        if utils::contains_subslice(s, b"//") {
-            return Err(PathParseError);
-        }
-
-        // Disallow upward traversal in the file hierarchy
-        if utils::contains_subslice(s, b"..") {
-            return Err(PathParseError);
-        }
-
         // If a '%' occures, validate the percent-encoding.
         if !utils::is_properly_percent_encoded(s) {
             return Err(PathParseError);
         }
-        // If a single '.' occurs, it shall not be used to access hidden folders
-        // or files.
-        if !s
-            .iter()
-            .enumerate()
-            .filter(|&(_, &c)| c == b'.')
-            .all(|(idx, _)| {
-                idx == s.len() - 1
-                    || !(idx > 0
-                        && s[idx + 1].is_ascii_alphanumeric()
-                        && s[idx - 1] == b'/')
-            })
-        {
-            return Err(PathParseError);
-        }
-        // '~' can only be the start of a name within the path
-        if !s
-            .iter()
-            .enumerate()
-            .filter(|&(_, &c)| c == b'~')
-            .all(|(idx, _)| {
-                0 < idx
-                    && idx < s.len() - 1
-                    && s[idx - 1] == b'/'
-                    && s[idx + 1].is_ascii_alphanumeric()
-            })
-        {
-            return Err(PathParseError);
-        }
 
+        // Dot segments, hidden names and the like are all valid here. They
+        // only mean something once a path is mapped onto a file system,
+        // which is where they have to be dealt with.
         Ok(Path::new(&String::from_utf8_lossy(s)))
     }
 }
@@ -99,39 +60,50 @@ mod tests {
 
     #[test]
     fn parse_ok_paths() {
-        assert_eq!("/".parse(), Ok(Path::new("/".into())));
-        assert_eq!("/home".parse(), Ok(Path::new("/home".into())));
-        assert_eq!(
-            "/products/123".parse(),
-            Ok(Path::new("/products/123".into()))
-        );
-        assert_eq!(
-            "/api/v1/users".parse(),
-            Ok(Path::new("/api/v1/users".into()))
-        );
+        assert_eq!("/".parse(), Ok(Path::new("/")));
+        assert_eq!("/home".parse(), Ok(Path::new("/home")));
+        assert_eq!("/products/123".parse(), Ok(Path::new("/products/123")));
+        assert_eq!("/api/v1/users".parse(), Ok(Path::new("/api/v1/users")));
         assert_eq!(
             "/images/profile.jpg".parse(),
-            Ok(Path::new("/images/profile.jpg".into()))
+            Ok(Path::new("/images/profile.jpg"))
         );
         assert_eq!(
             "/articles/tech/how-to".parse(),
-            Ok(Path::new("/articles/tech/how-to".into()))
+            Ok(Path::new("/articles/tech/how-to"))
         );
         assert_eq!(
             "/docs/user-guide/installation".parse(),
-            Ok(Path::new("/docs/user-guide/installation".into()))
+            Ok(Path::new("/docs/user-guide/installation"))
+        );
+        // Every pchar, and empty segments
+        assert_eq!(
+            "/wiki/Special:Search".parse(),
+            Ok(Path::new("/wiki/Special:Search"))
         );
+        assert_eq!("/a;jsessionid=1".parse(), Ok(Path::new("/a;jsessionid=1")));
+        assert_eq!("/search,all".parse(), Ok(Path::new("/search,all")));
+        assert_eq!("/@user".parse(), Ok(Path::new("/@user")));
+        assert_eq!("/!$&'()*+".parse(), Ok(Path::new("/!$&'()*+")));
+        assert_eq!("/a//b".parse(), Ok(Path::new("/a//b")));
+        assert_eq!("//".parse(), Ok(Path::new("//")));
+        // Percent-encoding at the very end of the path
+        assert_eq!("/a%20".parse(), Ok(Path::new("/a%20")));
+        assert_eq!("/%41/b".parse(), Ok(Path::new("/%41/b")));
+        // Dot segments, hidden names and tildes
+        assert_eq!("/.well-known/x".parse(), Ok(Path::new("/.well-known/x")));
+        assert_eq!("/file..txt".parse(), Ok(Path::new("/file..txt")));
+        assert_eq!("/docs/v1..2".parse(), Ok(Path::new("/docs/v1..2")));
+        assert_eq!("/a/b~c".parse(), Ok(Path::new("/a/b~c")));
+        assert_eq!("/path~".parse(), Ok(Path::new("/path~")));
+        assert_eq!("/~user".parse(), Ok(Path::new("/~user")));
+        assert_eq!("/path/..".parse(), Ok(Path::new("/path/..")));
+        assert_eq!("/.../dots".parse(), Ok(Path::new("/.../dots")));
     }
 
     #[test]
     fn parse_invalid_paths() {
-        assert_eq!(
-            "/path//with//double-slashes".parse::<Path>(),
-            Err(PathParseError)
-        );
         assert_eq!("/path with space".parse::<Path>(), Err(PathParseError));
-        assert_eq!("/somepath//extra".parse::<Path>(), Err(PathParseError));
-        assert_eq!("/.../dots".parse::<Path>(), Err(PathParseError));
         assert_eq!(
             "/path?query=1&filter=abc".parse::<Path>(),
             Err(PathParseError)
@@ -144,11 +116,11 @@ mod tests {
             Err(PathParseError)
         );
         assert_eq!("/path!@#^".parse::<Path>(), Err(PathParseError));
-        assert_eq!("/path~".parse::<Path>(), Err(PathParseError));
-        assert_eq!("/path&other".parse::<Path>(), Err(PathParseError));
+        assert_eq!("/path[0]".parse::<Path>(), Err(PathParseError));
+        assert_eq!("/a\"b\"".parse::<Path>(), Err(PathParseError));
         assert_eq!("/.. ".parse::<Path>(), Err(PathParseError));
-        assert_eq!("/path/..".parse::<Path>(), Err(PathParseError));
         assert_eq!(" / ".parse::<Path>(), Err(PathParseError));
-        assert_eq!("//".parse::<Path>(), Err(PathParseError));
+        assert_eq!("*".parse::<Path>(), Err(PathParseError));
+        assert_eq!("/a%2".parse::<Path>(), Err(PathParseError));
     }
 }
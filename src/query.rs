@@ -5,7 +5,7 @@ use crate::utils::{self, ALLOWED_QUERY_BYTES};
 // TODO: percent-encoding currently not handled. Maybe should be
 //       the responsibility of a validation earlier in the chain though.
 //       (for example if only uri exposes some API and query is left private).
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 struct QueryItem {
     field: String,
     value: Option<String>,
@@ -24,7 +24,7 @@ impl QueryItem {
 }
 
 #[derive(Debug, Eq, PartialEq)]
-enum QueryParseError {
+pub enum QueryParseError {
     InvalidCharacter,
     BadFieldValue,
     EmptyInput,
@@ -80,14 +80,14 @@ impl FromStr for QueryItem {
 // These vectors are expected to be small, which is why something like HashMap
 // is not used. In fact, HashMap may in many cases be outright slower than
 // using contiguous arrays for this particular use case.
-#[derive(Debug, Eq, PartialEq)]
-struct Query {
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Query {
     fields: Vec<String>,
     values: Vec<Vec<String>>,
 }
 
 impl Query {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             fields: vec![],
             values: vec![],
@@ -98,7 +98,7 @@ impl Query {
         self.fields.iter().position(|f| *f == field)
     }
 
-    fn insert(&mut self, field: &str, value: Option<&str>) {
+    pub fn insert(&mut self, field: &str, value: Option<&str>) {
         // If field already exists
         if let Some(index) = self.find(field) {
             if let Some(value) = value {
@@ -114,7 +114,7 @@ impl Query {
         }
     }
 
-    fn get(&self, field: &str) -> Option<&[String]> {
+    pub fn get(&self, field: &str) -> Option<&[String]> {
         if let Some(index) = self.find(field) {
             Some(&self.values[index][..])
         } else {
@@ -122,7 +122,7 @@ impl Query {
         }
     }
 
-    fn get_mut(&mut self, field: &str) -> Option<&mut [String]> {
+    pub fn get_mut(&mut self, field: &str) -> Option<&mut [String]> {
         if let Some(index) = self.find(field) {
             Some(&mut self.values[index][..])
         } else {
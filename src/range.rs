@@ -0,0 +1,468 @@
+use std::collections::VecDeque;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem;
+use std::ops::Range;
+
+use crate::header::{HeaderName, HeaderValue};
+use crate::parser;
+use crate::request::Request;
+
+// More ranges than this in one request are more likely an attempt to make
+// the server do a lot of work for little data than a legitimate client.
+const MAX_RANGES: usize = 32;
+
+// One element of the range set of a `Range` header, RFC 9110 section 14.1.1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    // int-range with a last-pos, e.g. "0-499"
+    Bounded { first: u64, last: u64 },
+    // int-range without a last-pos, e.g. "9500-"
+    Open { first: u64 },
+    // suffix-range, the last `len` bytes, e.g. "-500"
+    Suffix { len: u64 },
+}
+
+// The byte ranges requested by a `Range` header.
+//
+// Range = ranges-specifier
+// ranges-specifier = range-unit "=" range-set
+// range-set = 1#range-spec
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeSet {
+    ranges: Vec<ByteRange>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidRange;
+
+// None of the requested ranges overlaps the representation, which is
+// answered with a 416.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Unsatisfiable;
+
+impl RangeSet {
+    pub fn from_bytes(s: &[u8]) -> Result<Self, InvalidRange> {
+        let (unit, set) = s
+            .iter()
+            .position(|&b| b == b'=')
+            .map(|i| (&s[..i], &s[i + 1..]))
+            .ok_or(InvalidRange)?;
+        if !unit.eq_ignore_ascii_case(b"bytes") {
+            return Err(InvalidRange);
+        }
+        let ranges = parser::list_elements(set)
+            .map(parse_range_spec)
+            .collect::<Result<Vec<_>, _>>()?;
+        if ranges.is_empty() || ranges.len() > MAX_RANGES {
+            return Err(InvalidRange);
+        }
+        Ok(Self { ranges })
+    }
+
+    // The ranges requested by a GET request. A `Range` header that is
+    // invalid or uses a unit other than bytes has to be ignored, so it
+    // results in `None` just like a missing one.
+    pub fn from_request(request: &Request) -> Option<Self> {
+        Self::from_bytes(request.headers().get(HeaderName::RANGE)?.as_bytes())
+            .ok()
+    }
+
+    pub fn ranges(&self) -> &[ByteRange] {
+        &self.ranges
+    }
+
+    // Resolves the requested ranges against a representation of `len`
+    // bytes, dropping those that lie entirely past its end and clamping the
+    // rest. The result is sorted, with overlapping and adjacent ranges
+    // coalesced, so that no byte is sent twice.
+    pub fn resolve(&self, len: u64) -> Result<Vec<Range<u64>>, Unsatisfiable> {
+        let mut resolved: Vec<Range<u64>> = self
+            .ranges
+            .iter()
+            .filter_map(|&range| match range {
+                ByteRange::Bounded { first, last } if first < len => {
+                    Some(first..last.saturating_add(1).min(len))
+                }
+                ByteRange::Open { first } if first < len => Some(first..len),
+                ByteRange::Suffix { len: suffix } if suffix > 0 && len > 0 => {
+                    Some(len - suffix.min(len)..len)
+                }
+                _ => None,
+            })
+            .collect();
+        if resolved.is_empty() {
+            return Err(Unsatisfiable);
+        }
+
+        resolved.sort_by_key(|range| range.start);
+        let mut coalesced: Vec<Range<u64>> = vec![];
+        for range in resolved {
+            match coalesced.last_mut() {
+                Some(last) if range.start <= last.end => {
+                    last.end = last.end.max(range.end);
+                }
+                _ => coalesced.push(range),
+            }
+        }
+        Ok(coalesced)
+    }
+}
+
+// range-spec   = int-range / suffix-range / other-range
+// int-range    = first-pos "-" [ last-pos ]
+// suffix-range = "-" suffix-length
+fn parse_range_spec(spec: &[u8]) -> Result<ByteRange, InvalidRange> {
+    let dash = spec.iter().position(|&b| b == b'-').ok_or(InvalidRange)?;
+    let (first, last) = (&spec[..dash], &spec[dash + 1..]);
+    match (first.is_empty(), last.is_empty()) {
+        (true, true) => Err(InvalidRange),
+        (true, false) => Ok(ByteRange::Suffix {
+            len: parse_pos(last)?,
+        }),
+        (false, true) => Ok(ByteRange::Open {
+            first: parse_pos(first)?,
+        }),
+        (false, false) => {
+            let (first, last) = (parse_pos(first)?, parse_pos(last)?);
+            if last < first {
+                return Err(InvalidRange);
+            }
+            Ok(ByteRange::Bounded { first, last })
+        }
+    }
+}
+
+// Positions beyond what fits in a `u64` lie past the end of any
+// representation, so they saturate instead of failing.
+fn parse_pos(s: &[u8]) -> Result<u64, InvalidRange> {
+    if s.is_empty() || !s.iter().all(u8::is_ascii_digit) {
+        return Err(InvalidRange);
+    }
+    Ok(s.iter().fold(0u64, |n, &d| {
+        n.saturating_mul(10).saturating_add((d - b'0') as u64)
+    }))
+}
+
+// The `Content-Range` of a part of a 206 response, e.g.
+// "bytes 0-499/1234".
+pub fn content_range(range: &Range<u64>, len: u64) -> HeaderValue {
+    let value = format!("bytes {}-{}/{len}", range.start, range.end - 1);
+    HeaderValue::from_bytes(value.as_bytes()).expect("valid header value")
+}
+
+// The `Content-Range` of a 416 response, e.g. "bytes */1234".
+pub fn unsatisfied_range(len: u64) -> HeaderValue {
+    HeaderValue::from_bytes(format!("bytes */{len}").as_bytes())
+        .expect("valid header value")
+}
+
+// Builds the body of a `multipart/byteranges` response, used when more than
+// one range is sent (RFC 9110 section 14.6).
+#[derive(Debug)]
+pub struct MultipartByteranges {
+    boundary: String,
+    content_type: Option<HeaderValue>,
+    len: u64,
+    body: Vec<u8>,
+}
+
+impl MultipartByteranges {
+    // `content_type` is the type of the selected representation, repeated
+    // in every part, and `len` its complete length.
+    pub fn new(content_type: Option<HeaderValue>, len: u64) -> Self {
+        // A random boundary, so the parts cannot contain it by accident.
+        let random = RandomState::new().build_hasher().finish();
+        Self {
+            boundary: format!("websurfer-{random:016x}"),
+            content_type,
+            len,
+            body: vec![],
+        }
+    }
+
+    // The `Content-Type` of the response carrying the parts.
+    pub fn content_type(&self) -> HeaderValue {
+        let value = format!("multipart/byteranges; boundary={}", self.boundary);
+        HeaderValue::from_bytes(value.as_bytes()).expect("valid header value")
+    }
+
+    pub fn push(&mut self, range: &Range<u64>, data: &[u8]) {
+        let head = self.part_head(range, self.body.is_empty());
+        self.body.extend_from_slice(&head);
+        self.body.extend_from_slice(data);
+    }
+
+    // The delimiter and header section starting the part for `range`.
+    fn part_head(&self, range: &Range<u64>, first: bool) -> Vec<u8> {
+        let mut head = vec![];
+        if !first {
+            head.extend_from_slice(b"\r\n");
+        }
+        head.extend_from_slice(b"--");
+        head.extend_from_slice(self.boundary.as_bytes());
+        head.extend_from_slice(b"\r\n");
+        if let Some(content_type) = &self.content_type {
+            push_header(&mut head, HeaderName::CONTENT_TYPE, content_type);
+        }
+        let content_range = content_range(range, self.len);
+        push_header(&mut head, HeaderName::CONTENT_RANGE, &content_range);
+        head.extend_from_slice(b"\r\n");
+        head
+    }
+
+    fn close_delimiter(&self) -> Vec<u8> {
+        format!("\r\n--{}--\r\n", self.boundary).into_bytes()
+    }
+
+    // Closes the last part and returns the body.
+    pub fn finish(mut self) -> Vec<u8> {
+        let end = self.close_delimiter();
+        self.body.extend_from_slice(&end);
+        self.body
+    }
+
+    // Adds parts for `ranges` of `source`, which holds the complete
+    // representation, and closes the body. Rather than collecting the parts
+    // in memory, the reader returned reads each range from `source` in turn.
+    pub fn into_reader<R: Read + Seek>(
+        mut self,
+        source: R,
+        ranges: &[Range<u64>],
+    ) -> MultipartReader<R> {
+        let body = mem::take(&mut self.body);
+        let mut first = body.is_empty();
+        let mut segments =
+            VecDeque::from([Segment::Bytes(io::Cursor::new(body))]);
+        for range in ranges {
+            let head = self.part_head(range, first);
+            segments.push_back(Segment::Bytes(io::Cursor::new(head)));
+            segments.push_back(Segment::Range(range.clone()));
+            first = false;
+        }
+        segments
+            .push_back(Segment::Bytes(io::Cursor::new(self.close_delimiter())));
+        let len = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Bytes(bytes) => bytes.get_ref().len() as u64,
+                Segment::Range(range) => range.end - range.start,
+            })
+            .sum();
+        MultipartReader {
+            source,
+            position: None,
+            segments,
+            len,
+        }
+    }
+}
+
+fn push_header(head: &mut Vec<u8>, name: HeaderName, value: &HeaderValue) {
+    head.extend_from_slice(name.as_str().as_bytes());
+    head.extend_from_slice(b": ");
+    head.extend_from_slice(value.as_bytes());
+    head.extend_from_slice(b"\r\n");
+}
+
+#[derive(Debug)]
+enum Segment {
+    Bytes(io::Cursor<Vec<u8>>),
+    // What is left of a range of the source
+    Range(Range<u64>),
+}
+
+// Reads a `multipart/byteranges` body, see
+// `MultipartByteranges::into_reader`.
+#[derive(Debug)]
+pub struct MultipartReader<R> {
+    source: R,
+    // Where reading from `source` would continue, if known
+    position: Option<u64>,
+    segments: VecDeque<Segment>,
+    len: u64,
+}
+
+impl<R> MultipartReader<R> {
+    // The length of the whole body.
+    pub fn content_length(&self) -> u64 {
+        self.len
+    }
+}
+
+impl<R: Read + Seek> Read for MultipartReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(segment) = self.segments.front_mut() {
+            let n = match segment {
+                Segment::Bytes(bytes) => bytes.read(buf)?,
+                Segment::Range(range) if range.is_empty() => 0,
+                Segment::Range(range) => {
+                    if self.position != Some(range.start) {
+                        self.source.seek(SeekFrom::Start(range.start))?;
+                    }
+                    let max = (range.end - range.start).min(buf.len() as u64)
+                        as usize;
+                    let n = self.source.read(&mut buf[..max])?;
+                    if n == 0 && max > 0 {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    range.start += n as u64;
+                    self.position = Some(range.start);
+                    n
+                }
+            };
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            self.segments.pop_front();
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<Vec<ByteRange>, InvalidRange> {
+        RangeSet::from_bytes(s.as_bytes()).map(|set| set.ranges)
+    }
+
+    // As (start, end) pairs, which are easier to compare
+    fn resolve(s: &str, len: u64) -> Result<Vec<(u64, u64)>, Unsatisfiable> {
+        let set = RangeSet::from_bytes(s.as_bytes()).unwrap();
+        let ranges = set.resolve(len)?;
+        Ok(ranges
+            .iter()
+            .map(|range| (range.start, range.end))
+            .collect())
+    }
+
+    #[test]
+    fn parsing() {
+        assert_eq!(
+            parse("bytes=0-499, -500"),
+            Ok(vec![
+                ByteRange::Bounded {
+                    first: 0,
+                    last: 499
+                },
+                ByteRange::Suffix { len: 500 },
+            ])
+        );
+        assert_eq!(
+            parse("Bytes=9500-,,1-1"),
+            Ok(vec![
+                ByteRange::Open { first: 9500 },
+                ByteRange::Bounded { first: 1, last: 1 },
+            ])
+        );
+        assert_eq!(
+            parse("bytes=0-99999999999999999999999"),
+            Ok(vec![ByteRange::Bounded {
+                first: 0,
+                last: u64::MAX
+            }])
+        );
+
+        for invalid in [
+            "bytes=",
+            "bytes=-",
+            "bytes=500-499",
+            "bytes=a-b",
+            "bytes=0-1-2",
+            "bytes=+1-2",
+            "bytes 0-1",
+            "items=0-1",
+            "=0-1",
+        ] {
+            assert_eq!(parse(invalid), Err(InvalidRange), "{invalid}");
+        }
+        let many = format!("bytes={}", vec!["0-0"; MAX_RANGES + 1].join(","));
+        assert_eq!(parse(&many), Err(InvalidRange));
+    }
+
+    #[test]
+    fn resolution() {
+        assert_eq!(resolve("bytes=0-499", 10000), Ok(vec![(0, 500)]));
+        assert_eq!(resolve("bytes=-500", 10000), Ok(vec![(9500, 10000)]));
+        assert_eq!(resolve("bytes=9500-", 10000), Ok(vec![(9500, 10000)]));
+        // Clamped to the representation
+        assert_eq!(resolve("bytes=0-499", 100), Ok(vec![(0, 100)]));
+        assert_eq!(resolve("bytes=-500", 100), Ok(vec![(0, 100)]));
+        // Unsatisfiable ranges are dropped
+        assert_eq!(resolve("bytes=100-200, 0-9", 100), Ok(vec![(0, 10)]));
+        // Sorted and coalesced
+        assert_eq!(
+            resolve("bytes=50-59, 0-9, 5-19, 20-29", 100),
+            Ok(vec![(0, 30), (50, 60)])
+        );
+        assert_eq!(resolve("bytes=0-, -1", 100), Ok(vec![(0, 100)]));
+
+        assert_eq!(resolve("bytes=100-", 100), Err(Unsatisfiable));
+        assert_eq!(resolve("bytes=-0", 100), Err(Unsatisfiable));
+        assert_eq!(resolve("bytes=0-0", 0), Err(Unsatisfiable));
+        assert_eq!(resolve("bytes=-1", 0), Err(Unsatisfiable));
+    }
+
+    #[test]
+    fn content_ranges() {
+        assert_eq!(
+            content_range(&(0..500), 1234).as_bytes(),
+            b"bytes 0-499/1234"
+        );
+        assert_eq!(unsatisfied_range(1234).as_bytes(), b"bytes */1234");
+    }
+
+    #[test]
+    fn multipart() {
+        let content_type = HeaderValue::from_bytes(b"text/plain").unwrap();
+        let mut multipart = MultipartByteranges::new(Some(content_type), 10);
+        let header = multipart.content_type();
+        let boundary = header
+            .to_str()
+            .unwrap()
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap()
+            .to_owned();
+        multipart.push(&(0..2), b"ab");
+        let mut streamed = vec![];
+        let mut reader = multipart
+            .into_reader(io::Cursor::new(b"abcdefghij"), &[4..6, 8..10]);
+        let len = reader.content_length();
+        reader.read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed.len() as u64, len);
+        let mut multipart = MultipartByteranges::new(
+            Some(HeaderValue::from_bytes(b"text/plain").unwrap()),
+            10,
+        );
+        multipart.boundary = boundary.clone();
+        multipart.push(&(0..2), b"ab");
+        multipart.push(&(4..6), b"ef");
+        multipart.push(&(8..10), b"ij");
+        let body = multipart.finish();
+        assert_eq!(streamed, body);
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            format!(
+                "--{boundary}\r\n\
+                 content-type: text/plain\r\n\
+                 content-range: bytes 0-1/10\r\n\
+                 \r\n\
+                 ab\r\n\
+                 --{boundary}\r\n\
+                 content-type: text/plain\r\n\
+                 content-range: bytes 4-5/10\r\n\
+                 \r\n\
+                 ef\r\n\
+                 --{boundary}\r\n\
+                 content-type: text/plain\r\n\
+                 content-range: bytes 8-9/10\r\n\
+                 \r\n\
+                 ij\r\n\
+                 --{boundary}--\r\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use std::io::{self, Write};
+use std::net::SocketAddr;
+
+use crate::header::{HeaderMap, HeaderName, HeaderValue};
+use crate::method::Method;
+use crate::parser::{self, RawRequest};
+use crate::response;
+use crate::uri::RequestTarget;
+use crate::version::Version;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    method: Method,
+    target: RequestTarget,
+    version: Version,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    // Filled in by the router
+    params: PathParams,
+    // Filled in by the server
+    peer_addr: Option<SocketAddr>,
+}
+
+impl Request {
+    pub fn new(method: Method, target: RequestTarget) -> Self {
+        Self {
+            method,
+            target,
+            version: Version::Http1_1,
+            headers: HeaderMap::new(),
+            body: vec![],
+            params: PathParams::new(),
+            peer_addr: None,
+        }
+    }
+
+    // Takes ownership of a parsed request head. The body is read separately.
+    pub fn from_raw(raw: RawRequest, body: Vec<u8>) -> Self {
+        Self {
+            method: raw.method,
+            target: raw.target,
+            version: raw.version,
+            headers: parser::headers_to_map(raw.headers),
+            body,
+            params: PathParams::new(),
+            peer_addr: None,
+        }
+    }
+
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    pub fn with_body(mut self, body: Vec<u8>) -> Self {
+        self.body = body;
+        self
+    }
+
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    pub fn target(&self) -> &RequestTarget {
+        &self.target
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn set_version(&mut self, version: Version) {
+        self.version = version;
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn set_body(&mut self, body: Vec<u8>) {
+        self.body = body;
+    }
+
+    // The parameters captured from the path by the route that matched.
+    pub fn params(&self) -> &PathParams {
+        &self.params
+    }
+
+    pub fn set_params(&mut self, params: PathParams) {
+        self.params = params;
+    }
+
+    // Shorthand for `params().get(name)`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name)
+    }
+
+    // The address of the client the request came from, if it came over the
+    // network. Behind a proxy, this is the proxy.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    pub fn set_peer_addr(&mut self, addr: Option<SocketAddr>) {
+        self.peer_addr = addr;
+    }
+
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+
+    // Writes the request line and header section, see
+    // `Response::write_head`.
+    pub fn write_head<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut head = Vec::with_capacity(256);
+        write!(head, "{} {} {}\r\n", self.method, self.target, self.version)?;
+        response::write_headers(&mut head, &self.headers);
+        w.write_all(&head)
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_head(w)?;
+        w.write_all(&self.body)
+    }
+}
+
+// Named values taken from the segments of a request path, such as the "42"
+// of "/users/42" for a route "/users/{id}". Values are percent-decoded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathParams {
+    params: Vec<(String, String)>,
+}
+
+impl PathParams {
+    pub fn new() -> Self {
+        Self { params: vec![] }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn push(&mut self, name: String, value: String) {
+        self.params.push((name, value));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{RawHeader, RequestParser, Status};
+
+    fn parse(buf: &[u8]) -> Request {
+        let mut headers = [RawHeader::EMPTY; 16];
+        let Ok(Status::Complete(raw)) =
+            RequestParser::new().parse(buf, &mut headers)
+        else {
+            panic!("expected a complete request");
+        };
+        let body = buf[raw.head_len..].to_vec();
+        Request::from_raw(raw, body)
+    }
+
+    #[test]
+    fn round_trip_serialize_parse() {
+        let target = RequestTarget::parse("/submit?draft=1", &Method::Post);
+        let request = Request::new(Method::Post, target.unwrap())
+            .with_header(HeaderName::HOST, "example.com".parse().unwrap())
+            .with_header(HeaderName::CONTENT_LENGTH, 2u64.into())
+            .with_body(b"hi".to_vec());
+        let mut out = vec![];
+        request.write_to(&mut out).unwrap();
+        assert_eq!(
+            out,
+            b"POST /submit?draft=1 HTTP/1.1\r\nhost: example.com\r\ncontent-length: 2\r\n\r\nhi"
+        );
+        assert_eq!(parse(&out), request);
+    }
+
+    #[test]
+    fn round_trip_parse_serialize() {
+        for input in [
+            &b"GET / HTTP/1.1\r\nhost: a\r\n\r\n"[..],
+            b"OPTIONS * HTTP/1.1\r\nhost: a\r\n\r\n",
+            b"CONNECT example.com:443 HTTP/1.1\r\nhost: example.com:443\r\n\r\n",
+            b"GET http://example.com/a?b HTTP/1.0\r\n\r\n",
+        ] {
+            let mut out = vec![];
+            parse(input).write_to(&mut out).unwrap();
+            assert_eq!(out, input);
+        }
+    }
+}
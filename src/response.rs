@@ -0,0 +1,287 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::header::{HeaderMap, HeaderName, HeaderValue};
+use crate::parser::{self, RawResponse};
+use crate::status::StatusCode;
+use crate::version::Version;
+
+// A body that is read as the response is written out, for bodies too large
+// to hold in memory or produced while they are sent, such as a file or the
+// output of a compressor.
+pub struct BodyReader {
+    reader: Box<dyn Read + Send>,
+    len: Option<u64>,
+}
+
+impl BodyReader {
+    // `len` is the number of bytes the reader yields, if known up front.
+    // Bodies of unknown length are sent with the chunked transfer coding.
+    pub fn new<R: Read + Send + 'static>(reader: R, len: Option<u64>) -> Self {
+        Self {
+            reader: Box::new(reader),
+            len,
+        }
+    }
+
+    pub fn known_len(&self) -> Option<u64> {
+        self.len
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl fmt::Debug for BodyReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BodyReader")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    version: Version,
+    status: StatusCode,
+    // Only set for parsed responses whose reason phrase differs from the
+    // canonical one.
+    reason: Option<String>,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    // Takes the place of `body` when set
+    reader: Option<BodyReader>,
+}
+
+impl Response {
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            version: Version::Http1_1,
+            status,
+            reason: None,
+            headers: HeaderMap::new(),
+            body: vec![],
+            reader: None,
+        }
+    }
+
+    // Takes ownership of a parsed response head. The body is read separately.
+    pub fn from_raw(raw: RawResponse, body: Vec<u8>) -> Self {
+        let reason = Some(String::from_utf8_lossy(raw.reason).into_owned())
+            .filter(|r| Some(r.as_str()) != raw.status.canonical_reason());
+        Self {
+            version: raw.version,
+            status: raw.status,
+            reason,
+            headers: parser::headers_to_map(raw.headers),
+            body,
+            reader: None,
+        }
+    }
+
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    pub fn with_body(mut self, body: Vec<u8>) -> Self {
+        self.set_body(body);
+        self
+    }
+
+    pub fn with_reader(mut self, reader: BodyReader) -> Self {
+        self.set_reader(reader);
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn set_status(&mut self, status: StatusCode) {
+        self.status = status;
+        self.reason = None;
+    }
+
+    pub fn reason(&self) -> &str {
+        self.reason
+            .as_deref()
+            .or(self.status.canonical_reason())
+            .unwrap_or_default()
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn set_version(&mut self, version: Version) {
+        self.version = version;
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    // The body held in memory, which is empty if the body is read from a
+    // `BodyReader` instead.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn set_body(&mut self, body: Vec<u8>) {
+        self.body = body;
+        self.reader = None;
+    }
+
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+
+    pub fn reader(&self) -> Option<&BodyReader> {
+        self.reader.as_ref()
+    }
+
+    // Replaces the body with one read as the response is written out.
+    pub fn set_reader(&mut self, reader: BodyReader) {
+        self.body = vec![];
+        self.reader = Some(reader);
+    }
+
+    pub fn take_reader(&mut self) -> Option<BodyReader> {
+        self.reader.take()
+    }
+
+    // Writes the status line and header section, including the empty line
+    // separating the head from the body. The head is assembled in memory
+    // first so that it goes out in a single write.
+    pub fn write_head<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut head = Vec::with_capacity(256);
+        write!(
+            head,
+            "{} {:03} {}\r\n",
+            self.version,
+            self.status.as_u16(),
+            self.reason()
+        )?;
+        write_headers(&mut head, &self.headers);
+        w.write_all(&head)
+    }
+
+    // Writes the head followed by the body held in memory as is. Framing
+    // headers such as `Content-Length` are the caller's responsibility, and
+    // so is a body read from a `BodyReader`.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_head(w)?;
+        w.write_all(&self.body)
+    }
+}
+
+// field-line CRLF for every header followed by the terminating CRLF.
+pub(crate) fn write_headers(head: &mut Vec<u8>, headers: &HeaderMap) {
+    for (name, value) in headers {
+        head.extend_from_slice(name.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{RawHeader, ResponseParser, Status};
+
+    fn parse(buf: &[u8]) -> Response {
+        let mut headers = [RawHeader::EMPTY; 16];
+        let Ok(Status::Complete(raw)) =
+            ResponseParser::new().parse(buf, &mut headers)
+        else {
+            panic!("expected a complete response");
+        };
+        let body = buf[raw.head_len..].to_vec();
+        Response::from_raw(raw, body)
+    }
+
+    #[test]
+    fn serialize() {
+        let response = Response::new(StatusCode::OK)
+            .with_header(HeaderName::CONTENT_TYPE, "text/html".parse().unwrap())
+            .with_header(HeaderName::CONTENT_LENGTH, 5u64.into())
+            .with_body(b"hello".to_vec());
+        let mut out = vec![];
+        response.write_to(&mut out).unwrap();
+        assert_eq!(
+            out,
+            b"HTTP/1.1 200 OK\r\ncontent-type: text/html\r\ncontent-length: 5\r\n\r\nhello"
+        );
+
+        let mut out = vec![];
+        Response::new(StatusCode::from_u16(299).unwrap())
+            .write_head(&mut out)
+            .unwrap();
+        assert_eq!(out, b"HTTP/1.1 299 \r\n\r\n");
+    }
+
+    #[test]
+    fn readers() {
+        let mut response = Response::new(StatusCode::OK)
+            .with_body(b"in memory".to_vec())
+            .with_reader(BodyReader::new(&b"read"[..], Some(4)));
+        assert!(response.body().is_empty());
+        assert_eq!(response.reader().and_then(BodyReader::known_len), Some(4));
+        let mut body = String::new();
+        response
+            .take_reader()
+            .unwrap()
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "read");
+
+        response.set_reader(BodyReader::new(io::empty(), None));
+        response.set_body(b"in memory".to_vec());
+        assert!(response.reader().is_none());
+    }
+
+    #[test]
+    fn round_trip_serialize_parse() {
+        let mut response = Response::new(StatusCode::NOT_FOUND)
+            .with_header(HeaderName::SERVER, "websurfer".parse().unwrap())
+            .with_header(HeaderName::SET_COOKIE, "a=1".parse().unwrap())
+            .with_header(HeaderName::SET_COOKIE, "b=2".parse().unwrap())
+            .with_header(HeaderName::CONTENT_LENGTH, 9u64.into())
+            .with_body(b"not found".to_vec());
+        response.set_version(Version::Http1_0);
+
+        let mut out = vec![];
+        response.write_to(&mut out).unwrap();
+        let parsed = parse(&out);
+        assert_eq!(parsed.version(), response.version());
+        assert_eq!(parsed.status(), response.status());
+        assert_eq!(parsed.headers(), response.headers());
+        assert_eq!(parsed.body(), response.body());
+    }
+
+    #[test]
+    fn round_trip_parse_serialize() {
+        let input: &[u8] = b"HTTP/1.1 503 Service Unavailable\r\nretry-after: 120\r\ncontent-length: 0\r\n\r\n";
+        let mut out = vec![];
+        parse(input).write_to(&mut out).unwrap();
+        assert_eq!(out, input);
+
+        // Custom reason phrases survive the round trip
+        let input: &[u8] = b"HTTP/1.1 200 Everything Is Fine\r\n\r\n";
+        let response = parse(input);
+        assert_eq!(response.reason(), "Everything Is Fine");
+        let mut out = vec![];
+        response.write_to(&mut out).unwrap();
+        assert_eq!(out, input);
+    }
+}
@@ -1,11 +1,12 @@
+use std::fmt;
 use std::str::FromStr;
 
 use crate::utils::ALLOWED_SCHEME_BYTES;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct SchemeParseError;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 enum CommonSchemes {
     Http,
     Https,
@@ -23,12 +24,12 @@ impl FromStr for CommonSchemes {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Scheme {
     inner: Repr,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 enum Repr {
     Standard(CommonSchemes),
     Custom(String),
@@ -67,6 +68,21 @@ impl Scheme {
     fn is_valid_scheme_byte(b: &u8) -> bool {
         ALLOWED_SCHEME_BYTES.contains(b)
     }
+
+    pub fn as_str(&self) -> &str {
+        match &self.inner {
+            Repr::Standard(CommonSchemes::Http) => "http",
+            Repr::Standard(CommonSchemes::Https) => "https",
+            Repr::Custom(s) => s,
+            Repr::Empty => "",
+        }
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 impl FromStr for Scheme {
@@ -120,4 +136,12 @@ mod tests {
         // does not start with an alphabetic character
         assert_eq!("1http".parse::<Scheme>(), Err(SchemeParseError));
     }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(Scheme::HTTP.to_string(), "http");
+        assert_eq!(Scheme::HTTPS.to_string(), "https");
+        assert_eq!(Scheme::EMPTY.to_string(), "");
+        assert_eq!("Web+Cal".parse::<Scheme>().unwrap().to_string(), "web+cal");
+    }
 }
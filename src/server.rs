@@ -0,0 +1,1026 @@
+mod access_log;
+mod compress;
+mod conn;
+mod cors;
+mod files;
+mod middleware;
+mod pool;
+mod router;
+mod shutdown;
+
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{fmt, io, thread};
+
+pub use self::access_log::{
+    AccessLog, AccessRecord, Exchange, LogFormat, LogSink, RotatingFile, Stdout,
+};
+pub use self::compress::Compress;
+use self::conn::Connection;
+pub use self::cors::Cors;
+pub use self::files::StaticFiles;
+pub use self::middleware::{
+    BasicAuth, DefaultHeaders, Layered, Middleware, RequestId,
+};
+use self::pool::ThreadPool;
+pub use self::router::{RouteError, Router};
+pub use self::shutdown::ShutdownHandle;
+use crate::request::Request;
+use crate::response::Response;
+
+// Turns a request into a response. Handlers are shared between all worker
+// threads, so any state they keep needs its own synchronization.
+pub trait Handler: Send + Sync + 'static {
+    fn handle(&self, request: Request) -> Response;
+
+    // Wraps the handler in a middleware, which sees requests before it and
+    // responses after it.
+    fn layer<M: Middleware>(self, middleware: M) -> Layered<M, Self>
+    where
+        Self: Sized,
+    {
+        Layered::new(middleware, self)
+    }
+}
+
+impl<F> Handler for F
+where
+    F: Fn(Request) -> Response + Send + Sync + 'static,
+{
+    fn handle(&self, request: Request) -> Response {
+        self(request)
+    }
+}
+
+// How long the accept loop pauses after the first failure to accept a
+// connection, doubling with each failure in a row up to the maximum.
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(5);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+type ErrorHook = Arc<dyn Fn(&io::Error) + Send + Sync>;
+type ResponseHook = Arc<dyn Fn(&Exchange) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Config {
+    workers: usize,
+    queue_len: usize,
+    max_request_line_len: usize,
+    max_head_size: usize,
+    max_headers: usize,
+    max_body_size: usize,
+    decode_request_bodies: bool,
+    header_read_timeout: Duration,
+    body_read_timeout: Duration,
+    write_timeout: Duration,
+    keep_alive_timeout: Duration,
+    max_requests: usize,
+    shutdown_timeout: Duration,
+    accept_error_hook: Option<ErrorHook>,
+    response_hook: Option<ResponseHook>,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        let workers = thread::available_parallelism()
+            .map(|n| n.get() * 4)
+            .unwrap_or(4)
+            .max(4);
+        Self {
+            workers,
+            queue_len: 128,
+            max_request_line_len: 8 * 1024,
+            max_head_size: 16 * 1024,
+            max_headers: 64,
+            max_body_size: 1024 * 1024,
+            decode_request_bodies: true,
+            header_read_timeout: Duration::from_secs(10),
+            body_read_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(30),
+            keep_alive_timeout: Duration::from_secs(5),
+            max_requests: 100,
+            shutdown_timeout: Duration::from_secs(30),
+            accept_error_hook: None,
+            response_hook: None,
+        }
+    }
+
+    // Number of connections served concurrently.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    // Number of accepted connections waiting for a free worker before the
+    // accept loop stops accepting.
+    pub fn queue_len(mut self, queue_len: usize) -> Self {
+        self.queue_len = queue_len;
+        self
+    }
+
+    // Requests whose request line exceeds this many bytes get a 414.
+    pub fn max_request_line_len(mut self, len: usize) -> Self {
+        self.max_request_line_len = len;
+        self
+    }
+
+    // Requests whose head exceeds this many bytes get a 431.
+    pub fn max_head_size(mut self, size: usize) -> Self {
+        self.max_head_size = size;
+        self
+    }
+
+    // Requests with more header fields than this get a 431.
+    pub fn max_headers(mut self, count: usize) -> Self {
+        self.max_headers = count;
+        self
+    }
+
+    // Requests with a body larger than this get a 413.
+    pub fn max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = size;
+        self
+    }
+
+    // Whether request bodies with a `Content-Encoding` are decoded before
+    // they reach the handler. The decoded body is held to `max_body_size`
+    // too, and requests with a coding the server does not know get a 415.
+    // Only gzip, deflate and, with the "brotli" feature, br are known, so a
+    // body encoded with "zstd", even on top of gzip, is refused.
+    pub fn decode_request_bodies(mut self, enabled: bool) -> Self {
+        self.decode_request_bodies = enabled;
+        self
+    }
+
+    // How long a client may take to send a request head, starting from its
+    // first byte. Requests taking longer get a 408.
+    pub fn header_read_timeout(mut self, timeout: Duration) -> Self {
+        self.header_read_timeout = timeout;
+        self
+    }
+
+    // How long a client may take to send a request body, starting once the
+    // head has been read. Requests taking longer get a 408.
+    pub fn body_read_timeout(mut self, timeout: Duration) -> Self {
+        self.body_read_timeout = timeout;
+        self
+    }
+
+    // How long writing to a client may block before the connection is
+    // dropped.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    // How long a persistent connection may stay idle waiting for the next
+    // request before it is closed.
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.keep_alive_timeout = timeout;
+        self
+    }
+
+    // Number of requests served on one connection before it is closed. One
+    // disables persistent connections.
+    pub fn max_requests(mut self, count: usize) -> Self {
+        self.max_requests = count.max(1);
+        self
+    }
+
+    // How long requests in flight are given to finish after a shutdown
+    // before their connections are closed forcibly.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    // Called with every error accepting a connection, which the server
+    // otherwise ignores. Errors such as running out of file descriptors
+    // make the accept loop pause before trying again, so the hook is not
+    // called in a busy loop.
+    pub fn on_accept_error<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&io::Error) + Send + Sync + 'static,
+    {
+        self.accept_error_hook = Some(Arc::new(hook));
+        self
+    }
+
+    // Called once a response has been written, whether it came from the
+    // handler or the server rejected the request itself, with the number of
+    // body bytes that actually went out. Connections closed without a
+    // response, such as ones the client abandons mid-request, are not
+    // reported. Replaces any earlier hook, including an `access_log`.
+    pub fn on_response<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Exchange) + Send + Sync + 'static,
+    {
+        self.response_hook = Some(Arc::new(hook));
+        self
+    }
+
+    // Logs every response through `log`, as an `on_response` hook.
+    pub fn access_log(self, log: AccessLog) -> Self {
+        self.on_response(move |exchange| log.log(exchange))
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("workers", &self.workers)
+            .field("queue_len", &self.queue_len)
+            .field("max_request_line_len", &self.max_request_line_len)
+            .field("max_head_size", &self.max_head_size)
+            .field("max_headers", &self.max_headers)
+            .field("max_body_size", &self.max_body_size)
+            .field("decode_request_bodies", &self.decode_request_bodies)
+            .field("header_read_timeout", &self.header_read_timeout)
+            .field("body_read_timeout", &self.body_read_timeout)
+            .field("write_timeout", &self.write_timeout)
+            .field("keep_alive_timeout", &self.keep_alive_timeout)
+            .field("max_requests", &self.max_requests)
+            .field("shutdown_timeout", &self.shutdown_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Server<H> {
+    listener: TcpListener,
+    handler: Arc<H>,
+    config: Config,
+    shutdown: Arc<shutdown::State>,
+}
+
+impl<H: Handler> Server<H> {
+    pub fn bind<A: ToSocketAddrs>(addr: A, handler: H) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let shutdown = shutdown::State::new(listener.local_addr()?);
+        Ok(Self {
+            listener,
+            handler: Arc::new(handler),
+            config: Config::new(),
+            shutdown: Arc::new(shutdown),
+        })
+    }
+
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    // Useful after binding to port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // A handle for stopping the server from another thread once it runs.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle::new(Arc::clone(&self.shutdown))
+    }
+
+    // Accepts connections until shut down through a `ShutdownHandle`. Errors
+    // on individual connections, such as a client resetting the connection
+    // before it is accepted, go to `Config::on_accept_error` and do not stop
+    // the server.
+    //
+    // On shutdown, the listener is closed and open connections are given
+    // `Config::shutdown_timeout` to finish the requests in flight before
+    // they are closed forcibly. Returns once every worker is done.
+    pub fn run(self) -> io::Result<()> {
+        let config = Arc::new(self.config);
+        let pool = ThreadPool::new(config.workers, config.queue_len);
+        let mut backoff = None;
+        for stream in self.listener.incoming() {
+            if self.shutdown.is_draining() {
+                break;
+            }
+            let registration = match stream.and_then(|stream| {
+                Ok((self.shutdown.register(&stream)?, stream))
+            }) {
+                Ok(registration) => {
+                    backoff = None;
+                    registration
+                }
+                Err(e) => {
+                    if let Some(hook) = &config.accept_error_hook {
+                        hook(&e);
+                    }
+                    if !is_connection_error(&e) {
+                        let pause = next_backoff(backoff);
+                        backoff = Some(pause);
+                        thread::sleep(pause);
+                    }
+                    continue;
+                }
+            };
+            let config = Arc::clone(&config);
+            let handler = Arc::clone(&self.handler);
+            let shutdown = Arc::clone(&self.shutdown);
+            pool.execute(move || {
+                let (registration, stream) = registration;
+                Connection::new(stream, config, handler, shutdown).serve();
+                drop(registration);
+            });
+        }
+        drop(self.listener);
+        self.shutdown
+            .drain(Instant::now() + config.shutdown_timeout);
+        drop(pool);
+        Ok(())
+    }
+}
+
+// Errors that only concern the connection being accepted, rather than the
+// listener or the process, such as running out of file descriptors.
+fn is_connection_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::Interrupted
+    )
+}
+
+fn next_backoff(previous: Option<Duration>) -> Duration {
+    previous.map_or(MIN_ACCEPT_BACKOFF, |pause| {
+        (pause * 2).min(MAX_ACCEPT_BACKOFF)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::mem;
+    use std::net::{Shutdown, TcpStream};
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::coding::{self, ContentCoding};
+    use crate::date::HttpDate;
+    use crate::header::HeaderName;
+    use crate::method::Method;
+    use crate::parser::{RawHeader, ResponseParser, Status};
+    use crate::response::BodyReader;
+    use crate::status::StatusCode;
+
+    fn echo(request: Request) -> Response {
+        match request.target().path().map(|p| p.as_str()) {
+            Some("/panic") => panic!("handler panicked"),
+            Some("/slow") => thread::sleep(Duration::from_millis(300)),
+            // Framing fields that do not match the body
+            Some("/bad-length") => {
+                return Response::new(StatusCode::OK)
+                    .with_header(HeaderName::CONTENT_LENGTH, 100u64.into())
+                    .with_body(b"hi".to_vec());
+            }
+            Some("/bad-chunked") => {
+                return Response::new(StatusCode::OK)
+                    .with_header(
+                        HeaderName::TRANSFER_ENCODING,
+                        "chunked".parse().unwrap(),
+                    )
+                    .with_body(b"hi".to_vec());
+            }
+            Some("/no-content") => {
+                return Response::new(StatusCode::NO_CONTENT)
+                    .with_header(HeaderName::CONTENT_LENGTH, 5u64.into());
+            }
+            Some("/stream") => {
+                return Response::new(StatusCode::OK)
+                    .with_reader(BodyReader::new(&b"streamed"[..], None));
+            }
+            Some("/stream-length") => {
+                return Response::new(StatusCode::OK)
+                    .with_reader(BodyReader::new(&b"streamed"[..], Some(8)));
+            }
+            // Shorter than it claims to be
+            Some("/stream-short") => {
+                return Response::new(StatusCode::OK)
+                    .with_reader(BodyReader::new(&b"streamed"[..], Some(9)));
+            }
+            Some("/peer") => {
+                let peer = request.peer_addr().unwrap().ip().to_string();
+                return Response::new(StatusCode::OK).with_body(peer.into());
+            }
+            _ => {}
+        }
+        let body = match request.method() {
+            Method::Post => request.into_body(),
+            _ => b"hello".to_vec(),
+        };
+        Response::new(StatusCode::OK).with_body(body)
+    }
+
+    // Starts a server on an ephemeral loopback port. The server thread is
+    // left running for the rest of the test process.
+    fn spawn(config: Config) -> SocketAddr {
+        let server = Server::bind("127.0.0.1:0", echo)
+            .unwrap()
+            .with_config(config.workers(2));
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+        addr
+    }
+
+    // Reads one response off a persistent connection, keeping whatever
+    // follows it in `buf`.
+    fn read_response(stream: &mut TcpStream, buf: &mut Vec<u8>) -> Response {
+        loop {
+            let mut headers = [RawHeader::EMPTY; 16];
+            if let Status::Complete(raw) =
+                ResponseParser::new().parse(buf, &mut headers).unwrap()
+            {
+                let head_len = raw.head_len;
+                let response = Response::from_raw(raw, vec![]);
+                let len = response
+                    .headers()
+                    .get(HeaderName::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str()?.parse().ok())
+                    .unwrap_or(0);
+                while buf.len() < head_len + len {
+                    read_more(stream, buf);
+                }
+                let body = buf[head_len..head_len + len].to_vec();
+                buf.drain(..head_len + len);
+                return response.with_body(body);
+            }
+            read_more(stream, buf);
+        }
+    }
+
+    fn read_more(stream: &mut TcpStream, buf: &mut Vec<u8>) {
+        let mut chunk = [0; 1024];
+        let n = stream.read(&mut chunk).unwrap();
+        assert!(n > 0, "connection closed mid-response");
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    fn is_closed(stream: &mut TcpStream) -> bool {
+        matches!(stream.read(&mut [0; 1]), Ok(0))
+    }
+
+    fn exchange(addr: SocketAddr, request: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request).unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    // Checks and removes the automatic `Date` header, whose value changes
+    // from run to run.
+    fn without_date(response: &str) -> String {
+        let start = response.find("\r\ndate: ").expect("date header") + 2;
+        let end = start + response[start..].find("\r\n").unwrap() + 2;
+        let date = &response[start + "date: ".len()..end - 2];
+        let date: HttpDate = date.parse().unwrap();
+        let now = HttpDate::now().unix_secs();
+        assert!(now - date.unix_secs() < 60, "{date}");
+        format!("{}{}", &response[..start], &response[end..])
+    }
+
+    #[test]
+    fn serves_requests() {
+        let addr = spawn(Config::new());
+        let response = exchange(addr, b"GET / HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert_eq!(
+            without_date(&response),
+            "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello"
+        );
+
+        // Same head, no body
+        let response = exchange(addr, b"HEAD / HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert_eq!(
+            without_date(&response),
+            "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n"
+        );
+
+        let response = exchange(
+            addr,
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 4\r\n\r\nping",
+        );
+        assert!(response.ends_with("\r\n\r\nping"));
+
+        let response = exchange(
+            addr,
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n2\r\npi\r\n2\r\nng\r\n0\r\n\r\n",
+        );
+        assert!(response.ends_with("\r\n\r\nping"));
+    }
+
+    #[test]
+    fn framing_fields() {
+        let addr = spawn(Config::new());
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut buf = vec![];
+        for target in ["/bad-length", "/bad-chunked", "/"] {
+            write!(stream, "GET {target} HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+            let response = read_response(&mut stream, &mut buf);
+            assert!(
+                !response
+                    .headers()
+                    .contains_key(HeaderName::TRANSFER_ENCODING)
+            );
+            let expected: &[u8] = match target {
+                "/" => b"hello",
+                _ => b"hi",
+            };
+            assert_eq!(response.body(), expected, "{target}");
+        }
+
+        // Still advertised for HEAD
+        let response =
+            exchange(addr, b"HEAD /bad-length HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.contains("content-length: 100\r\n"), "{response}");
+
+        // Nor is Transfer-Encoding ever sent to HTTP/1.0 clients
+        let response = exchange(addr, b"GET /bad-chunked HTTP/1.0\r\n\r\n");
+        assert!(!response.contains("transfer-encoding"), "{response}");
+        assert!(response.contains("content-length: 2\r\n"), "{response}");
+        assert!(response.ends_with("\r\n\r\nhi"), "{response}");
+
+        let response =
+            exchange(addr, b"GET /no-content HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(!response.contains("content-length"), "{response}");
+    }
+
+    #[test]
+    fn streamed_bodies() {
+        let addr = spawn(Config::new());
+        let response =
+            exchange(addr, b"GET /stream HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.contains("transfer-encoding: chunked\r\n"));
+        assert!(!response.contains("content-length"), "{response}");
+        assert!(response.ends_with("\r\n\r\n8\r\nstreamed\r\n0\r\n\r\n"));
+
+        // Closing the connection marks the end for HTTP/1.0 clients
+        let response = exchange(
+            addr,
+            b"GET /stream HTTP/1.0\r\nConnection: keep-alive\r\n\r\n",
+        );
+        assert!(!response.contains("transfer-encoding"), "{response}");
+        assert!(response.contains("connection: close\r\n"), "{response}");
+        assert!(response.ends_with("\r\n\r\nstreamed"));
+
+        let response =
+            exchange(addr, b"HEAD /stream HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.ends_with("\r\n\r\n"), "{response}");
+        assert!(!response.contains("content-length"), "{response}");
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut buf = vec![];
+        for _ in 0..2 {
+            stream
+                .write_all(b"GET /stream-length HTTP/1.1\r\nHost: a\r\n\r\n")
+                .unwrap();
+            let response = read_response(&mut stream, &mut buf);
+            assert_eq!(response.body(), b"streamed");
+        }
+
+        // Cut off rather than left hanging
+        let response =
+            exchange(addr, b"GET /stream-short HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.contains("content-length: 9\r\n"), "{response}");
+        assert!(response.ends_with("\r\n\r\nstreamed"));
+    }
+
+    #[test]
+    fn peer_addr() {
+        let addr = spawn(Config::new());
+        let response = exchange(addr, b"GET /peer HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.ends_with("\r\n\r\n127.0.0.1"), "{response:?}");
+    }
+
+    #[test]
+    fn access_log() {
+        let sink = Arc::new(Mutex::new(vec![]));
+        let log = AccessLog::new().sink(LogFormat::Common, Arc::clone(&sink));
+        let addr = spawn(Config::new().max_body_size(8).access_log(log));
+        for (request, line) in [
+            (&b"GET / HTTP/1.1\r\nHost: a\r\n\r\n"[..], "\"GET / HTTP/1.1\" 200 5"),
+            (b"HEAD / HTTP/1.1\r\nHost: a\r\n\r\n", "\"HEAD / HTTP/1.1\" 200 -"),
+            (
+                b"GET /stream-length HTTP/1.1\r\nHost: a\r\n\r\n",
+                "\"GET /stream-length HTTP/1.1\" 200 8",
+            ),
+            // Counted without the chunked framing
+            (
+                b"GET /stream HTTP/1.1\r\nHost: a\r\n\r\n",
+                "\"GET /stream HTTP/1.1\" 200 8",
+            ),
+            // Rejected by the server, with or without a request line
+            (b"GET /\r\n\r\n", "\"-\" 400 -"),
+            (
+                b"POST /big HTTP/1.1\r\nHost: a\r\nContent-Length: 9\r\n\r\n123456789",
+                "\"POST /big HTTP/1.1\" 413 -",
+            ),
+            (
+                b"GET /panic HTTP/1.1\r\nHost: a\r\n\r\n",
+                "\"GET /panic HTTP/1.1\" 500 -",
+            ),
+        ] {
+            exchange(addr, request);
+            let output = mem::take(&mut *sink.lock().unwrap());
+            let output = String::from_utf8(output).unwrap();
+            assert!(output.starts_with("127.0.0.1 - - ["), "{output:?}");
+            assert!(output.ends_with(&format!("] {line}\n")), "{output:?}");
+        }
+    }
+
+    #[test]
+    fn expect_continue() {
+        let addr = spawn(Config::new());
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 4\r\nExpect: 100-continue\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut interim = [0; 25];
+        stream.read_exact(&mut interim).unwrap();
+        assert_eq!(&interim, b"HTTP/1.1 100 Continue\r\n\r\n");
+        stream.write_all(b"ping").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nping"));
+
+        let response = exchange(
+            addr,
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 4\r\nExpect: magic\r\n\r\nping",
+        );
+        assert!(response.starts_with("HTTP/1.1 417 Expectation Failed\r\n"));
+    }
+
+    #[test]
+    fn error_responses() {
+        let addr = spawn(Config::new().max_headers(4).max_body_size(8));
+        for (request, status) in [
+            (&b"GET / HTTP/1.1\r\n\r\n"[..], StatusCode::BAD_REQUEST),
+            (b"GET /\r\n\r\n", StatusCode::BAD_REQUEST),
+            (b"BREW / HTTP/1.1\r\nHost: a\r\n\r\n", StatusCode::NOT_IMPLEMENTED),
+            (
+                b"GET / HTTP/2.0\r\nHost: a\r\n\r\n",
+                StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+            ),
+            (
+                b"GET / HTTP/1.1\r\nHost: a\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\n\r\n",
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 9\r\n\r\n123456789",
+                StatusCode::CONTENT_TOO_LARGE,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n9\r\n123456789\r\n0\r\n\r\n",
+                StatusCode::CONTENT_TOO_LARGE,
+            ),
+            (
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                b"GET /panic HTTP/1.1\r\nHost: a\r\n\r\n",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ] {
+            let response = exchange(addr, request);
+            let status_line = format!("HTTP/1.1 {status}\r\n");
+            assert!(
+                response.starts_with(&status_line),
+                "{}: {response:?}",
+                request.escape_ascii()
+            );
+            assert!(response.contains("connection: close\r\n"));
+        }
+    }
+
+    #[test]
+    fn decodes_request_bodies() {
+        let post = |addr, coding: &str, body: &[u8]| {
+            let mut request = format!(
+                "POST / HTTP/1.1\r\nHost: a\r\nContent-Encoding: {coding}\r\n\
+                 Content-Length: {}\r\n\r\n",
+                body.len()
+            )
+            .into_bytes();
+            request.extend_from_slice(body);
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(&request).unwrap();
+            read_response(&mut stream, &mut vec![])
+        };
+        let text = b"hello hello hello hello";
+        let gzip = ContentCoding::Gzip.encode(text);
+        let stacked = ContentCoding::Deflate.encode(&gzip);
+
+        let addr = spawn(Config::new().max_body_size(100));
+        let response = post(addr, "gzip", &gzip);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), text);
+        let response = post(addr, "gzip, deflate", &stacked);
+        assert_eq!(response.body(), text);
+
+        // Zstandard is not supported, even on top of a supported coding
+        let response = post(addr, "gzip, zstd", &gzip);
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let response = post(addr, "zstd", &gzip);
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(
+            response.headers().get(HeaderName::ACCEPT_ENCODING),
+            Some(&coding::accept_encoding())
+        );
+        #[cfg(feature = "brotli")]
+        {
+            let brotli = ContentCoding::Brotli.encode(&gzip);
+            let response = post(addr, "gzip, br", &brotli);
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.body(), text);
+        }
+        #[cfg(not(feature = "brotli"))]
+        {
+            let response = post(addr, "gzip, br", &gzip);
+            assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+        let response = post(addr, "deflate", &gzip);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response =
+            post(addr, "gzip", &ContentCoding::Gzip.encode(&[0; 101]));
+        assert_eq!(response.status(), StatusCode::CONTENT_TOO_LARGE);
+
+        let addr = spawn(Config::new().decode_request_bodies(false));
+        let response = post(addr, "zstd", &gzip);
+        assert_eq!(response.body(), gzip);
+    }
+
+    #[test]
+    fn accept_backoff() {
+        let mut pause = None;
+        let pauses: Vec<_> = (0..10)
+            .map(|_| {
+                pause = Some(next_backoff(pause));
+                pause.unwrap().as_millis()
+            })
+            .collect();
+        assert_eq!(pauses, [5, 10, 20, 40, 80, 160, 320, 640, 1000, 1000]);
+
+        let error = |kind| io::Error::from(kind);
+        assert!(is_connection_error(&error(io::ErrorKind::ConnectionReset)));
+        assert!(!is_connection_error(&io::Error::from_raw_os_error(24)));
+        assert!(!is_connection_error(&error(io::ErrorKind::OutOfMemory)));
+    }
+
+    #[test]
+    fn oversized_head() {
+        let addr = spawn(Config::new().max_head_size(64));
+        let mut request = b"GET / HTTP/1.1\r\nHost: a\r\nX-Long: ".to_vec();
+        request.extend_from_slice(&[b'a'; 128]);
+        request.extend_from_slice(b"\r\n\r\n");
+        let response = exchange(addr, &request);
+        assert!(response.starts_with(&format!(
+            "HTTP/1.1 {}\r\n",
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        )));
+
+        let response = exchange(addr, b"GET / HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn keep_alive() {
+        let addr = spawn(Config::new());
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut buf = vec![];
+        for body in ["a", "bc", "def"] {
+            write!(
+                stream,
+                "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+            let response = read_response(&mut stream, &mut buf);
+            assert_eq!(response.body(), body.as_bytes());
+            assert!(!response.headers().contains_key(HeaderName::CONNECTION));
+        }
+
+        stream
+            .write_all(
+                b"GET / HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n",
+            )
+            .unwrap();
+        let response = read_response(&mut stream, &mut buf);
+        assert_eq!(
+            response
+                .headers()
+                .get(HeaderName::CONNECTION)
+                .unwrap()
+                .as_bytes(),
+            b"close"
+        );
+        assert!(is_closed(&mut stream));
+    }
+
+    #[test]
+    fn http_1_0_keep_alive() {
+        let addr = spawn(Config::new());
+        // Closed by default
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut buf = vec![];
+        stream.write_all(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+        let response = read_response(&mut stream, &mut buf);
+        assert_eq!(
+            response
+                .headers()
+                .get(HeaderName::CONNECTION)
+                .unwrap()
+                .as_bytes(),
+            b"close"
+        );
+        assert!(is_closed(&mut stream));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        for _ in 0..2 {
+            stream
+                .write_all(b"GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n")
+                .unwrap();
+            let response = read_response(&mut stream, &mut buf);
+            assert_eq!(
+                response
+                    .headers()
+                    .get(HeaderName::CONNECTION)
+                    .unwrap()
+                    .as_bytes(),
+                b"keep-alive"
+            );
+        }
+    }
+
+    #[test]
+    fn pipelining() {
+        let addr = spawn(Config::new());
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut requests = vec![];
+        for body in ["first", "second", "third"] {
+            write!(
+                requests,
+                "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        }
+        requests.extend_from_slice(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n");
+        stream.write_all(&requests).unwrap();
+
+        let mut buf = vec![];
+        for body in ["first", "second", "third", "hello"] {
+            let response = read_response(&mut stream, &mut buf);
+            assert_eq!(response.body(), body.as_bytes());
+        }
+    }
+
+    #[test]
+    fn connection_limits() {
+        let addr = spawn(
+            Config::new()
+                .max_requests(2)
+                .keep_alive_timeout(Duration::from_millis(100)),
+        );
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut buf = vec![];
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\n")
+            .unwrap();
+        let response = read_response(&mut stream, &mut buf);
+        assert!(!response.headers().contains_key(HeaderName::CONNECTION));
+        let response = read_response(&mut stream, &mut buf);
+        assert_eq!(
+            response
+                .headers()
+                .get(HeaderName::CONNECTION)
+                .unwrap()
+                .as_bytes(),
+            b"close"
+        );
+        // The third request is never answered
+        assert!(is_closed(&mut stream));
+
+        // Idle connections are closed
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n")
+            .unwrap();
+        read_response(&mut stream, &mut buf);
+        thread::sleep(Duration::from_millis(300));
+        assert!(is_closed(&mut stream));
+    }
+
+    #[test]
+    fn long_request_line() {
+        let addr = spawn(Config::new().max_request_line_len(32));
+        let mut request = b"GET /".to_vec();
+        request.extend_from_slice(&[b'a'; 64]);
+        request.extend_from_slice(b" HTTP/1.1\r\nHost: a\r\n\r\n");
+        let response = exchange(addr, &request);
+        assert!(response.starts_with("HTTP/1.1 414 URI Too Long\r\n"));
+    }
+
+    #[test]
+    fn read_timeouts() {
+        let addr = spawn(
+            Config::new()
+                .header_read_timeout(Duration::from_millis(200))
+                .body_read_timeout(Duration::from_millis(200)),
+        );
+
+        // Trickling in the head does not extend the deadline
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        for _ in 0..3 {
+            thread::sleep(Duration::from_millis(100));
+            if stream.write_all(b"X-Slow: 1\r\n").is_err() {
+                break;
+            }
+        }
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(
+                b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 9\r\n\r\nabc",
+            )
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    }
+
+    #[test]
+    fn graceful_shutdown() {
+        let server = Server::bind("127.0.0.1:0", echo).unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let server = thread::spawn(move || server.run());
+
+        // Idle after one request
+        let mut idle = TcpStream::connect(addr).unwrap();
+        let mut buf = vec![];
+        idle.write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n")
+            .unwrap();
+        read_response(&mut idle, &mut buf);
+
+        let mut busy = TcpStream::connect(addr).unwrap();
+        busy.write_all(b"GET /slow HTTP/1.1\r\nHost: a\r\n\r\n")
+            .unwrap();
+        thread::sleep(Duration::from_millis(100));
+        handle.shutdown();
+        assert!(handle.is_shutting_down());
+
+        assert!(is_closed(&mut idle));
+        // The request in flight is answered, but the connection not kept
+        let response = read_response(&mut busy, &mut buf);
+        assert_eq!(response.body(), b"hello");
+        assert_eq!(
+            response
+                .headers()
+                .get(HeaderName::CONNECTION)
+                .unwrap()
+                .as_bytes(),
+            b"close"
+        );
+        assert!(is_closed(&mut busy));
+
+        server.join().unwrap().unwrap();
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    #[test]
+    fn shutdown_deadline() {
+        let config = Config::new()
+            .header_read_timeout(Duration::from_secs(10))
+            .shutdown_timeout(Duration::from_millis(100));
+        let server = Server::bind("127.0.0.1:0", echo)
+            .unwrap()
+            .with_config(config);
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let server = thread::spawn(move || server.run());
+
+        // Never finishes its request
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        let start = Instant::now();
+        handle.shutdown();
+        server.join().unwrap().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        let mut response = vec![];
+        assert!(matches!(stream.read_to_end(&mut response), Ok(0) | Err(_)));
+    }
+}
@@ -0,0 +1,550 @@
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::date::HttpDate;
+use crate::header::{HeaderMap, HeaderName};
+use crate::json;
+use crate::method::Method;
+use crate::request::Request;
+use crate::status::StatusCode;
+use crate::version::Version;
+
+const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 5;
+const REDACTED: &str = "[redacted]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    // Apache's Common Log Format:
+    // host ident user [time] "request line" status bytes
+    Common,
+    // The Common Log Format followed by the quoted referrer and user agent
+    Combined,
+    // One JSON object per line, with every field of `AccessRecord`
+    Json,
+}
+
+// A response as the server wrote it, along with what is known about the
+// request it answers. Passed to the `Config::on_response` hook.
+#[derive(Debug)]
+pub struct Exchange<'a> {
+    // When the request started to arrive
+    pub time: HttpDate,
+    pub peer_addr: Option<SocketAddr>,
+    // The request line and fields, or `None` if the request was rejected
+    // before they could be parsed. The body is not kept.
+    pub request: Option<&'a Request>,
+    pub status: StatusCode,
+    // Bytes of the body written, less the chunked framing. Short of the
+    // whole body if the connection failed while writing it.
+    pub bytes: u64,
+    // From the start of the request until the response was written
+    pub duration: Duration,
+}
+
+// Everything logged about one request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessRecord {
+    pub time: HttpDate,
+    pub peer_addr: Option<SocketAddr>,
+    // The request line, unless the request was rejected before it could
+    // be parsed
+    pub method: Option<Method>,
+    pub target: Option<String>,
+    pub version: Option<Version>,
+    pub status: StatusCode,
+    // Size of the response body as sent, so zero for HEAD
+    pub bytes: u64,
+    pub duration: Duration,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    // The request fields configured to be logged, sensitive ones redacted
+    pub headers: Vec<(HeaderName, String)>,
+}
+
+impl AccessRecord {
+    pub fn format(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Common => self.common(),
+            LogFormat::Combined => format!(
+                "{} \"{}\" \"{}\"",
+                self.common(),
+                clf_escape(self.referer.as_deref().unwrap_or("-")),
+                clf_escape(self.user_agent.as_deref().unwrap_or("-"))
+            ),
+            LogFormat::Json => self.json(),
+        }
+    }
+
+    fn common(&self) -> String {
+        let host = self
+            .peer_addr
+            .map_or_else(|| "-".to_string(), |addr| addr.ip().to_string());
+        let bytes = match self.bytes {
+            0 => "-".to_string(),
+            n => n.to_string(),
+        };
+        let request_line = match (self.method, &self.target, self.version) {
+            (Some(method), Some(target), Some(version)) => {
+                format!("{method} {} {version}", clf_escape(target))
+            }
+            _ => "-".to_string(),
+        };
+        format!(
+            "{host} - - [{}] \"{request_line}\" {} {bytes}",
+            self.time.to_common_log(),
+            self.status.as_u16(),
+        )
+    }
+
+    fn json(&self) -> String {
+        let mut json = String::from("{");
+        let _ =
+            write!(json, "\"time\":{}", json::string(&self.time.to_rfc3339()));
+        let peer = self.peer_addr.map(|addr| addr.to_string());
+        let _ = write!(json, ",\"peer_addr\":{}", json_option(peer.as_deref()));
+        let method = self.method.map(|method| method.to_string());
+        let _ = write!(json, ",\"method\":{}", json_option(method.as_deref()));
+        let _ =
+            write!(json, ",\"target\":{}", json_option(self.target.as_deref()));
+        let version = self.version.map(|version| version.to_string());
+        let _ =
+            write!(json, ",\"version\":{}", json_option(version.as_deref()));
+        let _ = write!(json, ",\"status\":{}", self.status.as_u16());
+        let _ = write!(json, ",\"bytes\":{}", self.bytes);
+        let _ = write!(json, ",\"duration_us\":{}", self.duration.as_micros());
+        let _ = write!(
+            json,
+            ",\"referer\":{}",
+            json_option(self.referer.as_deref())
+        );
+        let _ = write!(
+            json,
+            ",\"user_agent\":{}",
+            json_option(self.user_agent.as_deref())
+        );
+        if !self.headers.is_empty() {
+            json.push_str(",\"headers\":{");
+            for (i, (name, value)) in self.headers.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                let _ = write!(
+                    json,
+                    "{}:{}",
+                    json::string(name.as_str()),
+                    json::string(value)
+                );
+            }
+            json.push('}');
+        }
+        json.push('}');
+        json
+    }
+}
+
+// Quoted fields of the Common Log Format escape quotes, backslashes and
+// anything unprintable, so a request cannot forge log lines.
+fn clf_escape(s: &str) -> String {
+    s.bytes()
+        .flat_map(|b| b.escape_ascii())
+        .map(char::from)
+        .collect()
+}
+
+fn json_option(s: Option<&str>) -> String {
+    s.map_or_else(|| "null".into(), json::string)
+}
+
+// Header values as text, with bytes that are not UTF-8 escaped.
+fn header_text(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers.get(name).map(|value| match value.to_str() {
+        Some(s) => s.into(),
+        None => value.as_bytes().escape_ascii().to_string(),
+    })
+}
+
+// Where log lines go. Writing must not block serving for long, and errors
+// are ignored, as failing requests over a full disk helps nobody.
+pub trait LogSink: Send + Sync + 'static {
+    // Writes one line, without its line break.
+    fn write_line(&self, line: &str) -> io::Result<()>;
+}
+
+impl<S: LogSink> LogSink for Arc<S> {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        (**self).write_line(line)
+    }
+}
+
+// Any writer, such as a `Vec<u8>` in tests or stderr.
+impl<W: Write + Send + 'static> LogSink for Mutex<W> {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        let mut writer = self.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(writer, "{line}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stdout;
+
+impl LogSink for Stdout {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        writeln!(io::stdout().lock(), "{line}")
+    }
+}
+
+// A log file that is rotated once it would grow past a size limit: "x.log"
+// is renamed to "x.log.1", "x.log.1" to "x.log.2" and so on, dropping the
+// oldest, and a new "x.log" is started.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    // The open file and its size
+    file: Mutex<(File, u64)>,
+}
+
+impl RotatingFile {
+    // Appends to the file if it exists already.
+    pub fn new<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_size: DEFAULT_MAX_FILE_SIZE,
+            max_files: DEFAULT_MAX_FILES,
+            file: Mutex::new((file, size)),
+        })
+    }
+
+    // The size a file may grow to before it is rotated.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = size;
+        self
+    }
+
+    // Number of rotated files kept next to the current one.
+    pub fn max_files(mut self, count: usize) -> Self {
+        self.max_files = count;
+        self
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(format!(".{n}"));
+        path.into()
+    }
+
+    fn rotate(&self) -> io::Result<File> {
+        if self.max_files > 0 {
+            for n in (1..self.max_files).rev() {
+                let from = self.rotated_path(n);
+                if from.exists() {
+                    fs::rename(from, self.rotated_path(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)
+    }
+}
+
+impl LogSink for RotatingFile {
+    fn write_line(&self, line: &str) -> io::Result<()> {
+        let mut state = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        let (file, size) = &mut *state;
+        let len = line.len() as u64 + 1;
+        if *size > 0 && *size + len > self.max_size {
+            *file = self.rotate()?;
+            *size = 0;
+        }
+        // One write per line, so lines stay whole even if another process
+        // appends to the same file
+        file.write_all(format!("{line}\n").as_bytes())?;
+        *size += len;
+        Ok(())
+    }
+}
+
+// Logs a line for every response the server writes to each of its sinks,
+// in that sink's format. It is installed with `Config::access_log`, so that
+// requests the server rejects itself, and handler panics turned into 500s,
+// are logged too.
+//
+// Only the request line, response status and size, timing and a few request
+// fields are logged, never bodies. Further request fields can be logged in
+// JSON, but `Authorization`, `Proxy-Authorization`, `Cookie` and
+// `Set-Cookie`, along with any fields marked with `redact`, only ever show
+// up as "[redacted]".
+pub struct AccessLog {
+    sinks: Vec<(LogFormat, Box<dyn LogSink>)>,
+    headers: Vec<HeaderName>,
+    redacted: Vec<HeaderName>,
+}
+
+impl AccessLog {
+    pub fn new() -> Self {
+        Self {
+            sinks: vec![],
+            headers: vec![],
+            redacted: vec![
+                HeaderName::AUTHORIZATION,
+                HeaderName::PROXY_AUTHORIZATION,
+                HeaderName::COOKIE,
+                HeaderName::SET_COOKIE,
+            ],
+        }
+    }
+
+    pub fn sink<S: LogSink>(mut self, format: LogFormat, sink: S) -> Self {
+        self.sinks.push((format, Box::new(sink)));
+        self
+    }
+
+    // Adds a request field to the JSON records.
+    pub fn log_header(mut self, name: HeaderName) -> Self {
+        self.headers.push(name);
+        self
+    }
+
+    // Hides the value of a field that would otherwise be logged.
+    pub fn redact(mut self, name: HeaderName) -> Self {
+        self.redacted.push(name);
+        self
+    }
+
+    fn logged_headers(&self, headers: &HeaderMap) -> Vec<(HeaderName, String)> {
+        self.headers
+            .iter()
+            .filter_map(|name| {
+                let value = if self.redacted.contains(name) {
+                    headers.contains_key(name).then(|| REDACTED.into())
+                } else {
+                    header_text(headers, name.clone())
+                }?;
+                Some((name.clone(), value))
+            })
+            .collect()
+    }
+
+    // Writes a record of the exchange to every sink.
+    pub fn log(&self, exchange: &Exchange) {
+        let request = exchange.request;
+        let redacted = |name: HeaderName| {
+            let headers = request?.headers();
+            if self.redacted.contains(&name) {
+                headers.contains_key(&name).then(|| REDACTED.into())
+            } else {
+                header_text(headers, name)
+            }
+        };
+        let record = AccessRecord {
+            time: exchange.time,
+            peer_addr: exchange.peer_addr,
+            method: request.map(|request| request.method()),
+            target: request.map(|request| request.target().to_string()),
+            version: request.map(|request| request.version()),
+            status: exchange.status,
+            bytes: exchange.bytes,
+            duration: exchange.duration,
+            referer: redacted(HeaderName::REFERER),
+            user_agent: redacted(HeaderName::USER_AGENT),
+            headers: request.map_or_else(Vec::new, |request| {
+                self.logged_headers(request.headers())
+            }),
+        };
+        for (format, sink) in &self.sinks {
+            let _ = sink.write_line(&record.format(*format));
+        }
+    }
+}
+
+impl Default for AccessLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+    use crate::uri::RequestTarget;
+
+    fn record() -> AccessRecord {
+        AccessRecord {
+            time: HttpDate::from_unix_secs(971186136),
+            peer_addr: Some(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                5000,
+            )),
+            method: Some(Method::Get),
+            target: Some("/apache_pb.gif".into()),
+            version: Some(Version::Http1_0),
+            status: StatusCode::OK,
+            bytes: 2326,
+            duration: Duration::from_micros(1500),
+            referer: Some("http://www.example.com/start.html".into()),
+            user_agent: Some("Mozilla/4.08 [en] (Win98; I ;Nav)".into()),
+            headers: vec![],
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let record = record();
+        assert_eq!(
+            record.format(LogFormat::Common),
+            "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \
+             \"GET /apache_pb.gif HTTP/1.0\" 200 2326"
+        );
+        assert_eq!(
+            record.format(LogFormat::Combined),
+            "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \
+             \"GET /apache_pb.gif HTTP/1.0\" 200 2326 \
+             \"http://www.example.com/start.html\" \
+             \"Mozilla/4.08 [en] (Win98; I ;Nav)\""
+        );
+        assert_eq!(
+            record.format(LogFormat::Json),
+            "{\"time\":\"2000-10-10T13:55:36Z\",\
+             \"peer_addr\":\"127.0.0.1:5000\",\"method\":\"GET\",\
+             \"target\":\"/apache_pb.gif\",\"version\":\"HTTP/1.0\",\
+             \"status\":200,\"bytes\":2326,\"duration_us\":1500,\
+             \"referer\":\"http://www.example.com/start.html\",\
+             \"user_agent\":\"Mozilla/4.08 [en] (Win98; I ;Nav)\"}"
+        );
+
+        let record = AccessRecord {
+            peer_addr: None,
+            bytes: 0,
+            referer: None,
+            user_agent: Some("evil\" \"agent\n".into()),
+            headers: vec![(HeaderName::HOST, "a\u{1}b".into())],
+            ..record
+        };
+        assert_eq!(
+            record.format(LogFormat::Combined),
+            "- - - [10/Oct/2000:13:55:36 +0000] \
+             \"GET /apache_pb.gif HTTP/1.0\" 200 - \
+             \"-\" \"evil\\\" \\\"agent\\n\""
+        );
+        assert!(record.format(LogFormat::Json).ends_with(
+            "\"referer\":null,\"user_agent\":\"evil\\\" \\\"agent\\n\",\
+             \"headers\":{\"host\":\"a\\u0001b\"}}"
+        ));
+
+        // Rejected before the request line was parsed
+        let record = AccessRecord {
+            method: None,
+            target: None,
+            version: None,
+            status: StatusCode::BAD_REQUEST,
+            bytes: 11,
+            referer: None,
+            user_agent: None,
+            headers: vec![],
+            ..record
+        };
+        assert_eq!(
+            record.format(LogFormat::Common),
+            "- - - [10/Oct/2000:13:55:36 +0000] \"-\" 400 11"
+        );
+        assert!(record.format(LogFormat::Json).contains(
+            "\"method\":null,\"target\":null,\"version\":null,\
+             \"status\":400"
+        ));
+    }
+
+    #[test]
+    fn logs_requests() {
+        let sink = Arc::new(Mutex::new(vec![]));
+        let log = AccessLog::new()
+            .sink(LogFormat::Common, Arc::clone(&sink))
+            .sink(LogFormat::Json, Arc::clone(&sink))
+            .log_header(HeaderName::HOST)
+            .log_header(HeaderName::AUTHORIZATION)
+            .log_header("x-api-key".parse().unwrap())
+            .redact("x-api-key".parse().unwrap());
+        let exchange = |request, status, bytes| Exchange {
+            time: HttpDate::now(),
+            peer_addr: Some("10.0.0.1:1234".parse().unwrap()),
+            request,
+            status,
+            bytes,
+            duration: Duration::from_millis(1),
+        };
+
+        let target = RequestTarget::parse("/x?y=1", &Method::Head).unwrap();
+        let request = Request::new(Method::Head, target)
+            .with_header(HeaderName::HOST, "example.com".parse().unwrap())
+            .with_header(
+                HeaderName::AUTHORIZATION,
+                "Basic abc".parse().unwrap(),
+            )
+            .with_header(
+                "x-api-key".parse().unwrap(),
+                "secret".parse().unwrap(),
+            )
+            .with_header(HeaderName::USER_AGENT, "test".parse().unwrap());
+        log.log(&exchange(Some(&request), StatusCode::OK, 0));
+        let target = RequestTarget::parse("/", &Method::Post).unwrap();
+        let request = Request::new(Method::Post, target);
+        log.log(&exchange(Some(&request), StatusCode::CREATED, 5));
+
+        let output = String::from_utf8(sink.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("10.0.0.1 - - ["));
+        assert!(lines[0].ends_with("] \"HEAD /x?y=1 HTTP/1.1\" 200 -"));
+        assert!(lines[1].contains("\"user_agent\":\"test\""));
+        assert!(lines[1].ends_with(
+            "\"headers\":{\"host\":\"example.com\",\
+             \"authorization\":\"[redacted]\",\
+             \"x-api-key\":\"[redacted]\"}}"
+        ));
+        assert!(!output.contains("secret") && !output.contains("abc"));
+        assert!(lines[2].ends_with("] \"POST / HTTP/1.1\" 201 5"));
+        assert!(lines[3].contains("\"bytes\":5"));
+    }
+
+    #[test]
+    fn rotation() {
+        let dir = std::env::temp_dir()
+            .join(format!("websurfer-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+        let read = |name: &str| fs::read_to_string(dir.join(name)).ok();
+
+        let file = RotatingFile::new(&path).unwrap().max_size(8).max_files(2);
+        for line in ["one", "two", "three", "four", "five"] {
+            file.write_line(line).unwrap();
+        }
+        assert_eq!(read("access.log").as_deref(), Some("five\n"));
+        assert_eq!(read("access.log.1").as_deref(), Some("four\n"));
+        assert_eq!(read("access.log.2").as_deref(), Some("three\n"));
+        assert_eq!(read("access.log.3"), None);
+        drop(file);
+
+        // Appends to what is there
+        let file = RotatingFile::new(&path).unwrap().max_size(10);
+        file.write_line("six").unwrap();
+        assert_eq!(read("access.log").as_deref(), Some("five\nsix\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,403 @@
+use std::io::{self, Read, Write};
+use std::mem;
+
+use super::{Handler, Middleware};
+use crate::coding::{ContentCoding, Encoder};
+use crate::etag::ETag;
+use crate::header::HeaderName;
+use crate::method::Method;
+use crate::mime;
+use crate::negotiation::{self, AcceptEncoding};
+use crate::request::Request;
+use crate::response::{BodyReader, Response};
+use crate::status::StatusCode;
+
+// Compressing less than this saves too little to be worth the CPU time, and
+// may even make the body larger.
+const DEFAULT_MIN_SIZE: usize = 1024;
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+
+// Compresses the responses of the handler it wraps with the content coding the
+// client prefers, as negotiated from `Accept-Encoding`.
+//
+// Only successful responses to GET and HEAD are compressed, and only if their
+// `Content-Type` is compressible, they are not encoded already and their body
+// is at least `min_size` bytes. Responses that could have been compressed
+// get `Vary: Accept-Encoding` whether they were or not, so caches keep the
+// variants apart.
+//
+// Bodies held in memory are encoded at once and keep a `Content-Length`.
+// Bodies read from a `BodyReader` are encoded as they are sent, so their
+// encoded length is unknown and the server falls back to the chunked
+// transfer coding. A response to HEAD gets the same `Content-Encoding`,
+// `Vary` and `ETag` as the response to GET would, and a `Content-Length`
+// only if the handler produced the body in memory.
+#[derive(Debug, Clone)]
+pub struct Compress {
+    codings: Vec<ContentCoding>,
+    min_size: usize,
+}
+
+impl Compress {
+    pub fn new() -> Self {
+        Self {
+            codings: vec![ContentCoding::Gzip, ContentCoding::Deflate],
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+
+    // The codings offered to clients, in order of preference for when the
+    // client likes several equally.
+    pub fn codings<I>(mut self, codings: I) -> Self
+    where
+        I: IntoIterator<Item = ContentCoding>,
+    {
+        self.codings = codings.into_iter().collect();
+        self
+    }
+
+    // The smallest body worth compressing.
+    pub fn min_size(mut self, size: usize) -> Self {
+        self.min_size = size;
+        self
+    }
+
+    fn is_compressible(&self, method: Method, response: &Response) -> bool {
+        let status = response.status();
+        let headers = response.headers();
+        // Handlers may leave out the body of a response to HEAD, but still
+        // give its length. Streams of unknown length are assumed to be long.
+        let len = match response.reader() {
+            Some(reader) => reader
+                .known_len()
+                .map_or(usize::MAX, |len| len.try_into().unwrap_or(usize::MAX)),
+            None if method == Method::Head && response.body().is_empty() => {
+                headers
+                    .get(HeaderName::CONTENT_LENGTH)
+                    .and_then(|value| value.to_str())
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(0)
+            }
+            None => response.body().len(),
+        };
+        matches!(method, Method::Get | Method::Head)
+            && status.is_success()
+            // Content-Range refers to the unencoded representation
+            && status != StatusCode::PARTIAL_CONTENT
+            && status != StatusCode::NO_CONTENT
+            && !headers.contains_key(HeaderName::CONTENT_ENCODING)
+            && len >= self.min_size
+            && headers
+                .get(HeaderName::CONTENT_TYPE)
+                .and_then(|value| value.to_str())
+                .is_some_and(mime::is_compressible)
+    }
+}
+
+impl Default for Compress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for Compress {
+    fn call(&self, request: Request, next: &dyn Handler) -> Response {
+        let accept = AcceptEncoding::from_request(&request);
+        let method = request.method();
+        let mut response = next.handle(request);
+        if !self.is_compressible(method, &response) {
+            return response;
+        }
+        negotiation::vary(&mut response, HeaderName::ACCEPT_ENCODING);
+
+        let mut offers: Vec<&str> =
+            self.codings.iter().map(|coding| coding.as_str()).collect();
+        offers.push("identity");
+        let Some(coding) = accept
+            .negotiate(&offers)
+            .and_then(|offer| ContentCoding::from_bytes(offer.as_bytes()))
+        else {
+            return response;
+        };
+
+        let body = match response.take_reader() {
+            Some(reader) => {
+                let reader = EncodingReader::new(reader, coding);
+                response.set_reader(BodyReader::new(reader, None));
+                None
+            }
+            None if method == Method::Head && response.body().is_empty() => {
+                None
+            }
+            None => Some(coding.encode(response.body())),
+        };
+        let headers = response.headers_mut();
+        headers.insert(HeaderName::CONTENT_ENCODING, coding.into());
+        match &body {
+            Some(body) => {
+                headers.insert(HeaderName::CONTENT_LENGTH, body.len().into());
+            }
+            None => {
+                headers.remove(HeaderName::CONTENT_LENGTH);
+            }
+        }
+        // The encoded body is not byte-for-byte the same representation, but
+        // still semantically equivalent, which is what a weak tag says.
+        if let Some(etag) = headers
+            .get(HeaderName::ETAG)
+            .and_then(|value| ETag::from_bytes(value.as_bytes()).ok())
+            && !etag.is_weak()
+        {
+            let weak = ETag::weak(etag.tag()).expect("tag is valid");
+            headers.insert(HeaderName::ETAG, (&weak).into());
+        }
+        if let Some(body) = body {
+            response.set_body(body);
+        }
+        response
+    }
+}
+
+// Encodes a body as it is read.
+struct EncodingReader {
+    source: BodyReader,
+    // Gone once the source is exhausted and the encoding finished
+    encoder: Option<Encoder<Vec<u8>>>,
+    // Encoded bytes not read yet, from `pos` on
+    out: Vec<u8>,
+    pos: usize,
+}
+
+impl EncodingReader {
+    fn new(source: BodyReader, coding: ContentCoding) -> Self {
+        Self {
+            source,
+            encoder: Some(Encoder::new(vec![], coding)),
+            out: vec![],
+            pos: 0,
+        }
+    }
+}
+
+impl Read for EncodingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.out.len() {
+            let Some(encoder) = &mut self.encoder else {
+                return Ok(0);
+            };
+            let mut chunk = [0; READ_CHUNK_SIZE];
+            let n = self.source.read(&mut chunk)?;
+            self.out = if n == 0 {
+                let encoder = self.encoder.take().expect("encoder is there");
+                encoder.finish()?
+            } else {
+                encoder.write_all(&chunk[..n])?;
+                mem::take(encoder.get_mut())
+            };
+            self.pos = 0;
+        }
+        let n = buf.len().min(self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coding::DecodingReader;
+    use crate::header::HeaderValue;
+    use crate::uri::RequestTarget;
+
+    fn handler(request: Request) -> Response {
+        let (content_type, len) =
+            match request.target().path().unwrap().as_str() {
+                "/small" => ("text/plain", 100),
+                "/png" => ("image/png", 5000),
+                _ => ("text/html; charset=utf-8", 5000),
+            };
+        Response::new(StatusCode::OK)
+            .with_header(
+                HeaderName::CONTENT_TYPE,
+                HeaderValue::from_bytes(content_type.as_bytes()).unwrap(),
+            )
+            .with_header(HeaderName::ETAG, "\"v1\"".parse().unwrap())
+            .with_header(HeaderName::CONTENT_LENGTH, len.into())
+            .with_body(b"<p>".repeat(len / 3 + 1)[..len].to_vec())
+    }
+
+    fn get(
+        compress: &impl Handler,
+        method: Method,
+        target: &str,
+        accept_encoding: Option<&str>,
+    ) -> Response {
+        let target = RequestTarget::parse(target, &method).unwrap();
+        let mut request = Request::new(method, target);
+        if let Some(value) = accept_encoding {
+            request = request.with_header(
+                HeaderName::ACCEPT_ENCODING,
+                value.parse().unwrap(),
+            );
+        }
+        compress.handle(request)
+    }
+
+    fn header(response: &Response, name: HeaderName) -> Option<&[u8]> {
+        response.headers().get(name).map(|value| value.as_bytes())
+    }
+
+    #[test]
+    fn compresses() {
+        let compress =
+            (handler as fn(Request) -> Response).layer(Compress::new());
+
+        let response = get(&compress, Method::Get, "/", Some("gzip, deflate"));
+        assert_eq!(
+            header(&response, HeaderName::CONTENT_ENCODING),
+            Some(&b"gzip"[..])
+        );
+        assert_eq!(
+            header(&response, HeaderName::VARY),
+            Some(&b"accept-encoding"[..])
+        );
+        assert_eq!(header(&response, HeaderName::ETAG), Some(&b"W/\"v1\""[..]));
+        let len = response.body().len();
+        assert!(len < 100, "{len}");
+        assert_eq!(
+            header(&response, HeaderName::CONTENT_LENGTH),
+            Some(len.to_string().as_bytes())
+        );
+        assert!(response.body().starts_with(&[0x1f, 0x8b]));
+
+        let response =
+            get(&compress, Method::Get, "/", Some("gzip;q=0.5, deflate"));
+        assert_eq!(
+            header(&response, HeaderName::CONTENT_ENCODING),
+            Some(&b"deflate"[..])
+        );
+
+        // Not wanted
+        for accept_encoding in [Some("br"), Some(""), Some("gzip;q=0")] {
+            let response = get(&compress, Method::Get, "/", accept_encoding);
+            assert_eq!(header(&response, HeaderName::CONTENT_ENCODING), None);
+            assert_eq!(
+                header(&response, HeaderName::VARY),
+                Some(&b"accept-encoding"[..])
+            );
+            assert_eq!(response.body().len(), 5000);
+        }
+
+        // Not worth it
+        for target in ["/small", "/png"] {
+            let response = get(&compress, Method::Get, target, Some("gzip"));
+            assert_eq!(header(&response, HeaderName::CONTENT_ENCODING), None);
+            assert_eq!(header(&response, HeaderName::VARY), None);
+        }
+
+        let compress = (handler as fn(Request) -> Response).layer(
+            Compress::new()
+                .codings([ContentCoding::Deflate])
+                .min_size(10),
+        );
+        let response = get(&compress, Method::Get, "/small", None);
+        assert_eq!(
+            header(&response, HeaderName::CONTENT_ENCODING),
+            Some(&b"deflate"[..])
+        );
+    }
+
+    #[test]
+    fn streams() {
+        let streamed = |request: Request| {
+            let mut response = handler(request);
+            let body = response.body().to_vec();
+            let len = body.len() as u64;
+            response
+                .set_reader(BodyReader::new(io::Cursor::new(body), Some(len)));
+            response
+        };
+        let compress = streamed.layer(Compress::new());
+        let mut response = get(&compress, Method::Get, "/", Some("gzip"));
+        assert_eq!(
+            header(&response, HeaderName::CONTENT_ENCODING),
+            Some(&b"gzip"[..])
+        );
+        assert_eq!(header(&response, HeaderName::CONTENT_LENGTH), None);
+        let reader = response.take_reader().unwrap();
+        assert_eq!(reader.known_len(), None);
+        let mut body = vec![];
+        DecodingReader::new(reader, &[ContentCoding::Gzip])
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(body, b"<p>".repeat(5000 / 3 + 1)[..5000]);
+
+        // Too short to be worth it, as far as the length says
+        let mut response = get(&compress, Method::Get, "/small", Some("gzip"));
+        assert_eq!(header(&response, HeaderName::CONTENT_ENCODING), None);
+        assert_eq!(response.take_reader().unwrap().known_len(), Some(100));
+
+        #[cfg(feature = "brotli")]
+        {
+            let compress = streamed.layer(
+                Compress::new()
+                    .codings([ContentCoding::Brotli, ContentCoding::Gzip]),
+            );
+            let mut response =
+                get(&compress, Method::Get, "/", Some("gzip, br"));
+            assert_eq!(
+                header(&response, HeaderName::CONTENT_ENCODING),
+                Some(&b"br"[..])
+            );
+            let mut body = vec![];
+            DecodingReader::new(
+                response.take_reader().unwrap(),
+                &[ContentCoding::Brotli],
+            )
+            .read_to_end(&mut body)
+            .unwrap();
+            assert_eq!(body, b"<p>".repeat(5000 / 3 + 1)[..5000]);
+        }
+    }
+
+    #[test]
+    fn head() {
+        let compress =
+            (handler as fn(Request) -> Response).layer(Compress::new());
+        let get_response = get(&compress, Method::Get, "/", Some("gzip"));
+        let head_response = get(&compress, Method::Head, "/", Some("gzip"));
+        for name in [
+            HeaderName::CONTENT_ENCODING,
+            HeaderName::CONTENT_LENGTH,
+            HeaderName::VARY,
+            HeaderName::ETAG,
+        ] {
+            assert!(header(&get_response, name.clone()).is_some());
+            assert_eq!(
+                header(&head_response, name.clone()),
+                header(&get_response, name)
+            );
+        }
+
+        // Without the body, the encoded length is unknown
+        let headers_only = |request: Request| {
+            let mut response = handler(request);
+            response.set_body(vec![]);
+            response
+        };
+        let compress = headers_only.layer(Compress::new());
+        let response = get(&compress, Method::Head, "/", Some("gzip"));
+        assert_eq!(
+            header(&response, HeaderName::CONTENT_ENCODING),
+            Some(&b"gzip"[..])
+        );
+        assert_eq!(
+            header(&response, HeaderName::VARY),
+            Some(&b"accept-encoding"[..])
+        );
+        assert_eq!(header(&response, HeaderName::CONTENT_LENGTH), None);
+        let response = get(&compress, Method::Head, "/small", Some("gzip"));
+        assert_eq!(header(&response, HeaderName::CONTENT_ENCODING), None);
+    }
+}
@@ -0,0 +1,620 @@
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{Config, Exchange, Handler, shutdown};
+use crate::chunked::{ChunkedDecoder, ChunkedWriter};
+use crate::coding::{self, DecodeError, DecodeLimits, DecodingReader};
+use crate::date::HttpDate;
+use crate::framing::{self, BodyKind, has_connection_option, is_timeout};
+use crate::header::{HeaderName, HeaderValue};
+use crate::method::Method;
+use crate::parser::{ParseError, RawHeader, RequestParser, Status};
+use crate::request::Request;
+use crate::response::{BodyReader, Response};
+use crate::status::StatusCode;
+use crate::version::Version;
+
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+const LINGER_TIMEOUT: Duration = Duration::from_secs(2);
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Why a request could not be read off the connection.
+#[derive(Debug)]
+enum RequestError {
+    // The request is invalid and should be answered with this status before
+    // closing the connection.
+    Status(StatusCode),
+    // The connection is broken, there is nobody to respond to.
+    Closed,
+}
+
+impl From<io::Error> for RequestError {
+    fn from(_: io::Error) -> Self {
+        Self::Closed
+    }
+}
+
+impl From<ParseError> for RequestError {
+    fn from(e: ParseError) -> Self {
+        Self::Status(match e {
+            ParseError::TooManyHeaders | ParseError::HeadTooLarge => {
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+            }
+            ParseError::RequestLineTooLong => StatusCode::URI_TOO_LONG,
+            ParseError::UnknownMethod => StatusCode::NOT_IMPLEMENTED,
+            ParseError::UnsupportedVersion => {
+                StatusCode::HTTP_VERSION_NOT_SUPPORTED
+            }
+            _ => StatusCode::BAD_REQUEST,
+        })
+    }
+}
+
+pub(crate) struct Connection<H> {
+    stream: TcpStream,
+    peer_addr: Option<SocketAddr>,
+    // Bytes read off the stream but not consumed yet
+    buf: Vec<u8>,
+    config: Arc<Config>,
+    handler: Arc<H>,
+    shutdown: Arc<shutdown::State>,
+    // When reading the current request has to be done by
+    deadline: Instant,
+    // Number of responses sent so far
+    served: usize,
+    // When the current request started to arrive, for the response hook
+    started: Instant,
+    time: HttpDate,
+    // The head of the current request once parsed, kept for the response
+    // hook if there is one
+    head: Option<Request>,
+}
+
+impl<H: Handler> Connection<H> {
+    pub(crate) fn new(
+        stream: TcpStream,
+        config: Arc<Config>,
+        handler: Arc<H>,
+        shutdown: Arc<shutdown::State>,
+    ) -> Self {
+        Self {
+            peer_addr: stream.peer_addr().ok(),
+            stream,
+            buf: Vec::with_capacity(READ_CHUNK_SIZE),
+            config,
+            handler,
+            shutdown,
+            deadline: Instant::now(),
+            served: 0,
+            started: Instant::now(),
+            time: HttpDate::now(),
+            head: None,
+        }
+    }
+
+    pub(crate) fn serve(mut self) {
+        // Clients that stop reading would otherwise hold on to the worker
+        if self
+            .stream
+            .set_write_timeout(Some(self.config.write_timeout))
+            .is_err()
+        {
+            return;
+        }
+        loop {
+            let mut request = match self.read_request() {
+                Ok(Some(request)) => request,
+                // Closed or timed out between requests
+                Ok(None) | Err(RequestError::Closed) => return,
+                Err(RequestError::Status(status)) => {
+                    let mut response = Response::new(status);
+                    // Tells the client which codings it may use instead
+                    // (RFC 9110 section 12.5.3)
+                    if status == StatusCode::UNSUPPORTED_MEDIA_TYPE {
+                        response.headers_mut().insert(
+                            HeaderName::ACCEPT_ENCODING,
+                            coding::accept_encoding(),
+                        );
+                    }
+                    if self
+                        .write_response(
+                            response,
+                            Method::Get,
+                            Version::Http1_1,
+                            false,
+                        )
+                        .is_ok()
+                    {
+                        self.linger();
+                    }
+                    return;
+                }
+            };
+            self.served += 1;
+            request.set_peer_addr(self.peer_addr);
+            let method = request.method();
+            let version = request.version();
+            let keep_alive = wants_keep_alive(&request)
+                && self.served < self.config.max_requests;
+            let response = self.call(request);
+            // Handlers can close the connection by setting `Connection: close`
+            let keep_alive = keep_alive
+                && !has_connection_option(response.headers(), "close")
+                && !self.shutdown.is_draining();
+            let Ok(keep_alive) =
+                self.write_response(response, method, version, keep_alive)
+            else {
+                return;
+            };
+            if !keep_alive {
+                // Pipelined requests that will not be answered
+                if !self.buf.is_empty() {
+                    self.linger();
+                }
+                return;
+            }
+        }
+    }
+
+    // After rejecting a request, the client may still be sending the rest
+    // of it. Closing a socket with unread data makes the kernel send a reset,
+    // which can destroy the error response before the client reads it, so
+    // the write side is closed first and the remaining input discarded for
+    // a short while (RFC 9112 section 9.6).
+    fn linger(&mut self) {
+        if self.stream.shutdown(Shutdown::Write).is_err()
+            || self.stream.set_read_timeout(Some(LINGER_TIMEOUT)).is_err()
+        {
+            return;
+        }
+        let mut chunk = [0; READ_CHUNK_SIZE];
+        let deadline = Instant::now() + LINGER_TIMEOUT;
+        while Instant::now() < deadline {
+            match self.stream.read(&mut chunk) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+        }
+    }
+
+    // Runs the handler, turning a panic into a 500 so that a bug in one
+    // handler does not take the worker thread down with it. The connection
+    // is closed afterwards as the handler may have left things in a bad
+    // state.
+    fn call(&self, request: Request) -> Response {
+        let handler = &self.handler;
+        panic::catch_unwind(AssertUnwindSafe(|| handler.handle(request)))
+            .unwrap_or_else(|_| {
+                Response::new(StatusCode::INTERNAL_SERVER_ERROR).with_header(
+                    HeaderName::CONNECTION,
+                    HeaderValue::from_bytes(b"close")
+                        .expect("valid header value"),
+                )
+            })
+    }
+
+    // Waits for the first bytes of the next request. Returns false if the
+    // client closed the connection or kept it idle for too long.
+    //
+    // Connections that have been used before are also closed once the
+    // server starts draining, so the wait is cut into short slices. A
+    // request may already be on its way on fresh ones, so they get to send
+    // it.
+    fn wait_for_request(&mut self) -> io::Result<bool> {
+        let idle_deadline = Instant::now() + self.config.keep_alive_timeout;
+        loop {
+            if self.served > 0 && self.shutdown.is_draining() {
+                return Ok(false);
+            }
+            let Some(timeout) = remaining(idle_deadline) else {
+                return Ok(false);
+            };
+            let timeout = match self.served {
+                0 => timeout,
+                _ => timeout.min(DRAIN_POLL_INTERVAL),
+            };
+            self.stream.set_read_timeout(Some(timeout))?;
+            let mut chunk = [0; READ_CHUNK_SIZE];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(n) => {
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    return Ok(true);
+                }
+                Err(e) if is_timeout(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Reads more bytes into `buf`, returning how many were read. Running
+    // into the deadline fails the request with a 408.
+    fn fill_buf(&mut self) -> Result<usize, RequestError> {
+        let timeout = remaining(self.deadline)
+            .ok_or(RequestError::Status(StatusCode::REQUEST_TIMEOUT))?;
+        self.stream.set_read_timeout(Some(timeout))?;
+        let mut chunk = [0; READ_CHUNK_SIZE];
+        let n = match self.stream.read(&mut chunk) {
+            Ok(n) => n,
+            Err(e) if is_timeout(&e) => {
+                return Err(RequestError::Status(StatusCode::REQUEST_TIMEOUT));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        self.buf.extend_from_slice(&chunk[..n]);
+        Ok(n)
+    }
+
+    fn read_request(&mut self) -> Result<Option<Request>, RequestError> {
+        if self.buf.is_empty() && !self.wait_for_request()? {
+            return Ok(None);
+        }
+        self.started = Instant::now();
+        self.time = HttpDate::now();
+        self.head = None;
+
+        // The whole head has to arrive in time, no matter how steadily it
+        // trickles in. Otherwise a client sending a byte every few seconds
+        // could hold on to a worker indefinitely.
+        self.deadline = Instant::now() + self.config.header_read_timeout;
+        let mut parser = RequestParser::new()
+            .max_request_line_len(self.config.max_request_line_len)
+            .max_head_size(self.config.max_head_size);
+        let (request, kind) = loop {
+            let mut headers = vec![RawHeader::EMPTY; self.config.max_headers];
+            if let Status::Complete(raw) =
+                parser.parse(&self.buf, &mut headers)?
+            {
+                let kind = BodyKind::of_request(&raw)
+                    .map_err(|e| RequestError::Status(e.status()))?;
+                let head_len = raw.head_len;
+                let mut request = Request::from_raw(raw, vec![]);
+                request.set_peer_addr(self.peer_addr);
+                if self.config.response_hook.is_some() {
+                    self.head = Some(request.clone());
+                }
+                self.buf.drain(..head_len);
+                break (request, kind);
+            }
+            if self.fill_buf()? == 0 {
+                return Err(RequestError::Closed);
+            }
+        };
+
+        if let BodyKind::ContentLength(n) = kind
+            && n > self.config.max_body_size as u64
+        {
+            return Err(RequestError::Status(StatusCode::CONTENT_TOO_LARGE));
+        }
+        if kind != BodyKind::None {
+            self.handle_expect(&request)?;
+            self.deadline = Instant::now() + self.config.body_read_timeout;
+        }
+        let body = self.read_body(kind)?;
+        let mut request = request.with_body(body);
+        if self.config.decode_request_bodies {
+            self.decode_body(&mut request)?;
+        }
+        Ok(Some(request))
+    }
+
+    // Undoes the content codings of the request body, so that handlers see
+    // what the client meant to send.
+    fn decode_body(&self, request: &mut Request) -> Result<(), RequestError> {
+        let codings =
+            coding::content_codings(request.headers()).map_err(|_| {
+                RequestError::Status(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+            })?;
+        if codings.is_empty() {
+            return Ok(());
+        }
+        let limits =
+            DecodeLimits::new().max_size(self.config.max_body_size as u64);
+        let mut body = vec![];
+        DecodingReader::new(request.body(), &codings)
+            .limits(limits)
+            .read_to_end(&mut body)
+            .map_err(|e| {
+                RequestError::Status(match DecodeError::from_io(&e) {
+                    Some(
+                        DecodeError::TooLarge | DecodeError::RatioExceeded,
+                    ) => StatusCode::CONTENT_TOO_LARGE,
+                    _ => StatusCode::BAD_REQUEST,
+                })
+            })?;
+        let headers = request.headers_mut();
+        headers.remove(HeaderName::CONTENT_ENCODING);
+        if headers.contains_key(HeaderName::CONTENT_LENGTH) {
+            headers.insert(HeaderName::CONTENT_LENGTH, body.len().into());
+        }
+        request.set_body(body);
+        Ok(())
+    }
+
+    // A client sending `Expect: 100-continue` waits for an interim response
+    // before sending the body (RFC 9110 section 10.1.1).
+    fn handle_expect(&mut self, request: &Request) -> Result<(), RequestError> {
+        let Some(expect) = request.headers().get(HeaderName::EXPECT) else {
+            return Ok(());
+        };
+        if !expect.as_bytes().eq_ignore_ascii_case(b"100-continue") {
+            return Err(RequestError::Status(StatusCode::EXPECTATION_FAILED));
+        }
+        if request.version() >= Version::Http1_1 {
+            self.stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        }
+        Ok(())
+    }
+
+    fn read_body(&mut self, kind: BodyKind) -> Result<Vec<u8>, RequestError> {
+        match kind {
+            // Only responses can be delimited by closing the connection
+            BodyKind::None | BodyKind::CloseDelimited => Ok(vec![]),
+            BodyKind::ContentLength(n) => {
+                let n = n as usize;
+                while self.buf.len() < n {
+                    if self.fill_buf()? == 0 {
+                        return Err(RequestError::Closed);
+                    }
+                }
+                Ok(self.buf.drain(..n).collect())
+            }
+            BodyKind::Chunked => {
+                let mut decoder = ChunkedDecoder::new();
+                let mut body = vec![];
+                loop {
+                    let consumed =
+                        decoder.decode_to_vec(&self.buf, &mut body).map_err(
+                            |_| RequestError::Status(StatusCode::BAD_REQUEST),
+                        )?;
+                    self.buf.drain(..consumed);
+                    if body.len() > self.config.max_body_size {
+                        return Err(RequestError::Status(
+                            StatusCode::CONTENT_TOO_LARGE,
+                        ));
+                    }
+                    if decoder.is_done() {
+                        return Ok(body);
+                    }
+                    if self.fill_buf()? == 0 {
+                        return Err(RequestError::Closed);
+                    }
+                }
+            }
+        }
+    }
+
+    // Adds the framing and connection management fields the server is
+    // responsible for and writes the response out. Returns whether the
+    // connection stays open, which it cannot if the body had to be
+    // delimited by closing it.
+    fn write_response(
+        &mut self,
+        mut response: Response,
+        request_method: Method,
+        request_version: Version,
+        mut keep_alive: bool,
+    ) -> io::Result<bool> {
+        response.set_version(Version::Http1_1);
+        let status = response.status();
+        let has_body = framing::response_has_body(request_method, status);
+        let reader = response.take_reader();
+        // The framing is the server's to decide, whatever the handler set.
+        // Bodies held in memory go out with their own length, so a wrong
+        // `Content-Length` cannot throw the connection out of step.
+        let len = match &reader {
+            Some(reader) => reader.known_len(),
+            None => Some(response.body().len() as u64),
+        };
+        let mut kind = match len {
+            Some(len) => BodyKind::ContentLength(len),
+            None => BodyKind::CloseDelimited,
+        };
+        let headers = response.headers_mut();
+        headers.remove(HeaderName::TRANSFER_ENCODING);
+        if status.is_informational() || status == StatusCode::NO_CONTENT {
+            headers.remove(HeaderName::CONTENT_LENGTH);
+        } else if !framing::response_has_body(Method::Get, status) {
+            // A 304 may advertise the length of the selected representation
+        } else if request_method == Method::Head {
+            // A response to HEAD still advertises the length of the body it
+            // would have had, unless the handler left out both.
+            match (&reader, len) {
+                (Some(_), Some(len)) => {
+                    headers.insert(HeaderName::CONTENT_LENGTH, len.into());
+                }
+                (Some(_), None) => {
+                    headers.remove(HeaderName::CONTENT_LENGTH);
+                }
+                (None, Some(len))
+                    if len > 0
+                        && !headers
+                            .contains_key(HeaderName::CONTENT_LENGTH) =>
+                {
+                    headers.insert(HeaderName::CONTENT_LENGTH, len.into());
+                }
+                (None, _) => {}
+            }
+        } else if let Some(len) = len {
+            headers.insert(HeaderName::CONTENT_LENGTH, len.into());
+        } else {
+            headers.remove(HeaderName::CONTENT_LENGTH);
+            // HTTP/1.0 clients do not know the chunked coding, so the end
+            // of the body is marked by closing the connection instead.
+            if request_version >= Version::Http1_1 {
+                headers.insert(
+                    HeaderName::TRANSFER_ENCODING,
+                    HeaderValue::from_bytes(b"chunked")
+                        .expect("valid header value"),
+                );
+                kind = BodyKind::Chunked;
+            } else {
+                keep_alive = false;
+            }
+        }
+        if !response.headers().contains_key(HeaderName::DATE) {
+            response
+                .headers_mut()
+                .insert(HeaderName::DATE, current_date());
+        }
+        // Persistence is the default in HTTP/1.1 but has to be confirmed to
+        // HTTP/1.0 clients that asked for it.
+        let connection: &[u8] = match (keep_alive, request_version) {
+            (false, _) => b"close",
+            (true, Version::Http1_0) => b"keep-alive",
+            (true, _) => b"",
+        };
+        if !connection.is_empty() {
+            response.headers_mut().insert(
+                HeaderName::CONNECTION,
+                HeaderValue::from_bytes(connection)
+                    .expect("valid header value"),
+            );
+        }
+
+        if !has_body {
+            kind = BodyKind::None;
+        }
+        let mut written = 0;
+        let result =
+            send(&mut self.stream, &response, reader, kind, &mut written);
+        self.report(status, written);
+        result.map(|()| keep_alive)
+    }
+
+    // Passes a response that has been written to the response hook.
+    fn report(&mut self, status: StatusCode, bytes: u64) {
+        let Some(hook) = &self.config.response_hook else {
+            return;
+        };
+        hook(&Exchange {
+            time: self.time,
+            peer_addr: self.peer_addr,
+            request: self.head.take().as_ref(),
+            status,
+            bytes,
+            duration: self.started.elapsed(),
+        });
+    }
+}
+
+// Writes the head of a response and its body, framed as `kind` says,
+// counting the body bytes written.
+fn send(
+    stream: &mut TcpStream,
+    response: &Response,
+    reader: Option<BodyReader>,
+    kind: BodyKind,
+    written: &mut u64,
+) -> io::Result<()> {
+    response.write_head(stream)?;
+    match (reader, kind) {
+        (_, BodyKind::None) => {}
+        (None, _) => {
+            CountingWriter::new(&mut *stream, written)
+                .write_all(response.body())?;
+        }
+        (Some(reader), kind) => write_body(stream, reader, kind, written)?,
+    }
+    stream.flush()
+}
+
+// Writes a body read from a `BodyReader`, framed as `kind` says.
+fn write_body(
+    stream: &mut TcpStream,
+    mut reader: BodyReader,
+    kind: BodyKind,
+    written: &mut u64,
+) -> io::Result<()> {
+    match kind {
+        BodyKind::None => {}
+        BodyKind::ContentLength(len) => {
+            // A reader falling short of its length would leave the client
+            // waiting for the rest, so the connection is dropped instead.
+            let mut out = CountingWriter::new(stream, written);
+            if io::copy(&mut (&mut reader).take(len), &mut out)? < len {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "response body shorter than its length",
+                ));
+            }
+        }
+        BodyKind::Chunked => {
+            // Counted on the way in, so the chunk framing is left out
+            let mut writer = ChunkedWriter::new(stream);
+            io::copy(
+                &mut reader,
+                &mut CountingWriter::new(&mut writer, written),
+            )?;
+            writer.finish()?;
+        }
+        BodyKind::CloseDelimited => {
+            io::copy(&mut reader, &mut CountingWriter::new(stream, written))?;
+        }
+    }
+    Ok(())
+}
+
+// Adds up the bytes written through it.
+struct CountingWriter<'a, W> {
+    inner: W,
+    count: &'a mut u64,
+}
+
+impl<'a, W: Write> CountingWriter<'a, W> {
+    fn new(inner: W, count: &'a mut u64) -> Self {
+        Self { inner, count }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        *self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// The value of the `Date` header, which only changes once per second, so
+// each worker formats it at most that often.
+fn current_date() -> HeaderValue {
+    thread_local! {
+        static CACHE: RefCell<Option<(HttpDate, HeaderValue)>> =
+            const { RefCell::new(None) };
+    }
+    let now = HttpDate::now();
+    CACHE.with_borrow_mut(|cache| match cache {
+        Some((date, value)) if *date == now => value.clone(),
+        _ => cache.insert((now, now.into())).1.clone(),
+    })
+}
+
+// HTTP/1.1 connections persist unless either side sends the "close" option,
+// HTTP/1.0 connections only if the client sends "keep-alive" (RFC 9112
+// section 9.3).
+fn wants_keep_alive(request: &Request) -> bool {
+    let headers = request.headers();
+    match request.version() {
+        Version::Http1_0 => {
+            has_connection_option(headers, "keep-alive")
+                && !has_connection_option(headers, "close")
+        }
+        _ => !has_connection_option(headers, "close"),
+    }
+}
+
+// Time left until `deadline`, if any. Zero counts as none, as it is not a
+// valid socket timeout.
+fn remaining(deadline: Instant) -> Option<Duration> {
+    deadline
+        .checked_duration_since(Instant::now())
+        .filter(|timeout| !timeout.is_zero())
+}
@@ -0,0 +1,567 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{Handler, Middleware};
+use crate::header::{HeaderMap, HeaderName, HeaderValue};
+use crate::method::Method;
+use crate::origin::Origin;
+use crate::request::Request;
+use crate::response::Response;
+use crate::scheme::Scheme;
+use crate::status::StatusCode;
+use crate::{negotiation, parser};
+
+type Predicate = dyn Fn(&Origin) -> bool + Send + Sync;
+
+#[derive(Clone)]
+enum AllowOrigin {
+    Any,
+    Exact(Origin),
+    // Any subdomain of `domain`, with the scheme and port given
+    Subdomains {
+        scheme: Scheme,
+        domain: String,
+        port: Option<u16>,
+    },
+    Predicate(Arc<Predicate>),
+}
+
+impl AllowOrigin {
+    fn allows(&self, origin: &Origin) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Exact(allowed) => allowed == origin,
+            Self::Subdomains {
+                scheme,
+                domain,
+                port,
+            } => {
+                origin.scheme() == scheme
+                    && origin.port() == *port
+                    && origin
+                        .host()
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.'))
+            }
+            Self::Predicate(predicate) => predicate(origin),
+        }
+    }
+}
+
+impl fmt::Debug for AllowOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Any => f.write_str("Any"),
+            Self::Exact(origin) => write!(f, "Exact({origin})"),
+            Self::Subdomains {
+                scheme,
+                domain,
+                port,
+            } => {
+                write!(f, "Subdomains({scheme}://*.{domain}")?;
+                if let Some(port) = port {
+                    write!(f, ":{port}")?;
+                }
+                f.write_str(")")
+            }
+            Self::Predicate(_) => f.write_str("Predicate"),
+        }
+    }
+}
+
+// Cross-origin resource sharing, letting pages from other origins read the
+// responses of the handler it wraps (the Fetch standard, "CORS protocol").
+//
+// Preflight requests, OPTIONS requests with an `Origin` and an
+// `Access-Control-Request-Method`, are answered right away and never reach
+// the handler. Other requests from an allowed origin get their response
+// tagged with the `Access-Control-*` fields. Responses to requests from
+// origins that are not allowed go out without them, which is what makes
+// browsers withhold them from the page.
+//
+// Nothing is allowed by default. Unless any origin is allowed without
+// credentials, the response depends on the `Origin` of the request, so every
+// response gets `Vary: Origin`.
+#[derive(Debug, Clone)]
+pub struct Cors {
+    origins: Vec<AllowOrigin>,
+    methods: Vec<Method>,
+    // `None` allows whatever headers the preflight asks for
+    headers: Option<Vec<HeaderName>>,
+    expose_headers: Vec<HeaderName>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Cors {
+    // Allows GET, HEAD and POST, once origins are allowed.
+    pub fn new() -> Self {
+        Self {
+            origins: vec![],
+            methods: vec![Method::Get, Method::Head, Method::Post],
+            headers: Some(vec![]),
+            expose_headers: vec![],
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    // Allows every origin. With credentials, this lets any site act on
+    // behalf of the user, so it is only safe for public resources.
+    pub fn allow_any_origin(mut self) -> Self {
+        self.origins.push(AllowOrigin::Any);
+        self
+    }
+
+    pub fn allow_origin(mut self, origin: Origin) -> Self {
+        self.origins.push(AllowOrigin::Exact(origin));
+        self
+    }
+
+    // Allows the subdomains of a domain, given as a pattern such as
+    // "https://*.example.com" or "http://*.example.com:8080". The domain
+    // itself is not included. Panics if the pattern is not of that form.
+    pub fn allow_origin_pattern(mut self, pattern: &str) -> Self {
+        let invalid = || panic!("invalid origin pattern {pattern:?}");
+        let Some((scheme, rest)) = pattern.split_once("://*.") else {
+            invalid()
+        };
+        let Ok(example) = format!("{scheme}://x.{rest}").parse::<Origin>()
+        else {
+            invalid()
+        };
+        let domain = example.host()["x.".len()..].to_string();
+        self.origins.push(AllowOrigin::Subdomains {
+            scheme: example.scheme().clone(),
+            domain,
+            port: example.port(),
+        });
+        self
+    }
+
+    // Allows the origins a function accepts.
+    pub fn allow_origin_fn<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Origin) -> bool + Send + Sync + 'static,
+    {
+        self.origins
+            .push(AllowOrigin::Predicate(Arc::new(predicate)));
+        self
+    }
+
+    // The methods preflights may ask for. GET, HEAD and POST never need one.
+    pub fn allow_methods<I>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        self.methods = methods.into_iter().collect();
+        self
+    }
+
+    // The request fields preflights may ask for.
+    pub fn allow_headers<I>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        self.headers = Some(headers.into_iter().collect());
+        self
+    }
+
+    pub fn allow_any_header(mut self) -> Self {
+        self.headers = None;
+        self
+    }
+
+    // Response fields beyond the safelisted ones that pages may read.
+    pub fn expose_headers<I>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        self.expose_headers = headers.into_iter().collect();
+        self
+    }
+
+    // Whether requests may carry cookies and other credentials.
+    pub fn allow_credentials(mut self, allowed: bool) -> Self {
+        self.credentials = allowed;
+        self
+    }
+
+    // How long browsers may cache the result of a preflight.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    // Whether `Access-Control-Allow-Origin` can be "*" for everyone.
+    fn is_wildcard(&self) -> bool {
+        !self.credentials
+            && self.origins.iter().any(|o| matches!(o, AllowOrigin::Any))
+    }
+
+    // The value of `Access-Control-Allow-Origin` for a request, if its
+    // origin is allowed.
+    fn allow_origin_value(&self, request: &Request) -> Option<HeaderValue> {
+        if self.is_wildcard() {
+            return Some(
+                HeaderValue::from_bytes(b"*").expect("valid header value"),
+            );
+        }
+        let origin = Origin::from_request(request)?;
+        self.origins
+            .iter()
+            .any(|allowed| allowed.allows(&origin))
+            .then(|| (&origin).into())
+    }
+
+    fn add_origin_headers(&self, headers: &mut HeaderMap, origin: HeaderValue) {
+        headers.insert(HeaderName::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        if self.credentials {
+            headers.insert(
+                HeaderName::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_bytes(b"true").expect("valid header value"),
+            );
+        }
+    }
+
+    fn preflight(&self, request: &Request) -> Response {
+        let mut response = Response::new(StatusCode::NO_CONTENT);
+        for name in [
+            HeaderName::ORIGIN,
+            HeaderName::ACCESS_CONTROL_REQUEST_METHOD,
+            HeaderName::ACCESS_CONTROL_REQUEST_HEADERS,
+        ] {
+            negotiation::vary(&mut response, name);
+        }
+        let Some(origin) = self.allow_origin_value(request) else {
+            return response;
+        };
+
+        let headers = request.headers();
+        let method = headers
+            .get(HeaderName::ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|value| value.to_str()?.parse::<Method>().ok());
+        let is_safelisted =
+            |m: &Method| matches!(m, Method::Get | Method::Head | Method::Post);
+        let method_allowed = method
+            .is_some_and(|m| is_safelisted(&m) || self.methods.contains(&m));
+        let requested: Vec<&[u8]> = headers
+            .get_all(HeaderName::ACCESS_CONTROL_REQUEST_HEADERS)
+            .flat_map(|value| parser::list_elements(value.as_bytes()))
+            .collect();
+        let headers_allowed = match &self.headers {
+            None => true,
+            Some(allowed) => requested.iter().all(|name| {
+                allowed
+                    .iter()
+                    .any(|a| a.as_str().as_bytes().eq_ignore_ascii_case(name))
+            }),
+        };
+        if !method_allowed || !headers_allowed {
+            return response;
+        }
+
+        let response_headers = response.headers_mut();
+        self.add_origin_headers(response_headers, origin);
+        let methods: Vec<&str> =
+            self.methods.iter().map(Method::as_str).collect();
+        response_headers.insert(
+            HeaderName::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_bytes(methods.join(", ").as_bytes())
+                .expect("valid header value"),
+        );
+        let allow_headers = match &self.headers {
+            // Echoing the request is the only way to allow any header along
+            // with credentials, where "*" is taken literally.
+            None => requested.join(&b", "[..]),
+            Some(allowed) => {
+                let names: Vec<&str> =
+                    allowed.iter().map(HeaderName::as_str).collect();
+                names.join(", ").into_bytes()
+            }
+        };
+        if !allow_headers.is_empty() {
+            response_headers.insert(
+                HeaderName::ACCESS_CONTROL_ALLOW_HEADERS,
+                HeaderValue::from_bytes(&allow_headers)
+                    .expect("valid header value"),
+            );
+        }
+        if let Some(max_age) = self.max_age {
+            response_headers.insert(
+                HeaderName::ACCESS_CONTROL_MAX_AGE,
+                max_age.as_secs().into(),
+            );
+        }
+        response
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for Cors {
+    fn call(&self, request: Request, next: &dyn Handler) -> Response {
+        let headers = request.headers();
+        if request.method() == Method::Options
+            && headers.contains_key(HeaderName::ORIGIN)
+            && headers.contains_key(HeaderName::ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            return self.preflight(&request);
+        }
+
+        let origin = self.allow_origin_value(&request);
+        let mut response = next.handle(request);
+        if !self.is_wildcard() {
+            negotiation::vary(&mut response, HeaderName::ORIGIN);
+        }
+        if let Some(origin) = origin {
+            let headers = response.headers_mut();
+            self.add_origin_headers(headers, origin);
+            if !self.expose_headers.is_empty() {
+                let names: Vec<&str> = self
+                    .expose_headers
+                    .iter()
+                    .map(HeaderName::as_str)
+                    .collect();
+                headers.insert(
+                    HeaderName::ACCESS_CONTROL_EXPOSE_HEADERS,
+                    HeaderValue::from_bytes(names.join(", ").as_bytes())
+                        .expect("valid header value"),
+                );
+            }
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uri::RequestTarget;
+
+    fn ok(_: Request) -> Response {
+        Response::new(StatusCode::OK).with_body(b"data".to_vec())
+    }
+
+    fn send(
+        cors: &Cors,
+        method: Method,
+        headers: &[(HeaderName, &str)],
+    ) -> Response {
+        let target = RequestTarget::parse("/", &method).unwrap();
+        let request = headers.iter().fold(
+            Request::new(method, target),
+            |request, (name, value)| {
+                request.with_header(name.clone(), value.parse().unwrap())
+            },
+        );
+        (ok as fn(Request) -> Response)
+            .layer(cors.clone())
+            .handle(request)
+    }
+
+    fn header(response: &Response, name: HeaderName) -> Option<&str> {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str())
+    }
+
+    fn from(origin: &str) -> [(HeaderName, &str); 1] {
+        [(HeaderName::ORIGIN, origin)]
+    }
+
+    #[test]
+    fn simple_requests() {
+        let cors = Cors::new()
+            .allow_origin("https://app.example.com".parse().unwrap())
+            .allow_origin_pattern("https://*.example.org")
+            .allow_origin_fn(|origin| origin.host() == "localhost")
+            .expose_headers([HeaderName::ETAG]);
+
+        for origin in [
+            "https://app.example.com",
+            "https://app.example.com:443",
+            "https://a.b.example.org",
+            "http://localhost:3000",
+        ] {
+            let response = send(&cors, Method::Get, &from(origin));
+            assert_eq!(response.body(), b"data");
+            let allowed: Origin = origin.parse().unwrap();
+            assert_eq!(
+                header(&response, HeaderName::ACCESS_CONTROL_ALLOW_ORIGIN),
+                Some(allowed.to_string().as_str())
+            );
+            assert_eq!(
+                header(&response, HeaderName::ACCESS_CONTROL_EXPOSE_HEADERS),
+                Some("etag")
+            );
+            assert_eq!(header(&response, HeaderName::VARY), Some("origin"));
+            assert_eq!(
+                header(&response, HeaderName::ACCESS_CONTROL_ALLOW_CREDENTIALS),
+                None
+            );
+        }
+
+        for origin in [
+            "http://app.example.com",
+            "https://app.example.com:8443",
+            "https://example.org",
+            "https://evilexample.org",
+            "null",
+            "garbage",
+        ] {
+            let response = send(&cors, Method::Get, &from(origin));
+            assert_eq!(response.body(), b"data");
+            assert_eq!(
+                header(&response, HeaderName::ACCESS_CONTROL_ALLOW_ORIGIN),
+                None,
+                "{origin}"
+            );
+            assert_eq!(header(&response, HeaderName::VARY), Some("origin"));
+        }
+
+        // Same-origin and non-browser requests may be cached too
+        let response = send(&cors, Method::Get, &[]);
+        assert_eq!(header(&response, HeaderName::VARY), Some("origin"));
+    }
+
+    #[test]
+    fn any_origin() {
+        let cors = Cors::new().allow_any_origin();
+        let response = send(&cors, Method::Get, &from("https://a.com"));
+        assert_eq!(
+            header(&response, HeaderName::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some("*")
+        );
+        assert_eq!(header(&response, HeaderName::VARY), None);
+
+        // "*" does not work with credentials
+        let cors = cors.allow_credentials(true);
+        let response = send(&cors, Method::Get, &from("https://a.com"));
+        assert_eq!(
+            header(&response, HeaderName::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some("https://a.com")
+        );
+        assert_eq!(
+            header(&response, HeaderName::ACCESS_CONTROL_ALLOW_CREDENTIALS),
+            Some("true")
+        );
+        assert_eq!(header(&response, HeaderName::VARY), Some("origin"));
+    }
+
+    #[test]
+    fn preflights() {
+        let cors = Cors::new()
+            .allow_origin("https://app.example.com".parse().unwrap())
+            .allow_methods([Method::Get, Method::Put, Method::Delete])
+            .allow_headers([
+                HeaderName::CONTENT_TYPE,
+                "x-token".parse().unwrap(),
+            ])
+            .allow_credentials(true)
+            .max_age(Duration::from_secs(600));
+        let preflight = |origin, method, headers: Option<&str>| {
+            let mut fields = vec![
+                (HeaderName::ORIGIN, origin),
+                (HeaderName::ACCESS_CONTROL_REQUEST_METHOD, method),
+            ];
+            if let Some(headers) = headers {
+                fields.push((
+                    HeaderName::ACCESS_CONTROL_REQUEST_HEADERS,
+                    headers,
+                ));
+            }
+            send(&cors, Method::Options, &fields)
+        };
+
+        let response = preflight(
+            "https://app.example.com",
+            "PUT",
+            Some("X-Token, content-type"),
+        );
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.body(), b"");
+        assert_eq!(
+            header(&response, HeaderName::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some("https://app.example.com")
+        );
+        assert_eq!(
+            header(&response, HeaderName::ACCESS_CONTROL_ALLOW_METHODS),
+            Some("GET, PUT, DELETE")
+        );
+        assert_eq!(
+            header(&response, HeaderName::ACCESS_CONTROL_ALLOW_HEADERS),
+            Some("content-type, x-token")
+        );
+        assert_eq!(
+            header(&response, HeaderName::ACCESS_CONTROL_ALLOW_CREDENTIALS),
+            Some("true")
+        );
+        assert_eq!(
+            header(&response, HeaderName::ACCESS_CONTROL_MAX_AGE),
+            Some("600")
+        );
+        assert_eq!(
+            header(&response, HeaderName::VARY),
+            Some(
+                "origin, access-control-request-method, \
+                 access-control-request-headers"
+            )
+        );
+
+        // POST is always fine
+        let response = preflight("https://app.example.com", "POST", None);
+        assert!(
+            header(&response, HeaderName::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .is_some()
+        );
+
+        for (origin, method, headers) in [
+            ("https://evil.com", "PUT", None),
+            ("https://app.example.com", "PATCH", None),
+            ("https://app.example.com", "PUT", Some("x-other")),
+            ("https://app.example.com", "not a method", None),
+        ] {
+            let response = preflight(origin, method, headers);
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+            assert_eq!(
+                header(&response, HeaderName::ACCESS_CONTROL_ALLOW_ORIGIN),
+                None
+            );
+            assert_eq!(
+                header(&response, HeaderName::ACCESS_CONTROL_ALLOW_METHODS),
+                None
+            );
+        }
+
+        // Any requested header is echoed back
+        let cors = cors.allow_any_header();
+        let fields = [
+            (HeaderName::ORIGIN, "https://app.example.com"),
+            (HeaderName::ACCESS_CONTROL_REQUEST_METHOD, "GET"),
+            (HeaderName::ACCESS_CONTROL_REQUEST_HEADERS, "x-a,x-b"),
+        ];
+        let response = send(&cors, Method::Options, &fields);
+        assert_eq!(
+            header(&response, HeaderName::ACCESS_CONTROL_ALLOW_HEADERS),
+            Some("x-a, x-b")
+        );
+
+        // Without the request method, OPTIONS is an ordinary request
+        let response =
+            send(&cors, Method::Options, &from("https://app.example.com"));
+        assert_eq!(response.body(), b"data");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid origin pattern")]
+    fn invalid_pattern() {
+        Cors::new().allow_origin_pattern("https://example.*");
+    }
+}
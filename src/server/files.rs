@@ -0,0 +1,807 @@
+use std::fmt::Write as _;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Component, Path as FsPath, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use super::Handler;
+use crate::coding::ContentCoding;
+use crate::conditional::{Precondition, Validators};
+use crate::date::HttpDate;
+use crate::etag::ETag;
+use crate::header::{HeaderName, HeaderValue};
+use crate::method::Method;
+use crate::negotiation::{self, AcceptEncoding};
+use crate::path::Path;
+use crate::range::{self, MultipartByteranges, RangeSet, Unsatisfiable};
+use crate::request::Request;
+use crate::response::{BodyReader, Response};
+use crate::status::StatusCode;
+use crate::{mime, utils};
+
+// Serves the files below a root directory.
+//
+// Request paths are percent-decoded segment by segment and every segment has
+// to name a regular, non-hidden entry, so a request cannot climb out of the
+// root. As symbolic links below the root may still point elsewhere, the final
+// path is resolved and checked against the root before anything is read.
+// Anything that cannot be served is a 404, whether it exists or not.
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    // Canonical, so resolved file paths can be compared against it
+    root: PathBuf,
+    index_files: Vec<String>,
+    directory_listing: bool,
+    precompressed: bool,
+}
+
+impl StaticFiles {
+    pub fn new<P: AsRef<FsPath>>(root: P) -> io::Result<Self> {
+        let root = fs::canonicalize(root)?;
+        if !root.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "static file root is not a directory",
+            ));
+        }
+        Ok(Self {
+            root,
+            index_files: vec!["index.html".into()],
+            directory_listing: false,
+            precompressed: false,
+        })
+    }
+
+    // Files served for a request naming a directory, tried in order.
+    pub fn index_files<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.index_files = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    // Whether directories without an index file get a generated listing
+    // instead of a 404.
+    pub fn directory_listing(mut self, enabled: bool) -> Self {
+        self.directory_listing = enabled;
+        self
+    }
+
+    // Whether a gzip-compressed copy of a file, stored next to it with a
+    // ".gz" suffix, is served instead of the file to clients that accept
+    // gzip. The copies have to be kept up to date with the files.
+    pub fn precompressed(mut self, enabled: bool) -> Self {
+        self.precompressed = enabled;
+        self
+    }
+
+    // Maps a request path onto the file system, without checking that the
+    // result exists.
+    fn map_path(&self, path: &Path) -> Option<PathBuf> {
+        let mut mapped = self.root.clone();
+        for segment in path.as_str().split('/').filter(|s| !s.is_empty()) {
+            let segment =
+                String::from_utf8(utils::percent_decode(segment.as_bytes()))
+                    .ok()?;
+            // Whatever the platform would read as anything but a plain name,
+            // such as separators, drive prefixes or `..`, is rejected.
+            let mut components = FsPath::new(&segment).components();
+            let (Some(Component::Normal(_)), None) =
+                (components.next(), components.next())
+            else {
+                return None;
+            };
+            if segment.starts_with('.')
+                || segment.contains(['/', '\\', ':', '\0'])
+            {
+                return None;
+            }
+            mapped.push(segment);
+        }
+        Some(mapped)
+    }
+
+    // Follows symbolic links, refusing anything that ends up outside of the
+    // root.
+    fn resolve(&self, path: &FsPath) -> Option<(PathBuf, Metadata)> {
+        let resolved = fs::canonicalize(path).ok()?;
+        if !resolved.starts_with(&self.root) {
+            return None;
+        }
+        let metadata = fs::metadata(&resolved).ok()?;
+        Some((resolved, metadata))
+    }
+
+    fn serve(&self, request: &Request) -> Response {
+        let Some(path) = request.target().path() else {
+            return Response::new(StatusCode::NOT_FOUND);
+        };
+        let Some((resolved, metadata)) =
+            self.map_path(path).and_then(|p| self.resolve(&p))
+        else {
+            return Response::new(StatusCode::NOT_FOUND);
+        };
+        if metadata.is_file() {
+            return self.serve_file(request, &resolved, &metadata);
+        }
+        if !metadata.is_dir() {
+            return Response::new(StatusCode::NOT_FOUND);
+        }
+
+        // Relative links in an index page resolve against the directory only
+        // if its URL ends in a slash.
+        if !path.as_str().ends_with('/') {
+            let mut location = format!("{}/", path.as_str());
+            if let Some(query) = request.target().query() {
+                location.push('?');
+                location.push_str(query);
+            }
+            return Response::new(StatusCode::MOVED_PERMANENTLY).with_header(
+                HeaderName::LOCATION,
+                HeaderValue::from_bytes(location.as_bytes())
+                    .expect("request paths are valid header values"),
+            );
+        }
+        for name in &self.index_files {
+            if let Some((index, metadata)) = self.resolve(&resolved.join(name))
+                && metadata.is_file()
+            {
+                return self.serve_file(request, &index, &metadata);
+            }
+        }
+        if self.directory_listing {
+            return match list_directory(path, &resolved) {
+                Ok(listing) => html_response(request, listing),
+                Err(_) => Response::new(StatusCode::NOT_FOUND),
+            };
+        }
+        Response::new(StatusCode::NOT_FOUND)
+    }
+
+    fn serve_file(
+        &self,
+        request: &Request,
+        path: &FsPath,
+        metadata: &Metadata,
+    ) -> Response {
+        let content_type = mime::from_path(path);
+        let Some((gzipped, gzipped_metadata)) = self.gzipped(path) else {
+            return serve_file(request, path, metadata, content_type);
+        };
+        let accept = AcceptEncoding::from_request(request);
+        let gzip = ContentCoding::Gzip.as_str();
+        let mut response = if accept.negotiate(&[gzip, "identity"])
+            == Some(&gzip)
+        {
+            let mut response =
+                serve_file(request, &gzipped, &gzipped_metadata, content_type);
+            if response.status().is_success()
+                || response.status() == StatusCode::NOT_MODIFIED
+            {
+                response.headers_mut().insert(
+                    HeaderName::CONTENT_ENCODING,
+                    ContentCoding::Gzip.into(),
+                );
+            }
+            response
+        } else {
+            serve_file(request, path, metadata, content_type)
+        };
+        negotiation::vary(&mut response, HeaderName::ACCEPT_ENCODING);
+        response
+    }
+
+    // The precompressed copy of a file, if enabled and there is one.
+    fn gzipped(&self, path: &FsPath) -> Option<(PathBuf, Metadata)> {
+        if !self.precompressed {
+            return None;
+        }
+        let mut gzipped = path.as_os_str().to_owned();
+        gzipped.push(".gz");
+        self.resolve(FsPath::new(&gzipped))
+            .filter(|(_, metadata)| metadata.is_file())
+    }
+}
+
+impl Handler for StaticFiles {
+    fn handle(&self, request: Request) -> Response {
+        match request.method() {
+            Method::Get | Method::Head => self.serve(&request),
+            _ => Response::new(StatusCode::METHOD_NOT_ALLOWED).with_header(
+                HeaderName::ALLOW,
+                HeaderValue::from_bytes(b"GET, HEAD")
+                    .expect("valid header value"),
+            ),
+        }
+    }
+}
+
+// Serves a file as the given content type. Its validators are derived from
+// the file itself, so a precompressed copy gets its own. The body is read
+// from the file while it is sent, so files of any size can be served.
+fn serve_file(
+    request: &Request,
+    path: &FsPath,
+    metadata: &Metadata,
+    content_type: &str,
+) -> Response {
+    let validators = validators(metadata);
+    match validators.evaluate(request) {
+        Precondition::Proceed => {}
+        Precondition::NotModified => {
+            let mut response = Response::new(StatusCode::NOT_MODIFIED);
+            validators.apply(&mut response);
+            return response;
+        }
+        Precondition::Failed => {
+            return Response::new(StatusCode::PRECONDITION_FAILED);
+        }
+    }
+
+    let content_type = HeaderValue::from_bytes(content_type.as_bytes())
+        .expect("media types are valid header values");
+    // Range requests are only defined for GET
+    if request.method() == Method::Get
+        && let Some(ranges) = RangeSet::from_request(request)
+        && validators.if_range(request)
+    {
+        let mut response = match ranges.resolve(metadata.len()) {
+            Ok(ranges) => {
+                match read_ranges(path, metadata.len(), content_type, &ranges) {
+                    Ok(response) => response,
+                    Err(_) => return Response::new(StatusCode::NOT_FOUND),
+                }
+            }
+            Err(Unsatisfiable) => {
+                Response::new(StatusCode::RANGE_NOT_SATISFIABLE).with_header(
+                    HeaderName::CONTENT_RANGE,
+                    range::unsatisfied_range(metadata.len()),
+                )
+            }
+        };
+        validators.apply(&mut response);
+        return response;
+    }
+
+    let mut response = Response::new(StatusCode::OK)
+        .with_header(HeaderName::CONTENT_TYPE, content_type)
+        .with_header(HeaderName::CONTENT_LENGTH, metadata.len().into())
+        .with_header(
+            HeaderName::ACCEPT_RANGES,
+            HeaderValue::from_bytes(b"bytes").expect("valid header value"),
+        );
+    // The server leaves out the body of a response to HEAD, so there is no
+    // need to open the file in the first place.
+    if request.method() != Method::Head {
+        let Ok(file) = File::open(path) else {
+            return Response::new(StatusCode::NOT_FOUND);
+        };
+        response.set_reader(BodyReader::new(file, Some(metadata.len())));
+    }
+    validators.apply(&mut response);
+    response
+}
+
+// A 206 response with the given ranges of a file of `len` bytes, in a
+// multipart body if there is more than one. Only the requested ranges are
+// read, so seeking into large files stays cheap.
+fn read_ranges(
+    path: &FsPath,
+    len: u64,
+    content_type: HeaderValue,
+    ranges: &[Range<u64>],
+) -> io::Result<Response> {
+    let mut file = File::open(path)?;
+    let response = Response::new(StatusCode::PARTIAL_CONTENT);
+    let (response, body) = match ranges {
+        [range] => {
+            let range_len = range.end - range.start;
+            file.seek(SeekFrom::Start(range.start))?;
+            let response = response
+                .with_header(HeaderName::CONTENT_TYPE, content_type)
+                .with_header(
+                    HeaderName::CONTENT_RANGE,
+                    range::content_range(range, len),
+                );
+            (
+                response,
+                BodyReader::new(file.take(range_len), Some(range_len)),
+            )
+        }
+        _ => {
+            let multipart = MultipartByteranges::new(Some(content_type), len);
+            let response = response.with_header(
+                HeaderName::CONTENT_TYPE,
+                multipart.content_type(),
+            );
+            let reader = multipart.into_reader(file, ranges);
+            let body_len = reader.content_length();
+            (response, BodyReader::new(reader, Some(body_len)))
+        }
+    };
+    let body_len = body.known_len().expect("ranges have a known length");
+    Ok(response
+        .with_header(HeaderName::CONTENT_LENGTH, body_len.into())
+        .with_reader(body))
+}
+
+// Derived from the metadata alone, so they are cheap enough to compute on
+// every request. The entity tag combines the modification time at full
+// precision with the size, at least one of which changes with every edit in
+// practice.
+fn validators(metadata: &Metadata) -> Validators {
+    let Ok(modified) = metadata.modified() else {
+        return Validators::new();
+    };
+    let nanos = modified
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let tag = format!("{nanos:x}-{:x}", metadata.len());
+    Validators::new()
+        .with_etag(ETag::strong(&tag).expect("hex digits are valid etagc"))
+        // A modification time in the future would keep clients from ever
+        // revalidating successfully.
+        .with_last_modified(HttpDate::from(modified).min(HttpDate::now()))
+}
+
+fn html_response(request: &Request, html: String) -> Response {
+    let len = html.len();
+    let body = match request.method() {
+        Method::Head => vec![],
+        _ => html.into_bytes(),
+    };
+    Response::new(StatusCode::OK)
+        .with_header(
+            HeaderName::CONTENT_TYPE,
+            HeaderValue::from_bytes(b"text/html; charset=utf-8")
+                .expect("valid header value"),
+        )
+        .with_header(HeaderName::CONTENT_LENGTH, len.into())
+        .with_body(body)
+}
+
+// A minimal HTML page linking to every non-hidden entry, directories first.
+fn list_directory(path: &Path, dir: &FsPath) -> io::Result<String> {
+    let mut entries = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        entries.push((!entry.file_type()?.is_dir(), name));
+    }
+    entries.sort();
+
+    let title = html_escape(&String::from_utf8_lossy(&utils::percent_decode(
+        path.as_str().as_bytes(),
+    )));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Index of {title}</title>\n</head>\n<body>\n\
+         <h1>Index of {title}</h1>\n<ul>\n"
+    );
+    if path.as_str() != "/" {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for (is_file, name) in entries {
+        let slash = if is_file { "" } else { "/" };
+        let _ = writeln!(
+            html,
+            "<li><a href=\"{}{slash}\">{}{slash}</a></li>",
+            utils::percent_encode(name.as_bytes()),
+            html_escape(&name),
+        );
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    Ok(html)
+}
+
+fn html_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::uri::RequestTarget;
+
+    // A fresh directory tree below the system temp directory:
+    //
+    //   root/index.html
+    //   root/style.css
+    //   root/docs/guide.txt
+    //   root/docs/a b.txt
+    //   root/.secret
+    //   root/empty/
+    //   outside.txt
+    struct Fixture {
+        dir: PathBuf,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let dir = std::env::temp_dir().join(format!(
+                "websurfer-files-{}-{}",
+                std::process::id(),
+                COUNT.fetch_add(1, Ordering::Relaxed)
+            ));
+            let root = dir.join("root");
+            fs::create_dir_all(root.join("docs")).unwrap();
+            fs::create_dir_all(root.join("empty")).unwrap();
+            fs::write(root.join("index.html"), "<h1>home</h1>").unwrap();
+            fs::write(root.join("style.css"), "h1 {}").unwrap();
+            fs::write(root.join("docs/guide.txt"), "guide").unwrap();
+            fs::write(root.join("docs/a b.txt"), "spaced").unwrap();
+            fs::write(root.join(".secret"), "hidden").unwrap();
+            fs::write(dir.join("outside.txt"), "outside").unwrap();
+            Self { dir }
+        }
+
+        fn root(&self) -> PathBuf {
+            self.dir.join("root")
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn get(files: &StaticFiles, method: Method, target: &str) -> Response {
+        get_with(files, method, target, &[])
+    }
+
+    fn get_with(
+        files: &StaticFiles,
+        method: Method,
+        target: &str,
+        headers: &[(HeaderName, &[u8])],
+    ) -> Response {
+        let target = RequestTarget::parse(target, &method).unwrap();
+        let mut request = Request::new(method, target);
+        for (name, value) in headers {
+            request
+                .headers_mut()
+                .append(name.clone(), HeaderValue::from_bytes(value).unwrap());
+        }
+        // Buffer streamed bodies so that tests can look at them
+        let mut response = files.handle(request);
+        if let Some(mut reader) = response.take_reader() {
+            let mut body = vec![];
+            reader.read_to_end(&mut body).unwrap();
+            assert_eq!(reader.known_len(), Some(body.len() as u64));
+            response.set_body(body);
+        }
+        response
+    }
+
+    fn header(response: &Response, name: HeaderName) -> &[u8] {
+        response.headers().get(name).unwrap().as_bytes()
+    }
+
+    #[test]
+    fn serves_files() {
+        let fixture = Fixture::new();
+        let files = StaticFiles::new(fixture.root()).unwrap();
+
+        let response = get(&files, Method::Get, "/style.css");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            header(&response, HeaderName::CONTENT_TYPE),
+            b"text/css; charset=utf-8"
+        );
+        assert_eq!(header(&response, HeaderName::CONTENT_LENGTH), b"5");
+        assert_eq!(response.body(), b"h1 {}");
+
+        let response = get(&files, Method::Head, "/style.css");
+        assert_eq!(header(&response, HeaderName::CONTENT_LENGTH), b"5");
+        assert!(response.body().is_empty());
+
+        // Bodies are streamed from the file rather than read up front
+        let target = RequestTarget::parse("/style.css", &Method::Get).unwrap();
+        let response = files.handle(Request::new(Method::Get, target));
+        assert_eq!(response.reader().and_then(|r| r.known_len()), Some(5));
+        let target = RequestTarget::parse("/style.css", &Method::Head).unwrap();
+        let response = files.handle(Request::new(Method::Head, target));
+        assert!(response.reader().is_none());
+
+        let response = get(&files, Method::Get, "/docs/a%20b.txt");
+        assert_eq!(response.body(), b"spaced");
+
+        let response = get(&files, Method::Post, "/style.css");
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(header(&response, HeaderName::ALLOW), b"GET, HEAD");
+    }
+
+    #[test]
+    fn directories() {
+        let fixture = Fixture::new();
+        let files = StaticFiles::new(fixture.root()).unwrap();
+
+        let response = get(&files, Method::Get, "/");
+        assert_eq!(response.body(), b"<h1>home</h1>");
+        let response = get(&files, Method::Get, "/docs?x=1");
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(header(&response, HeaderName::LOCATION), b"/docs/?x=1");
+        assert_eq!(
+            get(&files, Method::Get, "/docs/").status(),
+            StatusCode::NOT_FOUND
+        );
+
+        let files = files.directory_listing(true);
+        let response = get(&files, Method::Get, "/docs/");
+        assert_eq!(response.status(), StatusCode::OK);
+        let html = String::from_utf8(response.into_body()).unwrap();
+        assert!(html.contains("<a href=\"a%20b.txt\">a b.txt</a>"));
+        assert!(html.contains("<a href=\"guide.txt\">guide.txt</a>"));
+        assert!(html.contains("<a href=\"../\">"));
+        let html = get(&files, Method::Get, "/empty/").into_body();
+        assert_eq!(String::from_utf8(html).unwrap().matches("<li>").count(), 1);
+
+        let files = files.index_files(["guide.txt"]);
+        let response = get(&files, Method::Get, "/docs/");
+        assert_eq!(response.body(), b"guide");
+        let html = String::from_utf8(get(&files, Method::Get, "/").into_body());
+        assert!(html.unwrap().contains("docs/"));
+    }
+
+    #[test]
+    fn no_escapes() {
+        let fixture = Fixture::new();
+        let files = StaticFiles::new(fixture.root()).unwrap();
+        for target in [
+            "/.secret",
+            "/../outside.txt",
+            "/docs/../../outside.txt",
+            "/%2esecret",
+            "/%2e%2e/outside.txt",
+            "/docs/%2e%2e/%2e%2e/outside.txt",
+            "/docs%2f%2e%2e%2f%2e%2e%2foutside.txt",
+            "/docs%5c%2e%2e%5c%2e%2e%5coutside.txt",
+            "/C%3a/outside.txt",
+            "/style.css%00",
+            "/missing.txt",
+        ] {
+            let response = get(&files, Method::Get, target);
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{target}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let fixture = Fixture::new();
+        let root = fixture.root();
+        symlink(fixture.dir.join("outside.txt"), root.join("out.txt")).unwrap();
+        symlink(&fixture.dir, root.join("up")).unwrap();
+        symlink(root.join("style.css"), root.join("alias.css")).unwrap();
+        let files = StaticFiles::new(&root).unwrap();
+
+        for target in ["/out.txt", "/up/outside.txt"] {
+            let response = get(&files, Method::Get, target);
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{target}");
+        }
+        // Links staying inside the root are fine
+        let response = get(&files, Method::Get, "/alias.css");
+        assert_eq!(response.body(), b"h1 {}");
+    }
+
+    #[test]
+    fn conditional_requests() {
+        let fixture = Fixture::new();
+        let files = StaticFiles::new(fixture.root()).unwrap();
+        let response = get(&files, Method::Get, "/style.css");
+        let etag = header(&response, HeaderName::ETAG).to_vec();
+        let last_modified =
+            header(&response, HeaderName::LAST_MODIFIED).to_vec();
+        assert!(!ETag::from_bytes(&etag).unwrap().is_weak());
+
+        for (method, name, value) in [
+            (Method::Get, HeaderName::IF_NONE_MATCH, &etag[..]),
+            (Method::Head, HeaderName::IF_NONE_MATCH, &etag[..]),
+            (
+                Method::Get,
+                HeaderName::IF_MODIFIED_SINCE,
+                &last_modified[..],
+            ),
+        ] {
+            let headers = [(name.clone(), value)];
+            let response = get_with(&files, method, "/style.css", &headers);
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "{name}");
+            assert_eq!(header(&response, HeaderName::ETAG), etag);
+            assert!(response.body().is_empty());
+        }
+
+        let headers = [(HeaderName::IF_MATCH, &b"\"stale\""[..])];
+        let response = get_with(&files, Method::Get, "/style.css", &headers);
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+        // Editing the file changes its validators
+        fs::write(fixture.root().join("style.css"), "h1 { color: red }")
+            .unwrap();
+        let headers = [(HeaderName::IF_NONE_MATCH, &etag[..])];
+        let response = get_with(&files, Method::Get, "/style.css", &headers);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(header(&response, HeaderName::ETAG), etag);
+    }
+
+    #[test]
+    fn ranges() {
+        let fixture = Fixture::new();
+        let files = StaticFiles::new(fixture.root()).unwrap();
+        let response = get(&files, Method::Get, "/docs/guide.txt");
+        assert_eq!(header(&response, HeaderName::ACCEPT_RANGES), b"bytes");
+        let etag = header(&response, HeaderName::ETAG).to_vec();
+        let range = |value: &'static [u8]| [(HeaderName::RANGE, value)];
+
+        let response = get_with(
+            &files,
+            Method::Get,
+            "/docs/guide.txt",
+            &range(b"bytes=1-2"),
+        );
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            header(&response, HeaderName::CONTENT_RANGE),
+            b"bytes 1-2/5"
+        );
+        assert_eq!(header(&response, HeaderName::CONTENT_LENGTH), b"2");
+        assert_eq!(
+            header(&response, HeaderName::CONTENT_TYPE),
+            b"text/plain; charset=utf-8"
+        );
+        assert_eq!(header(&response, HeaderName::ETAG), etag);
+        assert_eq!(response.body(), b"ui");
+
+        let response = get_with(
+            &files,
+            Method::Get,
+            "/docs/guide.txt",
+            &range(b"bytes=-2"),
+        );
+        assert_eq!(response.body(), b"de");
+
+        let response = get_with(
+            &files,
+            Method::Get,
+            "/docs/guide.txt",
+            &range(b"bytes=0-0, -1"),
+        );
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let content_type = header(&response, HeaderName::CONTENT_TYPE);
+        let boundary = content_type
+            .strip_prefix(b"multipart/byteranges; boundary=")
+            .unwrap();
+        let body = response.body();
+        assert!(body.starts_with(&[b"--", boundary].concat()));
+        assert!(body.ends_with(&[b"\r\n--", boundary, b"--\r\n"].concat()));
+        let body = String::from_utf8_lossy(body);
+        assert!(body.contains("content-range: bytes 0-0/5\r\n\r\ng\r\n"));
+        assert!(body.contains("content-range: bytes 4-4/5\r\n\r\ne\r\n"));
+        assert_eq!(
+            header(&response, HeaderName::CONTENT_LENGTH),
+            body.len().to_string().as_bytes()
+        );
+
+        let response = get_with(
+            &files,
+            Method::Get,
+            "/docs/guide.txt",
+            &range(b"bytes=5-"),
+        );
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(header(&response, HeaderName::CONTENT_RANGE), b"bytes */5");
+
+        // Ignored for HEAD, when invalid, and when If-Range does not match
+        let response = get_with(
+            &files,
+            Method::Head,
+            "/docs/guide.txt",
+            &range(b"bytes=1-2"),
+        );
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = get_with(
+            &files,
+            Method::Get,
+            "/docs/guide.txt",
+            &range(b"bytes=2-1"),
+        );
+        assert_eq!(response.status(), StatusCode::OK);
+        for (if_range, expected) in [
+            (&etag[..], StatusCode::PARTIAL_CONTENT),
+            (b"\"stale\"", StatusCode::OK),
+        ] {
+            let headers = [
+                (HeaderName::RANGE, &b"bytes=1-2"[..]),
+                (HeaderName::IF_RANGE, if_range),
+            ];
+            let response =
+                get_with(&files, Method::Get, "/docs/guide.txt", &headers);
+            assert_eq!(response.status(), expected);
+        }
+    }
+
+    #[test]
+    fn precompressed() {
+        let fixture = Fixture::new();
+        let root = fixture.root();
+        let gzipped = ContentCoding::Gzip.encode(b"h1 {}");
+        fs::write(root.join("style.css.gz"), &gzipped).unwrap();
+        let gzip = [(HeaderName::ACCEPT_ENCODING, &b"gzip"[..])];
+
+        // Off by default
+        let files = StaticFiles::new(&root).unwrap();
+        let response = get_with(&files, Method::Get, "/style.css", &gzip);
+        assert_eq!(response.body(), b"h1 {}");
+
+        let files = files.precompressed(true);
+        let response = get_with(&files, Method::Get, "/style.css", &gzip);
+        assert_eq!(header(&response, HeaderName::CONTENT_ENCODING), b"gzip");
+        assert_eq!(
+            header(&response, HeaderName::CONTENT_TYPE),
+            b"text/css; charset=utf-8"
+        );
+        assert_eq!(header(&response, HeaderName::VARY), b"accept-encoding");
+        assert_eq!(response.body(), gzipped);
+        let gzipped_etag = header(&response, HeaderName::ETAG).to_vec();
+
+        for accept_encoding in [&b"br"[..], b"gzip;q=0"] {
+            let headers = [(HeaderName::ACCEPT_ENCODING, accept_encoding)];
+            let response =
+                get_with(&files, Method::Get, "/style.css", &headers);
+            assert!(
+                !response
+                    .headers()
+                    .contains_key(HeaderName::CONTENT_ENCODING)
+            );
+            assert_eq!(header(&response, HeaderName::VARY), b"accept-encoding");
+            assert_eq!(response.body(), b"h1 {}");
+            assert_ne!(header(&response, HeaderName::ETAG), gzipped_etag);
+        }
+
+        // The copy is revalidated on its own
+        let headers = [
+            (HeaderName::ACCEPT_ENCODING, &b"gzip"[..]),
+            (HeaderName::IF_NONE_MATCH, &gzipped_etag[..]),
+        ];
+        let response = get_with(&files, Method::Get, "/style.css", &headers);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // Files without a copy are served as usual
+        let response = get_with(&files, Method::Get, "/docs/guide.txt", &gzip);
+        assert_eq!(response.body(), b"guide");
+        assert!(!response.headers().contains_key(HeaderName::VARY));
+    }
+
+    #[test]
+    fn html_escaping() {
+        assert_eq!(
+            html_escape("<a href=\"x\">Tom & Jerry's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+}
@@ -0,0 +1,416 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::Handler;
+use crate::header::{HeaderMap, HeaderName, HeaderValue, InvalidHeaderValue};
+use crate::request::Request;
+use crate::response::Response;
+use crate::status::StatusCode;
+use crate::utils;
+
+// Longest request ID taken over from a client, anything longer is replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+// Wraps a handler to do something before and after it, such as checking
+// credentials or adding headers to every response.
+//
+// A middleware gets the request along with the handler next in line. It may
+// change the request before passing it on, change the response on the way
+// back, or answer the request itself without calling `next` at all.
+//
+// Layers apply from the inside out: in `handler.layer(a).layer(b)`, `b` sees
+// the request first and the response last.
+pub trait Middleware: Send + Sync + 'static {
+    fn call(&self, request: Request, next: &dyn Handler) -> Response;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(Request, &dyn Handler) -> Response + Send + Sync + 'static,
+{
+    fn call(&self, request: Request, next: &dyn Handler) -> Response {
+        self(request, next)
+    }
+}
+
+// A handler wrapped in a middleware, see `Handler::layer`.
+#[derive(Debug, Clone)]
+pub struct Layered<M, H> {
+    middleware: M,
+    inner: H,
+}
+
+impl<M: Middleware, H: Handler> Layered<M, H> {
+    pub fn new(middleware: M, inner: H) -> Self {
+        Self { middleware, inner }
+    }
+
+    pub fn get_ref(&self) -> &H {
+        &self.inner
+    }
+}
+
+impl<M: Middleware, H: Handler> Handler for Layered<M, H> {
+    fn handle(&self, request: Request) -> Response {
+        self.middleware.call(request, &self.inner)
+    }
+}
+
+// Tags every request with an ID, in `X-Request-Id` by default, and echoes it
+// in the response so the two can be matched up in logs on either end.
+//
+// IDs sent by clients are replaced unless they are trusted, as is the case
+// behind a proxy that assigns them. Even trusted IDs have to be short
+// tokens.
+#[derive(Debug)]
+pub struct RequestId {
+    header: HeaderName,
+    trust_incoming: bool,
+    keys: RandomState,
+    counter: AtomicU64,
+}
+
+impl RequestId {
+    pub fn new() -> Self {
+        Self {
+            header: HeaderName::from_bytes(b"x-request-id")
+                .expect("valid header name"),
+            trust_incoming: false,
+            keys: RandomState::new(),
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    pub fn header(mut self, name: HeaderName) -> Self {
+        self.header = name;
+        self
+    }
+
+    // Whether an ID that came with the request is kept.
+    pub fn trust_incoming(mut self, trusted: bool) -> Self {
+        self.trust_incoming = trusted;
+        self
+    }
+
+    // 128 bits that are unique within this process and hard to guess.
+    fn generate(&self) -> HeaderValue {
+        let n = self.counter.fetch_add(1, Ordering::Relaxed);
+        let high = self.keys.hash_one((n, 0u8));
+        let low = self.keys.hash_one((n, 1u8));
+        HeaderValue::from_bytes(format!("{high:016x}{low:016x}").as_bytes())
+            .expect("valid header value")
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for RequestId {
+    fn call(&self, mut request: Request, next: &dyn Handler) -> Response {
+        let incoming = request.headers().get(&self.header).filter(|id| {
+            self.trust_incoming
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && utils::is_token(id.as_bytes())
+        });
+        let id = match incoming {
+            Some(id) => id.clone(),
+            None => self.generate(),
+        };
+        request
+            .headers_mut()
+            .insert(self.header.clone(), id.clone());
+        let mut response = next.handle(request);
+        response.headers_mut().insert(self.header.clone(), id);
+        response
+    }
+}
+
+// Adds header fields to every response that does not set them itself, such
+// as `Cache-Control` or security policies.
+#[derive(Debug, Clone, Default)]
+pub struct DefaultHeaders {
+    headers: HeaderMap,
+}
+
+impl DefaultHeaders {
+    pub fn new() -> Self {
+        Self {
+            headers: HeaderMap::new(),
+        }
+    }
+
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+}
+
+impl Middleware for DefaultHeaders {
+    fn call(&self, request: Request, next: &dyn Handler) -> Response {
+        let mut response = next.handle(request);
+        let headers = response.headers_mut();
+        for name in self.headers.keys() {
+            if !headers.contains_key(name) {
+                for value in self.headers.get_all(name) {
+                    headers.append(name.clone(), value.clone());
+                }
+            }
+        }
+        response
+    }
+}
+
+type Verify = dyn Fn(&str, &str) -> bool + Send + Sync;
+
+// Requires HTTP Basic authentication (RFC 7617), answering requests without
+// valid credentials with a 401 and a challenge for the realm.
+//
+// Credentials are only base64-encoded, so this is only safe over TLS or on
+// trusted networks.
+#[derive(Clone)]
+pub struct BasicAuth {
+    realm: String,
+    challenge: HeaderValue,
+    verify: Arc<Verify>,
+}
+
+impl BasicAuth {
+    // `verify` gets the user name and password of every request that has
+    // them and decides whether to let it through. Fails if the realm has
+    // control characters, which cannot be sent even in a quoted string.
+    pub fn new<F>(realm: &str, verify: F) -> Result<Self, InvalidHeaderValue>
+    where
+        F: Fn(&str, &str) -> bool + Send + Sync + 'static,
+    {
+        if realm.chars().any(|c| c.is_ascii_control() && c != '\t') {
+            return Err(InvalidHeaderValue);
+        }
+        let escaped = realm.replace('\\', "\\\\").replace('"', "\\\"");
+        let challenge = format!("Basic realm=\"{escaped}\", charset=\"UTF-8\"");
+        Ok(Self {
+            realm: realm.into(),
+            challenge: HeaderValue::from_bytes(challenge.as_bytes())?,
+            verify: Arc::new(verify),
+        })
+    }
+
+    fn is_authorized(&self, request: &Request) -> bool {
+        let Some(authorization) =
+            request.headers().get(HeaderName::AUTHORIZATION)
+        else {
+            return false;
+        };
+        let Some((scheme, credentials)) = authorization
+            .to_str()
+            .and_then(|value| value.trim().split_once(' '))
+        else {
+            return false;
+        };
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return false;
+        }
+        let Some(credentials) =
+            utils::base64_decode(credentials.trim_start().as_bytes())
+                .and_then(|decoded| String::from_utf8(decoded).ok())
+        else {
+            return false;
+        };
+        credentials
+            .split_once(':')
+            .is_some_and(|(user, password)| (self.verify)(user, password))
+    }
+}
+
+impl fmt::Debug for BasicAuth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BasicAuth")
+            .field("realm", &self.realm)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Middleware for BasicAuth {
+    fn call(&self, request: Request, next: &dyn Handler) -> Response {
+        if self.is_authorized(&request) {
+            return next.handle(request);
+        }
+        Response::new(StatusCode::UNAUTHORIZED)
+            .with_header(HeaderName::WWW_AUTHENTICATE, self.challenge.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::method::Method;
+    use crate::uri::RequestTarget;
+
+    fn request(headers: &[(HeaderName, &str)]) -> Request {
+        let target = RequestTarget::parse("/", &Method::Get).unwrap();
+        headers.iter().fold(
+            Request::new(Method::Get, target),
+            |request, (name, value)| {
+                request.with_header(name.clone(), value.parse().unwrap())
+            },
+        )
+    }
+
+    // Responds with the request's header fields as its body, one per line.
+    fn echo_headers(request: Request) -> Response {
+        let mut body = vec![];
+        for (name, value) in request.headers() {
+            body.extend_from_slice(format!("{name}: ").as_bytes());
+            body.extend_from_slice(value.as_bytes());
+            body.push(b'\n');
+        }
+        Response::new(StatusCode::OK).with_body(body)
+    }
+
+    fn header<'r>(response: &'r Response, name: &str) -> Option<&'r [u8]> {
+        response.headers().get(name).map(|value| value.as_bytes())
+    }
+
+    #[test]
+    fn ordering() {
+        // Each layer records itself in a request header on the way in and
+        // a response header on the way out.
+        let tag = |name: &'static str| {
+            move |mut request: Request, next: &dyn Handler| {
+                request
+                    .headers_mut()
+                    .append("x-in".parse().unwrap(), name.parse().unwrap());
+                let mut response = next.handle(request);
+                response
+                    .headers_mut()
+                    .append("x-out".parse().unwrap(), name.parse().unwrap());
+                response
+            }
+        };
+        let handler = (echo_headers as fn(Request) -> Response)
+            .layer(tag("inner"))
+            .layer(tag("outer"));
+        let response = handler.handle(request(&[]));
+        assert_eq!(response.body(), b"x-in: outer\nx-in: inner\n");
+        let out: Vec<&[u8]> = response
+            .headers()
+            .get_all("x-out")
+            .map(|value| value.as_bytes())
+            .collect();
+        assert_eq!(out, [&b"inner"[..], b"outer"]);
+
+        // Short-circuiting skips everything further in
+        let handler = (echo_headers as fn(Request) -> Response)
+            .layer(tag("inner"))
+            .layer(|_: Request, _: &dyn Handler| {
+                Response::new(StatusCode::FORBIDDEN)
+            })
+            .layer(tag("outer"));
+        let response = handler.handle(request(&[]));
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(header(&response, "x-out"), Some(&b"outer"[..]));
+    }
+
+    #[test]
+    fn request_ids() {
+        let handler =
+            (echo_headers as fn(Request) -> Response).layer(RequestId::new());
+        let first = handler.handle(request(&[]));
+        let second = handler.handle(request(&[]));
+        let id = header(&first, "x-request-id").unwrap();
+        assert_eq!(id.len(), 32);
+        assert_ne!(Some(id), header(&second, "x-request-id"));
+        // The handler sees the same ID
+        let line = [&b"x-request-id: "[..], id, b"\n"].concat();
+        assert_eq!(first.body(), line);
+
+        let incoming = request(&[(
+            HeaderName::from_bytes(b"x-request-id").unwrap(),
+            "abc-123",
+        )]);
+        let response = handler.handle(incoming.clone());
+        assert_ne!(header(&response, "x-request-id"), Some(&b"abc-123"[..]));
+
+        let handler = (echo_headers as fn(Request) -> Response)
+            .layer(RequestId::new().trust_incoming(true));
+        let response = handler.handle(incoming);
+        assert_eq!(header(&response, "x-request-id"), Some(&b"abc-123"[..]));
+        let invalid = request(&[(
+            HeaderName::from_bytes(b"x-request-id").unwrap(),
+            "not a token",
+        )]);
+        let response = handler.handle(invalid);
+        assert_eq!(header(&response, "x-request-id").unwrap().len(), 32);
+    }
+
+    #[test]
+    fn default_headers() {
+        let handler = (|_: Request| {
+            Response::new(StatusCode::OK).with_header(
+                HeaderName::CACHE_CONTROL,
+                "no-store".parse().unwrap(),
+            )
+        })
+        .layer(
+            DefaultHeaders::new()
+                .header(
+                    HeaderName::CACHE_CONTROL,
+                    "max-age=60".parse().unwrap(),
+                )
+                .header(
+                    HeaderName::from_bytes(b"x-frame-options").unwrap(),
+                    "DENY".parse().unwrap(),
+                ),
+        );
+        let response = handler.handle(request(&[]));
+        assert_eq!(header(&response, "cache-control"), Some(&b"no-store"[..]));
+        assert_eq!(header(&response, "x-frame-options"), Some(&b"DENY"[..]));
+    }
+
+    #[test]
+    fn basic_auth() {
+        let handler = (echo_headers as fn(Request) -> Response).layer(
+            BasicAuth::new("Admin \"area\"", |user, password| {
+                user == "Aladdin" && password == "open sesame"
+            })
+            .unwrap(),
+        );
+
+        let response = handler.handle(request(&[(
+            HeaderName::AUTHORIZATION,
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==",
+        )]));
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for authorization in [
+            None,
+            Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ"),
+            Some("Basic QWxhZGRpbjpjbG9zZSBzZXNhbWU="),
+            Some("Bearer QWxhZGRpbjpvcGVuIHNlc2FtZQ=="),
+            Some("Basic QWxhZGRpbg=="),
+        ] {
+            let headers: Vec<_> = authorization
+                .map(|value| (HeaderName::AUTHORIZATION, value))
+                .into_iter()
+                .collect();
+            let response = handler.handle(request(&headers));
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(
+                header(&response, "www-authenticate"),
+                Some(
+                    &b"Basic realm=\"Admin \\\"area\\\"\", charset=\"UTF-8\""[..]
+                )
+            );
+        }
+
+        for realm in ["a\nb", "a\rb", "a\0b", "a\u{7f}b"] {
+            assert!(BasicAuth::new(realm, |_, _| true).is_err(), "{realm:?}");
+        }
+        assert!(BasicAuth::new("tab\tand ünïcode", |_, _| true).is_ok());
+    }
+}
@@ -0,0 +1,666 @@
+use std::fmt;
+use std::sync::Arc;
+
+use super::{Handler, Middleware};
+use crate::header::{HeaderName, HeaderValue};
+use crate::method::Method;
+use crate::request::{PathParams, Request};
+use crate::response::Response;
+use crate::status::StatusCode;
+use crate::uri::RequestTarget;
+use crate::utils;
+
+// Every method, in the order they are listed in `Allow`.
+const METHODS: [Method; 9] = [
+    Method::Get,
+    Method::Head,
+    Method::Post,
+    Method::Put,
+    Method::Delete,
+    Method::Connect,
+    Method::Options,
+    Method::Trace,
+    Method::Patch,
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RouteError {
+    // The pattern is not a path, or has a malformed parameter
+    InvalidPattern(String),
+    // The route would handle the same requests as one registered before
+    Conflict {
+        method: Method,
+        pattern: String,
+        existing: String,
+    },
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidPattern(pattern) => {
+                write!(f, "invalid route pattern {pattern:?}")
+            }
+            Self::Conflict {
+                method,
+                pattern,
+                existing,
+            } => write!(
+                f,
+                "route {method} {pattern:?} conflicts with {method} \
+                 {existing:?}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RouteError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    // "{name}", matching any one non-empty segment
+    Param(String),
+    // "{*name}", matching the rest of the path
+    Wildcard(String),
+}
+
+impl Segment {
+    // Lower is more specific and wins when several routes match.
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Literal(_) => 0,
+            Self::Param(_) => 1,
+            Self::Wildcard(_) => 2,
+        }
+    }
+
+    // Whether both match the same segments, whatever their names.
+    fn same_shape(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Literal(a), Self::Literal(b)) => a == b,
+            _ => self.rank() == other.rank(),
+        }
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Result<Vec<Segment>, RouteError> {
+    let invalid = || RouteError::InvalidPattern(pattern.into());
+    let rest = pattern.strip_prefix('/').ok_or_else(invalid)?;
+    let mut segments = vec![];
+    for s in rest.split('/') {
+        if matches!(segments.last(), Some(Segment::Wildcard(_))) {
+            return Err(invalid());
+        }
+        let segment =
+            match s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                Some(name) => match name.strip_prefix('*') {
+                    Some(name) => Segment::Wildcard(name.into()),
+                    None => Segment::Param(name.into()),
+                },
+                None if s.contains(['{', '}']) => return Err(invalid()),
+                None => Segment::Literal(s.into()),
+            };
+        if let Segment::Param(name) | Segment::Wildcard(name) = &segment {
+            let is_name_byte = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+            let taken = segments.iter().any(|s| {
+                matches!(s, Segment::Param(n) | Segment::Wildcard(n) if n == name)
+            });
+            if name.is_empty() || !name.bytes().all(is_name_byte) || taken {
+                return Err(invalid());
+            }
+        }
+        segments.push(segment);
+    }
+    Ok(segments)
+}
+
+// The percent-decoded segments of a request path.
+fn path_segments(path: &str) -> Vec<String> {
+    path.strip_prefix('/')
+        .unwrap_or(path)
+        .split('/')
+        .map(|s| {
+            String::from_utf8_lossy(&utils::percent_decode(s.as_bytes()))
+                .into_owned()
+        })
+        .collect()
+}
+
+struct Route {
+    pattern: String,
+    segments: Vec<Segment>,
+    handlers: Vec<(Method, Arc<dyn Handler>)>,
+}
+
+impl Route {
+    fn handler(&self, method: Method) -> Option<&Arc<dyn Handler>> {
+        self.handlers
+            .iter()
+            .find(|(m, _)| *m == method)
+            .map(|(_, handler)| handler)
+    }
+
+    fn matches(&self, path: &[String]) -> Option<PathParams> {
+        let mut params = PathParams::new();
+        let mut path = path.iter();
+        for segment in &self.segments {
+            match segment {
+                Segment::Wildcard(name) => {
+                    let rest: Vec<&str> = path.map(String::as_str).collect();
+                    if rest.is_empty() {
+                        return None;
+                    }
+                    params.push(name.clone(), rest.join("/"));
+                    return Some(params);
+                }
+                Segment::Param(name) => {
+                    let value = path.next().filter(|s| !s.is_empty())?;
+                    params.push(name.clone(), value.clone());
+                }
+                Segment::Literal(literal) => {
+                    if path.next()? != literal {
+                        return None;
+                    }
+                }
+            }
+        }
+        path.next().is_none().then_some(params)
+    }
+}
+
+// Dispatches requests to handlers by method and path.
+//
+// Patterns are paths whose segments may be parameters, "{name}" for a single
+// segment and "{*name}" for the rest of the path, which the handler finds in
+// `Request::params`. When several routes match a path, literal segments win
+// over parameters and parameters over the rest of the path, comparing from
+// the left. Registering a route that would handle the same requests as an
+// existing one is an error.
+//
+// Routes without a HEAD handler answer HEAD with their GET handler, and
+// OPTIONS with the allowed methods unless it has a handler of its own.
+// Requests for a path that has routes, but none for the method, get a 405
+// with `Allow`. Anything else goes to the fallback, a 404 by default.
+pub struct Router {
+    routes: Vec<Route>,
+    fallback: Option<Arc<dyn Handler>>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self {
+            routes: vec![],
+            fallback: None,
+        }
+    }
+
+    // Registers a handler, panicking if the pattern is invalid or conflicts
+    // with another route. See `try_route`.
+    pub fn route<H: Handler>(
+        self,
+        method: Method,
+        pattern: &str,
+        handler: H,
+    ) -> Self {
+        self.try_route(method, pattern, handler)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub fn try_route<H: Handler>(
+        mut self,
+        method: Method,
+        pattern: &str,
+        handler: H,
+    ) -> Result<Self, RouteError> {
+        let segments = parse_pattern(pattern)?;
+        self.add(method, pattern.into(), segments, Arc::new(handler))?;
+        Ok(self)
+    }
+
+    pub fn get<H: Handler>(self, pattern: &str, handler: H) -> Self {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    pub fn post<H: Handler>(self, pattern: &str, handler: H) -> Self {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    pub fn put<H: Handler>(self, pattern: &str, handler: H) -> Self {
+        self.route(Method::Put, pattern, handler)
+    }
+
+    pub fn delete<H: Handler>(self, pattern: &str, handler: H) -> Self {
+        self.route(Method::Delete, pattern, handler)
+    }
+
+    pub fn patch<H: Handler>(self, pattern: &str, handler: H) -> Self {
+        self.route(Method::Patch, pattern, handler)
+    }
+
+    // Mounts the routes of another router below a prefix, panicking on
+    // conflicts. See `try_nest`.
+    pub fn nest(self, prefix: &str, router: Router) -> Self {
+        self.try_nest(prefix, router)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    // Mounts the routes of another router below a prefix, which may contain
+    // parameters but not the rest of the path. A route for "/" becomes one
+    // for the prefix itself. The fallback of the nested router is not used.
+    pub fn try_nest(
+        mut self,
+        prefix: &str,
+        router: Router,
+    ) -> Result<Self, RouteError> {
+        let prefix_segments = parse_pattern(prefix)?;
+        if matches!(prefix_segments.last(), Some(Segment::Wildcard(_))) {
+            return Err(RouteError::InvalidPattern(prefix.into()));
+        }
+        let prefix = prefix.trim_end_matches('/');
+        for route in router.routes {
+            let pattern = match route.pattern.as_str() {
+                "/" if !prefix.is_empty() => prefix.to_string(),
+                pattern => format!("{prefix}{pattern}"),
+            };
+            let segments = parse_pattern(&pattern)?;
+            for (method, handler) in route.handlers {
+                self.add(method, pattern.clone(), segments.clone(), handler)?;
+            }
+        }
+        Ok(self)
+    }
+
+    // Wraps the handlers of every route registered so far in a middleware,
+    // leaving later routes, the fallback and the automatic 404, 405 and
+    // OPTIONS responses alone. Layers added later run first.
+    pub fn route_layer<M: Middleware>(mut self, middleware: M) -> Self {
+        let middleware: Arc<dyn Middleware> = Arc::new(middleware);
+        for route in &mut self.routes {
+            for (_, handler) in &mut route.handlers {
+                *handler = Arc::new(RouteLayer {
+                    middleware: Arc::clone(&middleware),
+                    inner: Arc::clone(handler),
+                });
+            }
+        }
+        self
+    }
+
+    // Handles the requests no route matches.
+    pub fn fallback<H: Handler>(mut self, handler: H) -> Self {
+        self.fallback = Some(Arc::new(handler));
+        self
+    }
+
+    fn add(
+        &mut self,
+        method: Method,
+        pattern: String,
+        segments: Vec<Segment>,
+        handler: Arc<dyn Handler>,
+    ) -> Result<(), RouteError> {
+        let existing = self.routes.iter_mut().find(|route| {
+            route.segments.len() == segments.len()
+                && route
+                    .segments
+                    .iter()
+                    .zip(&segments)
+                    .all(|(a, b)| a.same_shape(b))
+        });
+        let Some(route) = existing else {
+            self.routes.push(Route {
+                pattern,
+                segments,
+                handlers: vec![(method, handler)],
+            });
+            return Ok(());
+        };
+        // The same shape with other parameter names would leave handlers
+        // looking for names that are not there.
+        if route.segments != segments || route.handler(method).is_some() {
+            return Err(RouteError::Conflict {
+                method,
+                pattern,
+                existing: route.pattern.clone(),
+            });
+        }
+        route.handlers.push((method, handler));
+        Ok(())
+    }
+
+    fn not_found(&self, request: Request) -> Response {
+        match &self.fallback {
+            Some(fallback) => fallback.handle(request),
+            None => Response::new(StatusCode::NOT_FOUND),
+        }
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Router {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let routes = self.routes.iter().flat_map(|route| {
+            route
+                .handlers
+                .iter()
+                .map(|(method, _)| format!("{method} {}", route.pattern))
+        });
+        f.debug_struct("Router")
+            .field("routes", &routes.collect::<Vec<_>>())
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl Handler for Router {
+    fn handle(&self, mut request: Request) -> Response {
+        let method = request.method();
+        let Some(path) = request.target().path() else {
+            // "OPTIONS *" asks about the server as a whole
+            if *request.target() == RequestTarget::Asterisk
+                && method == Method::Options
+            {
+                return allowed(StatusCode::NO_CONTENT, self.routes.iter());
+            }
+            return self.not_found(request);
+        };
+
+        let path = path_segments(path.as_str());
+        let mut matched: Vec<(&Route, PathParams)> = self
+            .routes
+            .iter()
+            .filter_map(|route| Some((route, route.matches(&path)?)))
+            .collect();
+        if matched.is_empty() {
+            return self.not_found(request);
+        }
+        matched.sort_by_key(|(route, _)| {
+            route.segments.iter().map(Segment::rank).collect::<Vec<_>>()
+        });
+
+        for (route, params) in &matched {
+            let handler = route.handler(method).or_else(|| {
+                (method == Method::Head)
+                    .then(|| route.handler(Method::Get))
+                    .flatten()
+            });
+            if let Some(handler) = handler {
+                request.set_params(params.clone());
+                return handler.handle(request);
+            }
+        }
+        let routes = matched.iter().map(|(route, _)| *route);
+        if method == Method::Options {
+            allowed(StatusCode::NO_CONTENT, routes)
+        } else {
+            allowed(StatusCode::METHOD_NOT_ALLOWED, routes)
+        }
+    }
+}
+
+// A middleware shared between the handlers of several routes.
+struct RouteLayer {
+    middleware: Arc<dyn Middleware>,
+    inner: Arc<dyn Handler>,
+}
+
+impl Handler for RouteLayer {
+    fn handle(&self, request: Request) -> Response {
+        self.middleware.call(request, &*self.inner)
+    }
+}
+
+// A response listing the methods the routes allow in `Allow`.
+fn allowed<'r>(
+    status: StatusCode,
+    routes: impl Iterator<Item = &'r Route>,
+) -> Response {
+    let mut methods = vec![Method::Options];
+    for route in routes {
+        methods.extend(route.handlers.iter().map(|(method, _)| *method));
+    }
+    if methods.contains(&Method::Get) {
+        methods.push(Method::Head);
+    }
+    let allow: Vec<&str> = METHODS
+        .iter()
+        .filter(|method| methods.contains(method))
+        .map(Method::as_str)
+        .collect();
+    Response::new(status).with_header(
+        HeaderName::ALLOW,
+        HeaderValue::from_bytes(allow.join(", ").as_bytes())
+            .expect("valid header value"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Responds with the method, route name and parameters it was called
+    // with.
+    fn named(name: &'static str) -> impl Handler {
+        move |request: Request| {
+            let mut body = format!("{} {name}", request.method());
+            for (param, value) in request.params().iter() {
+                body.push_str(&format!(" {param}={value}"));
+            }
+            Response::new(StatusCode::OK).with_body(body.into_bytes())
+        }
+    }
+
+    fn call(router: &Router, method: Method, target: &str) -> Response {
+        let target = RequestTarget::parse(target, &method).unwrap();
+        router.handle(Request::new(method, target))
+    }
+
+    fn body(router: &Router, method: Method, target: &str) -> String {
+        let response = call(router, method, target);
+        assert_eq!(response.status(), StatusCode::OK, "{target}");
+        String::from_utf8(response.body().to_vec()).unwrap()
+    }
+
+    fn allow(response: &Response) -> Option<&[u8]> {
+        response
+            .headers()
+            .get(HeaderName::ALLOW)
+            .map(|value| value.as_bytes())
+    }
+
+    #[test]
+    fn dispatches() {
+        let router = Router::new()
+            .get("/", named("index"))
+            .get("/users", named("list"))
+            .post("/users", named("create"))
+            .get("/users/me", named("me"))
+            .get("/users/{id}", named("show"))
+            .delete("/users/{id}", named("remove"))
+            .get("/users/{id}/posts/{post}", named("post"))
+            .get("/files/{*path}", named("file"));
+
+        assert_eq!(body(&router, Method::Get, "/"), "GET index");
+        assert_eq!(body(&router, Method::Get, "/users?page=2"), "GET list");
+        assert_eq!(body(&router, Method::Post, "/users"), "POST create");
+        assert_eq!(body(&router, Method::Get, "/users/me"), "GET me");
+        assert_eq!(body(&router, Method::Get, "/users/42"), "GET show id=42");
+        assert_eq!(
+            body(&router, Method::Delete, "/users/me"),
+            "DELETE remove id=me"
+        );
+        assert_eq!(
+            body(&router, Method::Get, "/users/a%20b/posts/7"),
+            "GET post id=a b post=7"
+        );
+        assert_eq!(
+            body(&router, Method::Get, "/files/css/site.css"),
+            "GET file path=css/site.css"
+        );
+
+        for target in ["/nope", "/users/", "/users/42/posts", "/files"] {
+            let response = call(&router, Method::Get, target);
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{target}");
+        }
+
+        let router = router.fallback(named("fallback"));
+        assert_eq!(body(&router, Method::Get, "/nope"), "GET fallback");
+    }
+
+    #[test]
+    fn method_handling() {
+        let router = Router::new()
+            .get("/items", named("list"))
+            .post("/items", named("create"))
+            .route(Method::Head, "/head", named("head"))
+            .get("/head", named("get"))
+            .route(Method::Options, "/options", named("options"));
+
+        // HEAD falls back to GET
+        assert_eq!(body(&router, Method::Head, "/items"), "HEAD list");
+        assert_eq!(body(&router, Method::Head, "/head"), "HEAD head");
+
+        let response = call(&router, Method::Delete, "/items");
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(allow(&response), Some(&b"GET, HEAD, POST, OPTIONS"[..]));
+
+        let response = call(&router, Method::Options, "/items");
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(allow(&response), Some(&b"GET, HEAD, POST, OPTIONS"[..]));
+        assert_eq!(
+            body(&router, Method::Options, "/options"),
+            "OPTIONS options"
+        );
+
+        let response = call(&router, Method::Options, "*");
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(allow(&response), Some(&b"GET, HEAD, POST, OPTIONS"[..]));
+    }
+
+    #[test]
+    fn nesting() {
+        let users = Router::new()
+            .get("/", named("list"))
+            .get("/{id}", named("show"));
+        let api = Router::new()
+            .nest("/users", users)
+            .get("/health", named("health"));
+        let router = Router::new()
+            .get("/", named("index"))
+            .nest("/api/{version}", api);
+
+        assert_eq!(body(&router, Method::Get, "/"), "GET index");
+        assert_eq!(
+            body(&router, Method::Get, "/api/v1/users"),
+            "GET list version=v1"
+        );
+        assert_eq!(
+            body(&router, Method::Get, "/api/v2/users/7"),
+            "GET show version=v2 id=7"
+        );
+        assert_eq!(
+            body(&router, Method::Get, "/api/v1/health"),
+            "GET health version=v1"
+        );
+    }
+
+    #[test]
+    fn conflicts() {
+        let conflict = |a: &str, b: &str| {
+            Router::new()
+                .get(a, named("a"))
+                .try_route(Method::Get, b, named("b"))
+                .unwrap_err()
+        };
+        assert_eq!(
+            conflict("/users/{id}", "/users/{id}"),
+            RouteError::Conflict {
+                method: Method::Get,
+                pattern: "/users/{id}".into(),
+                existing: "/users/{id}".into(),
+            }
+        );
+        conflict("/users/{id}", "/users/{name}");
+        conflict("/files/{*path}", "/files/{*rest}");
+        // Other parameter names for another method are confusing too
+        let result = Router::new().get("/users/{id}", named("a")).try_route(
+            Method::Post,
+            "/users/{name}",
+            named("b"),
+        );
+        assert!(result.is_err());
+
+        let nested = Router::new().get("/users", named("b"));
+        let result = Router::new()
+            .get("/api/users", named("a"))
+            .try_nest("/api", nested);
+        assert!(matches!(result, Err(RouteError::Conflict { .. })));
+
+        // These are fine
+        Router::new()
+            .get("/users/{id}", named("a"))
+            .post("/users/{id}", named("b"))
+            .get("/users/me", named("c"))
+            .get("/users/{id}/{*rest}", named("d"));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for pattern in [
+            "",
+            "users",
+            "/users/{}",
+            "/users/{id",
+            "/users/id}",
+            "/users/x{id}",
+            "/users/{a-b}",
+            "/{id}/{id}",
+            "/{*rest}/more",
+        ] {
+            let result =
+                Router::new().try_route(Method::Get, pattern, named(""));
+            assert_eq!(
+                result.unwrap_err(),
+                RouteError::InvalidPattern(pattern.into())
+            );
+        }
+        let result = Router::new().try_nest("/static/{*path}", Router::new());
+        assert!(matches!(result, Err(RouteError::InvalidPattern(_))));
+    }
+
+    #[test]
+    fn route_layers() {
+        let deny =
+            |_: Request, _: &dyn Handler| Response::new(StatusCode::FORBIDDEN);
+        let router = Router::new()
+            .get("/admin", named("admin"))
+            .delete("/admin/{id}", named("remove"))
+            .route_layer(deny)
+            .get("/", named("index"));
+
+        assert_eq!(body(&router, Method::Get, "/"), "GET index");
+        for (method, target) in
+            [(Method::Get, "/admin"), (Method::Delete, "/admin/1")]
+        {
+            let response = call(&router, method, target);
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+        // The automatic responses are not guarded
+        let response = call(&router, Method::Post, "/admin");
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let response = call(&router, Method::Get, "/nope");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicts with")]
+    fn route_panics_on_conflict() {
+        Router::new().get("/", named("a")).get("/", named("b"));
+    }
+}
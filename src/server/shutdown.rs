@@ -0,0 +1,217 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+// Shared between the accept loop, every connection and any number of
+// `ShutdownHandle`s.
+#[derive(Debug)]
+pub(crate) struct State {
+    draining: AtomicBool,
+    // Where the listener can be reached to wake up a blocking accept
+    wake_addr: SocketAddr,
+    connections: Mutex<Connections>,
+    // Signalled whenever a connection is closed
+    closed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Connections {
+    next_id: u64,
+    // Clones of the open streams, so they can be shut down from the outside
+    streams: Vec<(u64, TcpStream)>,
+}
+
+impl State {
+    pub(crate) fn new(local_addr: SocketAddr) -> Self {
+        // Connecting to the unspecified address does not work everywhere
+        let ip = match local_addr.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => Ipv4Addr::LOCALHOST.into(),
+            IpAddr::V6(ip) if ip.is_unspecified() => Ipv6Addr::LOCALHOST.into(),
+            ip => ip,
+        };
+        Self {
+            draining: AtomicBool::new(false),
+            wake_addr: SocketAddr::new(ip, local_addr.port()),
+            connections: Mutex::default(),
+            closed: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Acquire)
+    }
+
+    fn connections(&self) -> MutexGuard<'_, Connections> {
+        // The lock is never held across anything that can panic
+        self.connections.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Tracks an accepted connection until the returned guard is dropped.
+    pub(crate) fn register(
+        self: &Arc<Self>,
+        stream: &TcpStream,
+    ) -> io::Result<Registration> {
+        let clone = stream.try_clone()?;
+        let mut connections = self.connections();
+        let id = connections.next_id;
+        connections.next_id += 1;
+        connections.streams.push((id, clone));
+        Ok(Registration {
+            id,
+            state: Arc::clone(self),
+        })
+    }
+
+    // Waits for every connection to close on its own until `deadline`, then
+    // forcibly closes those still open.
+    pub(crate) fn drain(&self, deadline: Instant) {
+        let mut connections = self.connections();
+        while !connections.streams.is_empty() {
+            let Some(timeout) = deadline
+                .checked_duration_since(Instant::now())
+                .filter(|timeout| !timeout.is_zero())
+            else {
+                break;
+            };
+            connections = self
+                .closed
+                .wait_timeout(connections, timeout)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        for (_, stream) in &connections.streams {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+        }
+    }
+}
+
+pub(crate) struct Registration {
+    id: u64,
+    state: Arc<State>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut connections = self.state.connections();
+        connections.streams.retain(|(id, _)| *id != self.id);
+        drop(connections);
+        self.state.closed.notify_all();
+    }
+}
+
+// Stops a running `Server`, see `Server::shutdown_handle`.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    state: Arc<State>,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(state: Arc<State>) -> Self {
+        Self { state }
+    }
+
+    // Makes the server stop accepting connections and start draining the
+    // open ones. Returns immediately, `Server::run` returns once draining is
+    // done. Calling this more than once has no further effect.
+    pub fn shutdown(&self) {
+        if self.state.draining.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        // The accept loop only notices once `accept` returns, so hand it a
+        // connection. It is closed right away.
+        let _ = TcpStream::connect_timeout(
+            &self.state.wake_addr,
+            Duration::from_secs(1),
+        );
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.state.is_draining()
+    }
+
+    // Calls `shutdown` on the first SIGINT or SIGTERM (Ctrl-C or the console
+    // closing on Windows). A second signal terminates the process as usual.
+    #[cfg(feature = "signal")]
+    pub fn shutdown_on_signal(&self) -> io::Result<()> {
+        signal::install()?;
+        let handle = self.clone();
+        std::thread::Builder::new()
+            .name("websurfer-signal".into())
+            .spawn(move || {
+                while !signal::RECEIVED.load(Ordering::Acquire) {
+                    std::thread::sleep(signal::POLL_INTERVAL);
+                }
+                handle.shutdown();
+            })?;
+        Ok(())
+    }
+}
+
+// Signal handlers may only do very little, so the handler just sets a flag
+// that a regular thread polls.
+#[cfg(feature = "signal")]
+mod signal {
+    use std::io;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    pub(super) static RECEIVED: AtomicBool = AtomicBool::new(false);
+    pub(super) const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    #[cfg(unix)]
+    pub(super) fn install() -> io::Result<()> {
+        use std::ffi::c_int;
+
+        const SIGINT: c_int = 2;
+        const SIGTERM: c_int = 15;
+        const SIG_DFL: usize = 0;
+        const SIG_ERR: usize = usize::MAX;
+
+        unsafe extern "C" {
+            fn signal(signum: c_int, handler: usize) -> usize;
+        }
+
+        extern "C" fn handle(signum: c_int) {
+            RECEIVED.store(true, Ordering::Release);
+            // Let the next one take the default action
+            unsafe { signal(signum, SIG_DFL) };
+        }
+
+        for signum in [SIGINT, SIGTERM] {
+            let handler = handle as extern "C" fn(c_int) as usize;
+            if unsafe { signal(signum, handler) } == SIG_ERR {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    pub(super) fn install() -> io::Result<()> {
+        type Handler = unsafe extern "system" fn(u32) -> i32;
+
+        #[link(name = "kernel32")]
+        unsafe extern "system" {
+            fn SetConsoleCtrlHandler(handler: Option<Handler>, add: i32)
+            -> i32;
+        }
+
+        // Runs on a thread of its own, returning nonzero marks the event as
+        // handled so the process is not terminated.
+        unsafe extern "system" fn handle(_event: u32) -> i32 {
+            !RECEIVED.swap(true, Ordering::AcqRel) as i32
+        }
+
+        if unsafe { SetConsoleCtrlHandler(Some(handle), 1) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    pub(super) fn install() -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
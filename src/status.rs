@@ -0,0 +1,216 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Generates the `StatusCode` constants and the canonical reason phrase table
+// from a single list.
+macro_rules! status_codes {
+    ($(($code:literal, $konst:ident, $reason:literal);)+) => {
+        impl StatusCode {
+            $(
+                pub const $konst: StatusCode = StatusCode($code);
+            )+
+
+            pub fn canonical_reason(&self) -> Option<&'static str> {
+                match self.0 {
+                    $($code => Some($reason),)+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+status_codes! {
+    (100, CONTINUE, "Continue");
+    (101, SWITCHING_PROTOCOLS, "Switching Protocols");
+    (103, EARLY_HINTS, "Early Hints");
+    (200, OK, "OK");
+    (201, CREATED, "Created");
+    (202, ACCEPTED, "Accepted");
+    (203, NON_AUTHORITATIVE_INFORMATION, "Non-Authoritative Information");
+    (204, NO_CONTENT, "No Content");
+    (205, RESET_CONTENT, "Reset Content");
+    (206, PARTIAL_CONTENT, "Partial Content");
+    (300, MULTIPLE_CHOICES, "Multiple Choices");
+    (301, MOVED_PERMANENTLY, "Moved Permanently");
+    (302, FOUND, "Found");
+    (303, SEE_OTHER, "See Other");
+    (304, NOT_MODIFIED, "Not Modified");
+    (307, TEMPORARY_REDIRECT, "Temporary Redirect");
+    (308, PERMANENT_REDIRECT, "Permanent Redirect");
+    (400, BAD_REQUEST, "Bad Request");
+    (401, UNAUTHORIZED, "Unauthorized");
+    (402, PAYMENT_REQUIRED, "Payment Required");
+    (403, FORBIDDEN, "Forbidden");
+    (404, NOT_FOUND, "Not Found");
+    (405, METHOD_NOT_ALLOWED, "Method Not Allowed");
+    (406, NOT_ACCEPTABLE, "Not Acceptable");
+    (407, PROXY_AUTHENTICATION_REQUIRED, "Proxy Authentication Required");
+    (408, REQUEST_TIMEOUT, "Request Timeout");
+    (409, CONFLICT, "Conflict");
+    (410, GONE, "Gone");
+    (411, LENGTH_REQUIRED, "Length Required");
+    (412, PRECONDITION_FAILED, "Precondition Failed");
+    (413, CONTENT_TOO_LARGE, "Content Too Large");
+    (414, URI_TOO_LONG, "URI Too Long");
+    (415, UNSUPPORTED_MEDIA_TYPE, "Unsupported Media Type");
+    (416, RANGE_NOT_SATISFIABLE, "Range Not Satisfiable");
+    (417, EXPECTATION_FAILED, "Expectation Failed");
+    (421, MISDIRECTED_REQUEST, "Misdirected Request");
+    (422, UNPROCESSABLE_CONTENT, "Unprocessable Content");
+    (426, UPGRADE_REQUIRED, "Upgrade Required");
+    (428, PRECONDITION_REQUIRED, "Precondition Required");
+    (429, TOO_MANY_REQUESTS, "Too Many Requests");
+    (431, REQUEST_HEADER_FIELDS_TOO_LARGE, "Request Header Fields Too Large");
+    (500, INTERNAL_SERVER_ERROR, "Internal Server Error");
+    (501, NOT_IMPLEMENTED, "Not Implemented");
+    (502, BAD_GATEWAY, "Bad Gateway");
+    (503, SERVICE_UNAVAILABLE, "Service Unavailable");
+    (504, GATEWAY_TIMEOUT, "Gateway Timeout");
+    (505, HTTP_VERSION_NOT_SUPPORTED, "HTTP Version Not Supported");
+}
+
+// A three-digit status code in the range 100-599.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StatusCode(u16);
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidStatusCode;
+
+// The first digit of a status code defines its class (RFC 9110 section 15).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusClass {
+    Informational,
+    Successful,
+    Redirection,
+    ClientError,
+    ServerError,
+}
+
+impl StatusCode {
+    pub fn from_u16(code: u16) -> Result<Self, InvalidStatusCode> {
+        match code {
+            100..=599 => Ok(Self(code)),
+            _ => Err(InvalidStatusCode),
+        }
+    }
+
+    // status-code = 3DIGIT
+    pub fn from_bytes(s: &[u8]) -> Result<Self, InvalidStatusCode> {
+        match *s {
+            [a, b, c]
+                if a.is_ascii_digit()
+                    && b.is_ascii_digit()
+                    && c.is_ascii_digit() =>
+            {
+                Self::from_u16(
+                    (a - b'0') as u16 * 100
+                        + (b - b'0') as u16 * 10
+                        + (c - b'0') as u16,
+                )
+            }
+            _ => Err(InvalidStatusCode),
+        }
+    }
+
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    pub fn class(&self) -> StatusClass {
+        match self.0 {
+            100..=199 => StatusClass::Informational,
+            200..=299 => StatusClass::Successful,
+            300..=399 => StatusClass::Redirection,
+            400..=499 => StatusClass::ClientError,
+            _ => StatusClass::ServerError,
+        }
+    }
+
+    pub fn is_informational(&self) -> bool {
+        self.class() == StatusClass::Informational
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.class() == StatusClass::Successful
+    }
+
+    pub fn is_redirection(&self) -> bool {
+        self.class() == StatusClass::Redirection
+    }
+
+    pub fn is_client_error(&self) -> bool {
+        self.class() == StatusClass::ClientError
+    }
+
+    pub fn is_server_error(&self) -> bool {
+        self.class() == StatusClass::ServerError
+    }
+}
+
+impl FromStr for StatusCode {
+    type Err = InvalidStatusCode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_bytes(s.as_bytes())
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.canonical_reason() {
+            Some(reason) => write!(f, "{} {reason}", self.0),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        assert_eq!("200".parse(), Ok(StatusCode::OK));
+        assert_eq!("404".parse(), Ok(StatusCode::NOT_FOUND));
+        assert_eq!("599".parse(), Ok(StatusCode(599)));
+
+        assert_eq!("099".parse::<StatusCode>(), Err(InvalidStatusCode));
+        assert_eq!("600".parse::<StatusCode>(), Err(InvalidStatusCode));
+        assert_eq!("20".parse::<StatusCode>(), Err(InvalidStatusCode));
+        assert_eq!("2000".parse::<StatusCode>(), Err(InvalidStatusCode));
+        assert_eq!("+20".parse::<StatusCode>(), Err(InvalidStatusCode));
+        assert_eq!(" 200".parse::<StatusCode>(), Err(InvalidStatusCode));
+        assert_eq!("".parse::<StatusCode>(), Err(InvalidStatusCode));
+        assert_eq!(StatusCode::from_u16(1000), Err(InvalidStatusCode));
+    }
+
+    #[test]
+    fn reasons() {
+        assert_eq!(StatusCode::OK.canonical_reason(), Some("OK"));
+        assert_eq!(
+            StatusCode::CONTENT_TOO_LARGE.canonical_reason(),
+            Some("Content Too Large")
+        );
+        assert_eq!(StatusCode(299).canonical_reason(), None);
+
+        assert_eq!(StatusCode::NOT_FOUND.to_string(), "404 Not Found");
+        assert_eq!(StatusCode(299).to_string(), "299");
+    }
+
+    #[test]
+    fn classes() {
+        assert_eq!(StatusCode::CONTINUE.class(), StatusClass::Informational);
+        assert_eq!(StatusCode::NO_CONTENT.class(), StatusClass::Successful);
+        assert_eq!(StatusCode::FOUND.class(), StatusClass::Redirection);
+        assert_eq!(StatusCode::GONE.class(), StatusClass::ClientError);
+        assert_eq!(StatusCode::BAD_GATEWAY.class(), StatusClass::ServerError);
+
+        assert!(StatusCode::SWITCHING_PROTOCOLS.is_informational());
+        assert!(StatusCode::CREATED.is_success());
+        assert!(StatusCode::NOT_MODIFIED.is_redirection());
+        assert!(StatusCode::URI_TOO_LONG.is_client_error());
+        assert!(StatusCode::NOT_IMPLEMENTED.is_server_error());
+        assert!(!StatusCode::OK.is_client_error());
+    }
+}
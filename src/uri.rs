@@ -1 +1,500 @@
+use std::fmt;
+use std::str::FromStr;
 
+use crate::authority::Authority;
+use crate::method::Method;
+use crate::path::Path;
+use crate::query::{Query, QueryParseError};
+use crate::scheme::Scheme;
+use crate::utils::{self, RESERVED_BYTES, UNRESERVED_BYTES};
+
+// An absolute URI (`http://example.com/path?query`) or, when neither scheme
+// nor authority is present, just the path and query of one (as found in the
+// origin-form of a request target).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Uri {
+    scheme: Option<Scheme>,
+    authority: Option<Authority>,
+    path: Path,
+    query: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum UriParseError {
+    InvalidScheme,
+    InvalidAuthority,
+    InvalidPath,
+    InvalidQuery,
+    // Fragments are never sent to a server.
+    UnexpectedFragment,
+    Empty,
+}
+
+impl Uri {
+    // query = *( pchar / "/" / "?" )
+    //
+    // Which works out to every reserved and unreserved character except '#',
+    // '[' and ']'.
+    fn is_valid_query_byte(b: &u8) -> bool {
+        UNRESERVED_BYTES.contains(b)
+            || (RESERVED_BYTES.contains(b) && !matches!(b, b'#' | b'[' | b']'))
+    }
+
+    fn parse_query(s: &str) -> Result<String, UriParseError> {
+        if !s.as_bytes().iter().all(Self::is_valid_query_byte)
+            || !utils::is_properly_percent_encoded(s.as_bytes())
+        {
+            return Err(UriParseError::InvalidQuery);
+        }
+        Ok(s.into())
+    }
+
+    // origin-form = absolute-path [ "?" query ]
+    fn parse_origin_form(s: &str) -> Result<Self, UriParseError> {
+        let (path, query) = match s.split_once('?') {
+            Some((path, query)) => (path, Some(Self::parse_query(query)?)),
+            None => (s, None),
+        };
+        Ok(Self {
+            scheme: None,
+            authority: None,
+            path: path.parse().map_err(|_| UriParseError::InvalidPath)?,
+            query,
+        })
+    }
+
+    // absolute-URI = scheme ":" hier-part [ "?" query ]
+    // hier-part    = "//" authority path-abempty
+    fn parse_absolute_form(s: &str) -> Result<Self, UriParseError> {
+        let Some((scheme, rest)) = s.split_once("://") else {
+            return Err(UriParseError::InvalidScheme);
+        };
+        let scheme = match scheme.parse::<Scheme>() {
+            Ok(scheme) if scheme != Scheme::EMPTY => scheme,
+            _ => return Err(UriParseError::InvalidScheme),
+        };
+        let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+        let (authority, rest) = rest.split_at(authority_end);
+        let authority = authority
+            .parse::<Authority>()
+            .map_err(|_| UriParseError::InvalidAuthority)?;
+        // An empty path is equivalent to "/"
+        let mut uri = if rest.is_empty() || rest.starts_with('?') {
+            Self::parse_origin_form(&format!("/{rest}"))?
+        } else {
+            Self::parse_origin_form(rest)?
+        };
+        uri.scheme = Some(scheme);
+        uri.authority = Some(authority);
+        Ok(uri)
+    }
+
+    pub fn scheme(&self) -> Option<&Scheme> {
+        self.scheme.as_ref()
+    }
+
+    pub fn authority(&self) -> Option<&Authority> {
+        self.authority.as_ref()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // The raw, still percent-encoded query.
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    pub fn parse_query_pairs(&self) -> Result<Query, QueryParseError> {
+        self.query.as_deref().unwrap_or_default().parse()
+    }
+
+    pub fn is_absolute(&self) -> bool {
+        self.scheme.is_some()
+    }
+
+    // Resolves a URI reference, such as the value of a `Location` header,
+    // against this URI (RFC 3986 section 5.2). A fragment in the reference
+    // is dropped.
+    pub fn resolve(&self, reference: &str) -> Result<Self, UriParseError> {
+        let reference = reference.split_once('#').map_or(reference, |r| r.0);
+        if reference.starts_with("//") || has_scheme(reference) {
+            let (scheme, rest) = match reference.strip_prefix("//") {
+                Some(rest) => {
+                    let scheme = self
+                        .scheme
+                        .as_ref()
+                        .ok_or(UriParseError::InvalidScheme)?;
+                    (scheme.as_str(), rest)
+                }
+                None => reference
+                    .split_once("://")
+                    .ok_or(UriParseError::InvalidScheme)?,
+            };
+            let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+            let (authority, rest) = rest.split_at(authority_end);
+            let (path, query) = split_query(rest);
+            let path = match path {
+                "" => String::new(),
+                path => remove_dot_segments(path),
+            };
+            return Self::parse_absolute_form(&format!(
+                "{scheme}://{authority}{path}{query}"
+            ));
+        }
+        let (path, query) = split_query(reference);
+        let (path, query) = match path {
+            "" if query.is_empty() => (
+                self.path.as_str().to_owned(),
+                self.query
+                    .as_ref()
+                    .map_or(String::new(), |q| format!("?{q}")),
+            ),
+            "" => (self.path.as_str().to_owned(), query.to_owned()),
+            path if path.starts_with('/') => {
+                (remove_dot_segments(path), query.to_owned())
+            }
+            path => {
+                let base = self.path.as_str();
+                let base = &base[..base.rfind('/').map_or(0, |i| i + 1)];
+                (
+                    remove_dot_segments(&format!("{base}{path}")),
+                    query.to_owned(),
+                )
+            }
+        };
+        let mut uri = Self::parse_origin_form(&format!("{path}{query}"))?;
+        uri.scheme = self.scheme.clone();
+        uri.authority = self.authority.clone();
+        Ok(uri)
+    }
+}
+
+// scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )
+fn has_scheme(reference: &str) -> bool {
+    let Some((scheme, _)) = reference.split_once(':') else {
+        return false;
+    };
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.bytes().all(|b| {
+            b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.')
+        })
+}
+
+// Splits off the query, keeping the '?' with it.
+fn split_query(s: &str) -> (&str, &str) {
+    s.split_at(s.find('?').unwrap_or(s.len()))
+}
+
+// Interprets the "." and ".." segments of an absolute path (RFC 3986
+// section 5.2.4). Going up from the root stays at the root.
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments = vec![];
+    let mut trailing_slash = false;
+    for segment in path.split('/').skip(1) {
+        trailing_slash = matches!(segment, "." | "..");
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    let mut path = String::with_capacity(path.len());
+    for segment in &segments {
+        path.push('/');
+        path.push_str(segment);
+    }
+    if trailing_slash || segments.is_empty() {
+        path.push('/');
+    }
+    path
+}
+
+impl FromStr for Uri {
+    type Err = UriParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(UriParseError::Empty);
+        }
+        if s.contains('#') {
+            return Err(UriParseError::UnexpectedFragment);
+        }
+        if s.starts_with('/') {
+            Self::parse_origin_form(s)
+        } else {
+            Self::parse_absolute_form(s)
+        }
+    }
+}
+
+impl fmt::Display for Uri {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(scheme) = &self.scheme {
+            write!(f, "{scheme}://")?;
+        }
+        if let Some(authority) = &self.authority {
+            write!(f, "{authority}")?;
+        }
+        f.write_str(self.path.as_str())?;
+        if let Some(query) = &self.query {
+            write!(f, "?{query}")?;
+        }
+        Ok(())
+    }
+}
+
+// The four forms a request target can take (RFC 9112 section 3.2).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestTarget {
+    // /where?q=now
+    Origin(Uri),
+    // http://www.example.org/pub/WWW/TheProject.html
+    Absolute(Uri),
+    // www.example.com:80 (CONNECT only)
+    Authority(Authority),
+    // * (server-wide OPTIONS only)
+    Asterisk,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RequestTargetParseError {
+    InvalidUri(UriParseError),
+    InvalidAuthority,
+    // The form of the target is not allowed for the method
+    FormNotAllowed,
+}
+
+impl RequestTarget {
+    pub fn parse(
+        s: &str,
+        method: &Method,
+    ) -> Result<Self, RequestTargetParseError> {
+        match (method, s) {
+            (Method::Connect, s) => s
+                .parse()
+                .map(Self::Authority)
+                .map_err(|_| RequestTargetParseError::InvalidAuthority),
+            (Method::Options, "*") => Ok(Self::Asterisk),
+            (_, "*") => Err(RequestTargetParseError::FormNotAllowed),
+            (_, s) => {
+                let uri = s
+                    .parse::<Uri>()
+                    .map_err(RequestTargetParseError::InvalidUri)?;
+                if uri.is_absolute() {
+                    Ok(Self::Absolute(uri))
+                } else {
+                    Ok(Self::Origin(uri))
+                }
+            }
+        }
+    }
+
+    pub fn uri(&self) -> Option<&Uri> {
+        match self {
+            Self::Origin(uri) | Self::Absolute(uri) => Some(uri),
+            _ => None,
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.uri().map(Uri::path)
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.uri().and_then(Uri::query)
+    }
+}
+
+impl fmt::Display for RequestTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Origin(uri) | Self::Absolute(uri) => write!(f, "{uri}"),
+            Self::Authority(authority) => write!(f, "{authority}"),
+            Self::Asterisk => f.write_str("*"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_origin_form() {
+        let uri = "/where?q=now".parse::<Uri>().unwrap();
+        assert_eq!(uri.scheme(), None);
+        assert_eq!(uri.authority(), None);
+        assert_eq!(uri.path().as_str(), "/where");
+        assert_eq!(uri.query(), Some("q=now"));
+
+        let uri = "/".parse::<Uri>().unwrap();
+        assert_eq!(uri.path().as_str(), "/");
+        assert_eq!(uri.query(), None);
+
+        let uri = "/search?q=a+b&lang=en/US&next=/?x".parse::<Uri>().unwrap();
+        assert_eq!(uri.query(), Some("q=a+b&lang=en/US&next=/?x"));
+
+        assert_eq!("/path[0]".parse::<Uri>(), Err(UriParseError::InvalidPath));
+        assert_eq!(
+            "/path?bad query".parse::<Uri>(),
+            Err(UriParseError::InvalidQuery)
+        );
+        assert_eq!(
+            "/path?q=%zz".parse::<Uri>(),
+            Err(UriParseError::InvalidQuery)
+        );
+        assert_eq!(
+            "/path#section".parse::<Uri>(),
+            Err(UriParseError::UnexpectedFragment)
+        );
+        assert_eq!("".parse::<Uri>(), Err(UriParseError::Empty));
+    }
+
+    #[test]
+    fn parse_absolute_form() {
+        let uri = "http://www.example.org:8080/pub/WWW/TheProject.html?a=b"
+            .parse::<Uri>()
+            .unwrap();
+        assert_eq!(uri.scheme(), Some(&Scheme::HTTP));
+        assert_eq!(uri.authority().unwrap().host(), "www.example.org");
+        assert_eq!(uri.authority().unwrap().port(), Some(8080));
+        assert_eq!(uri.path().as_str(), "/pub/WWW/TheProject.html");
+        assert_eq!(uri.query(), Some("a=b"));
+
+        // Empty path is normalized to "/"
+        let uri = "HTTPS://Example.com".parse::<Uri>().unwrap();
+        assert_eq!(uri.scheme(), Some(&Scheme::HTTPS));
+        assert_eq!(uri.path().as_str(), "/");
+        assert_eq!(uri.to_string(), "https://example.com/");
+        let uri = "http://example.com?x".parse::<Uri>().unwrap();
+        assert_eq!(uri.to_string(), "http://example.com/?x");
+        let uri = "http://h/wiki/Special:Search".parse::<Uri>().unwrap();
+        assert_eq!(uri.authority().unwrap().host(), "h");
+        assert_eq!(uri.path().as_str(), "/wiki/Special:Search");
+
+        assert_eq!(
+            "example.com/path".parse::<Uri>(),
+            Err(UriParseError::InvalidScheme)
+        );
+        assert_eq!(
+            "://example.com/path".parse::<Uri>(),
+            Err(UriParseError::InvalidScheme)
+        );
+        assert_eq!(
+            "http://exa mple.com/".parse::<Uri>(),
+            Err(UriParseError::InvalidAuthority)
+        );
+    }
+
+    #[test]
+    fn formatting() {
+        for s in [
+            "/",
+            "/where?q=now",
+            "http://www.example.org/pub/WWW/TheProject.html",
+            "http://localhost:8080/api/v1/users?id=3&sort=",
+        ] {
+            assert_eq!(s.parse::<Uri>().unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn resolve() {
+        // The examples of RFC 3986 section 5.4
+        let base = "http://a/b/c/d;p?q".parse::<Uri>().unwrap();
+        let resolve = |reference| base.resolve(reference).unwrap().to_string();
+        assert_eq!(resolve("g"), "http://a/b/c/g");
+        assert_eq!(resolve("./g"), "http://a/b/c/g");
+        assert_eq!(resolve("g/"), "http://a/b/c/g/");
+        assert_eq!(resolve("/g"), "http://a/g");
+        assert_eq!(resolve("//g"), "http://g/");
+        assert_eq!(resolve("?y"), "http://a/b/c/d;p?y");
+        assert_eq!(resolve("g?y"), "http://a/b/c/g?y");
+        assert_eq!(resolve("#s"), "http://a/b/c/d;p?q");
+        assert_eq!(resolve("g#s"), "http://a/b/c/g");
+        assert_eq!(resolve(";x"), "http://a/b/c/;x");
+        assert_eq!(resolve("g;x"), "http://a/b/c/g;x");
+        assert_eq!(resolve("g;x?y#s"), "http://a/b/c/g;x?y");
+        assert_eq!(resolve(""), "http://a/b/c/d;p?q");
+        assert_eq!(resolve("."), "http://a/b/c/");
+        assert_eq!(resolve("./"), "http://a/b/c/");
+        assert_eq!(resolve(".."), "http://a/b/");
+        assert_eq!(resolve("../"), "http://a/b/");
+        assert_eq!(resolve("../g"), "http://a/b/g");
+        assert_eq!(resolve("../.."), "http://a/");
+        assert_eq!(resolve("../../g"), "http://a/g");
+        assert_eq!(resolve("../../../g"), "http://a/g");
+        assert_eq!(resolve("/./g"), "http://a/g");
+        assert_eq!(resolve("/../g"), "http://a/g");
+        assert_eq!(resolve("g."), "http://a/b/c/g.");
+        assert_eq!(resolve(".g"), "http://a/b/c/.g");
+        assert_eq!(resolve("g.."), "http://a/b/c/g..");
+        assert_eq!(resolve("..g"), "http://a/b/c/..g");
+        assert_eq!(resolve("./g/."), "http://a/b/c/g/");
+        assert_eq!(resolve("g/./h"), "http://a/b/c/g/h");
+        assert_eq!(resolve("g/../h"), "http://a/b/c/h");
+        assert_eq!(resolve("g;x=1/./y"), "http://a/b/c/g;x=1/y");
+        assert_eq!(resolve("g;x=1/../y"), "http://a/b/c/y");
+        assert_eq!(resolve("./../g"), "http://a/b/g");
+
+        // Empty segments are kept
+        assert_eq!(resolve("g//h/../i"), "http://a/b/c/g//i");
+
+        // Absolute references
+        assert_eq!(resolve("https://b:8443"), "https://b:8443/");
+        assert_eq!(resolve("http://b/x/../y?z"), "http://b/y?z");
+        assert_eq!(
+            base.resolve("mailto:a@example.com"),
+            Err(UriParseError::InvalidScheme)
+        );
+        assert_eq!(base.resolve("g h"), Err(UriParseError::InvalidPath));
+
+        let base = "/a/b".parse::<Uri>().unwrap();
+        assert_eq!(base.resolve("c").unwrap().to_string(), "/a/c");
+        assert_eq!(base.resolve("//c"), Err(UriParseError::InvalidScheme));
+    }
+
+    #[test]
+    fn request_targets() {
+        assert!(matches!(
+            RequestTarget::parse("/index.html", &Method::Get),
+            Ok(RequestTarget::Origin(_))
+        ));
+        assert!(matches!(
+            RequestTarget::parse("http://example.com/", &Method::Get),
+            Ok(RequestTarget::Absolute(_))
+        ));
+        assert!(matches!(
+            RequestTarget::parse("example.com:443", &Method::Connect),
+            Ok(RequestTarget::Authority(_))
+        ));
+        assert_eq!(
+            RequestTarget::parse("*", &Method::Options),
+            Ok(RequestTarget::Asterisk)
+        );
+
+        assert_eq!(
+            RequestTarget::parse("*", &Method::Get),
+            Err(RequestTargetParseError::FormNotAllowed)
+        );
+        assert_eq!(
+            RequestTarget::parse("/path", &Method::Connect),
+            Err(RequestTargetParseError::InvalidAuthority)
+        );
+        assert_eq!(
+            RequestTarget::parse("/a b", &Method::Get),
+            Err(RequestTargetParseError::InvalidUri(
+                UriParseError::InvalidPath
+            ))
+        );
+
+        let target = RequestTarget::parse("/a?b=c", &Method::Get).unwrap();
+        assert_eq!(target.path().unwrap().as_str(), "/a");
+        assert_eq!(target.query(), Some("b=c"));
+        assert_eq!(target.to_string(), "/a?b=c");
+    }
+}
@@ -1,3 +1,7 @@
+// The tables below are spelled out byte by byte on purpose, to make it easy to
+// see at a glance exactly which characters are in each set.
+#![allow(clippy::byte_char_slices)]
+
 pub const UNRESERVED_BYTES: [u8; 66] = [
     b'-', b'.', b'0', b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9',
     b'A', b'B', b'C', b'D', b'E', b'F', b'G', b'H', b'I', b'J', b'K', b'L',
@@ -30,13 +34,16 @@ pub const ALLOWED_HOSTNAME_BYTES: [u8; 64] = [
     b'w', b'x', b'y', b'z',
 ];
 
-pub const ALLOWED_PATH_BYTES: [u8; 70] = [
-    b'%', b'*', b'+', b'-', b'.', b'/', b'0', b'1', b'2', b'3', b'4', b'5',
-    b'6', b'7', b'8', b'9', b'A', b'B', b'C', b'D', b'E', b'F', b'G', b'H',
-    b'I', b'J', b'K', b'L', b'M', b'N', b'O', b'P', b'Q', b'R', b'S', b'T',
-    b'U', b'V', b'W', b'X', b'Y', b'Z', b'_', b'a', b'b', b'c', b'd', b'e',
-    b'f', b'g', b'h', b'i', b'j', b'k', b'l', b'm', b'n', b'o', b'p', b'q',
-    b'r', b's', b't', b'u', b'v', b'w', b'x', b'y', b'z', b'~',
+// pchar = unreserved / pct-encoded / sub-delims / ":" / "@", plus the '/'
+// separating segments
+pub const ALLOWED_PATH_BYTES: [u8; 81] = [
+    b'!', b'$', b'%', b'&', b'\'', b'(', b')', b'*', b'+', b',', b'-', b'.',
+    b'/', b'0', b'1', b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b':',
+    b';', b'=', b'@', b'A', b'B', b'C', b'D', b'E', b'F', b'G', b'H', b'I',
+    b'J', b'K', b'L', b'M', b'N', b'O', b'P', b'Q', b'R', b'S', b'T', b'U',
+    b'V', b'W', b'X', b'Y', b'Z', b'_', b'a', b'b', b'c', b'd', b'e', b'f',
+    b'g', b'h', b'i', b'j', b'k', b'l', b'm', b'n', b'o', b'p', b'q', b'r',
+    b's', b't', b'u', b'v', b'w', b'x', b'y', b'z', b'~',
 ];
 
 pub const ALLOWED_QUERY_BYTES: [u8; 67] = [
@@ -63,18 +70,141 @@ pub fn is_properly_percent_encoded(bytes: &[u8]) -> bool {
         .enumerate()
         .filter(|&(_, &c)| c == b'%')
         .all(|(idx, _)| {
-            idx + 2 < bytes.len() - 1
+            idx + 2 < bytes.len()
                 && is_percent_encoding(bytes[idx + 1], bytes[idx + 2])
         })
 }
 
-// TODO: Optimize with better algorithm? Currently using naive approach.
-//       Might not be worth it anyway. Should be cache-friendly and most inputs
-//       are expected to be shorter strings.
-pub fn contains_subslice(src: &[u8], subslice: &[u8]) -> bool {
-    let n = subslice.len();
-    if n > src.len() || n == 0 {
-        return false;
+// tchar = "!" / "#" / "$" / "%" / "&" / "'" / "*" / "+" / "-" / "." /
+//         "^" / "_" / "`" / "|" / "~" / DIGIT / ALPHA
+pub const TOKEN_BYTES: [u8; 77] = [
+    b'!', b'#', b'$', b'%', b'&', b'\'', b'*', b'+', b'-', b'.', b'0', b'1',
+    b'2', b'3', b'4', b'5', b'6', b'7', b'8', b'9', b'A', b'B', b'C', b'D',
+    b'E', b'F', b'G', b'H', b'I', b'J', b'K', b'L', b'M', b'N', b'O', b'P',
+    b'Q', b'R', b'S', b'T', b'U', b'V', b'W', b'X', b'Y', b'Z', b'^', b'_',
+    b'`', b'a', b'b', b'c', b'd', b'e', b'f', b'g', b'h', b'i', b'j', b'k',
+    b'l', b'm', b'n', b'o', b'p', b'q', b'r', b's', b't', b'u', b'v', b'w',
+    b'x', b'y', b'z', b'|', b'~',
+];
+
+pub fn is_token(bytes: &[u8]) -> bool {
+    !bytes.is_empty() && bytes.iter().all(|b| TOKEN_BYTES.contains(b))
+}
+
+// Decodes every percent-encoded octet, leaving everything else as is.
+// Expects input that passed `is_properly_percent_encoded`.
+pub fn percent_decode(bytes: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut rest = bytes;
+    while let Some((&b, tail)) = rest.split_first() {
+        match (b, tail) {
+            (b'%', [hi, lo, tail @ ..]) if is_percent_encoding(*hi, *lo) => {
+                decoded.push(hex_value(*hi) << 4 | hex_value(*lo));
+                rest = tail;
+            }
+            _ => {
+                decoded.push(b);
+                rest = tail;
+            }
+        }
+    }
+    decoded
+}
+
+// Percent-encodes every byte outside of the unreserved set.
+pub fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for &b in bytes {
+        if UNRESERVED_BYTES.contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    encoded
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Encodes base64 with padding (RFC 4648 section 4).
+#[cfg(feature = "secure-cookies")]
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let mut buf = [0; 4];
+        buf[1..1 + chunk.len()].copy_from_slice(chunk);
+        let n = u32::from_be_bytes(buf);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (n >> (18 - 6 * i)) & 0x3f;
+                encoded.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+// Decodes base64 with padding (RFC 4648 section 4), rejecting anything but
+// canonical input.
+pub fn base64_decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    if !encoded.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    for (i, chunk) in encoded.chunks(4).enumerate() {
+        let is_last = i + 1 == encoded.len() / 4;
+        let padding = chunk.iter().rev().take_while(|&&b| b == b'=').count();
+        if padding > 2 || (padding > 0 && !is_last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for (j, &b) in chunk[..4 - padding].iter().enumerate() {
+            let value = BASE64_ALPHABET.iter().position(|&a| a == b)?;
+            n |= (value as u32) << (18 - 6 * j);
+        }
+        let bytes = n.to_be_bytes();
+        let len = 3 - padding;
+        // Bits past the end have to be zero for the encoding to be canonical
+        if bytes[1 + len..].iter().any(|&b| b != 0) {
+            return None;
+        }
+        decoded.extend_from_slice(&bytes[1..1 + len]);
+    }
+    Some(decoded)
+}
+
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64() {
+        for (decoded, encoded) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+            (b"\xff\xfe\xfd", "//79"),
+        ] {
+            assert_eq!(
+                base64_decode(encoded.as_bytes()).as_deref(),
+                Some(decoded)
+            );
+        }
+        for invalid in ["Zg", "Zg=", "Zh==", "Z===", "Zg==Zg==", "Zm9v!A=="] {
+            assert_eq!(base64_decode(invalid.as_bytes()), None);
+        }
     }
-    src.windows(n).any(|slice| slice == subslice)
 }
@@ -1,6 +1,9 @@
+use std::fmt;
 use std::str::FromStr;
 
-#[derive(Debug, PartialEq, Eq)]
+// Variants are declared in ascending order so that the derived `Ord` matches
+// protocol ordering, e.g. `version >= Version::Http1_1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Version {
     Http0_9,
     Http1_0,
@@ -12,18 +15,83 @@ pub enum Version {
 #[derive(Debug, PartialEq, Eq)]
 pub struct InvalidHttpVersion;
 
+impl Version {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Http0_9 => "HTTP/0.9",
+            Self::Http1_0 => "HTTP/1.0",
+            Self::Http1_1 => "HTTP/1.1",
+            Self::Http2 => "HTTP/2",
+            Self::Http3 => "HTTP/3",
+        }
+    }
+
+    // Protocol identifiers as registered in the IANA "TLS Application-Layer
+    // Protocol Negotiation (ALPN) Protocol IDs" registry.
+    pub fn alpn_id(&self) -> &'static [u8] {
+        match self {
+            Self::Http0_9 => b"http/0.9",
+            Self::Http1_0 => b"http/1.0",
+            Self::Http1_1 => b"http/1.1",
+            Self::Http2 => b"h2",
+            Self::Http3 => b"h3",
+        }
+    }
+
+    pub fn from_alpn_id(id: &[u8]) -> Result<Self, InvalidHttpVersion> {
+        match id {
+            b"http/0.9" => Ok(Self::Http0_9),
+            b"http/1.0" => Ok(Self::Http1_0),
+            b"http/1.1" => Ok(Self::Http1_1),
+            b"h2" => Ok(Self::Http2),
+            b"h3" => Ok(Self::Http3),
+            _ => Err(InvalidHttpVersion),
+        }
+    }
+
+    // HTTP-version = HTTP-name "/" DIGIT "." DIGIT
+    // HTTP-name    = %s"HTTP"
+    //
+    // HTTP/2 and HTTP/3 are also accepted without the minor version since
+    // that is how they are written everywhere outside of the HTTP/1.1 grammar.
+    pub fn from_bytes(s: &[u8]) -> Result<Self, InvalidHttpVersion> {
+        let Some(rest) = s.strip_prefix(b"HTTP/") else {
+            return Err(InvalidHttpVersion);
+        };
+        let (major, minor) = match *rest {
+            [major] if major.is_ascii_digit() => (major - b'0', None),
+            [major, b'.', minor]
+                if major.is_ascii_digit() && minor.is_ascii_digit() =>
+            {
+                (major - b'0', Some(minor - b'0'))
+            }
+            _ => return Err(InvalidHttpVersion),
+        };
+        match (major, minor) {
+            (0, Some(9)) => Ok(Self::Http0_9),
+            (1, Some(0)) => Ok(Self::Http1_0),
+            // RFC 9112 section 2.3: a recipient of a higher minor version
+            // than it implements should process the message as if it was the
+            // highest minor version within that major version.
+            (1, Some(_)) => Ok(Self::Http1_1),
+            (2, None | Some(0)) => Ok(Self::Http2),
+            (3, None | Some(0)) => Ok(Self::Http3),
+            _ => Err(InvalidHttpVersion),
+        }
+    }
+}
+
 impl FromStr for Version {
     type Err = InvalidHttpVersion;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            r"HTTP/0.9" => Ok(Self::Http0_9),
-            r"HTTP/1.0" => Ok(Self::Http1_0),
-            r"HTTP/1.1" => Ok(Self::Http1_1),
-            r"HTTP/2" => Ok(Self::Http2),
-            r"HTTP/3" => Ok(Self::Http3),
-            _ => Err(InvalidHttpVersion),
-        }
+        Self::from_bytes(s.as_bytes())
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
     }
 }
 
@@ -45,4 +113,67 @@ mod tests {
         assert_eq!("".parse::<Version>(), Err(InvalidHttpVersion));
         assert_eq!("blablabla".parse::<Version>(), Err(InvalidHttpVersion));
     }
+
+    #[test]
+    fn parsing_major_minor() {
+        assert_eq!("HTTP/2.0".parse(), Ok(Version::Http2));
+        assert_eq!("HTTP/3.0".parse(), Ok(Version::Http3));
+        // Unknown minor versions of HTTP/1 are treated as HTTP/1.1
+        assert_eq!("HTTP/1.2".parse(), Ok(Version::Http1_1));
+        assert_eq!("HTTP/1.9".parse(), Ok(Version::Http1_1));
+
+        // Unknown major versions
+        assert_eq!("HTTP/4.0".parse::<Version>(), Err(InvalidHttpVersion));
+        assert_eq!("HTTP/1".parse::<Version>(), Err(InvalidHttpVersion));
+        assert_eq!("HTTP/2.1".parse::<Version>(), Err(InvalidHttpVersion));
+        // Only single digits are allowed
+        assert_eq!("HTTP/1.10".parse::<Version>(), Err(InvalidHttpVersion));
+        assert_eq!("HTTP/01.1".parse::<Version>(), Err(InvalidHttpVersion));
+        // No whitespace
+        assert_eq!("HTTP/1.1 ".parse::<Version>(), Err(InvalidHttpVersion));
+        assert_eq!("HTTP/ 1.1".parse::<Version>(), Err(InvalidHttpVersion));
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(Version::Http0_9.to_string(), "HTTP/0.9");
+        assert_eq!(Version::Http1_0.to_string(), "HTTP/1.0");
+        assert_eq!(Version::Http1_1.to_string(), "HTTP/1.1");
+        assert_eq!(Version::Http2.to_string(), "HTTP/2");
+        assert_eq!(Version::Http3.to_string(), "HTTP/3");
+        assert_eq!(
+            "HTTP/2.0".parse::<Version>().unwrap().to_string(),
+            "HTTP/2"
+        );
+    }
+
+    #[test]
+    fn ordering() {
+        assert!(Version::Http0_9 < Version::Http1_0);
+        assert!(Version::Http1_0 < Version::Http1_1);
+        assert!(Version::Http1_1 < Version::Http2);
+        assert!(Version::Http2 < Version::Http3);
+        assert!(Version::Http1_1 >= Version::Http1_1);
+    }
+
+    #[test]
+    fn alpn() {
+        assert_eq!(Version::Http1_1.alpn_id(), b"http/1.1");
+        assert_eq!(Version::Http2.alpn_id(), b"h2");
+        assert_eq!(Version::Http3.alpn_id(), b"h3");
+
+        for version in [
+            Version::Http0_9,
+            Version::Http1_0,
+            Version::Http1_1,
+            Version::Http2,
+            Version::Http3,
+        ] {
+            assert_eq!(Version::from_alpn_id(version.alpn_id()), Ok(version));
+        }
+
+        assert_eq!(Version::from_alpn_id(b"h2c"), Err(InvalidHttpVersion));
+        assert_eq!(Version::from_alpn_id(b"HTTP/1.1"), Err(InvalidHttpVersion));
+        assert_eq!(Version::from_alpn_id(b""), Err(InvalidHttpVersion));
+    }
 }